@@ -200,6 +200,19 @@ struct MadtProcessorLocalApic {
     flags: u32, // bit 0: Processor Enabled
 }
 
+/// MADT エントリ: Processor Local x2APIC
+///
+/// APIC IDが255を超えるプロセッサ（x2APICモード必須）はこのエントリで列挙される。
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct MadtProcessorLocalX2Apic {
+    header: MadtEntryHeader,
+    reserved: u16,
+    x2apic_id: u32,
+    flags: u32, // bit 0: Processor Enabled
+    acpi_processor_uid: u32,
+}
+
 /// MADT エントリ: I/O APIC
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
@@ -211,6 +224,30 @@ struct MadtIoApic {
     global_system_interrupt_base: u32,
 }
 
+/// MADT エントリ: Interrupt Source Override
+///
+/// ISA IRQ（バス相対の割り込み番号）がどのGSIに接続されているかを上書きする。
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct MadtInterruptSourceOverride {
+    header: MadtEntryHeader,
+    bus: u8,    // 0 = ISA
+    source: u8, // バス相対IRQ番号
+    global_system_interrupt: u32,
+    flags: u16, // MPS INTI flags（極性・トリガモード）
+}
+
+/// MADT エントリ: NMI Source
+///
+/// NMIとして扱うべきI/O APIC入力（GSI）を示す。
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct MadtNmiSource {
+    header: MadtEntryHeader,
+    flags: u16, // MPS INTI flags（極性・トリガモード）
+    global_system_interrupt: u32,
+}
+
 /// MADT (Multiple APIC Description Table) テーブル
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
@@ -253,6 +290,56 @@ struct HpetAddress {
     address: u64,
 }
 
+/// FADT (Fixed ACPI Description Table)
+///
+/// PM Timerの情報（`flags`）までのACPI 1.0互換部分のみを定義する。
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct Fadt {
+    header: AcpiTableHeader,
+    firmware_ctrl: u32,
+    dsdt: u32,
+    reserved0: u8,
+    preferred_pm_profile: u8,
+    sci_int: u16,
+    smi_cmd: u32,
+    acpi_enable: u8,
+    acpi_disable: u8,
+    s4bios_req: u8,
+    pstate_cnt: u8,
+    pm1a_evt_blk: u32,
+    pm1b_evt_blk: u32,
+    pm1a_cnt_blk: u32,
+    pm1b_cnt_blk: u32,
+    pm2_cnt_blk: u32,
+    pm_tmr_blk: u32, // PM TimerのI/Oポート（0なら非搭載）
+    gpe0_blk: u32,
+    gpe1_blk: u32,
+    pm1_evt_len: u8,
+    pm1_cnt_len: u8,
+    pm2_cnt_len: u8,
+    pm_tmr_len: u8, // PM Timerのポート幅（4でなければ無効）
+    gpe0_blk_len: u8,
+    gpe1_blk_len: u8,
+    gpe1_base: u8,
+    cst_cnt: u8,
+    p_lvl2_lat: u16,
+    p_lvl3_lat: u16,
+    flush_size: u16,
+    flush_stride: u16,
+    duty_offset: u8,
+    duty_width: u8,
+    day_alrm: u8,
+    mon_alrm: u8,
+    century: u8,
+    iapc_boot_arch: u16,
+    reserved1: u8,
+    flags: u32,
+}
+
+/// FADT flags: PM Timerのカウンタが32ビット幅（TMR_VAL_EXT）
+const FADT_FLAG_TMR_VAL_EXT: u32 = 1 << 8;
+
 /// MCFG Configuration Space Base Address Allocation Structure
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
@@ -446,6 +533,11 @@ fn parse_sdt<E: SdtEntry>(sdt_phys_addr: u64) -> Result<(), AcpiError> {
                     info!("MCFG parsing failed: {:?}, continuing without MCFG", e);
                 }
             }
+            "FACP" => {
+                if let Err(e) = parse_fadt(table_phys_addr) {
+                    info!("FADT parsing failed: {:?}, continuing without FADT", e);
+                }
+            }
             "HPET" => {
                 if let Err(e) = parse_hpet(table_phys_addr) {
                     info!(
@@ -501,6 +593,7 @@ fn parse_madt(madt_phys_addr: u64) -> Result<(), AcpiError> {
     let mut current_addr = entries_start;
     let mut cpu_count = 0;
     let mut io_apic_count = 0;
+    let mut override_count = 0;
 
     // エントリをイテレート
     while current_addr < entries_end {
@@ -531,6 +624,7 @@ fn parse_madt(madt_phys_addr: u64) -> Result<(), AcpiError> {
                         acpi_id,
                         apic_id
                     );
+                    crate::smp::register_processor(apic_id as u32);
                 }
             }
             1 => {
@@ -550,6 +644,60 @@ fn parse_madt(madt_phys_addr: u64) -> Result<(), AcpiError> {
                     io_apic_address,
                     gsi_base
                 );
+
+                // I/O APICモジュールに登録（マッピングはioapic::init()で行う）
+                crate::ioapic::register_io_apic(io_apic_id, io_apic_address as u64, gsi_base);
+            }
+            2 => {
+                // Interrupt Source Override
+                // SAFETY: entry_type == 2 でInterrupt Source Overrideエントリであることを確認済み。
+                // current_addrはMADTテーブル内の有効なアドレス。#[repr(C, packed)]により非アラインアクセスが許可される。
+                let iso_entry = unsafe { &*(current_addr as *const MadtInterruptSourceOverride) };
+                let bus = iso_entry.bus;
+                let source = iso_entry.source;
+                let gsi = iso_entry.global_system_interrupt;
+                let iso_flags = iso_entry.flags;
+
+                override_count += 1;
+                info!(
+                    "  Interrupt Source Override: Bus={}, IRQ={} -> GSI={}, Flags=0x{:04X}",
+                    bus, source, gsi, iso_flags
+                );
+
+                crate::ioapic::register_source_override(source, gsi, iso_flags);
+            }
+            3 => {
+                // NMI Source
+                // SAFETY: entry_type == 3 でNMI Sourceエントリであることを確認済み。
+                // current_addrはMADTテーブル内の有効なアドレス。#[repr(C, packed)]により非アラインアクセスが許可される。
+                let nmi_entry = unsafe { &*(current_addr as *const MadtNmiSource) };
+                let gsi = nmi_entry.global_system_interrupt;
+                let nmi_flags = nmi_entry.flags;
+
+                info!("  NMI Source: GSI={}, Flags=0x{:04X}", gsi, nmi_flags);
+
+                crate::ioapic::register_nmi_source(gsi, nmi_flags);
+            }
+            9 => {
+                // Processor Local x2APIC
+                // SAFETY: entry_type == 9 でProcessor Local x2APICエントリであることを確認済み。
+                // current_addrはMADTテーブル内の有効なアドレス。#[repr(C, packed)]により非アラインアクセスが許可される。
+                let x2apic_entry = unsafe { &*(current_addr as *const MadtProcessorLocalX2Apic) };
+                let acpi_uid = x2apic_entry.acpi_processor_uid;
+                let x2apic_id = x2apic_entry.x2apic_id;
+                let entry_flags = x2apic_entry.flags;
+
+                // bit 0 が 1 なら有効なプロセッサ
+                if (entry_flags & 1) != 0 {
+                    cpu_count += 1;
+                    info!(
+                        "  CPU #{}: ACPI UID={}, x2APIC ID={}, Enabled",
+                        cpu_count - 1,
+                        acpi_uid,
+                        x2apic_id
+                    );
+                    crate::smp::register_processor(x2apic_id);
+                }
             }
             _ => {
                 // その他のエントリタイプはスキップ
@@ -561,8 +709,8 @@ fn parse_madt(madt_phys_addr: u64) -> Result<(), AcpiError> {
     }
 
     info!(
-        "MADT Summary: {} CPU(s), {} I/O APIC(s)",
-        cpu_count, io_apic_count
+        "MADT Summary: {} CPU(s), {} I/O APIC(s), {} override(s)",
+        cpu_count, io_apic_count, override_count
     );
 
     Ok(())
@@ -632,6 +780,44 @@ fn parse_mcfg(mcfg_phys_addr: u64) -> Result<(), AcpiError> {
     Ok(())
 }
 
+/// FADT (Fixed ACPI Description Table) を解析
+///
+/// 現在はPM Timerの情報のみを取得する。
+///
+/// # Errors
+/// * `AcpiError::AddressConversionFailed` - FADTのアドレス変換に失敗した場合
+/// * `AcpiError::ChecksumFailed` - チェックサム検証に失敗した場合
+/// * `AcpiError::NotSupported` - FADTがPM Timerのフィールドを含まないほど短い場合
+fn parse_fadt(fadt_phys_addr: u64) -> Result<(), AcpiError> {
+    let fadt_virt_addr =
+        phys_to_virt(fadt_phys_addr).map_err(|_| AcpiError::AddressConversionFailed)?;
+    // SAFETY: phys_to_virtで変換した有効なアドレス。ACPIテーブルはUEFIが配置し
+    // カーネル実行中有効。#[repr(C, packed)]により非アラインアクセスが許可される。
+    let header = unsafe { &*(fadt_virt_addr as *const AcpiTableHeader) };
+
+    // SAFETY: headerは有効なACPIテーブルヘッダを指し、lengthバイトが読み取り可能
+    if !unsafe { header.verify_checksum() } {
+        return Err(AcpiError::ChecksumFailed);
+    }
+    if (header.length as usize) < core::mem::size_of::<Fadt>() {
+        return Err(AcpiError::NotSupported);
+    }
+
+    // SAFETY: テーブル長がFadt構造体のサイズ以上であることを確認済み
+    let fadt = unsafe { &*(fadt_virt_addr as *const Fadt) };
+
+    // packed struct のフィールドはローカル変数にコピー
+    let pm_tmr_blk = fadt.pm_tmr_blk;
+    let pm_tmr_len = fadt.pm_tmr_len;
+    let flags = fadt.flags;
+
+    // PM TimerはI/Oポート空間（16ビット）にあり、ポート幅は4バイト
+    if pm_tmr_blk != 0 && pm_tmr_blk <= u16::MAX as u32 && pm_tmr_len == 4 {
+        crate::acpi_pm::init(pm_tmr_blk as u16, flags & FADT_FLAG_TMR_VAL_EXT != 0);
+    }
+    Ok(())
+}
+
 /// HPET (High Precision Event Timer) テーブルを解析
 ///
 /// # Errors
@@ -0,0 +1,105 @@
+//! ACPI PM Timer 実装
+//!
+//! FADTで通知されるI/Oポート上の3.579545MHzの固定周波数カウンタです。
+//! 周波数がCPUの状態に依存しないため、TSCが使えない環境のクロックソースとして使用します。
+//! カウンタ幅は24ビット（FADTのTMR_VAL_EXTフラグが立っていれば32ビット）で、
+//! 読み取りは1回のポートアクセスで完結します。
+
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+
+use crate::io::port_read_u32;
+use crate::time::ClockSource;
+use crate::timer_device::TimerDevice;
+
+/// PM Timerの周波数（Hz）
+pub const PM_TIMER_FREQUENCY: u64 = 3_579_545;
+
+/// PM TimerのI/Oポート（0は未検出）
+static PM_TIMER_PORT: AtomicU16 = AtomicU16::new(0);
+
+/// カウンタが32ビット幅かどうか
+static PM_TIMER_32BIT: AtomicBool = AtomicBool::new(false);
+
+/// FADTから得たPM Timerの情報を登録
+///
+/// # Arguments
+/// * `port` - PM_TMR_BLKのI/Oポート
+/// * `extended` - カウンタが32ビット幅かどうか（TMR_VAL_EXT）
+pub fn init(port: u16, extended: bool) {
+    PM_TIMER_32BIT.store(extended, Ordering::Relaxed);
+    PM_TIMER_PORT.store(port, Ordering::Release);
+    crate::info!(
+        "ACPI PM Timer found: port=0x{:X}, {}-bit",
+        port,
+        if extended { 32 } else { 24 }
+    );
+}
+
+/// PM Timerが利用可能かどうか
+pub fn is_available() -> bool {
+    PM_TIMER_PORT.load(Ordering::Acquire) != 0
+}
+
+/// カウンタの有効ビットマスク
+pub fn counter_mask() -> u64 {
+    if PM_TIMER_32BIT.load(Ordering::Relaxed) {
+        0xFFFF_FFFF
+    } else {
+        0xFF_FFFF
+    }
+}
+
+/// カウンタの現在値を読み取る
+///
+/// PM Timerが利用できない場合は0を返す
+pub fn read_counter() -> u64 {
+    let port = PM_TIMER_PORT.load(Ordering::Acquire);
+    if port == 0 {
+        return 0;
+    }
+    // SAFETY: portはFADTで通知されたPM TimerのI/Oポートで、読み取りに副作用はない
+    let value = unsafe { port_read_u32(port) };
+    value as u64 & counter_mask()
+}
+
+/// ACPI PM Timer タイマーデバイス
+pub struct AcpiPmTimer;
+
+impl TimerDevice for AcpiPmTimer {
+    fn is_available(&self) -> bool {
+        is_available()
+    }
+
+    fn frequency(&self) -> u64 {
+        PM_TIMER_FREQUENCY
+    }
+
+    fn delay_ns(&self, ns: u64) {
+        if !is_available() {
+            return;
+        }
+        let target = (ns as u128 * PM_TIMER_FREQUENCY as u128 / 1_000_000_000) as u64;
+        let mask = counter_mask();
+        let mut last = read_counter();
+        let mut elapsed = 0u64;
+        // カウンタ幅が狭いため、差分を積算してラップアラウンドに対応する
+        while elapsed < target {
+            let now = read_counter();
+            elapsed += now.wrapping_sub(last) & mask;
+            last = now;
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// グローバルACPI PM Timerインスタンス
+pub static ACPI_PM_TIMER: AcpiPmTimer = AcpiPmTimer;
+
+/// PM Timerの周波数（Hz）
+fn frequency() -> u64 {
+    PM_TIMER_FREQUENCY
+}
+
+/// ACPI PM Timerクロックソース
+pub static ACPI_PM_CLOCKSOURCE: ClockSource =
+    ClockSource::new("acpi_pm", 200, read_counter, counter_mask, frequency);
@@ -1,15 +1,22 @@
 //! Local APIC (Advanced Programmable Interrupt Controller) 実装
 //!
 //! Intel SDM Vol 3A Chapter 10 に基づく実装
+//!
+//! CPUがx2APICをサポートしている場合はx2APICモードに切り替え、レジスタへは
+//! MMIOではなくMSR（0x800 + オフセット/16）経由でアクセスします。
+//! x2APICモードではAPIC IDが32ビットになり、255を超えるAPIC IDを扱えます。
+//! どちらのモードでも公開APIは同じです。
 
-use core::arch::asm;
 use core::ptr::{read_volatile, write_volatile};
-use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, Ordering};
 
 use crate::hpet;
+use crate::msr;
 use crate::paging::KERNEL_VIRTUAL_BASE;
 use crate::pit;
+use crate::time::{ClockEventDevice, ClockEventError};
 use crate::timer_device::TimerDevice;
+use crate::tsc;
 
 /// APIC操作のエラー型
 #[allow(dead_code)]
@@ -23,6 +30,8 @@ pub enum ApicError {
     InitFailed,
     /// 無効な周波数（0など）
     InvalidFrequency,
+    /// CPUがTSC-deadlineモードをサポートしていない
+    TscDeadlineUnsupported,
 }
 
 impl core::fmt::Display for ApicError {
@@ -32,6 +41,7 @@ impl core::fmt::Display for ApicError {
             ApicError::CalibrationFailed => write!(f, "APIC Timer calibration failed"),
             ApicError::InitFailed => write!(f, "APIC initialization failed"),
             ApicError::InvalidFrequency => write!(f, "Invalid APIC timer frequency"),
+            ApicError::TscDeadlineUnsupported => write!(f, "TSC-deadline mode not supported"),
         }
     }
 }
@@ -49,6 +59,21 @@ static APIC_PHYS_BASE: AtomicU64 = AtomicU64::new(DEFAULT_APIC_PHYS_BASE);
 /// enable_apic()成功後にtrueに設定される
 static APIC_MMIO_MAPPED: AtomicBool = AtomicBool::new(false);
 
+/// x2APICモードで動作しているかどうか（全CPUで共通）
+static X2APIC_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// IA32_APIC_BASE: x2APICモード有効化ビット (EXTD)
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+
+/// IA32_APIC_BASE: APICグローバル有効化ビット (EN)
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+/// x2APICレジスタのMSRベースアドレス
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+/// CPUID.01H:ECX のx2APICサポートビット
+const CPUID_ECX_X2APIC: u32 = 1 << 21;
+
 /// 現在のAPIC物理ベースアドレスを取得
 fn apic_phys_base() -> u64 {
     APIC_PHYS_BASE.load(Ordering::SeqCst)
@@ -68,6 +93,8 @@ fn apic_virt_base() -> u64 {
 
 /// Local APICレジスタのオフセット
 mod registers {
+    /// Local APIC ID Register
+    pub const ID: u32 = 0x20;
     /// Spurious Interrupt Vector Register
     pub const SPURIOUS_INTERRUPT_VECTOR: u32 = 0xF0;
     /// End of Interrupt Register
@@ -80,14 +107,86 @@ mod registers {
     pub const TIMER_INITIAL_COUNT: u32 = 0x380;
     /// Timer Current Count Register
     pub const TIMER_CURRENT_COUNT: u32 = 0x390;
+    /// Interrupt Command Register (bits 0-31)
+    pub const ICR_LOW: u32 = 0x300;
+    /// Interrupt Command Register (bits 32-63)
+    pub const ICR_HIGH: u32 = 0x310;
+}
+
+/// Interrupt Command Registerのビットフィールド
+pub mod icr {
+    /// Delivery Mode: Fixed
+    pub const DELIVERY_FIXED: u32 = 0b000 << 8;
+    /// Delivery Mode: NMI
+    pub const DELIVERY_NMI: u32 = 0b100 << 8;
+    /// Delivery Mode: INIT
+    pub const DELIVERY_INIT: u32 = 0b101 << 8;
+    /// Delivery Mode: Start Up
+    pub const DELIVERY_STARTUP: u32 = 0b110 << 8;
+    /// Delivery Status（1 = 送信中）
+    pub const DELIVERY_PENDING: u32 = 1 << 12;
+    /// Level: Assert
+    pub const LEVEL_ASSERT: u32 = 1 << 14;
+    /// Trigger Mode: Level
+    pub const TRIGGER_LEVEL: u32 = 1 << 15;
+    /// Destination Shorthand: 自分自身
+    pub const SHORTHAND_SELF: u32 = 0b01 << 18;
+    /// Destination Shorthand: 自分以外の全CPU
+    pub const SHORTHAND_ALL_EXCLUDING_SELF: u32 = 0b11 << 18;
+    /// 宛先フィールドのシフト量（ICR_HIGHのbits 24-31）
+    pub const DEST_SHIFT: u32 = 24;
+}
+
+/// xAPICのMMIOオフセットに対応するx2APICのMSRアドレス
+fn x2apic_msr(offset: u32) -> u32 {
+    X2APIC_MSR_BASE + (offset >> 4)
+}
+
+/// x2APICのICR（64ビット、MSR 0x830）に書き込む値を組み立てる
+///
+/// xAPICと異なり宛先はbits 32-63の32ビットAPIC IDになる。
+fn x2apic_icr_value(apic_id: u32, icr_low: u32) -> u64 {
+    ((apic_id as u64) << 32) | icr_low as u64
+}
+
+/// CPUがx2APICをサポートしているかどうか（CPUID.01H:ECX[bit 21]）
+fn cpu_supports_x2apic() -> bool {
+    let ecx: u32;
+    // SAFETY: CPUID命令はx86_64で常に利用可能で、副作用はない。
+    // RBXはLLVMが予約しているため退避・復元する。
+    unsafe {
+        core::arch::asm!(
+            "push rbx",
+            "cpuid",
+            "pop rbx",
+            inout("eax") 1u32 => _,
+            inout("ecx") 0u32 => ecx,
+            lateout("edx") _,
+            options(nomem),
+        );
+    }
+    ecx & CPUID_ECX_X2APIC != 0
+}
+
+/// x2APICモードで動作しているかどうか
+pub fn is_x2apic() -> bool {
+    X2APIC_ENABLED.load(Ordering::Relaxed)
 }
 
 /// Local APICレジスタへの書き込み
 ///
+/// x2APICモードではMSR経由で書き込む。
+///
 /// # Safety
 /// - APICが有効化されMMIOマッピングが完了していること（enable_apic()呼び出し後）
+///   またはx2APICモードであること
 /// - offsetが有効なAPICレジスタオフセットであること
 unsafe fn write_apic_register(offset: u32, value: u32) {
+    if is_x2apic() {
+        // SAFETY: x2APICモードではoffsetに対応するMSRが存在する
+        unsafe { msr::write(x2apic_msr(offset), value as u64) };
+        return;
+    }
     let addr = (apic_virt_base() + offset as u64) as *mut u32;
     // SAFETY: 呼び出し元が上記の安全性要件を満たすことを保証する。
     // APICレジスタはメモリマップドI/Oであり、write_volatileで書き込む必要がある。
@@ -98,10 +197,16 @@ unsafe fn write_apic_register(offset: u32, value: u32) {
 
 /// Local APICレジスタからの読み込み
 ///
+/// x2APICモードではMSR経由で読み込む。
+///
 /// # Safety
-/// - APICが有効化されMMIOマッピングが完了していること
+/// - APICが有効化されMMIOマッピングが完了していること、またはx2APICモードであること
 /// - offsetが有効なAPICレジスタオフセットであること
 unsafe fn read_apic_register(offset: u32) -> u32 {
+    if is_x2apic() {
+        // SAFETY: x2APICモードではoffsetに対応するMSRが存在する
+        return unsafe { msr::read(x2apic_msr(offset)) as u32 };
+    }
     let addr = (apic_virt_base() + offset as u64) as *const u32;
     // SAFETY: 呼び出し元が上記の安全性要件を満たすことを保証する。
     // APICレジスタはメモリマップドI/Oであり、read_volatileで読み込む必要がある。
@@ -119,33 +224,50 @@ fn enable_apic() -> Result<(), crate::paging::PagingError> {
     // マッピング成功を記録（debug_assertで使用）
     APIC_MMIO_MAPPED.store(true, Ordering::SeqCst);
 
+    enable_local_apic();
+
+    Ok(())
+}
+
+/// 現在のCPUのLocal APICを有効化
+///
+/// xAPICモードではMMIOがマッピング済みであること。
+/// x2APICモードでは現在のCPUもx2APICモードに切り替える。
+fn enable_local_apic() {
     // SAFETY: IA32_APIC_BASE MSR (0x1B) はx86_64アーキテクチャで定義された
     // 標準的なMSRであり、APICの有効化に使用される。
+    // EXTDビットはcpu_supports_x2apic()で確認済みの場合のみセットする。
     // Spurious Interrupt Vector Registerへの書き込みも、APICが
-    // 設定されたベースアドレスに存在する前提で安全。
+    // 設定されたベースアドレス（またはx2APIC MSR）に存在する前提で安全。
     unsafe {
-        // IA32_APIC_BASE MSR (0x1B) を読み込み
-        const IA32_APIC_BASE_MSR: u32 = 0x1B;
-        let mut apic_base_msr = crate::msr::read(IA32_APIC_BASE_MSR);
+        let mut apic_base_msr = msr::read(msr::IA32_APIC_BASE);
 
         // APIC Enable bit (bit 11) をセット
-        apic_base_msr |= 1 << 11;
+        apic_base_msr |= APIC_BASE_ENABLE;
+        // x2APICモードへの切り替え（ENがセットされた状態でEXTDをセットする）
+        if is_x2apic() {
+            apic_base_msr |= APIC_BASE_X2APIC_ENABLE;
+        }
 
         // MSRに書き戻し
-        crate::msr::write(IA32_APIC_BASE_MSR, apic_base_msr);
+        msr::write(msr::IA32_APIC_BASE, apic_base_msr);
 
         // Spurious Interrupt Vector Registerを設定してAPICを有効化
         // bit 8: APIC Software Enable/Disable
         // bits 0-7: Spurious Vector (通常は0xFF)
-        write_apic_register(registers::SPURIOUS_INTERRUPT_VECTOR, 0x1FF);
+        write_apic_register(
+            registers::SPURIOUS_INTERRUPT_VECTOR,
+            0x100 | SPURIOUS_VECTOR as u32,
+        );
     }
-
-    Ok(())
 }
 
 /// タイマー割り込みベクタ番号
 pub const TIMER_INTERRUPT_VECTOR: u8 = 32;
 
+/// Local APICのスプリアス割り込みベクタ
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
 /// キャリブレーションされたAPIC Timerのバス周波数（Hz）
 /// 分周比を考慮した実効周波数
 static APIC_TIMER_FREQUENCY: AtomicU32 = AtomicU32::new(0);
@@ -267,7 +389,7 @@ pub fn calibrate_timer() -> Result<(), ApicError> {
     Ok(())
 }
 
-/// Local APIC Timerを初期化
+/// Local APIC Timerを初期化（起動時用、設定内容をログに出力する）
 ///
 /// # Arguments
 /// * `frequency_hz` - タイマー割り込みの周波数 (Hz)
@@ -276,20 +398,46 @@ pub fn calibrate_timer() -> Result<(), ApicError> {
 /// * `ApicError::NotCalibrated` - タイマーがキャリブレーションされていない場合
 /// * `ApicError::InvalidFrequency` - 周波数が0の場合
 pub fn init_timer(frequency_hz: u32) -> Result<(), ApicError> {
+    let initial_count = rearm_periodic(frequency_hz)?;
+    crate::info!(
+        "APIC Timer initialized: {} Hz (initial count: {})",
+        frequency_hz,
+        initial_count
+    );
+    Ok(())
+}
+
+/// Local APIC Timerを周期モードに設定し直す（ログ出力なし）
+///
+/// DIVIDE・LVT・INITIAL_COUNTのみを書き込む。tickless idleやワンショットから
+/// 周期モードへ戻る時に割り込みコンテキストから頻繁に呼ばれるため、ログは出力しない。
+///
+/// # Arguments
+/// * `frequency_hz` - タイマー割り込みの周波数 (Hz)
+///
+/// # Returns
+/// 設定したInitial Countの値
+///
+/// # Errors
+/// * `ApicError::NotCalibrated` - タイマーがキャリブレーションされていない場合
+/// * `ApicError::InvalidFrequency` - 周波数が0の場合
+pub fn rearm_periodic(frequency_hz: u32) -> Result<u32, ApicError> {
     if frequency_hz == 0 {
         return Err(ApicError::InvalidFrequency);
     }
 
+    // キャリブレーション結果を取得
+    let apic_freq = APIC_TIMER_FREQUENCY.load(Ordering::SeqCst);
+    if apic_freq == 0 {
+        return Err(ApicError::NotCalibrated);
+    }
+    // キャリブレーション結果を使って正確な値を計算
+    let initial_count = apic_freq / frequency_hz;
+
     // SAFETY: APICレジスタへのアクセスは、enable_apic()でAPICが有効化され、
     // calibrate_timer()でキャリブレーションが完了した後に行われる。
     // すべてのレジスタオフセットはIntel SDMで定義された有効な値。
     unsafe {
-        // キャリブレーション結果を取得
-        let apic_freq = APIC_TIMER_FREQUENCY.load(Ordering::SeqCst);
-        if apic_freq == 0 {
-            return Err(ApicError::NotCalibrated);
-        }
-
         // Timer Divide Configuration Register を設定
         // 0x3 = Divide by 16
         write_apic_register(registers::TIMER_DIVIDE_CONFIG, 0x3);
@@ -304,20 +452,159 @@ pub fn init_timer(frequency_hz: u32) -> Result<(), ApicError> {
         write_apic_register(registers::TIMER_LVT, lvt_value);
 
         // Initial Count Register を設定
-        // キャリブレーション結果を使って正確な値を計算
-        let initial_count = apic_freq / frequency_hz;
         write_apic_register(registers::TIMER_INITIAL_COUNT, initial_count);
+    }
+    Ok(initial_count)
+}
 
-        crate::info!(
-            "APIC Timer initialized: {} Hz (initial count: {})",
-            frequency_hz,
-            initial_count
+/// Local APIC Timerをワンショットモードで開始し、指定ナノ秒後に1回だけ割り込みを発生させる
+///
+/// # Errors
+/// * `ApicError::NotCalibrated` - タイマーがキャリブレーションされていない場合
+pub fn arm_oneshot_count_ns(ns: u64) -> Result<(), ApicError> {
+    let apic_freq = APIC_TIMER_FREQUENCY.load(Ordering::SeqCst);
+    if apic_freq == 0 {
+        return Err(ApicError::NotCalibrated);
+    }
+    // 0を書き込むとタイマーが停止するため、最低1カウントとする
+    let count = (ns as u128 * apic_freq as u128 / 1_000_000_000).clamp(1, u32::MAX as u128) as u32;
+
+    // SAFETY: APICが有効化され、キャリブレーションが完了した後に呼び出される。
+    // レジスタオフセットはIntel SDMで定義された有効な値。
+    unsafe {
+        write_apic_register(registers::TIMER_DIVIDE_CONFIG, 0x3);
+        // bit 17-18 = 0 (One-shot), bit 16 = 0 (Not masked)
+        write_apic_register(registers::TIMER_LVT, TIMER_INTERRUPT_VECTOR as u32);
+        write_apic_register(registers::TIMER_INITIAL_COUNT, count);
+    }
+    Ok(())
+}
+
+/// 現在のCPUのLocal APIC Timerを停止
+pub fn stop_timer() {
+    // SAFETY: APICが有効化された後に呼び出される。
+    // LVTをマスクし、Initial Countに0を書き込んでカウントを止める。
+    // LVTのモードがワンショットに戻るため、TSC-deadlineモードの期限も解除される。
+    unsafe {
+        write_apic_register(registers::TIMER_LVT, 1 << 16);
+        write_apic_register(registers::TIMER_INITIAL_COUNT, 0);
+    }
+}
+
+/// Timer LVT: TSC-deadlineモード（bits 17-18 = 0b10）
+const TIMER_MODE_TSC_DEADLINE: u32 = 0b10 << 17;
+
+/// 現在のCPUのAPIC TimerをTSC-deadlineモードに切り替える
+///
+/// 周期タイマーは停止し、以降のタイマー割り込みは`set_tsc_deadline()`で
+/// 設定したTSC値に達した時に1回だけ発生する（ワンショット）。
+///
+/// # Errors
+/// * `ApicError::TscDeadlineUnsupported` - CPUがTSC-deadlineモードをサポートしていない場合
+/// * `ApicError::NotCalibrated` - TSCがキャリブレーションされていない場合
+pub fn enable_tsc_deadline_mode() -> Result<(), ApicError> {
+    if !tsc::supports_deadline() {
+        return Err(ApicError::TscDeadlineUnsupported);
+    }
+    if tsc::frequency() == 0 {
+        return Err(ApicError::NotCalibrated);
+    }
+
+    // SAFETY: APICが有効化された後に呼び出される。レジスタオフセットは
+    // Intel SDMで定義された有効な値。
+    unsafe {
+        write_apic_register(registers::TIMER_INITIAL_COUNT, 0);
+        write_apic_register(
+            registers::TIMER_LVT,
+            TIMER_MODE_TSC_DEADLINE | TIMER_INTERRUPT_VECTOR as u32,
         );
+        // LVTへの書き込みがIA32_TSC_DEADLINEへの書き込みより先に完了するよう
+        // 順序付ける（Intel SDM Vol 3A 10.5.4.1）
+        core::arch::asm!("mfence", options(nostack, preserves_flags));
+    }
+    Ok(())
+}
+
+/// TSC-deadlineモードで次のタイマー割り込みを発生させるTSC値を設定
+///
+/// 既に過ぎたTSC値を設定した場合は直ちに割り込みが発生する。0を設定すると解除する。
+/// `enable_tsc_deadline_mode()`を呼び出したCPUで使用すること。
+pub fn set_tsc_deadline(deadline: u64) {
+    // SAFETY: IA32_TSC_DEADLINEはtsc::supports_deadline()がtrueのCPUで存在するMSRで、
+    // enable_tsc_deadline_mode()で確認済み。
+    unsafe { msr::write(msr::IA32_TSC_DEADLINE, deadline) };
+}
 
-        Ok(())
+/// TSC-deadlineモードで指定ナノ秒後にタイマー割り込みを発生させる
+pub fn arm_oneshot_ns(ns: u64) {
+    set_tsc_deadline(tsc::deadline_after_ns(ns));
+}
+
+/// 周期モードを開始（クロックイベント用）
+fn clockevent_set_periodic(hz: u32) -> Result<(), ClockEventError> {
+    rearm_periodic(hz)
+        .map(|_| ())
+        .map_err(|_| ClockEventError::DeviceFailed("Local APIC Timer"))
+}
+
+/// ワンショットの方式: 未判定（TSCのキャリブレーション待ち）
+const ONESHOT_UNDECIDED: u8 = 0;
+/// ワンショットの方式: カウントモード
+const ONESHOT_COUNT: u8 = 1;
+/// ワンショットの方式: TSC-deadlineモード
+const ONESHOT_TSC_DEADLINE: u8 = 2;
+
+/// クロックイベントのワンショットに使う方式
+static ONESHOT_MODE: AtomicU8 = AtomicU8::new(ONESHOT_UNDECIDED);
+
+/// クロックイベントのワンショットにTSC-deadlineモードを使うかどうか
+///
+/// CPUがTSC-deadlineモードをサポートし、TSCが安定している（クロックソースとして
+/// 使用している）場合に使う。CPUIDの実行を避けるため、判定結果は記録しておく。
+fn use_tsc_deadline() -> bool {
+    match ONESHOT_MODE.load(Ordering::Relaxed) {
+        ONESHOT_TSC_DEADLINE => true,
+        ONESHOT_COUNT => false,
+        _ => {
+            // TSCのキャリブレーション前は判定を保留し、カウントモードを使う
+            if tsc::frequency() == 0 {
+                return false;
+            }
+            let deadline = tsc::supports_deadline() && tsc::is_stable();
+            let mode = if deadline {
+                ONESHOT_TSC_DEADLINE
+            } else {
+                ONESHOT_COUNT
+            };
+            ONESHOT_MODE.store(mode, Ordering::Relaxed);
+            deadline
+        }
     }
 }
 
+/// ワンショットモードを設定（クロックイベント用）
+///
+/// TSC-deadlineモードが使える場合はTSC値で期限を設定し、
+/// 使えない場合はカウントモードで設定する。
+fn clockevent_set_oneshot(ns: u64) -> Result<(), ClockEventError> {
+    let result = if use_tsc_deadline() {
+        enable_tsc_deadline_mode().map(|()| arm_oneshot_ns(ns))
+    } else {
+        arm_oneshot_count_ns(ns)
+    };
+    result.map_err(|_| ClockEventError::DeviceFailed("Local APIC Timer"))
+}
+
+/// Local APIC Timerクロックイベントデバイス（CPUごとに独立）
+pub static APIC_CLOCKEVENT: ClockEventDevice = ClockEventDevice::new(
+    "lapic",
+    300,
+    true,
+    clockevent_set_periodic,
+    Some(clockevent_set_oneshot),
+    stop_timer,
+);
+
 /// End of Interrupt (EOI) を送信
 /// 割り込みハンドラの最後に呼び出す必要があります
 pub fn send_eoi() {
@@ -328,27 +615,100 @@ pub fn send_eoi() {
     }
 }
 
-/// レガシーPIC（8259 PIC）を無効化
-/// APICを使う場合、古いPICとの競合を避けるために無効化が必要
-fn disable_legacy_pic() {
-    // SAFETY: I/Oポート0x21と0xA1は8259 PICのIMRポートとして定義されている。
-    // 0xFFを書き込むことで全ての割り込みをマスクする標準的な操作。
-    // Ring 0で実行されることが前提。
+/// Local APICのレジスタにアクセス可能かどうか
+///
+/// xAPICモードではMMIOがマッピング済みの場合、x2APICモードでは常にtrue。
+/// 例外ハンドラなど、APIC初期化前にも実行されうるコードから参照する。
+pub fn is_mapped() -> bool {
+    APIC_MMIO_MAPPED.load(Ordering::SeqCst) || is_x2apic()
+}
+
+/// 現在のCPUのLocal APIC IDを取得
+///
+/// xAPICモードではIDレジスタのbits 24-31を、x2APICモードでは32ビット全体を返す
+pub fn local_apic_id() -> u32 {
+    // SAFETY: IDレジスタは読み取り専用で副作用がない。
+    // apic_virt_base()がenable_apic()前の呼び出しを検出してパニックする。
+    let id = unsafe { read_apic_register(registers::ID) };
+    if is_x2apic() { id } else { id >> 24 }
+}
+
+/// ICRの送信完了（Delivery Statusが0になる）を待つ
+///
+/// # Safety
+/// APICが有効化されていること
+unsafe fn wait_icr_idle() {
+    // SAFETY: 呼び出し元がAPIC有効化済みであることを保証する
     unsafe {
-        // マスターPICとスレーブPICの両方のIMR（Interrupt Mask Register）に
-        // 0xFFを書き込んで、すべての割り込みをマスク
-        asm!(
-            "mov al, 0xFF",
-            "out 0x21, al",  // マスターPIC IMR
-            "out 0xA1, al",  // スレーブPIC IMR
-            out("al") _,
-            options(nomem, nostack, preserves_flags)
-        );
+        while read_apic_register(registers::ICR_LOW) & icr::DELIVERY_PENDING != 0 {
+            core::hint::spin_loop();
+        }
     }
 }
 
+/// 指定したLocal APIC IDへIPIを送信
+///
+/// # Arguments
+/// * `apic_id` - 宛先のLocal APIC ID（`icr_low`でShorthandを指定した場合は無視される）
+/// * `icr_low` - ICR下位32ビット（ベクタ・配送モード等）
+pub fn send_ipi(apic_id: u32, icr_low: u32) {
+    // ICR_HIGHとICR_LOWの書き込みの間に同じCPUの割り込みハンドラが
+    // IPIを送信すると宛先が上書きされるため、割り込みを無効化する
+    crate::io::without_interrupts(|| {
+        if is_x2apic() {
+            // SAFETY: x2APICモードではICRは単一の64ビットMSRで、1回の書き込みで送信される。
+            // Delivery Statusビットは存在しないため完了待ちは不要。
+            unsafe {
+                msr::write(
+                    x2apic_msr(registers::ICR_LOW),
+                    x2apic_icr_value(apic_id, icr_low),
+                );
+            }
+            return;
+        }
+        // SAFETY: ICRへの書き込みはAPIC有効化後であれば安全。
+        // ICR_HIGHに宛先を書き込んでからICR_LOWへの書き込みで送信が開始される。
+        unsafe {
+            wait_icr_idle();
+            write_apic_register(registers::ICR_HIGH, apic_id << icr::DEST_SHIFT);
+            write_apic_register(registers::ICR_LOW, icr_low);
+            wait_icr_idle();
+        }
+    });
+}
+
+/// INIT IPIを送信（AP起動シーケンスの第1段階）
+pub fn send_init_ipi(apic_id: u32) {
+    send_ipi(
+        apic_id,
+        icr::DELIVERY_INIT | icr::LEVEL_ASSERT | icr::TRIGGER_LEVEL,
+    );
+}
+
+/// Startup IPI (SIPI) を送信
+///
+/// # Arguments
+/// * `apic_id` - 宛先のLocal APIC ID
+/// * `start_page` - APが実行を開始する物理ページ番号（物理アドレス >> 12、1MB未満）
+pub fn send_startup_ipi(apic_id: u32, start_page: u8) {
+    send_ipi(
+        apic_id,
+        icr::DELIVERY_STARTUP | icr::LEVEL_ASSERT | start_page as u32,
+    );
+}
+
+/// AP上でLocal APICを初期化
+///
+/// BSPでinit()が完了していることが前提。BSPがx2APICモードの場合はAPも
+/// x2APICモードに切り替える（全CPUで同じモードである必要がある）。
+pub fn init_ap() {
+    enable_local_apic();
+}
+
 /// Local APICを初期化
 ///
+/// CPUがx2APICをサポートしている場合はx2APICモードで有効化し、MMIOはマッピングしない。
+///
 /// # Arguments
 /// * `apic_base_addr` - MADTから取得したAPICベースアドレス。Noneの場合はデフォルト値(0xFEE00000)を使用。
 ///
@@ -378,9 +738,38 @@ pub fn init(apic_base_addr: Option<u64>) -> Result<(), crate::paging::PagingErro
         );
     }
 
-    // まずレガシーPICを無効化
-    disable_legacy_pic();
-    enable_apic()?;
+    // まずレガシーPICを無効化（ベクタを再マップした上で全IRQをマスク）
+    crate::pic::disable();
+    if cpu_supports_x2apic() {
+        X2APIC_ENABLED.store(true, Ordering::SeqCst);
+        enable_local_apic();
+        crate::info!("Local APIC enabled in x2APIC mode (MSR access)");
+    } else {
+        enable_apic()?;
+        crate::info!("Local APIC enabled in xAPIC mode (MMIO access)");
+    }
     // タイマーは別途 init_timer() で初期化
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_x2apic_msr_mapping() {
+        assert_eq!(x2apic_msr(registers::ID), 0x802);
+        assert_eq!(x2apic_msr(registers::EOI), 0x80B);
+        assert_eq!(x2apic_msr(registers::SPURIOUS_INTERRUPT_VECTOR), 0x80F);
+        assert_eq!(x2apic_msr(registers::ICR_LOW), 0x830);
+        assert_eq!(x2apic_msr(registers::TIMER_LVT), 0x832);
+        assert_eq!(x2apic_msr(registers::TIMER_DIVIDE_CONFIG), 0x83E);
+    }
+
+    #[test_case]
+    fn test_x2apic_icr_value() {
+        // 宛先は上位32ビット全体（255を超えるAPIC IDも表現できる）
+        assert_eq!(x2apic_icr_value(0x1234, 0x4040), 0x0000_1234_0000_4040);
+        assert_eq!(x2apic_icr_value(1, icr::DELIVERY_INIT), 0x1_0000_0500);
+    }
+}
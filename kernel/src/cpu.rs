@@ -0,0 +1,115 @@
+//! CPU識別
+//!
+//! Local APIC IDと論理CPU番号（0始まりの連番）の対応、および各CPUの
+//! オンライン状態を管理します。BSPは常に論理CPU番号0です。
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use crate::apic;
+use crate::percpu;
+
+/// サポートする最大CPU数
+pub const MAX_CPUS: usize = 16;
+
+/// 未登録スロットを示すAPIC ID
+const INVALID_APIC_ID: u32 = u32::MAX;
+
+/// 論理CPU番号 → Local APIC ID
+static CPU_APIC_IDS: [AtomicU32; MAX_CPUS] = [const { AtomicU32::new(INVALID_APIC_ID) }; MAX_CPUS];
+
+/// 各CPUのオンライン状態
+static CPU_ONLINE: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+/// 登録済みCPU数（論理CPU番号の割り当て済み数）
+static REGISTERED_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// 指定したLocal APIC IDのCPUを登録し、論理CPU番号を返す
+///
+/// BSPが最初に自身を登録し（論理CPU番号0）、その後MADTに列挙された
+/// APを起動前に登録する。
+///
+/// # Returns
+/// 割り当てられた論理CPU番号。MAX_CPUSを超える場合はNone
+pub fn register_cpu(apic_id: u32) -> Option<usize> {
+    let index = REGISTERED_CPUS.fetch_add(1, Ordering::SeqCst);
+    if index >= MAX_CPUS {
+        REGISTERED_CPUS.fetch_sub(1, Ordering::SeqCst);
+        return None;
+    }
+    CPU_APIC_IDS[index].store(apic_id, Ordering::SeqCst);
+    Some(index)
+}
+
+/// 現在のCPU（BSP）を登録してオンラインにする
+///
+/// Local APIC初期化後、他のCPUより先に1回だけ呼び出す。
+pub fn register_current_cpu() -> Option<usize> {
+    let index = register_cpu(apic::local_apic_id())?;
+    set_online(index);
+    Some(index)
+}
+
+/// 指定CPUをオンラインにする
+pub fn set_online(cpu: usize) {
+    if cpu < MAX_CPUS {
+        CPU_ONLINE[cpu].store(true, Ordering::SeqCst);
+    }
+}
+
+/// 指定CPUがオンラインかどうか
+pub fn is_online(cpu: usize) -> bool {
+    cpu < MAX_CPUS && CPU_ONLINE[cpu].load(Ordering::SeqCst)
+}
+
+/// 登録済みCPU数を取得（BSP登録前は1を返す）
+///
+/// 論理CPU番号は0..cpu_count()の範囲で割り当てられる。
+pub fn cpu_count() -> usize {
+    REGISTERED_CPUS.load(Ordering::SeqCst).clamp(1, MAX_CPUS)
+}
+
+/// オンラインCPU数を取得（BSP登録前は1を返す）
+pub fn online_cpus() -> usize {
+    (0..MAX_CPUS).filter(|&cpu| is_online(cpu)).count().max(1)
+}
+
+/// 論理CPU番号に対応するLocal APIC IDを取得
+pub fn apic_id_of(cpu: usize) -> Option<u32> {
+    if cpu >= MAX_CPUS {
+        return None;
+    }
+    match CPU_APIC_IDS[cpu].load(Ordering::SeqCst) {
+        INVALID_APIC_ID => None,
+        id => Some(id),
+    }
+}
+
+/// 現在実行中のCPUの論理CPU番号を取得
+///
+/// Per-CPU領域の初期化後はGSベースから取得する（高速パス）。
+/// それ以前はLocal APIC IDから探索し、Local APICが未初期化、
+/// または未登録のCPUからの呼び出しでは0（BSP）を返す。
+#[inline]
+pub fn current_cpu() -> usize {
+    if percpu::is_ready() {
+        return percpu::this_cpu_id();
+    }
+    current_cpu_slow()
+}
+
+/// Local APIC IDから論理CPU番号を探索
+fn current_cpu_slow() -> usize {
+    if !apic::is_mapped() {
+        return 0;
+    }
+    let apic_id = apic::local_apic_id();
+    let registered = REGISTERED_CPUS.load(Ordering::SeqCst).min(MAX_CPUS);
+    (0..registered)
+        .find(|&i| CPU_APIC_IDS[i].load(Ordering::SeqCst) == apic_id)
+        .unwrap_or(0)
+}
+
+/// 現在のCPUがBSPかどうか
+pub fn is_bsp() -> bool {
+    current_cpu() == 0
+}
@@ -0,0 +1,78 @@
+//! シリアルコンソールのデバッグコマンド
+//!
+//! COM1（ISA IRQ4）の受信割り込みで1文字のコマンドを受け付け、統計のダンプを
+//! シリアルに出力します。出力はタスクの一覧の作成やログ出力を伴うため、
+//! 割り込みハンドラでは行わずワークキューに任せます。
+//!
+//! | キー | 出力 |
+//! |------|------|
+//! | `i`  | 割り込み統計（`irq_stats::dump()`、`/proc/interrupts`相当） |
+//! | `t`  | タスク一覧（`sched::dump_tasks()`） |
+//! | `?`  | コマンドの一覧 |
+
+use crate::apic;
+use crate::ioapic::{self, IoApicError};
+use crate::irq::{self, InterruptFrame, IrqError, IrqVector};
+use crate::serial::{COM1, SerialPort};
+use crate::workqueue;
+
+/// COM1のISA IRQ番号
+const COM1_IRQ: u8 = 4;
+
+/// デバッグコンソールの初期化エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugConsoleError {
+    /// 割り込みベクタを確保できなかった
+    Irq(IrqError),
+    /// I/O APICの設定に失敗した
+    IoApic(IoApicError),
+}
+
+impl core::fmt::Display for DebugConsoleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            DebugConsoleError::Irq(e) => write!(f, "Failed to allocate serial IRQ vector: {}", e),
+            DebugConsoleError::IoApic(e) => write!(f, "Failed to route serial IRQ: {}", e),
+        }
+    }
+}
+
+/// COM1の受信割り込みをBSPに配送し、コマンドの受け付けを開始する
+///
+/// I/O APICとワークキューの初期化後に呼び出す。
+///
+/// # Errors
+/// * `DebugConsoleError::Irq` - 割り込みベクタの空きがない場合
+/// * `DebugConsoleError::IoApic` - I/O APICの設定に失敗した場合
+pub fn init() -> Result<(), DebugConsoleError> {
+    let vector = irq::request_irq(IrqVector::Any, serial_irq_handler, "serial")
+        .map_err(DebugConsoleError::Irq)?;
+    let (gsi, trigger, polarity) = ioapic::legacy_irq_to_gsi(COM1_IRQ);
+    let bsp_apic_id = crate::cpu::apic_id_of(0).unwrap_or_else(apic::local_apic_id);
+    ioapic::route_irq(gsi, vector, bsp_apic_id as u8, trigger, polarity)
+        .and_then(|()| ioapic::unmask(gsi))
+        .map_err(DebugConsoleError::IoApic)?;
+    SerialPort::new(COM1).enable_receive_interrupt();
+    Ok(())
+}
+
+/// 受信割り込みハンドラ（受信済みのバイトをすべてコマンドとして処理する）
+fn serial_irq_handler(_frame: &InterruptFrame) {
+    let port = SerialPort::new(COM1);
+    while let Some(byte) = port.read_byte() {
+        match byte {
+            b'i' => workqueue::queue_work(crate::irq_stats::dump),
+            b't' => workqueue::queue_work(crate::sched::dump_tasks),
+            b'?' => workqueue::queue_work(print_help),
+            _ => {}
+        }
+    }
+}
+
+/// コマンドの一覧をシリアルに出力
+fn print_help() {
+    crate::println!("Debug console commands:");
+    crate::println!("  i  interrupt statistics");
+    crate::println!("  t  task list");
+    crate::println!("  ?  this help");
+}
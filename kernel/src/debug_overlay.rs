@@ -1,9 +1,16 @@
 //! デバッグオーバーレイ
 //!
 //! 画面右上にFPSやシステム情報を表示するデバッグオーバーレイを提供します。
+//! 表示内容は複数のページ（システム情報、割り込み統計、タスク毎のCPU使用率）を
+//! 一定間隔で切り替えます。
 
 use crate::graphics::{Region, TaskWriter, compositor};
-use crate::hpet;
+use crate::irq_stats;
+use crate::sched::{self, TaskId};
+use crate::time;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt::Write;
 
 /// オーバーレイの幅（20文字 * 8px）
@@ -18,6 +25,138 @@ const MARGIN: u32 = 10;
 /// 更新間隔（ミリ秒）
 const UPDATE_INTERVAL_MS: u64 = 1000;
 
+/// ページを切り替えるまでの更新回数
+const PAGE_SWITCH_UPDATES: u32 = 5;
+
+/// 割り込みページに表示するベクタの最大数
+const MAX_IRQ_ROWS: usize = 2;
+
+/// タスクページに表示するタスクの最大数
+const MAX_TASK_ROWS: usize = 5;
+
+/// オーバーレイの表示ページ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OverlayPage {
+    /// FPS・Uptime
+    System,
+    /// 割り込み統計
+    Interrupts,
+    /// タスク毎のCPU使用率
+    Tasks,
+}
+
+impl OverlayPage {
+    fn next(self) -> Self {
+        match self {
+            OverlayPage::System => OverlayPage::Interrupts,
+            OverlayPage::Interrupts => OverlayPage::Tasks,
+            OverlayPage::Tasks => OverlayPage::System,
+        }
+    }
+}
+
+/// 直近の区間でのタスクのCPU使用率
+struct TaskUsage {
+    name: String,
+    /// CPU使用率（1CPU分を100%とする）
+    percent: u64,
+    /// 最後に実行されたCPU
+    cpu: usize,
+}
+
+/// タスク毎のCPU使用率の計測（`top`風）
+///
+/// 更新毎に全タスクのCPU時間を取得し、前回からの増分を経過時間で割って使用率とする。
+struct CpuUsageSampler {
+    /// 前回の計測時刻
+    last_ns: u64,
+    /// 前回計測時のタスク毎のCPU時間
+    last_cpu_time: BTreeMap<TaskId, u64>,
+    /// 直近の区間のCPU使用率（使用率の高い順）
+    usage: Vec<TaskUsage>,
+}
+
+impl CpuUsageSampler {
+    fn new() -> Self {
+        let mut sampler = Self {
+            last_ns: 0,
+            last_cpu_time: BTreeMap::new(),
+            usage: Vec::new(),
+        };
+        sampler.sample();
+        sampler
+    }
+
+    /// 前回の計測からのCPU使用率を計算する
+    fn sample(&mut self) {
+        let now = time::monotonic_ns();
+        let elapsed = now.saturating_sub(self.last_ns).max(1);
+
+        let mut cpu_time = BTreeMap::new();
+        let mut usage = Vec::new();
+        sched::for_each_task(|info| {
+            // 前回の計測後に作成されたタスクは全CPU時間が今回の区間のもの
+            let last = self.last_cpu_time.get(&info.id).copied().unwrap_or(0);
+            let delta = info.stats.cpu_time_ns.saturating_sub(last);
+            usage.push(TaskUsage {
+                name: info.name.clone(),
+                percent: delta * 100 / elapsed,
+                cpu: info.stats.last_cpu,
+            });
+            cpu_time.insert(info.id, info.stats.cpu_time_ns);
+        });
+        usage.sort_by_key(|task| core::cmp::Reverse(task.percent));
+
+        self.last_ns = now;
+        self.last_cpu_time = cpu_time;
+        self.usage = usage;
+    }
+}
+
+/// タスクページを描画
+///
+/// CPU使用率の高いタスクから順に、名前・使用率・最後に実行されたCPUを表示する。
+fn write_tasks_page<W: Write>(writer: &mut W, usage: &[TaskUsage]) {
+    let _ = writeln!(writer, "vitrOS Tasks");
+    for task in usage.iter().take(MAX_TASK_ROWS) {
+        let _ = writeln!(
+            writer,
+            "{:<12.12} {:>3}% C{}",
+            task.name, task.percent, task.cpu
+        );
+    }
+}
+
+/// 割り込みページを描画
+///
+/// 例外以外で発生回数の多いベクタを上位から表示し、続けてスプリアス・例外の合計を表示する。
+fn write_interrupts_page<W: Write>(writer: &mut W) {
+    let mut top: [(u8, u64); MAX_IRQ_ROWS] = [(0, 0); MAX_IRQ_ROWS];
+    for vector in 0..=u8::MAX {
+        if irq_stats::is_exception_vector(vector) || irq_stats::is_spurious_vector(vector) {
+            continue;
+        }
+        let count = irq_stats::total(vector);
+        if let Some(pos) = top.iter().position(|&(_, c)| count > c) {
+            top.copy_within(pos..MAX_IRQ_ROWS - 1, pos + 1);
+            top[pos] = (vector, count);
+        }
+    }
+
+    let _ = writeln!(writer, "vitrOS IRQs");
+    for &(vector, count) in top.iter().filter(|&&(_, c)| c > 0) {
+        let _ = writeln!(
+            writer,
+            "{:>3} {:>7} {}",
+            vector,
+            count,
+            irq_stats::vector_label(vector)
+        );
+    }
+    let _ = writeln!(writer, "SPU: {}", irq_stats::spurious_total());
+    let _ = writeln!(writer, "EXC: {}", irq_stats::exception_total());
+}
+
 /// デバッグオーバーレイタスクのエントリポイント
 pub extern "C" fn debug_overlay_task() -> ! {
     crate::info!("[DebugOverlay] Started");
@@ -36,12 +175,16 @@ pub extern "C" fn debug_overlay_task() -> ! {
     let buffer = compositor::register_writer(region).expect("Failed to register debug overlay");
     let mut writer = TaskWriter::new(buffer, 0xFFFFFFFF); // 白色
 
-    // FPS計算用の変数（HPETベース: ミリ秒精度）
-    let mut last_time_ms = hpet::elapsed_ms();
+    // FPS計算用の変数（TSC/HPETベース: ミリ秒精度）
+    let mut last_time_ms = time::monotonic_ms();
     let mut last_frame_count = compositor::frame_count();
 
+    let mut page = OverlayPage::System;
+    let mut updates_on_page = 0;
+    let mut cpu_usage = CpuUsageSampler::new();
+
     loop {
-        let current_time_ms = hpet::elapsed_ms();
+        let current_time_ms = time::monotonic_ms();
         let current_frame_count = compositor::frame_count();
 
         // FPS計算: (フレーム差分) * 1000 / (時間差分[ms])
@@ -54,15 +197,24 @@ pub extern "C" fn debug_overlay_task() -> ! {
             0
         };
 
-        // Uptime計算（秒）- HPETから直接取得
-        let uptime_secs = hpet::elapsed_secs();
+        // Uptime計算（秒）
+        let uptime_secs = time::monotonic_ns() / 1_000_000_000;
+
+        // CPU使用率は表示中のページに関わらず毎回計測する（区間を更新間隔に揃えるため）
+        cpu_usage.sample();
 
         // 画面をクリアして描画
         writer.clear(0x00000000); // 黒背景
-        let _ = writeln!(writer, "vitrOS Debug");
-        let _ = writeln!(writer, "-----------");
-        let _ = writeln!(writer, "FPS: {}", fps);
-        let _ = writeln!(writer, "Uptime: {}s", uptime_secs);
+        match page {
+            OverlayPage::System => {
+                let _ = writeln!(writer, "vitrOS Debug");
+                let _ = writeln!(writer, "-----------");
+                let _ = writeln!(writer, "FPS: {}", fps);
+                let _ = writeln!(writer, "Uptime: {}s", uptime_secs);
+            }
+            OverlayPage::Interrupts => write_interrupts_page(&mut writer),
+            OverlayPage::Tasks => write_tasks_page(&mut writer, &cpu_usage.usage),
+        }
         // ローカルバッファを共有バッファに一括転送
         writer.flush();
 
@@ -70,6 +222,13 @@ pub extern "C" fn debug_overlay_task() -> ! {
         last_time_ms = current_time_ms;
         last_frame_count = current_frame_count;
 
+        // 一定回数ごとにページを切り替え
+        updates_on_page += 1;
+        if updates_on_page >= PAGE_SWITCH_UPDATES {
+            updates_on_page = 0;
+            page = page.next();
+        }
+
         // 1秒待機
         crate::sched::sleep_ms(UPDATE_INTERVAL_MS);
     }
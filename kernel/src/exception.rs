@@ -0,0 +1,296 @@
+//! 例外・パニック時のクラッシュレポート
+//!
+//! 例外ハンドラが取得した`InterruptFrame`から、汎用レジスタ・制御レジスタ・
+//! 現在のタスク・フレームポインタを辿ったバックトレースを含むレポートを出力します。
+//! パニックハンドラも同じレポート形式を使用します。
+//!
+//! # バックトレース
+//! カーネルは`-C force-frame-pointers=yes`でビルドされるため、各関数のフレームは
+//! `[rbp] = 呼び出し元のrbp`、`[rbp + 8] = リターンアドレス`の形で連結されています。
+//! 不正なメモリ参照を避けるため、現在のタスクスタック（またはブートスタック）の
+//! 範囲内にあるフレームのみを辿ります。
+
+use core::arch::asm;
+
+use crate::irq::InterruptFrame;
+use crate::println;
+
+/// バックトレースの最大深さ
+const MAX_BACKTRACE_DEPTH: usize = 32;
+
+/// スタック範囲 [bottom, top)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackBounds {
+    pub bottom: u64,
+    pub top: u64,
+}
+
+impl StackBounds {
+    /// `addr`から`size`バイトが範囲内に収まるか
+    fn contains(&self, addr: u64, size: u64) -> bool {
+        addr >= self.bottom && addr.checked_add(size).is_some_and(|end| end <= self.top)
+    }
+}
+
+/// 制御レジスタのスナップショット
+#[derive(Debug, Clone, Copy)]
+struct ControlRegisters {
+    cr0: u64,
+    cr2: u64,
+    cr3: u64,
+    cr4: u64,
+}
+
+impl ControlRegisters {
+    fn read() -> Self {
+        let (cr0, cr2, cr3, cr4): (u64, u64, u64, u64);
+        // SAFETY: 制御レジスタの読み取りはRing 0では常に許可されており、副作用がない。
+        unsafe {
+            asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+        }
+        Self { cr0, cr2, cr3, cr4 }
+    }
+}
+
+/// レポートのヘッダを出力
+fn print_header(title: &str) {
+    println!("\n\n");
+    println!("========================================");
+    println!("{}", title);
+    println!("========================================");
+}
+
+/// 汎用レジスタとCPUフレームを出力
+fn print_registers(frame: &InterruptFrame) {
+    let sf = &frame.stack_frame;
+    println!(
+        "Vector: {}  Error code: 0x{:X}",
+        frame.vector, frame.error_code
+    );
+    println!(
+        "RIP: 0x{:016X}  CS: 0x{:04X}  RFLAGS: 0x{:016X}",
+        sf.rip, sf.cs, sf.rflags
+    );
+    println!("RSP: 0x{:016X}  SS: 0x{:04X}", sf.rsp, sf.ss);
+    println!(
+        "RAX: 0x{:016X}  RBX: 0x{:016X}  RCX: 0x{:016X}",
+        frame.rax, frame.rbx, frame.rcx
+    );
+    println!(
+        "RDX: 0x{:016X}  RSI: 0x{:016X}  RDI: 0x{:016X}",
+        frame.rdx, frame.rsi, frame.rdi
+    );
+    println!(
+        "RBP: 0x{:016X}  R8:  0x{:016X}  R9:  0x{:016X}",
+        frame.rbp, frame.r8, frame.r9
+    );
+    println!(
+        "R10: 0x{:016X}  R11: 0x{:016X}  R12: 0x{:016X}",
+        frame.r10, frame.r11, frame.r12
+    );
+    println!(
+        "R13: 0x{:016X}  R14: 0x{:016X}  R15: 0x{:016X}",
+        frame.r13, frame.r14, frame.r15
+    );
+}
+
+/// 制御レジスタを出力
+fn print_control_registers() {
+    let cr = ControlRegisters::read();
+    println!("CR0: 0x{:016X}  CR2: 0x{:016X}", cr.cr0, cr.cr2);
+    println!("CR3: 0x{:016X}  CR4: 0x{:016X}", cr.cr3, cr.cr4);
+}
+
+/// 現在のタスクを出力し、そのスタック範囲を返す
+///
+/// スケジューラのロックが保持中の場合は待たずに"unknown"と表示する。
+fn print_current_task() -> Option<StackBounds> {
+    let cpu = crate::cpu::current_cpu();
+    let info = crate::sched::try_with_current_task(|task| {
+        let (bottom, top) = task.stack_bounds();
+        println!(
+            "CPU: {}  Task: {} (id {})",
+            cpu,
+            task.name(),
+            task.id().as_u64()
+        );
+        StackBounds { bottom, top }
+    });
+    if info.is_none() {
+        println!(
+            "CPU: {}  Task: unknown (scheduler lock held or no task)",
+            cpu
+        );
+    }
+    info
+}
+
+/// ブートスタック（KernelMainが実行されるスタック）の範囲
+fn boot_stack_bounds() -> StackBounds {
+    StackBounds {
+        bottom: crate::stack::stack_bottom(),
+        top: crate::stack::stack_top(),
+    }
+}
+
+/// フレームポインタチェーンを辿り、リターンアドレスを`visit`に渡す
+///
+/// # Arguments
+/// * `rbp` - 開始フレームポインタ
+/// * `bounds` - フレームが存在しうるスタック範囲
+/// * `max_depth` - 辿る最大フレーム数
+/// * `visit` - 各リターンアドレスに対して呼ばれる
+///
+/// # Returns
+/// 辿ったフレーム数
+///
+/// # Safety
+/// `bounds`の範囲全体が読み取り可能なメモリであること
+pub unsafe fn walk_frame_pointers(
+    mut rbp: u64,
+    bounds: StackBounds,
+    max_depth: usize,
+    mut visit: impl FnMut(u64),
+) -> usize {
+    let mut depth = 0;
+    while depth < max_depth {
+        // フレームは[rbp]と[rbp+8]の16バイト、8バイト境界にあること
+        if rbp & 0x7 != 0 || !bounds.contains(rbp, 16) {
+            break;
+        }
+        // SAFETY: rbpは範囲チェック済みで、呼び出し元がbounds全体の読み取り可能性を保証する。
+        let (next_rbp, return_addr) = unsafe {
+            (
+                core::ptr::read_volatile(rbp as *const u64),
+                core::ptr::read_volatile((rbp + 8) as *const u64),
+            )
+        };
+        if return_addr == 0 {
+            break;
+        }
+        visit(return_addr);
+        depth += 1;
+
+        // スタックは下方向に伸びるため、呼び出し元のフレームは必ず上位アドレスにある
+        if next_rbp <= rbp {
+            break;
+        }
+        rbp = next_rbp;
+    }
+    depth
+}
+
+/// バックトレースを出力
+fn print_backtrace(rip: u64, rbp: u64, task_stack: Option<StackBounds>) {
+    println!("Backtrace:");
+    println!("  #0  0x{:016X}", rip);
+
+    let bounds = [task_stack, Some(boot_stack_bounds())]
+        .into_iter()
+        .flatten()
+        .find(|b| b.contains(rbp, 16));
+
+    let Some(bounds) = bounds else {
+        println!("  (RBP 0x{:016X} is outside known stack bounds)", rbp);
+        return;
+    };
+
+    let mut index = 1;
+    // SAFETY: boundsは現在のタスクスタックまたはブートスタックで、
+    // いずれもマッピング済みの読み取り可能なメモリ。
+    unsafe {
+        walk_frame_pointers(rbp, bounds, MAX_BACKTRACE_DEPTH, |addr| {
+            println!("  #{:<2} 0x{:016X}", index, addr);
+            index += 1;
+        });
+    }
+}
+
+/// 例外レポートを出力
+///
+/// # Arguments
+/// * `title` - 例外名（例: "EXCEPTION: Page Fault (#PF)"）
+/// * `frame` - 例外スタブが保存した割り込みフレーム
+pub fn report_exception(title: &str, frame: &InterruptFrame) {
+    print_header(title);
+    print_registers(frame);
+    print_control_registers();
+    let task_stack = print_current_task();
+    print_backtrace(frame.stack_frame.rip, frame.rbp, task_stack);
+    println!("");
+}
+
+/// パニックレポートを出力
+///
+/// 例外レポートと同じ形式で、制御レジスタ・現在のタスク・呼び出し元からの
+/// バックトレースを出力する。
+#[inline(never)]
+pub fn report_panic(info: &core::panic::PanicInfo) {
+    let (rip, rbp): (u64, u64);
+    // SAFETY: 現在のRIPとRBPを読み取るだけで副作用はない。
+    unsafe {
+        asm!("lea {}, [rip]", out(reg) rip, options(nomem, nostack, preserves_flags));
+        asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+    }
+
+    print_header("KERNEL PANIC");
+    println!("{}", info);
+    print_control_registers();
+    let task_stack = print_current_task();
+    print_backtrace(rip, rbp, task_stack);
+    println!("");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_walk_frame_pointers_follows_chain() {
+        // 擬似スタック: 3フレームのチェーン（[rbp]=次のrbp, [rbp+8]=リターンアドレス）
+        let mut stack = [0u64; 16];
+        let base = stack.as_ptr() as u64;
+        let addr = |i: usize| base + (i * 8) as u64;
+        stack[2] = addr(6);
+        stack[3] = 0x1111;
+        stack[6] = addr(10);
+        stack[7] = 0x2222;
+        stack[10] = 0;
+        stack[11] = 0x3333;
+
+        let bounds = StackBounds {
+            bottom: base,
+            top: base + (stack.len() * 8) as u64,
+        };
+        let mut found = [0u64; 4];
+        let mut n = 0;
+        // SAFETY: boundsはローカル配列の範囲
+        let depth = unsafe {
+            walk_frame_pointers(addr(2), bounds, MAX_BACKTRACE_DEPTH, |a| {
+                found[n] = a;
+                n += 1;
+            })
+        };
+        assert_eq!(depth, 3);
+        assert_eq!(&found[..3], &[0x1111, 0x2222, 0x3333]);
+    }
+
+    #[test_case]
+    fn test_walk_frame_pointers_stops_outside_bounds() {
+        let stack = [0u64; 4];
+        let base = stack.as_ptr() as u64;
+        let bounds = StackBounds {
+            bottom: base,
+            top: base + 32,
+        };
+        // SAFETY: 範囲外のrbpは読み取られない
+        let depth = unsafe { walk_frame_pointers(base + 32, bounds, 8, |_| {}) };
+        assert_eq!(depth, 0);
+        // SAFETY: 同上（アラインされていないrbp）
+        let depth = unsafe { walk_frame_pointers(base + 4, bounds, 8, |_| {}) };
+        assert_eq!(depth, 0);
+    }
+}
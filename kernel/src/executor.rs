@@ -0,0 +1,307 @@
+//! カーネル内の非同期エグゼキュータ
+//!
+//! `async fn`で書いたドライバの状態機械やプロトコル処理を、接続毎にブロックする
+//! カーネルタスクを用意せずに実行するためのエグゼキュータです。
+//!
+//! # スケジューラとの統合
+//! 各エグゼキュータは1つのカーネルタスクで、通常のタスクと同じくDeadline/Realtime/Normal/Idleの
+//! いずれかのクラスでプリエンプティブにスケジューリングされます。
+//! フューチャーの`Waker`はフューチャーを実行可能キューに入れ、エグゼキュータのタスクに
+//! `unblock_task()`を呼び出します。実行可能なフューチャーがなくなると、エグゼキュータの
+//! タスクは`block_current_task()`でブロックします（確認からブロックまでの間の起床は
+//! `WAKEUP_PENDING`で検出される）。
+//!
+//! # 非同期の待機
+//! - `sleep_ms()`: 指定時間の経過（タイマー）
+//! - `WaitQueue::wait_async()`/`wait_event_async()`: 待機キューでの起床
+//! - `Receiver::recv_async()`: チャネルの受信
+//! - `Completion::wait_async()`: デバイスI/Oの完了
+//!
+//! # Note
+//! フューチャーのポーリングはエグゼキュータのタスク上で行われるため、
+//! ポーリング中にブロックする処理（`BlockingMutex::lock()`など）を呼ぶと、
+//! 同じエグゼキュータの他のフューチャーも止まります。
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use spin::Mutex as SpinMutex;
+
+use crate::io::without_interrupts;
+use crate::sched::{SpawnOptions, Task, TaskError, TaskId};
+
+/// エグゼキュータで実行するフューチャー
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// エグゼキュータの共有部分（エグゼキュータのタスクと`Waker`で共有）
+struct ExecutorShared {
+    /// エグゼキュータのタスクID
+    task_id: AtomicU64,
+    /// 実行可能なフューチャー
+    ready: SpinMutex<VecDeque<Arc<FutureTask>>>,
+}
+
+impl ExecutorShared {
+    /// フューチャーを実行可能キューに入れ、エグゼキュータのタスクを起床させる
+    fn schedule(&self, task: Arc<FutureTask>) {
+        without_interrupts(|| self.ready.lock().push_back(task));
+        crate::sched::unblock_task(TaskId::from_u64(self.task_id.load(Ordering::Acquire)));
+    }
+
+    /// 実行可能なフューチャーを1つ取り出す
+    fn pop_ready(&self) -> Option<Arc<FutureTask>> {
+        without_interrupts(|| self.ready.lock().pop_front())
+    }
+}
+
+/// エグゼキュータで実行中のフューチャー（`Waker`の実体）
+struct FutureTask {
+    /// フューチャー（完了後はNone）
+    ///
+    /// ポーリングはエグゼキュータのタスクからのみ行われる。
+    future: SpinMutex<Option<BoxFuture>>,
+    /// 実行可能キューに入っているかどうか（二重に入れないため）
+    queued: AtomicBool,
+    /// 所属するエグゼキュータ
+    executor: Arc<ExecutorShared>,
+}
+
+impl FutureTask {
+    /// フューチャーを1回ポーリングする
+    fn poll(self: &Arc<Self>) {
+        // ポーリング中の起床で再びキューに入れられるよう、ポーリング前に解除する
+        self.queued.store(false, Ordering::Release);
+
+        let mut slot = self.future.lock();
+        let Some(future) = slot.as_mut() else {
+            return;
+        };
+        let waker = Waker::from(self.clone());
+        let mut cx = Context::from_waker(&waker);
+        if future.as_mut().poll(&mut cx).is_ready() {
+            // 完了したフューチャーはここで破棄する
+            *slot = None;
+        }
+    }
+}
+
+impl Wake for FutureTask {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.executor.schedule(self.clone());
+        }
+    }
+}
+
+/// 非同期エグゼキュータ
+///
+/// 複製したハンドルはすべて同じエグゼキュータのタスクを指す。
+/// エグゼキュータのタスクは終了せず、フューチャーがなくなるとブロックして待機する。
+#[derive(Clone)]
+pub struct Executor {
+    shared: Arc<ExecutorShared>,
+}
+
+impl Executor {
+    /// エグゼキュータのタスクを作成して起動
+    ///
+    /// # Arguments
+    /// * `name` - エグゼキュータのタスク名
+    /// * `options` - エグゼキュータのタスクのスケジューリングクラス・優先度・スタックサイズ
+    ///
+    /// # Errors
+    /// タスクの作成に失敗した場合（`sched::spawn()`と同じ）
+    pub fn start(name: impl Into<String>, options: SpawnOptions) -> Result<Self, TaskError> {
+        let shared = Arc::new(ExecutorShared {
+            task_id: AtomicU64::new(0),
+            ready: SpinMutex::new(VecDeque::new()),
+        });
+
+        let runner = shared.clone();
+        let task = Task::from_closure(name, options, move || -> () { run(&runner) })?;
+        // タスクの起動前に起床先を設定する（起動前の起床はWAKEUP_PENDINGに残るだけで無害）
+        shared.task_id.store(task.id().as_u64(), Ordering::Release);
+        crate::sched::try_add_task(task)?;
+        Ok(Self { shared })
+    }
+
+    /// フューチャーをエグゼキュータで実行する
+    ///
+    /// 割り込みコンテキストを含め、どのタスクからも呼び出せる。
+    pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        let task = Arc::new(FutureTask {
+            future: SpinMutex::new(Some(Box::pin(future))),
+            queued: AtomicBool::new(true),
+            executor: self.shared.clone(),
+        });
+        self.shared.schedule(task);
+    }
+
+    /// エグゼキュータのタスクID
+    pub fn task_id(&self) -> TaskId {
+        TaskId::from_u64(self.shared.task_id.load(Ordering::Acquire))
+    }
+}
+
+/// エグゼキュータのタスクの本体
+fn run(shared: &ExecutorShared) -> ! {
+    loop {
+        while let Some(task) = shared.pop_ready() {
+            task.poll();
+        }
+        // 実行可能なフューチャーがなければ、Wakerから起床されるまでブロック
+        crate::sched::block_current_task();
+    }
+}
+
+/// 現在のタスクを起床させる`Waker`（`block_on()`用）
+struct TaskWaker(TaskId);
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        crate::sched::unblock_task(self.0);
+    }
+}
+
+/// 現在のタスク上でフューチャーを完了まで実行する
+///
+/// フューチャーが`Pending`を返す間は現在のタスクをブロックし、
+/// `Waker`で起床されると再びポーリングする。
+///
+/// # Panics
+/// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    debug_assert!(
+        !crate::sched::is_interrupt_context(),
+        "block_on() cannot be called from interrupt context"
+    );
+    let mut future = core::pin::pin!(future);
+    let waker = Waker::from(Arc::new(TaskWaker(crate::sched::current_task_id())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        // ポーリングからブロックまでの間の起床はWAKEUP_PENDINGで検出される
+        crate::sched::block_current_task();
+    }
+}
+
+/// `sleep_ms()`のタイマーとフューチャーで共有する状態
+struct SleepState {
+    /// 期限が過ぎたかどうか
+    expired: AtomicBool,
+    /// 期限が過ぎた時に起床させるWaker
+    waker: SpinMutex<Option<Waker>>,
+}
+
+/// `sleep_ms()`が返すフューチャー
+pub struct Sleep {
+    /// タイムアウト（tick数）
+    ticks: u64,
+    /// 登録したタイマーのIDと共有状態（最初のポーリングまでNone）
+    timer: Option<(u64, Arc<SleepState>)>,
+}
+
+/// 指定したミリ秒数が経過すると完了するフューチャーを返す
+///
+/// `sched::sleep_ms()`の非同期版。タイマーは最初のポーリングで登録される。
+///
+/// # Arguments
+/// * `ms` - 待機時間（ミリ秒、最小1tick）
+pub fn sleep_ms(ms: u64) -> Sleep {
+    Sleep {
+        ticks: crate::timer::ms_to_ticks(ms).max(1),
+        timer: None,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let (_, state) = this.timer.get_or_insert_with(|| {
+            let state = Arc::new(SleepState {
+                expired: AtomicBool::new(false),
+                waker: SpinMutex::new(None),
+            });
+            let timer_state = state.clone();
+            let id = crate::timer::register_timer(
+                this.ticks,
+                Box::new(move || {
+                    timer_state.expired.store(true, Ordering::Release);
+                    if let Some(waker) = without_interrupts(|| timer_state.waker.lock().take()) {
+                        waker.wake();
+                    }
+                }),
+            );
+            (id, state)
+        });
+
+        // Wakerを登録してから期限を確認し、登録前の期限切れも取りこぼさない
+        without_interrupts(|| *state.waker.lock() = Some(cx.waker().clone()));
+        if state.expired.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some((id, state)) = &self.timer
+            && !state.expired.load(Ordering::Acquire)
+        {
+            crate::timer::cancel_timer(*id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{WaitQueue, unbounded_channel};
+
+    #[test_case]
+    fn test_block_on_ready_future() {
+        assert_eq!(block_on(async { 1 + 2 }), 3);
+    }
+
+    #[test_case]
+    fn test_wait_event_async_completes_when_condition_holds() {
+        let queue = WaitQueue::new();
+        let (tx, rx) = unbounded_channel();
+        tx.send(7).unwrap();
+        assert_eq!(block_on(rx.recv_async()), Ok(7));
+        let mut checks = 0;
+        block_on(queue.wait_event_async(|| {
+            checks += 1;
+            Some(())
+        }));
+        assert_eq!(checks, 1);
+        assert!(!queue.has_waiters());
+    }
+
+    #[test_case]
+    fn test_dropped_wait_future_is_removed_from_queue() {
+        let queue = WaitQueue::new();
+        let mut wait = Box::pin(queue.wait_async());
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(wait.as_mut().poll(&mut cx).is_pending());
+        assert!(queue.has_waiters());
+        drop(wait);
+        assert!(!queue.has_waiters());
+    }
+}
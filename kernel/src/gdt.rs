@@ -3,6 +3,7 @@
 //! x86_64アーキテクチャでは、セグメンテーションはほぼ使用されませんが、
 //! 特権レベル（Ring 0/3）の管理とTSS（Interrupt Stack Table用）のためにGDTは必須です。
 
+use crate::cpu::MAX_CPUS;
 use crate::info;
 use crate::paging::KERNEL_VIRTUAL_BASE;
 use core::arch::asm;
@@ -234,11 +235,12 @@ struct Gdtr {
     base: u64,
 }
 
-/// グローバルGDTインスタンス
-static mut GDT: Gdt = Gdt::new();
+/// CPU毎のGDTインスタンス
+/// TSSディスクリプタはCPU毎に異なるTSSを指すため、GDTもCPU毎に持つ
+static mut GDT: [Gdt; MAX_CPUS] = [const { Gdt::new() }; MAX_CPUS];
 
-/// グローバルTSSインスタンス
-static mut TSS: TaskStateSegment = TaskStateSegment::new();
+/// CPU毎のTSSインスタンス
+static mut TSS: [TaskStateSegment; MAX_CPUS] = [const { TaskStateSegment::new() }; MAX_CPUS];
 
 /// Double Fault用のISTスタック（16KB）
 /// Linux kernelと同様、Double Faultハンドラ用の専用スタックを提供
@@ -246,7 +248,9 @@ static mut TSS: TaskStateSegment = TaskStateSegment::new();
 #[repr(align(16))]
 struct DoubleFaultStack([u8; 16384]);
 
-static mut DOUBLE_FAULT_STACK: DoubleFaultStack = DoubleFaultStack([0; 16384]);
+/// CPU毎のDouble Fault用ISTスタック
+static mut DOUBLE_FAULT_STACK: [DoubleFaultStack; MAX_CPUS] =
+    [const { DoubleFaultStack([0; 16384]) }; MAX_CPUS];
 
 /// セグメントセレクタ
 pub mod selector {
@@ -268,11 +272,31 @@ pub mod selector {
 /// Double Fault用のISTインデックス
 pub const DOUBLE_FAULT_IST_INDEX: u8 = 1;
 
-/// GDTを初期化してロード
+/// GDTを初期化してロード（BSP用）
 pub fn init() -> Result<(), GdtError> {
+    load(0, true)
+}
+
+/// AP用のGDT/TSSを初期化してロード
+///
+/// # Arguments
+/// * `cpu` - 論理CPU番号（1以上）
+pub fn init_ap(cpu: usize) -> Result<(), GdtError> {
+    if cpu == 0 || cpu >= MAX_CPUS {
+        return Err(GdtError::InitFailed);
+    }
+    load(cpu, false)
+}
+
+/// 指定CPUのGDT/TSSを初期化し、現在のCPUにロード
+///
+/// # Arguments
+/// * `cpu` - 論理CPU番号
+/// * `verbose` - 初期化ログを出力するか
+fn load(cpu: usize, verbose: bool) -> Result<(), GdtError> {
     // SAFETY: この関数は以下の操作を行う：
-    // 1. 静的変数TSS, GDTへのアクセス - シングルスレッド初期化時に呼ばれ、
-    //    割り込み無効状態で実行されるため競合しない
+    // 1. 静的変数TSS[cpu], GDT[cpu]へのアクセス - 各CPUは自身のスロットのみを
+    //    初期化時に割り込み無効状態で操作するため競合しない
     // 2. LGDT命令 - 有効なGDT構造体へのポインタを渡す
     // 3. far return によるコードセグメントリロード - 有効なセレクタを使用
     // 4. セグメントレジスタへの代入 - 有効なセレクタを使用
@@ -280,30 +304,37 @@ pub fn init() -> Result<(), GdtError> {
     // すべての操作はカーネル初期化時のRing 0で実行され、
     // 必要な構造体は静的に確保されたメモリに存在する。
     unsafe {
+        let tss = core::ptr::addr_of_mut!(TSS[cpu]);
+        let gdt = core::ptr::addr_of_mut!(GDT[cpu]);
+
         // TSSを初期化（Double Fault用のISTスタックを設定）
-        let double_fault_stack_top = (&raw const DOUBLE_FAULT_STACK as u64)
+        let double_fault_stack_top = (core::ptr::addr_of!(DOUBLE_FAULT_STACK[cpu]) as u64)
             + core::mem::size_of::<DoubleFaultStack>() as u64;
 
-        TSS.ist1 = double_fault_stack_top;
+        (*tss).ist1 = double_fault_stack_top;
 
-        info!("TSS initialized:");
-        info!(
-            "  IST1 (Double Fault stack): 0x{:016X}",
-            double_fault_stack_top
-        );
+        if verbose {
+            info!("TSS initialized:");
+            info!(
+                "  IST1 (Double Fault stack): 0x{:016X}",
+                double_fault_stack_top
+            );
+        }
 
         // GDTにTSSディスクリプタを設定
-        let tss_addr = &raw const TSS as u64;
-        let gdt_ptr = core::ptr::addr_of_mut!(GDT);
+        // LTRはTSSディスクリプタをBusyにするため、再ロード時は毎回Availableで書き直す
+        let tss_addr = tss as u64;
         core::ptr::write(
-            core::ptr::addr_of_mut!((*gdt_ptr).tss),
+            core::ptr::addr_of_mut!((*gdt).tss),
             TssDescriptor::new(tss_addr),
         );
 
-        info!("TSS descriptor set in GDT at 0x{:016X}", tss_addr);
+        if verbose {
+            info!("TSS descriptor set in GDT at 0x{:016X}", tss_addr);
+        }
 
         // GDTのアドレスを取得（カーネルが高位アドレスでリンクされているため既に高位）
-        let gdt_addr = &raw const GDT as u64;
+        let gdt_addr = gdt as u64;
 
         let gdtr = Gdtr {
             limit: (core::mem::size_of::<Gdt>() - 1) as u16,
@@ -347,7 +378,9 @@ pub fn init() -> Result<(), GdtError> {
             options(nostack, preserves_flags)
         );
 
-        info!("TSS loaded into TR register");
+        if verbose {
+            info!("TSS loaded into TR register");
+        }
     }
     Ok(())
 }
@@ -2,15 +2,31 @@
 //!
 //! HPETはPITより高精度なタイマーで、APIC Timerのキャリブレーションに最適です。
 //! 周波数がACPIテーブルで定義されているため、キャリブレーション不要です。
+//!
+//! Timer 0のコンパレータはLegacy Replacementモード（ISA IRQ0に配送）で
+//! 周期・ワンショット割り込みの発生源としても使用できます。
 
 use core::ptr::{read_volatile, write_volatile};
 use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
+use crate::time::{ClockEventDevice, ClockEventError, ClockSource, clockevent};
+
 /// HPETエラー
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HpetError {
     /// HPETが初期化されていない
     NotInitialized,
+    /// 要求された機能をハードウェアがサポートしていない
+    Unsupported,
+}
+
+impl core::fmt::Display for HpetError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            HpetError::NotInitialized => write!(f, "HPET not initialized"),
+            HpetError::Unsupported => write!(f, "HPET feature not supported"),
+        }
+    }
 }
 
 /// HPETが利用可能かどうか
@@ -28,6 +44,12 @@ static HPET_PERIOD_FS: AtomicU64 = AtomicU64::new(0);
 /// HPET初期化時のカウンタ値（経過時間計算の基準点）
 static HPET_START_COUNTER: AtomicU64 = AtomicU64::new(0);
 
+/// メインカウンタが64ビット幅かどうか
+static HPET_COUNTER_64BIT: AtomicBool = AtomicBool::new(false);
+
+/// Legacy Replacementモードをサポートしているかどうか
+static HPET_LEGACY_CAPABLE: AtomicBool = AtomicBool::new(false);
+
 /// HPETレジスタオフセット
 mod registers {
     /// General Capabilities and ID Register
@@ -36,8 +58,38 @@ mod registers {
     pub const GENERAL_CONFIG: u64 = 0x010;
     /// Main Counter Value Register
     pub const MAIN_COUNTER: u64 = 0x0F0;
+    /// Timer 0 Configuration and Capability Register
+    pub const TIMER0_CONFIG: u64 = 0x100;
+    /// Timer 0 Comparator Value Register
+    pub const TIMER0_COMPARATOR: u64 = 0x108;
 }
 
+/// General Capabilities: メインカウンタが64ビット幅（COUNT_SIZE_CAP）
+const CAP_COUNT_SIZE_64: u64 = 1 << 13;
+
+/// General Capabilities: Legacy Replacementモードをサポート（LEG_RT_CAP）
+const CAP_LEGACY_ROUTE: u64 = 1 << 15;
+
+/// General Configuration: カウンタを有効化（ENABLE_CNF）
+const CONFIG_ENABLE: u64 = 1 << 0;
+
+/// General Configuration: Legacy Replacementモード（LEG_RT_CNF）
+///
+/// Timer 0がISA IRQ0（PITの代わり）、Timer 1がISA IRQ8に配送される。
+const CONFIG_LEGACY_ROUTE: u64 = 1 << 1;
+
+/// Timer Configuration: 割り込みを有効化（Tn_INT_ENB_CNF）
+const TIMER_INT_ENABLE: u64 = 1 << 2;
+
+/// Timer Configuration: 周期モード（Tn_TYPE_CNF）
+const TIMER_PERIODIC: u64 = 1 << 3;
+
+/// Timer Configuration: 周期モードをサポート（Tn_PER_INT_CAP、読み取り専用）
+const TIMER_PERIODIC_CAPABLE: u64 = 1 << 4;
+
+/// Timer Configuration: 次のコンパレータ書き込みで周期を設定（Tn_VAL_SET_CNF）
+const TIMER_VALUE_SET: u64 = 1 << 6;
+
 /// HPETレジスタからの読み込み（64bit）
 unsafe fn read_hpet_reg(offset: u64) -> Result<u64, HpetError> {
     let base = HPET_BASE.load(Ordering::SeqCst);
@@ -83,6 +135,9 @@ pub fn init(base_phys_addr: u64) -> Result<(), crate::paging::PagingError> {
         let cap_id =
             read_hpet_reg(registers::GENERAL_CAP_ID).expect("HPET base address already set");
 
+        HPET_COUNTER_64BIT.store(cap_id & CAP_COUNT_SIZE_64 != 0, Ordering::SeqCst);
+        HPET_LEGACY_CAPABLE.store(cap_id & CAP_LEGACY_ROUTE != 0, Ordering::SeqCst);
+
         // bits 63:32 = Counter Clock Period in femtoseconds
         let period_fs = cap_id >> 32;
         HPET_PERIOD_FS.store(period_fs, Ordering::SeqCst);
@@ -100,7 +155,7 @@ pub fn init(base_phys_addr: u64) -> Result<(), crate::paging::PagingError> {
         // bit 0 = ENABLE_CNF (Overall Enable)
         let config =
             read_hpet_reg(registers::GENERAL_CONFIG).expect("HPET base address already set");
-        write_hpet_reg(registers::GENERAL_CONFIG, config | 1)
+        write_hpet_reg(registers::GENERAL_CONFIG, config | CONFIG_ENABLE)
             .expect("HPET base address already set");
 
         // 初期カウンタ値を保存（経過時間計算の基準点）
@@ -140,6 +195,113 @@ pub fn read_counter() -> u64 {
     unsafe { read_hpet_reg(registers::MAIN_COUNTER).unwrap_or(0) }
 }
 
+/// メインカウンタの有効ビットマスク
+pub fn counter_mask() -> u64 {
+    if HPET_COUNTER_64BIT.load(Ordering::SeqCst) {
+        u64::MAX
+    } else {
+        u32::MAX as u64
+    }
+}
+
+// ============================================================================
+// Timer 0 コンパレータ（クロックイベント）
+// ============================================================================
+
+/// ナノ秒をHPETのカウント数に変換（最低1カウント）
+fn ns_to_counts(ns: u64) -> u64 {
+    let period_fs = HPET_PERIOD_FS.load(Ordering::SeqCst).max(1);
+    ((ns as u128 * 1_000_000 / period_fs as u128) as u64).max(1)
+}
+
+/// Legacy Replacementモードを有効化し、Timer 0の設定値を返す
+///
+/// # Errors
+/// * `HpetError::NotInitialized` - HPETが初期化されていない場合
+/// * `HpetError::Unsupported` - Legacy Replacementモードをサポートしていない場合
+fn prepare_timer0() -> Result<u64, HpetError> {
+    if !is_available() {
+        return Err(HpetError::NotInitialized);
+    }
+    if !HPET_LEGACY_CAPABLE.load(Ordering::SeqCst) {
+        return Err(HpetError::Unsupported);
+    }
+    // SAFETY: HPETは初期化済みで、レジスタオフセットはHPET仕様で定義された有効な値
+    unsafe {
+        let config = read_hpet_reg(registers::GENERAL_CONFIG)?;
+        write_hpet_reg(registers::GENERAL_CONFIG, config | CONFIG_LEGACY_ROUTE)?;
+        read_hpet_reg(registers::TIMER0_CONFIG)
+    }
+}
+
+/// Timer 0を周期モードで開始（ISA IRQ0に配送）
+///
+/// # Arguments
+/// * `hz` - 割り込み周波数
+///
+/// # Errors
+/// * `HpetError::NotInitialized` - HPETが初期化されていない場合
+/// * `HpetError::Unsupported` - Timer 0が周期モードまたはLegacy Replacementをサポートしていない場合
+pub fn start_periodic(hz: u32) -> Result<(), HpetError> {
+    let timer_config = prepare_timer0()?;
+    if timer_config & TIMER_PERIODIC_CAPABLE == 0 {
+        return Err(HpetError::Unsupported);
+    }
+    let period = ns_to_counts(1_000_000_000 / hz.max(1) as u64);
+
+    // SAFETY: HPETは初期化済みで、レジスタオフセットはHPET仕様で定義された有効な値
+    unsafe {
+        write_hpet_reg(
+            registers::TIMER0_CONFIG,
+            timer_config | TIMER_INT_ENABLE | TIMER_PERIODIC | TIMER_VALUE_SET,
+        )?;
+        // VAL_SETの後の1回目の書き込みは最初の発火時刻、2回目は周期として扱われる
+        write_hpet_reg(
+            registers::TIMER0_COMPARATOR,
+            read_counter().wrapping_add(period),
+        )?;
+        write_hpet_reg(registers::TIMER0_COMPARATOR, period)?;
+    }
+    Ok(())
+}
+
+/// Timer 0で指定ナノ秒後に1回だけ割り込みを発生させる（ISA IRQ0に配送）
+///
+/// # Errors
+/// * `HpetError::NotInitialized` - HPETが初期化されていない場合
+/// * `HpetError::Unsupported` - Legacy Replacementをサポートしていない場合
+pub fn arm_oneshot_ns(ns: u64) -> Result<(), HpetError> {
+    let timer_config = prepare_timer0()?;
+    // SAFETY: HPETは初期化済みで、レジスタオフセットはHPET仕様で定義された有効な値
+    unsafe {
+        write_hpet_reg(
+            registers::TIMER0_CONFIG,
+            (timer_config & !TIMER_PERIODIC) | TIMER_INT_ENABLE,
+        )?;
+        write_hpet_reg(
+            registers::TIMER0_COMPARATOR,
+            read_counter().wrapping_add(ns_to_counts(ns)),
+        )?;
+    }
+    Ok(())
+}
+
+/// Timer 0の割り込みを止め、ISA IRQ0をPITに戻す
+pub fn stop_timer0() {
+    // SAFETY: HPETが未初期化の場合はNotInitializedが返るだけで、アクセスは行われない
+    unsafe {
+        if let Ok(config) = read_hpet_reg(registers::TIMER0_CONFIG) {
+            let _ = write_hpet_reg(
+                registers::TIMER0_CONFIG,
+                config & !(TIMER_INT_ENABLE | TIMER_PERIODIC),
+            );
+        }
+        if let Ok(config) = read_hpet_reg(registers::GENERAL_CONFIG) {
+            let _ = write_hpet_reg(registers::GENERAL_CONFIG, config & !CONFIG_LEGACY_ROUTE);
+        }
+    }
+}
+
 /// 指定ナノ秒間待機（HPETを使用）
 ///
 /// # Arguments
@@ -255,3 +417,47 @@ impl ElapsedTimer for Hpet {
 
 /// グローバルHPETインスタンス
 pub static HPET: Hpet = Hpet;
+
+// ============================================================================
+// クロックソース / クロックイベント
+// ============================================================================
+
+/// HPETクロックソース
+pub static HPET_CLOCKSOURCE: ClockSource =
+    ClockSource::new("hpet", 250, read_counter, counter_mask, frequency);
+
+/// HPETのエラーをクロックイベントのエラーに変換
+fn to_clockevent_error(e: HpetError) -> ClockEventError {
+    match e {
+        HpetError::Unsupported => ClockEventError::Unsupported,
+        HpetError::NotInitialized => ClockEventError::DeviceFailed("HPET"),
+    }
+}
+
+/// Timer 0を周期モードで開始し、ISA IRQ0をBSPに配送する
+fn clockevent_set_periodic(hz: u32) -> Result<(), ClockEventError> {
+    start_periodic(hz).map_err(to_clockevent_error)?;
+    clockevent::route_legacy_timer_irq().inspect_err(|_| stop_timer0())
+}
+
+/// Timer 0をワンショットモードで設定し、ISA IRQ0をBSPに配送する
+fn clockevent_set_oneshot(ns: u64) -> Result<(), ClockEventError> {
+    arm_oneshot_ns(ns).map_err(to_clockevent_error)?;
+    clockevent::route_legacy_timer_irq().inspect_err(|_| stop_timer0())
+}
+
+/// Timer 0を停止し、ISA IRQ0をマスクする
+fn clockevent_shutdown() {
+    stop_timer0();
+    clockevent::mask_legacy_timer_irq();
+}
+
+/// HPET Timer 0クロックイベントデバイス
+pub static HPET_CLOCKEVENT: ClockEventDevice = ClockEventDevice::new(
+    "hpet",
+    250,
+    false,
+    clockevent_set_periodic,
+    Some(clockevent_set_oneshot),
+    clockevent_shutdown,
+);
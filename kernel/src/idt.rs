@@ -8,8 +8,12 @@ use lazy_static::lazy_static;
 use spin::Mutex;
 
 use crate::apic;
+use crate::exception;
 use crate::gdt;
+use crate::irq::{self, InterruptFrame};
+use crate::irq_stats;
 use crate::paging::KERNEL_VIRTUAL_BASE;
+use crate::time::{hrtimer, nohz};
 use crate::timer;
 
 // =============================================================================
@@ -18,36 +22,57 @@ use crate::timer;
 
 /// エラーコードなしの例外ハンドラを生成するマクロ
 ///
-/// レジスタの保存/復元とiretqを含むnaked関数を生成します。
+/// ダミーのエラーコード（0）とベクタ番号をpushし、全汎用レジスタを保存して
+/// `irq::InterruptFrame`を構築した上で内部ハンドラを呼び出すnaked関数を生成します。
 macro_rules! exception_handler {
-    ($name:ident, $inner:ident) => {
+    ($name:ident, $vector:expr, $inner:ident) => {
         #[unsafe(naked)]
         extern "C" fn $name() {
             core::arch::naked_asm!(
-                // caller-savedレジスタを保存
+                // ダミーのエラーコードをpush（エラーコード付き例外とフレーム形式を揃える）
+                "push 0",
+                // ベクタ番号をpush
+                "push {vector}",
+                // 全汎用レジスタを保存（irq::InterruptFrameのフィールド順の逆順）
                 "push rax",
+                "push rbx",
                 "push rcx",
                 "push rdx",
                 "push rsi",
                 "push rdi",
+                "push rbp",
                 "push r8",
                 "push r9",
                 "push r10",
                 "push r11",
-                // 実際のハンドラを呼び出し
+                "push r12",
+                "push r13",
+                "push r14",
+                "push r15",
+                // InterruptFrameへのポインタを第1引数にしてハンドラを呼び出し
+                "mov rdi, rsp",
                 "call {handler_inner}",
                 // レジスタを復元
+                "pop r15",
+                "pop r14",
+                "pop r13",
+                "pop r12",
                 "pop r11",
                 "pop r10",
                 "pop r9",
                 "pop r8",
+                "pop rbp",
                 "pop rdi",
                 "pop rsi",
                 "pop rdx",
                 "pop rcx",
+                "pop rbx",
                 "pop rax",
+                // ベクタ番号とエラーコードを破棄
+                "add rsp, 16",
                 // 割り込みから復帰
                 "iretq",
+                vector = const $vector,
                 handler_inner = sym $inner,
             )
         }
@@ -56,36 +81,55 @@ macro_rules! exception_handler {
 
 /// エラーコード付きの例外ハンドラを生成するマクロ
 ///
-/// エラーコードをRDI（第1引数）に移動し、レジスタの保存/復元とiretqを含むnaked関数を生成します。
+/// CPUがpushしたエラーコードの上にベクタ番号をpushし、全汎用レジスタを保存して
+/// `irq::InterruptFrame`を構築した上で内部ハンドラを呼び出すnaked関数を生成します。
 macro_rules! exception_handler_with_error_code {
-    ($name:ident, $inner:ident) => {
+    ($name:ident, $vector:expr, $inner:ident) => {
         #[unsafe(naked)]
         extern "C" fn $name() {
             core::arch::naked_asm!(
-                // エラーコードをRDIレジスタに移動（System V ABIの第1引数）
-                "pop rdi",
-                // caller-savedレジスタを保存（rdiはエラーコードが入っているので保存しない）
+                // ベクタ番号をpush
+                "push {vector}",
+                // 全汎用レジスタを保存（irq::InterruptFrameのフィールド順の逆順）
                 "push rax",
+                "push rbx",
                 "push rcx",
                 "push rdx",
                 "push rsi",
+                "push rdi",
+                "push rbp",
                 "push r8",
                 "push r9",
                 "push r10",
                 "push r11",
-                // 実際のハンドラを呼び出し（RDIにエラーコード）
+                "push r12",
+                "push r13",
+                "push r14",
+                "push r15",
+                // InterruptFrameへのポインタを第1引数にしてハンドラを呼び出し
+                "mov rdi, rsp",
                 "call {handler_inner}",
                 // レジスタを復元
+                "pop r15",
+                "pop r14",
+                "pop r13",
+                "pop r12",
                 "pop r11",
                 "pop r10",
                 "pop r9",
                 "pop r8",
+                "pop rbp",
+                "pop rdi",
                 "pop rsi",
                 "pop rdx",
                 "pop rcx",
+                "pop rbx",
                 "pop rax",
+                // ベクタ番号とエラーコードを破棄
+                "add rsp, 16",
                 // 割り込みから復帰
                 "iretq",
+                vector = const $vector,
                 handler_inner = sym $inner,
             )
         }
@@ -223,128 +267,161 @@ extern "C" fn default_handler() {
     core::arch::naked_asm!("iretq")
 }
 
-/// タイマー割り込みハンドラ
-#[unsafe(naked)]
-extern "C" fn timer_interrupt_handler() {
-    core::arch::naked_asm!(
-        // レジスタを保存
-        "push rax",
-        "push rcx",
-        "push rdx",
-        "push rsi",
-        "push rdi",
-        "push r8",
-        "push r9",
-        "push r10",
-        "push r11",
-
-        // 実際のハンドラを呼び出し
-        "call {timer_handler_inner}",
-
-        // 割り込み復帰前処理（need_reschedチェック & スケジューリング）
-        // Linux風のアプローチ：割り込みハンドラはフラグをセットするだけで、
-        // 実際のスケジューリングは割り込み復帰時に行う
-        // RFLAGSの保存・復元はswitch_context()内部で自動的に処理される
-        "call {check_resched}",
-
-        // レジスタを復元
-        "pop r11",
-        "pop r10",
-        "pop r9",
-        "pop r8",
-        "pop rdi",
-        "pop rsi",
-        "pop rdx",
-        "pop rcx",
-        "pop rax",
-
-        // 割り込みから復帰（スタック上のRFLAGSが復元される）
-        "iretq",
-
-        timer_handler_inner = sym timer_handler_inner,
-        check_resched = sym check_resched_on_interrupt_exit_wrapper,
-    )
+/// スプリアス割り込みハンドラを生成するマクロ
+///
+/// Local APICのスプリアス割り込み（0xFF）とマスク中のレガシーPICが発生させる
+/// スプリアスIRQ7/IRQ15を受け止め、統計に記録する。
+/// スプリアス割り込みにはEOIを送ってはならない。
+macro_rules! spurious_handler {
+    ($name:ident, $vector:expr) => {
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            core::arch::naked_asm!(
+                // caller-savedレジスタを保存
+                "push rax",
+                "push rcx",
+                "push rdx",
+                "push rsi",
+                "push rdi",
+                "push r8",
+                "push r9",
+                "push r10",
+                "push r11",
+                // ベクタ番号を第1引数にして統計を記録
+                "mov edi, {vector}",
+                "call {record}",
+                // レジスタを復元
+                "pop r11",
+                "pop r10",
+                "pop r9",
+                "pop r8",
+                "pop rdi",
+                "pop rsi",
+                "pop rdx",
+                "pop rcx",
+                "pop rax",
+                "iretq",
+                vector = const $vector,
+                record = sym spurious_interrupt_inner,
+            )
+        }
+    };
 }
 
-/// 割り込み復帰時のスケジューリングチェック（ラッパー関数）
-///
-/// need_reschedフラグがセットされている場合、スケジューラを呼び出します。
-/// RFLAGSの保存・復元はswitch_context()内部で自動的に処理されます。
-extern "C" fn check_resched_on_interrupt_exit_wrapper() {
-    crate::sched::check_resched_on_interrupt_exit();
+spurious_handler!(
+    pic_spurious_interrupt_handler,
+    crate::pic::MASTER_SPURIOUS_VECTOR
+);
+spurious_handler!(apic_spurious_interrupt_handler, apic::SPURIOUS_VECTOR);
+
+/// スプリアス割り込みの記録
+extern "C" fn spurious_interrupt_inner(vector: u8) {
+    irq_stats::record(vector);
 }
 
-/// タイマー割り込みハンドラの実装
-extern "C" fn timer_handler_inner() {
-    // tick数をインクリメント
-    let _tick = timer::increment_tick();
+/// タイマー割り込みハンドラ
+///
+/// irqモジュールの共通エントリから呼び出される。EOI送信と
+/// 割り込み復帰時の再スケジューリングは共通エントリが行う。
+fn timer_interrupt_handler(_frame: &InterruptFrame) {
+    // ワンショット（tickless idle・hrtimer）による割り込みの場合、
+    // BSPでは止めていた間のtick数が補われ、APでは周期tickが再開される
+    let entry = nohz::timer_interrupt_enter();
+    let periodic = entry == nohz::TimerEntry::Periodic;
+
+    // グローバルなtick管理とタイマーキューの処理はBSPのみが行う
+    if crate::cpu::is_bsp() {
+        // tick数をインクリメント
+        if periodic {
+            timer::increment_tick();
+        }
+
+        // 期限切れタイマーをチェック（ペンディングキューに移動するだけ）
+        timer::check_timers();
 
-    // 期限切れタイマーをチェック（ペンディングキューに移動するだけ）
-    timer::check_timers();
+        // 期限切れの高精度タイマーを処理
+        hrtimer::run_expired(crate::time::monotonic_ns());
 
-    // 現在のタスクのvruntimeを更新（CFS風スケジューリング）
-    // タイマー周波数は250Hzなので、1tick = 4ms = 4,000,000ns
-    const TIMER_PERIOD_NS: u64 = 4_000_000;
-    crate::sched::update_current_task_vruntime(TIMER_PERIOD_NS);
+        // クロックソースの繰り込み・ウォッチドッグ・tick間隔の検査
+        crate::time::tick(periodic);
+
+        // 次のhrtimerの期限またはtickにイベントを設定
+        nohz::program_next_event(entry);
+    }
+
+    // 現在のタスクの実行時間を計上（CFS風スケジューリング）
+    crate::sched::account_tick();
 
     // スケジューリングが必要であることを示すフラグをセット
     // 実際のスケジューリングは割り込み復帰時に行われる（Linux風）
     crate::sched::set_need_resched();
 
-    // EOI (End of Interrupt) を送信
-    apic::send_eoi();
+    // 定期負荷分散のタイミングを判定（各CPUが自身のtickで判定する）
+    crate::sched::trigger_load_balance();
 }
 
 // =============================================================================
 // 例外ハンドラ実装
 // =============================================================================
 
+/// 回復不能な例外の後にCPUを停止
+fn halt_forever() -> ! {
+    loop {
+        // SAFETY: 割り込みを無効化してHLTするだけで、メモリ安全性に影響しない。
+        unsafe { asm!("cli; hlt") };
+    }
+}
+
 // Divide Error (#DE, ベクタ0) ハンドラ
 // ゼロ除算または除算結果がオーバーフローした場合に発生
-exception_handler!(divide_error_handler, divide_error_handler_inner);
+exception_handler!(divide_error_handler, 0, divide_error_handler_inner);
 
-extern "C" fn divide_error_handler_inner() {
-    println!("\n\n");
-    println!("========================================");
-    println!("EXCEPTION: Divide Error (#DE)");
-    println!("========================================");
+extern "C" fn divide_error_handler_inner(frame: &mut InterruptFrame) {
+    irq_stats::record(0);
+
+    exception::report_exception("EXCEPTION: Divide Error (#DE)", frame);
     println!("Division by zero or division overflow occurred.");
-    println!("");
 
-    // 停止
-    loop {
-        unsafe { asm!("hlt") };
-    }
+    halt_forever();
 }
 
 // Debug Exception (#DB, ベクタ1) ハンドラ
 // デバッグレジスタによるブレークポイントやシングルステップで発生
-exception_handler!(debug_exception_handler, debug_exception_handler_inner);
+exception_handler!(debug_exception_handler, 1, debug_exception_handler_inner);
+
+extern "C" fn debug_exception_handler_inner(frame: &mut InterruptFrame) {
+    irq_stats::record(1);
 
-extern "C" fn debug_exception_handler_inner() {
-    println!("\n\n");
-    println!("========================================");
-    println!("EXCEPTION: Debug Exception (#DB)");
-    println!("========================================");
+    exception::report_exception("EXCEPTION: Debug Exception (#DB)", frame);
     println!("Debug exception occurred.");
-    println!("");
 
-    loop {
-        unsafe { asm!("hlt") };
+    halt_forever();
+}
+
+// Non-Maskable Interrupt (NMI, ベクタ2) ハンドラ
+// ハードウェアエラー、またはipi::stop_other_cpus()による停止要求で発生
+exception_handler!(nmi_handler, 2, nmi_handler_inner);
+
+extern "C" fn nmi_handler_inner(frame: &mut InterruptFrame) {
+    irq_stats::record(2);
+
+    // 他CPUのパニックによる停止要求なら、ここで停止する（戻らない）
+    if crate::ipi::handle_stop_nmi() {
+        return;
     }
+
+    exception::report_exception("EXCEPTION: Non-Maskable Interrupt (NMI)", frame);
+    println!("Unexpected NMI received, continuing.");
 }
 
 // Breakpoint (#BP, ベクタ3) ハンドラ
 // INT3命令（0xCC）によって発生
-exception_handler!(breakpoint_handler, breakpoint_handler_inner);
-
-extern "C" fn breakpoint_handler_inner() {
-    println!("\n\n");
-    println!("========================================");
-    println!("EXCEPTION: Breakpoint (#BP)");
-    println!("========================================");
-    println!("Breakpoint exception occurred.");
-    println!("");
+exception_handler!(breakpoint_handler, 3, breakpoint_handler_inner);
+
+extern "C" fn breakpoint_handler_inner(frame: &mut InterruptFrame) {
+    irq_stats::record(3);
+
+    exception::report_exception("EXCEPTION: Breakpoint (#BP)", frame);
 
     // ブレークポイントは通常、続行可能
     println!("Control will transfer to debugger if attached.");
@@ -352,19 +429,15 @@ extern "C" fn breakpoint_handler_inner() {
 
 // Invalid Opcode (#UD, ベクタ6) ハンドラ
 // 無効な命令やサポートされていない命令を実行しようとした場合に発生
-exception_handler!(invalid_opcode_handler, invalid_opcode_handler_inner);
+exception_handler!(invalid_opcode_handler, 6, invalid_opcode_handler_inner);
 
-extern "C" fn invalid_opcode_handler_inner() {
-    println!("\n\n");
-    println!("========================================");
-    println!("EXCEPTION: Invalid Opcode (#UD)");
-    println!("========================================");
+extern "C" fn invalid_opcode_handler_inner(frame: &mut InterruptFrame) {
+    irq_stats::record(6);
+
+    exception::report_exception("EXCEPTION: Invalid Opcode (#UD)", frame);
     println!("Attempted to execute an invalid or unsupported instruction.");
-    println!("");
 
-    loop {
-        unsafe { asm!("hlt") };
-    }
+    halt_forever();
 }
 
 // =============================================================================
@@ -373,9 +446,11 @@ extern "C" fn invalid_opcode_handler_inner() {
 
 // Double Fault (#DF, ベクタ8) ハンドラ
 // 例外ハンドラ内で別の例外が発生した場合に発生（重大なエラー）
-exception_handler_with_error_code!(double_fault_handler, double_fault_handler_inner);
+exception_handler_with_error_code!(double_fault_handler, 8, double_fault_handler_inner);
+
+extern "C" fn double_fault_handler_inner(frame: &mut InterruptFrame) {
+    irq_stats::record(8);
 
-extern "C" fn double_fault_handler_inner(error_code: u64) {
     // CR2レジスタから最後のPage Fault違反アドレスを取得
     // Double FaultはPage Fault → Page Faultで発生するため、CR2には最初のPage Faultアドレスが残っている
     let fault_addr: u64;
@@ -387,27 +462,18 @@ extern "C" fn double_fault_handler_inner(error_code: u64) {
     if crate::stack::is_guard_page_fault(fault_addr) {
         // ガードページアドレスを表示用に取得
         let guard_page_addr = crate::stack::guard_page_address().unwrap_or(0);
-        println!("\n\n");
-        println!("========================================");
-        println!("FATAL: STACK OVERFLOW DETECTED");
-        println!("========================================");
+        exception::report_exception("FATAL: STACK OVERFLOW DETECTED", frame);
         println!("Kernel stack overflow occurred!");
-        println!("");
         println!("Guard Page address: 0x{:016X}", guard_page_addr);
         println!("Fault address (CR2): 0x{:016X}", fault_addr);
-        println!("Error code: 0x{:X}", error_code);
         println!("");
         println!("The kernel stack has been exhausted.");
         println!("Possible causes: infinite recursion or large local variables.");
         println!("");
     } else {
         // 通常のDouble Fault
-        println!("\n\n");
-        println!("========================================");
-        println!("FATAL: Double Fault (#DF)");
-        println!("========================================");
+        exception::report_exception("FATAL: Double Fault (#DF)", frame);
         println!("An exception occurred within an exception handler.");
-        println!("Error code: 0x{:X}", error_code);
         println!("Last Page Fault address (CR2): 0x{:016X}", fault_addr);
         println!("");
         println!("System is in a critical error state.");
@@ -415,25 +481,23 @@ extern "C" fn double_fault_handler_inner(error_code: u64) {
     }
 
     // 永久停止
-    loop {
-        unsafe { asm!("cli; hlt") };
-    }
+    halt_forever();
 }
 
 // General Protection Fault (#GP, ベクタ13) ハンドラ
 // セグメント違反、特権レベル違反、無効なメモリアクセスなどで発生
 exception_handler_with_error_code!(
     general_protection_fault_handler,
+    13,
     general_protection_fault_handler_inner
 );
 
-extern "C" fn general_protection_fault_handler_inner(error_code: u64) {
-    println!("\n\n");
-    println!("========================================");
-    println!("EXCEPTION: General Protection Fault (#GP)");
-    println!("========================================");
+extern "C" fn general_protection_fault_handler_inner(frame: &mut InterruptFrame) {
+    irq_stats::record(13);
+
+    let error_code = frame.error_code;
+    exception::report_exception("EXCEPTION: General Protection Fault (#GP)", frame);
     println!("Segment violation or privilege level violation occurred.");
-    println!("Error code: 0x{:X}", error_code);
 
     // エラーコードの詳細を解析
     if error_code != 0 {
@@ -458,29 +522,26 @@ extern "C" fn general_protection_fault_handler_inner(error_code: u64) {
     }
     println!("");
 
-    loop {
-        unsafe { asm!("hlt") };
-    }
+    halt_forever();
 }
 
 // Page Fault (#PF, ベクタ14) ハンドラ
 // 無効なページアクセス、権限違反、ページ未マップなどで発生
-exception_handler_with_error_code!(page_fault_handler, page_fault_handler_inner);
+exception_handler_with_error_code!(page_fault_handler, 14, page_fault_handler_inner);
+
+extern "C" fn page_fault_handler_inner(frame: &mut InterruptFrame) {
+    irq_stats::record(14);
 
-extern "C" fn page_fault_handler_inner(error_code: u64) {
     // CR2レジスタから違反アドレスを取得
     let fault_addr: u64;
     unsafe {
         asm!("mov {}, cr2", out(reg) fault_addr, options(nomem, nostack));
     }
 
-    println!("\n\n");
-    println!("========================================");
-    println!("EXCEPTION: Page Fault (#PF)");
-    println!("========================================");
+    let error_code = frame.error_code;
+    exception::report_exception("EXCEPTION: Page Fault (#PF)", frame);
     println!("Invalid memory access occurred.");
     println!("Fault address: 0x{:016X}", fault_addr);
-    println!("Error code: 0x{:X}", error_code);
 
     // エラーコードの詳細を解析
     println!("");
@@ -523,14 +584,12 @@ extern "C" fn page_fault_handler_inner(error_code: u64) {
     );
     println!("");
 
-    loop {
-        unsafe { asm!("hlt") };
-    }
+    halt_forever();
 }
 
 /// IDTエントリを設定
 ///
-/// MSIなど外部モジュールから割り込みハンドラを登録するために公開
+/// irqモジュールが共通スタブを登録するために公開
 pub fn set_idt_entry(vector: u8, handler: usize) {
     let mut idt = IDT.lock();
 
@@ -560,6 +619,7 @@ pub fn init() -> Result<(), IdtError> {
     // 例外ハンドラを登録
     set_idt_entry(0, divide_error_handler as usize); // #DE: Divide Error
     set_idt_entry(1, debug_exception_handler as usize); // #DB: Debug Exception
+    set_idt_entry(2, nmi_handler as usize); // NMI: Non-Maskable Interrupt
     set_idt_entry(3, breakpoint_handler as usize); // #BP: Breakpoint
     set_idt_entry(6, invalid_opcode_handler as usize); // #UD: Invalid Opcode
     // Double FaultハンドラにはIST1を設定（専用スタック使用）
@@ -571,12 +631,38 @@ pub fn init() -> Result<(), IdtError> {
     set_idt_entry(13, general_protection_fault_handler as usize); // #GP: General Protection Fault
     set_idt_entry(14, page_fault_handler as usize); // #PF: Page Fault
 
+    // 外部割り込み（ベクタ32-239）を共通スタブ経由でディスパッチ
+    irq::install_stubs();
+
     // タイマー割り込みハンドラを登録
+    irq::request_irq(
+        irq::IrqVector::Fixed(apic::TIMER_INTERRUPT_VECTOR),
+        timer_interrupt_handler,
+        "apic-timer",
+    )
+    .map_err(|_| IdtError::InitFailed)?;
+
+    // スプリアス割り込みハンドラを登録（PIC IRQ7/IRQ15、APICスプリアス）
+    // （PICのIRQ15スプリアスはAPICスプリアスと同じ0xFFに再マップされている）
     set_idt_entry(
-        apic::TIMER_INTERRUPT_VECTOR,
-        timer_interrupt_handler as usize,
+        crate::pic::MASTER_SPURIOUS_VECTOR,
+        pic_spurious_interrupt_handler as usize,
     );
+    set_idt_entry(
+        apic::SPURIOUS_VECTOR,
+        apic_spurious_interrupt_handler as usize,
+    );
+
+    load();
 
+    info!("IDT initialized with exception handlers");
+    Ok(())
+}
+
+/// IDTを現在のCPUにロード
+///
+/// 全CPUで同一のIDTを共有する。APは起動時にこの関数でIDTをロードする。
+pub fn load() {
     unsafe {
         // IDTのアドレスを取得（カーネルが高位アドレスでリンクされているため既に高位）
         let idt = IDT.lock();
@@ -594,7 +680,4 @@ pub fn init() -> Result<(), IdtError> {
             options(readonly, nostack, preserves_flags)
         );
     }
-
-    info!("IDT initialized with exception handlers");
-    Ok(())
 }
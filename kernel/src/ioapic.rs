@@ -0,0 +1,588 @@
+//! I/O APIC ドライバ
+//!
+//! Intel 82093AA I/O APIC データシートおよびACPI仕様（MADT）に基づく実装。
+//! MADTから登録されたI/O APICをマッピングし、GSI（Global System Interrupt）を
+//! Local APICのベクタへルーティングします。
+//!
+//! # 初期化の流れ
+//! 1. `acpi::init()` がMADTを走査し、I/O APIC・Interrupt Source Override・
+//!    NMI Sourceを `register_*` 関数で登録する
+//! 2. `ioapic::init()` が各I/O APICをUC属性でマッピングし、全エントリをマスクする
+//! 3. デバイスドライバが `route_irq()` / `unmask()` で必要な入力だけを有効化する
+
+use core::ptr::{read_volatile, write_volatile};
+use spin::Mutex;
+
+use crate::paging::PagingError;
+
+/// サポートするI/O APICの最大数
+const MAX_IO_APICS: usize = 8;
+
+/// 保持するInterrupt Source Overrideの最大数（ISA IRQは16本）
+const MAX_SOURCE_OVERRIDES: usize = 16;
+
+/// 保持するNMI Sourceの最大数
+const MAX_NMI_SOURCES: usize = 8;
+
+/// ISA IRQの本数
+pub const ISA_IRQ_COUNT: u8 = 16;
+
+/// I/O APIC操作のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoApicError {
+    /// I/O APICが初期化されていない
+    NotInitialized,
+    /// 指定GSIを担当するI/O APICが存在しない
+    GsiNotFound { gsi: u32 },
+    /// 無効なベクタ番号（0-31は例外用）
+    InvalidVector { vector: u8 },
+    /// MMIOマッピングに失敗
+    PagingError(PagingError),
+}
+
+impl From<PagingError> for IoApicError {
+    fn from(e: PagingError) -> Self {
+        IoApicError::PagingError(e)
+    }
+}
+
+impl core::fmt::Display for IoApicError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            IoApicError::NotInitialized => write!(f, "I/O APIC not initialized"),
+            IoApicError::GsiNotFound { gsi } => write!(f, "No I/O APIC handles GSI {}", gsi),
+            IoApicError::InvalidVector { vector } => write!(f, "Invalid vector {}", vector),
+            IoApicError::PagingError(e) => write!(f, "Paging error: {}", e),
+        }
+    }
+}
+
+/// I/O APICレジスタ
+mod registers {
+    /// I/O Register Select（MMIOオフセット）
+    pub const IOREGSEL: u64 = 0x00;
+    /// I/O Window（MMIOオフセット）
+    pub const IOWIN: u64 = 0x10;
+
+    /// I/O APIC ID（間接レジスタ）
+    pub const IOAPICID: u32 = 0x00;
+    /// I/O APIC Version（間接レジスタ）
+    /// bits 16-23: Maximum Redirection Entry
+    pub const IOAPICVER: u32 = 0x01;
+    /// Redirection Table の先頭（エントリnは 0x10 + 2n / 0x11 + 2n）
+    pub const IOREDTBL_BASE: u32 = 0x10;
+}
+
+/// Redirection Table Entry のビットフィールド
+mod redirection {
+    /// Delivery Mode: Fixed
+    pub const DELIVERY_FIXED: u64 = 0b000 << 8;
+    /// Delivery Mode: NMI
+    pub const DELIVERY_NMI: u64 = 0b100 << 8;
+    /// Interrupt Input Pin Polarity (1 = Active Low)
+    pub const POLARITY_LOW: u64 = 1 << 13;
+    /// Trigger Mode (1 = Level)
+    pub const TRIGGER_LEVEL: u64 = 1 << 15;
+    /// Interrupt Mask (1 = Masked)
+    pub const MASKED: u64 = 1 << 16;
+    /// Destination フィールドのシフト量（Physical Mode, APIC ID）
+    pub const DESTINATION_SHIFT: u64 = 56;
+}
+
+/// MPS INTI flags（MADTのOverride/NMI Sourceエントリで使用）
+mod inti_flags {
+    /// 極性フィールドのマスク（bits 0-1）
+    pub const POLARITY_MASK: u16 = 0b11;
+    /// Active High
+    pub const POLARITY_HIGH: u16 = 0b01;
+    /// Active Low
+    pub const POLARITY_LOW: u16 = 0b11;
+    /// トリガモードフィールドのマスク（bits 2-3）
+    pub const TRIGGER_MASK: u16 = 0b11 << 2;
+    /// Edge-triggered
+    pub const TRIGGER_EDGE: u16 = 0b01 << 2;
+    /// Level-triggered
+    pub const TRIGGER_LEVEL: u16 = 0b11 << 2;
+}
+
+/// 割り込みのトリガモード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// エッジトリガ（ISAデバイスの既定）
+    Edge,
+    /// レベルトリガ（PCI INTxの既定）
+    Level,
+}
+
+/// 割り込み入力の極性
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    /// アクティブHigh（ISAデバイスの既定）
+    ActiveHigh,
+    /// アクティブLow（PCI INTxの既定）
+    ActiveLow,
+}
+
+impl TriggerMode {
+    /// MPS INTI flagsからトリガモードを解釈
+    ///
+    /// "bus conforming"（00）の場合は `default` を返す。
+    fn from_inti_flags(flags: u16, default: TriggerMode) -> Self {
+        match flags & inti_flags::TRIGGER_MASK {
+            inti_flags::TRIGGER_EDGE => TriggerMode::Edge,
+            inti_flags::TRIGGER_LEVEL => TriggerMode::Level,
+            _ => default,
+        }
+    }
+}
+
+impl Polarity {
+    /// MPS INTI flagsから極性を解釈
+    ///
+    /// "bus conforming"（00）の場合は `default` を返す。
+    fn from_inti_flags(flags: u16, default: Polarity) -> Self {
+        match flags & inti_flags::POLARITY_MASK {
+            inti_flags::POLARITY_HIGH => Polarity::ActiveHigh,
+            inti_flags::POLARITY_LOW => Polarity::ActiveLow,
+            _ => default,
+        }
+    }
+}
+
+/// I/O APICインスタンス
+#[derive(Debug, Clone, Copy)]
+struct IoApic {
+    /// I/O APIC ID
+    id: u8,
+    /// MMIO物理アドレス
+    phys_addr: u64,
+    /// MMIO仮想アドレス（init()でマッピングされるまで0）
+    virt_addr: u64,
+    /// このI/O APICが担当する最初のGSI
+    gsi_base: u32,
+    /// Redirection Tableのエントリ数（init()で読み取るまで0）
+    entry_count: u32,
+}
+
+impl IoApic {
+    /// 間接レジスタを読み込む
+    ///
+    /// # Safety
+    /// virt_addrがマッピング済みのI/O APIC MMIO領域を指していること
+    unsafe fn read(&self, reg: u32) -> u32 {
+        // SAFETY: 呼び出し元がvirt_addrの有効性を保証する。
+        // IOREGSELに選択したいレジスタ番号を書き込み、IOWINから値を読む。
+        unsafe {
+            write_volatile((self.virt_addr + registers::IOREGSEL) as *mut u32, reg);
+            read_volatile((self.virt_addr + registers::IOWIN) as *const u32)
+        }
+    }
+
+    /// 間接レジスタへ書き込む
+    ///
+    /// # Safety
+    /// virt_addrがマッピング済みのI/O APIC MMIO領域を指していること
+    unsafe fn write(&self, reg: u32, value: u32) {
+        // SAFETY: 呼び出し元がvirt_addrの有効性を保証する。
+        unsafe {
+            write_volatile((self.virt_addr + registers::IOREGSEL) as *mut u32, reg);
+            write_volatile((self.virt_addr + registers::IOWIN) as *mut u32, value);
+        }
+    }
+
+    /// Redirection Table Entry を読み込む
+    ///
+    /// # Safety
+    /// virt_addrが有効であり、indexがentry_count未満であること
+    unsafe fn read_entry(&self, index: u32) -> u64 {
+        let reg = registers::IOREDTBL_BASE + index * 2;
+        // SAFETY: 呼び出し元がvirt_addrとindexの有効性を保証する。
+        unsafe {
+            let low = self.read(reg) as u64;
+            let high = self.read(reg + 1) as u64;
+            (high << 32) | low
+        }
+    }
+
+    /// Redirection Table Entry を書き込む
+    ///
+    /// 書き込み途中の中途半端な設定で割り込みが配送されないよう、
+    /// 先に下位32ビットをマスク状態にしてから上位→下位の順に書き込む。
+    ///
+    /// # Safety
+    /// virt_addrが有効であり、indexがentry_count未満であること
+    unsafe fn write_entry(&self, index: u32, value: u64) {
+        let reg = registers::IOREDTBL_BASE + index * 2;
+        // SAFETY: 呼び出し元がvirt_addrとindexの有効性を保証する。
+        unsafe {
+            self.write(reg, redirection::MASKED as u32);
+            self.write(reg + 1, (value >> 32) as u32);
+            self.write(reg, value as u32);
+        }
+    }
+
+    /// 指定GSIがこのI/O APICの担当範囲か
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.entry_count
+    }
+}
+
+/// Interrupt Source Override
+#[derive(Debug, Clone, Copy)]
+struct SourceOverride {
+    /// ISA IRQ番号
+    irq: u8,
+    /// 接続先GSI
+    gsi: u32,
+    /// MPS INTI flags
+    flags: u16,
+}
+
+/// NMI Source
+#[derive(Debug, Clone, Copy)]
+struct NmiSource {
+    /// NMIとして扱うGSI
+    gsi: u32,
+    /// MPS INTI flags
+    flags: u16,
+}
+
+/// MADTから収集したI/O APIC関連情報
+///
+/// ACPI解析はヒープ初期化前に行われるため、固定長配列で保持する。
+struct IoApicTable {
+    io_apics: [Option<IoApic>; MAX_IO_APICS],
+    overrides: [Option<SourceOverride>; MAX_SOURCE_OVERRIDES],
+    nmi_sources: [Option<NmiSource>; MAX_NMI_SOURCES],
+    /// init()が完了したか
+    initialized: bool,
+}
+
+impl IoApicTable {
+    const fn new() -> Self {
+        Self {
+            io_apics: [None; MAX_IO_APICS],
+            overrides: [None; MAX_SOURCE_OVERRIDES],
+            nmi_sources: [None; MAX_NMI_SOURCES],
+            initialized: false,
+        }
+    }
+
+    /// 指定GSIを担当するI/O APICとエントリ番号を返す
+    fn find(&self, gsi: u32) -> Result<(IoApic, u32), IoApicError> {
+        if !self.initialized {
+            return Err(IoApicError::NotInitialized);
+        }
+        self.io_apics
+            .iter()
+            .flatten()
+            .find(|io_apic| io_apic.handles(gsi))
+            .map(|io_apic| (*io_apic, gsi - io_apic.gsi_base))
+            .ok_or(IoApicError::GsiNotFound { gsi })
+    }
+}
+
+/// グローバルI/O APICテーブル
+///
+/// ロック保持中は割り込みを無効化すること（割り込みハンドラからmask/unmaskされ得るため）。
+static IO_APIC_TABLE: Mutex<IoApicTable> = Mutex::new(IoApicTable::new());
+
+/// MADTのI/O APICエントリを登録（acpi.rsから呼ばれる）
+///
+/// # Arguments
+/// * `id` - I/O APIC ID
+/// * `phys_addr` - MMIO物理アドレス
+/// * `gsi_base` - 担当する最初のGSI
+pub fn register_io_apic(id: u8, phys_addr: u64, gsi_base: u32) {
+    let mut table = IO_APIC_TABLE.lock();
+    match table.io_apics.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(IoApic {
+                id,
+                phys_addr,
+                virt_addr: 0,
+                gsi_base,
+                entry_count: 0,
+            })
+        }
+        None => crate::warn!("Too many I/O APICs, ignoring ID={}", id),
+    }
+}
+
+/// MADTのInterrupt Source Overrideエントリを登録（acpi.rsから呼ばれる）
+///
+/// # Arguments
+/// * `irq` - ISA IRQ番号
+/// * `gsi` - 接続先GSI
+/// * `flags` - MPS INTI flags
+pub fn register_source_override(irq: u8, gsi: u32, flags: u16) {
+    let mut table = IO_APIC_TABLE.lock();
+    match table.overrides.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => *slot = Some(SourceOverride { irq, gsi, flags }),
+        None => crate::warn!("Too many interrupt source overrides, ignoring IRQ {}", irq),
+    }
+}
+
+/// MADTのNMI Sourceエントリを登録（acpi.rsから呼ばれる）
+///
+/// # Arguments
+/// * `gsi` - NMIとして扱うGSI
+/// * `flags` - MPS INTI flags
+pub fn register_nmi_source(gsi: u32, flags: u16) {
+    let mut table = IO_APIC_TABLE.lock();
+    match table.nmi_sources.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => *slot = Some(NmiSource { gsi, flags }),
+        None => crate::warn!("Too many NMI sources, ignoring GSI {}", gsi),
+    }
+}
+
+/// I/O APICを初期化
+///
+/// MADTから登録された全I/O APICをUC属性でマッピングし、
+/// Redirection Tableの全エントリをマスクします。
+/// NMI Sourceとして登録されたGSIはNMI配送モードで設定します。
+///
+/// 割り込み無効状態で、Local APIC初期化後に呼び出すこと。
+///
+/// # Errors
+/// * `IoApicError::NotInitialized` - MADTにI/O APICが存在しない場合
+/// * `IoApicError::PagingError` - MMIOマッピングに失敗した場合
+pub fn init() -> Result<(), IoApicError> {
+    let mut table = IO_APIC_TABLE.lock();
+
+    if table.io_apics.iter().all(|slot| slot.is_none()) {
+        return Err(IoApicError::NotInitialized);
+    }
+
+    for io_apic in table.io_apics.iter_mut().flatten() {
+        io_apic.virt_addr =
+            crate::paging::map_mmio(io_apic.phys_addr, crate::paging::PAGE_SIZE as u64)?;
+
+        // SAFETY: 直前にI/O APICのMMIO領域をマッピング済み。
+        // IOAPICVERのbits 16-23は最大エントリ番号（エントリ数 - 1）。
+        unsafe {
+            let version = io_apic.read(registers::IOAPICVER);
+            io_apic.entry_count = ((version >> 16) & 0xFF) + 1;
+            let hw_id = (io_apic.read(registers::IOAPICID) >> 24) & 0x0F;
+
+            // 全エントリをマスク（ファームウェアの設定を引き継がない）
+            for index in 0..io_apic.entry_count {
+                io_apic.write_entry(index, redirection::MASKED);
+            }
+
+            crate::info!(
+                "I/O APIC ID={} (HW ID={}) version=0x{:02X}: GSI {}-{} masked",
+                io_apic.id,
+                hw_id,
+                version & 0xFF,
+                io_apic.gsi_base,
+                io_apic.gsi_base + io_apic.entry_count - 1
+            );
+        }
+    }
+
+    table.initialized = true;
+
+    // NMI Sourceを設定（BSPにNMIとして配送）
+    let bsp_apic_id = crate::apic::local_apic_id();
+    for nmi in table.nmi_sources.iter().flatten() {
+        let trigger = TriggerMode::from_inti_flags(nmi.flags, TriggerMode::Edge);
+        let polarity = Polarity::from_inti_flags(nmi.flags, Polarity::ActiveHigh);
+        match table.find(nmi.gsi) {
+            Ok((io_apic, index)) => {
+                let entry = redirection::DELIVERY_NMI
+                    | trigger_bits(trigger)
+                    | polarity_bits(polarity)
+                    | ((bsp_apic_id as u64) << redirection::DESTINATION_SHIFT);
+                // SAFETY: find()がマッピング済みのI/O APICと範囲内のindexを返す
+                unsafe { io_apic.write_entry(index, entry) };
+                crate::info!("I/O APIC: GSI {} configured as NMI", nmi.gsi);
+            }
+            Err(e) => crate::warn!("I/O APIC: NMI source GSI {}: {}", nmi.gsi, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// I/O APICが初期化済みかどうか
+pub fn is_available() -> bool {
+    crate::io::without_interrupts(|| IO_APIC_TABLE.lock().initialized)
+}
+
+/// ISA IRQを対応するGSIとトリガモード・極性に変換
+///
+/// Interrupt Source Overrideが登録されていればその値を使い、
+/// なければISAの既定値（GSI = IRQ、エッジトリガ、アクティブHigh）を返します。
+///
+/// # Arguments
+/// * `irq` - ISA IRQ番号（0-15）
+pub fn legacy_irq_to_gsi(irq: u8) -> (u32, TriggerMode, Polarity) {
+    crate::io::without_interrupts(|| {
+        let table = IO_APIC_TABLE.lock();
+        match table.overrides.iter().flatten().find(|o| o.irq == irq) {
+            Some(o) => (
+                o.gsi,
+                TriggerMode::from_inti_flags(o.flags, TriggerMode::Edge),
+                Polarity::from_inti_flags(o.flags, Polarity::ActiveHigh),
+            ),
+            None => (irq as u32, TriggerMode::Edge, Polarity::ActiveHigh),
+        }
+    })
+}
+
+fn trigger_bits(trigger: TriggerMode) -> u64 {
+    match trigger {
+        TriggerMode::Edge => 0,
+        TriggerMode::Level => redirection::TRIGGER_LEVEL,
+    }
+}
+
+fn polarity_bits(polarity: Polarity) -> u64 {
+    match polarity {
+        Polarity::ActiveHigh => 0,
+        Polarity::ActiveLow => redirection::POLARITY_LOW,
+    }
+}
+
+/// GSIをLocal APICのベクタへルーティング
+///
+/// Fixed配送・Physical宛先モードでRedirection Table Entryを設定し、
+/// マスクを解除します。
+///
+/// # Arguments
+/// * `gsi` - ルーティングするGSI
+/// * `vector` - 配送先ベクタ番号（32-254）
+/// * `dest` - 宛先Local APIC ID
+/// * `trigger` - トリガモード
+/// * `polarity` - 入力の極性
+///
+/// # Errors
+/// * `IoApicError::InvalidVector` - ベクタが例外用（0-31）またはスプリアス（255）の場合
+/// * `IoApicError::NotInitialized` - init()前に呼び出された場合
+/// * `IoApicError::GsiNotFound` - GSIを担当するI/O APICがない場合
+pub fn route_irq(
+    gsi: u32,
+    vector: u8,
+    dest: u8,
+    trigger: TriggerMode,
+    polarity: Polarity,
+) -> Result<(), IoApicError> {
+    if !(32..255).contains(&vector) {
+        return Err(IoApicError::InvalidVector { vector });
+    }
+
+    crate::io::without_interrupts(|| {
+        let table = IO_APIC_TABLE.lock();
+        let (io_apic, index) = table.find(gsi)?;
+
+        let entry = redirection::DELIVERY_FIXED
+            | trigger_bits(trigger)
+            | polarity_bits(polarity)
+            | (vector as u64)
+            | ((dest as u64) << redirection::DESTINATION_SHIFT);
+
+        // SAFETY: find()がマッピング済みのI/O APICと範囲内のindexを返す
+        unsafe { io_apic.write_entry(index, entry) };
+        Ok(())
+    })
+}
+
+/// 指定GSIをマスク（割り込みを止める）
+///
+/// # Errors
+/// * `IoApicError::NotInitialized` - init()前に呼び出された場合
+/// * `IoApicError::GsiNotFound` - GSIを担当するI/O APICがない場合
+pub fn mask(gsi: u32) -> Result<(), IoApicError> {
+    set_masked(gsi, true)
+}
+
+/// 指定GSIのマスクを解除（割り込みを再開）
+///
+/// # Errors
+/// * `IoApicError::NotInitialized` - init()前に呼び出された場合
+/// * `IoApicError::GsiNotFound` - GSIを担当するI/O APICがない場合
+pub fn unmask(gsi: u32) -> Result<(), IoApicError> {
+    set_masked(gsi, false)
+}
+
+fn set_masked(gsi: u32, masked: bool) -> Result<(), IoApicError> {
+    crate::io::without_interrupts(|| {
+        let table = IO_APIC_TABLE.lock();
+        let (io_apic, index) = table.find(gsi)?;
+
+        // SAFETY: find()がマッピング済みのI/O APICと範囲内のindexを返す。
+        // マスクビットは下位32ビットにあるため、下位のみ書き換える。
+        unsafe {
+            let reg = registers::IOREDTBL_BASE + index * 2;
+            let low = io_apic.read(reg) as u64;
+            let new_low = if masked {
+                low | redirection::MASKED
+            } else {
+                low & !redirection::MASKED
+            };
+            io_apic.write(reg, new_low as u32);
+        }
+        Ok(())
+    })
+}
+
+/// 指定GSIのRedirection Table Entryを読み取る（デバッグ用）
+///
+/// # Errors
+/// * `IoApicError::NotInitialized` - init()前に呼び出された場合
+/// * `IoApicError::GsiNotFound` - GSIを担当するI/O APICがない場合
+pub fn read_redirection_entry(gsi: u32) -> Result<u64, IoApicError> {
+    crate::io::without_interrupts(|| {
+        let table = IO_APIC_TABLE.lock();
+        let (io_apic, index) = table.find(gsi)?;
+        // SAFETY: find()がマッピング済みのI/O APICと範囲内のindexを返す
+        Ok(unsafe { io_apic.read_entry(index) })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_inti_flags_conforming_uses_default() {
+        assert_eq!(
+            TriggerMode::from_inti_flags(0, TriggerMode::Edge),
+            TriggerMode::Edge
+        );
+        assert_eq!(
+            Polarity::from_inti_flags(0, Polarity::ActiveHigh),
+            Polarity::ActiveHigh
+        );
+    }
+
+    #[test_case]
+    fn test_inti_flags_level_low() {
+        // QEMUのSCI（IRQ9）はレベルトリガ・アクティブHigh、PCIはレベル・アクティブLow
+        let flags = inti_flags::TRIGGER_LEVEL | inti_flags::POLARITY_LOW;
+        assert_eq!(
+            TriggerMode::from_inti_flags(flags, TriggerMode::Edge),
+            TriggerMode::Level
+        );
+        assert_eq!(
+            Polarity::from_inti_flags(flags, Polarity::ActiveHigh),
+            Polarity::ActiveLow
+        );
+    }
+
+    #[test_case]
+    fn test_io_apic_handles_range() {
+        let io_apic = IoApic {
+            id: 0,
+            phys_addr: 0xFEC0_0000,
+            virt_addr: 0,
+            gsi_base: 24,
+            entry_count: 24,
+        };
+        assert!(!io_apic.handles(23));
+        assert!(io_apic.handles(24));
+        assert!(io_apic.handles(47));
+        assert!(!io_apic.handles(48));
+    }
+}
@@ -0,0 +1,273 @@
+//! プロセッサ間割り込み（IPI）
+//!
+//! Local APICのICRを使ったIPI送信と、それを基盤とした以下の機能を提供します。
+//! - 特定CPU・自分以外の全CPU・自分自身へのIPI送信
+//! - 他CPU上での関数呼び出し（`smp_call_function`）
+//! - NMIによる全CPU停止（パニック時に他CPUの出力や状態変更を止める）
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::apic::{self, icr};
+use crate::cpu::{self, MAX_CPUS};
+use crate::io::without_interrupts;
+use crate::irq::{self, InterruptFrame, IrqVector};
+use crate::percpu;
+
+/// 停止要求がないことを示す値
+const NO_STOP_OWNER: usize = usize::MAX;
+
+/// 他CPUの停止を待つ最大スピン回数
+const STOP_WAIT_SPINS: usize = 10_000_000;
+
+/// 関数呼び出しIPIのベクタ（0は未登録）
+static CALL_FUNCTION_VECTOR: AtomicU8 = AtomicU8::new(0);
+
+/// 全CPU停止を要求したCPU（NO_STOP_OWNERなら要求なし）
+static STOP_OWNER: AtomicUsize = AtomicUsize::new(NO_STOP_OWNER);
+
+/// 停止要求に応じて停止したCPU数
+static STOPPED_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// IPI操作のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpiError {
+    /// 論理CPU番号が範囲外
+    InvalidCpu(usize),
+    /// 宛先CPUがオンラインでない
+    CpuOffline(usize),
+    /// 関数呼び出しIPIのベクタが未登録（init()前）
+    NotInitialized,
+}
+
+impl core::fmt::Display for IpiError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            IpiError::InvalidCpu(cpu) => write!(f, "Invalid CPU index: {}", cpu),
+            IpiError::CpuOffline(cpu) => write!(f, "CPU {} is offline", cpu),
+            IpiError::NotInitialized => write!(f, "IPI subsystem is not initialized"),
+        }
+    }
+}
+
+/// 他CPUで実行する関数呼び出し要求
+struct CallRequest {
+    func: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+    done: AtomicBool,
+}
+
+impl CallRequest {
+    /// 関数を実行して完了を通知
+    fn run(&self) {
+        let func = self.func.lock().take();
+        if let Some(func) = func {
+            func();
+        }
+        self.done.store(true, Ordering::Release);
+    }
+}
+
+percpu! {
+    /// このCPUで実行待ちの関数呼び出し要求
+    static CALL_QUEUE: Mutex<VecDeque<Arc<CallRequest>>> = Mutex::new(VecDeque::new());
+}
+
+/// Fixed配送のICR下位32ビットを組み立てる
+fn fixed_icr(vector: u8, shorthand: u32) -> u32 {
+    icr::DELIVERY_FIXED | icr::LEVEL_ASSERT | shorthand | vector as u32
+}
+
+/// 指定CPUへIPIを送信
+///
+/// # Arguments
+/// * `cpu` - 宛先の論理CPU番号
+/// * `vector` - 割り込みベクタ
+pub fn send_to_cpu(cpu: usize, vector: u8) -> Result<(), IpiError> {
+    if cpu >= MAX_CPUS {
+        return Err(IpiError::InvalidCpu(cpu));
+    }
+    if !cpu::is_online(cpu) {
+        return Err(IpiError::CpuOffline(cpu));
+    }
+    let apic_id = cpu::apic_id_of(cpu).ok_or(IpiError::InvalidCpu(cpu))?;
+    apic::send_ipi(apic_id, fixed_icr(vector, 0));
+    Ok(())
+}
+
+/// 自分以外の全CPUへIPIを送信
+pub fn send_to_all_but_self(vector: u8) {
+    if cpu::online_cpus() <= 1 {
+        return;
+    }
+    apic::send_ipi(0, fixed_icr(vector, icr::SHORTHAND_ALL_EXCLUDING_SELF));
+}
+
+/// 自分自身へIPIを送信
+pub fn send_to_self(vector: u8) {
+    apic::send_ipi(0, fixed_icr(vector, icr::SHORTHAND_SELF));
+}
+
+/// 関数呼び出しIPIハンドラ
+///
+/// このCPU宛ての要求を全て実行する。
+fn call_function_handler(_frame: &InterruptFrame) {
+    loop {
+        let request = CALL_QUEUE.get().lock().pop_front();
+        match request {
+            Some(request) => request.run(),
+            None => break,
+        }
+    }
+}
+
+/// 関数呼び出しIPIのベクタを登録
+pub fn init() {
+    match irq::request_irq(IrqVector::Any, call_function_handler, "call-function") {
+        Ok(vector) => CALL_FUNCTION_VECTOR.store(vector, Ordering::Release),
+        Err(e) => crate::warn!("Failed to register call-function IPI: {}", e),
+    }
+}
+
+/// 指定CPU上で関数を実行
+///
+/// 関数は宛先CPUの割り込みコンテキスト（割り込み無効）で実行されるため、
+/// ブロックしてはいけない。宛先が現在のCPUの場合は割り込みを無効化して直接実行する。
+///
+/// # Arguments
+/// * `cpu` - 実行先の論理CPU番号
+/// * `func` - 実行する関数
+/// * `wait` - trueなら実行完了まで待機する
+///
+/// # Note
+/// `wait = true`で割り込み無効状態から呼び出すと、宛先CPUが同時にこちらへ
+/// 待機付きの呼び出しを行った場合にデッドロックするため、割り込み有効状態で呼び出すこと。
+pub fn smp_call_function<F>(cpu: usize, func: F, wait: bool) -> Result<(), IpiError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    if cpu >= MAX_CPUS {
+        return Err(IpiError::InvalidCpu(cpu));
+    }
+
+    let request = Arc::new(CallRequest {
+        func: Mutex::new(Some(Box::new(func))),
+        done: AtomicBool::new(false),
+    });
+
+    let queued = without_interrupts(|| {
+        if cpu == cpu::current_cpu() {
+            request.run();
+            return Ok(false);
+        }
+        if !cpu::is_online(cpu) {
+            return Err(IpiError::CpuOffline(cpu));
+        }
+        let vector = CALL_FUNCTION_VECTOR.load(Ordering::Acquire);
+        if vector == 0 {
+            return Err(IpiError::NotInitialized);
+        }
+        CALL_QUEUE.get_for(cpu).lock().push_back(request.clone());
+        send_to_cpu(cpu, vector)?;
+        Ok(true)
+    })?;
+
+    if queued && wait {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "smp_call_function(wait = true) requires interrupts enabled"
+        );
+        while !request.done.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+    }
+    Ok(())
+}
+
+/// 現在のCPUを割り込み無効状態で停止（戻らない）
+fn park_forever() -> ! {
+    loop {
+        // SAFETY: 割り込みを無効化してHLTするだけで、メモリ安全性に影響しない。
+        unsafe { core::arch::asm!("cli; hlt", options(nomem, nostack)) };
+    }
+}
+
+/// NMIで自分以外の全CPUを停止
+///
+/// パニック時に呼び出し、他CPUがシリアル出力や共有状態を変更し続けることを防ぐ。
+/// 停止したCPUはNMIハンドラ内で割り込み無効のまま停止し、復帰しない。
+/// 既に他のCPUが停止処理を開始している場合、呼び出したCPU自身が停止する。
+pub fn stop_other_cpus() {
+    let this_cpu = cpu::current_cpu();
+    match STOP_OWNER.compare_exchange(NO_STOP_OWNER, this_cpu, Ordering::AcqRel, Ordering::Acquire)
+    {
+        Ok(_) => {}
+        // 同じCPUでのネストしたパニック
+        Err(owner) if owner == this_cpu => return,
+        // 別のCPUが先にパニックした
+        Err(_) => park_forever(),
+    }
+
+    let others = cpu::online_cpus().saturating_sub(1);
+    if others == 0 || !apic::is_mapped() {
+        return;
+    }
+
+    apic::send_ipi(
+        0,
+        icr::DELIVERY_NMI | icr::LEVEL_ASSERT | icr::SHORTHAND_ALL_EXCLUDING_SELF,
+    );
+
+    for _ in 0..STOP_WAIT_SPINS {
+        if STOPPED_CPUS.load(Ordering::Acquire) >= others {
+            return;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// NMIハンドラから呼ばれ、停止要求があればこのCPUを停止する
+///
+/// # Returns
+/// 停止要求がない場合はfalse（停止要求がある場合は戻らない）
+pub fn handle_stop_nmi() -> bool {
+    let owner = STOP_OWNER.load(Ordering::Acquire);
+    if owner == NO_STOP_OWNER || owner == cpu::current_cpu() {
+        return false;
+    }
+    STOPPED_CPUS.fetch_add(1, Ordering::AcqRel);
+    park_forever();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_fixed_icr_encoding() {
+        assert_eq!(fixed_icr(0x40, 0), 0x4040);
+        assert_eq!(
+            fixed_icr(0x41, icr::SHORTHAND_ALL_EXCLUDING_SELF),
+            0x000C_4041
+        );
+        assert_eq!(fixed_icr(0x42, icr::SHORTHAND_SELF), 0x0004_4042);
+    }
+
+    #[test_case]
+    fn test_smp_call_function_on_self_runs_inline() {
+        static CALLED: AtomicBool = AtomicBool::new(false);
+        smp_call_function(
+            cpu::current_cpu(),
+            || CALLED.store(true, Ordering::SeqCst),
+            true,
+        )
+        .unwrap();
+        assert!(CALLED.load(Ordering::SeqCst));
+        assert_eq!(
+            smp_call_function(MAX_CPUS, || {}, false),
+            Err(IpiError::InvalidCpu(MAX_CPUS))
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! 外部割り込み（IRQ）管理
+//!
+//! ベクタ32-239の各ベクタに共通のアセンブリスタブを用意し、
+//! `request_irq()` で登録されたRustハンドラへディスパッチします。
+//!
+//! # 割り込み処理の流れ
+//! 1. ベクタ毎のスタブがダミーエラーコードとベクタ番号をpushし、共通エントリへジャンプ
+//! 2. 共通エントリが全汎用レジスタを保存し、`irq_dispatch()` を呼び出す
+//! 3. `irq_dispatch()` が登録済みハンドラを呼び出し、EOIを送信
+//! 4. `check_resched_on_interrupt_exit()` でsoftirq処理と再スケジューリングを行う
+//! 5. レジスタを復元してiretq
+//!
+//! # ベクタ番号の割り当て
+//! - 0-31: CPU例外（idt.rsで個別に登録）
+//! - 32-47: システム予約（`IrqVector::Fixed` でのみ取得可能。例: APIC Timer）
+//! - 48-239: デバイス用（`IrqVector::Any` で動的に割り当て）
+//! - 240-255: スプリアス割り込み等（このモジュールの管理外）
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::apic;
+
+/// 共通スタブを持つ最初のベクタ
+pub const FIRST_IRQ_VECTOR: u8 = 32;
+
+/// 共通スタブを持つ最後のベクタ
+pub const LAST_IRQ_VECTOR: u8 = 239;
+
+/// 動的割り当ての対象となる最初のベクタ（32-47はシステム予約）
+pub const FIRST_DYNAMIC_VECTOR: u8 = 48;
+
+/// 共通スタブを持つベクタ数
+const IRQ_VECTOR_COUNT: usize = (LAST_IRQ_VECTOR - FIRST_IRQ_VECTOR) as usize + 1;
+
+/// スタブ1つあたりのサイズ（global_asm!内の.balignと一致させること）
+const IRQ_STUB_SIZE: usize = 16;
+
+/// 割り込みハンドラの型
+pub type IrqHandler = fn(&InterruptFrame);
+
+/// CPUが割り込み発生時に自動でpushするスタックフレーム
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptStackFrame {
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+/// 共通エントリが構築する割り込みフレーム
+///
+/// フィールドの順序は共通エントリのpush順（逆順）と一致させる必要がある。
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    /// 割り込みベクタ番号（スタブがpush）
+    pub vector: u64,
+    /// エラーコード（外部割り込みでは常に0）
+    pub error_code: u64,
+    /// CPUがpushしたフレーム
+    pub stack_frame: InterruptStackFrame,
+}
+
+/// ベクタの要求方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqVector {
+    /// 48-239の空きベクタを自動で割り当てる
+    Any,
+    /// 指定したベクタ（32-239）を使用する
+    Fixed(u8),
+}
+
+/// IRQ操作のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// 空きベクタが存在しない
+    NoFreeVector,
+    /// 指定ベクタは既に使用中
+    VectorInUse { vector: u8 },
+    /// 共通スタブの範囲外のベクタ
+    InvalidVector { vector: u8 },
+    /// 指定ベクタにハンドラが登録されていない
+    NotRegistered { vector: u8 },
+}
+
+impl core::fmt::Display for IrqError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            IrqError::NoFreeVector => write!(f, "No free interrupt vector"),
+            IrqError::VectorInUse { vector } => write!(f, "Vector {} is already in use", vector),
+            IrqError::InvalidVector { vector } => write!(f, "Invalid IRQ vector {}", vector),
+            IrqError::NotRegistered { vector } => {
+                write!(f, "No handler registered for vector {}", vector)
+            }
+        }
+    }
+}
+
+/// ベクタ割り当て状態（256ビットのビットマップ）
+struct VectorAllocator {
+    used: [u64; 4],
+}
+
+impl VectorAllocator {
+    const fn new() -> Self {
+        Self { used: [0; 4] }
+    }
+
+    fn is_used(&self, vector: u8) -> bool {
+        self.used[vector as usize / 64] & (1 << (vector % 64)) != 0
+    }
+
+    fn set(&mut self, vector: u8) {
+        self.used[vector as usize / 64] |= 1 << (vector % 64);
+    }
+
+    fn clear(&mut self, vector: u8) {
+        self.used[vector as usize / 64] &= !(1 << (vector % 64));
+    }
+
+    /// ベクタを確保する
+    fn allocate(&mut self, request: IrqVector) -> Result<u8, IrqError> {
+        match request {
+            IrqVector::Any => {
+                let vector = (FIRST_DYNAMIC_VECTOR..=LAST_IRQ_VECTOR)
+                    .find(|&v| !self.is_used(v))
+                    .ok_or(IrqError::NoFreeVector)?;
+                self.set(vector);
+                Ok(vector)
+            }
+            IrqVector::Fixed(vector) => {
+                if !(FIRST_IRQ_VECTOR..=LAST_IRQ_VECTOR).contains(&vector) {
+                    return Err(IrqError::InvalidVector { vector });
+                }
+                if self.is_used(vector) {
+                    return Err(IrqError::VectorInUse { vector });
+                }
+                self.set(vector);
+                Ok(vector)
+            }
+        }
+    }
+
+    /// ベクタを解放する
+    fn free(&mut self, vector: u8) -> Result<(), IrqError> {
+        if !(FIRST_IRQ_VECTOR..=LAST_IRQ_VECTOR).contains(&vector) {
+            return Err(IrqError::InvalidVector { vector });
+        }
+        if !self.is_used(vector) {
+            return Err(IrqError::NotRegistered { vector });
+        }
+        self.clear(vector);
+        Ok(())
+    }
+}
+
+/// 登録情報（ベクタ割り当てとハンドラ名）
+struct IrqTable {
+    allocator: VectorAllocator,
+    names: [Option<&'static str>; IRQ_VECTOR_COUNT],
+}
+
+static IRQ_TABLE: Mutex<IrqTable> = Mutex::new(IrqTable {
+    allocator: VectorAllocator::new(),
+    names: [None; IRQ_VECTOR_COUNT],
+});
+
+/// ベクタ毎のハンドラ（関数ポインタをusizeで保持、0は未登録）
+///
+/// 割り込みコンテキストからロックなしで参照するためアトミックで保持する。
+static HANDLERS: [AtomicUsize; IRQ_VECTOR_COUNT] =
+    [const { AtomicUsize::new(0) }; IRQ_VECTOR_COUNT];
+
+fn handler_index(vector: u8) -> usize {
+    (vector - FIRST_IRQ_VECTOR) as usize
+}
+
+// ベクタ毎のスタブテーブルと共通エントリ
+//
+// 各スタブは16バイト境界に配置し、`irq_stub_table + (vector - 32) * 16` で
+// アドレスを計算できるようにする。
+// スタブのサイズ: push imm8(2) + push imm32(5) + jmp rel32(5) = 最大12バイト
+core::arch::global_asm!(
+    ".section .text",
+    ".balign 16",
+    ".global irq_stub_table",
+    "irq_stub_table:",
+    ".set irq_vector, {first}",
+    ".rept {count}",
+    ".balign 16",
+    "pushq $0",
+    "pushq $irq_vector",
+    "jmp irq_common_entry",
+    ".set irq_vector, irq_vector + 1",
+    ".endr",
+    "",
+    "irq_common_entry:",
+    // 汎用レジスタを保存（InterruptFrameのフィールド順の逆順）
+    "pushq %rax",
+    "pushq %rbx",
+    "pushq %rcx",
+    "pushq %rdx",
+    "pushq %rsi",
+    "pushq %rdi",
+    "pushq %rbp",
+    "pushq %r8",
+    "pushq %r9",
+    "pushq %r10",
+    "pushq %r11",
+    "pushq %r12",
+    "pushq %r13",
+    "pushq %r14",
+    "pushq %r15",
+    // CPUフレーム(5) + エラーコード・ベクタ(2) + GPR(15) = 22個のpushで
+    // RSPは16バイト境界に揃っている（CPUが割り込み時にRSPを16バイト境界へ揃えるため）
+    "movq %rsp, %rdi",
+    "call {dispatch}",
+    // 割り込み復帰前処理（softirq & need_reschedチェック）
+    "call {check_resched}",
+    "popq %r15",
+    "popq %r14",
+    "popq %r13",
+    "popq %r12",
+    "popq %r11",
+    "popq %r10",
+    "popq %r9",
+    "popq %r8",
+    "popq %rbp",
+    "popq %rdi",
+    "popq %rsi",
+    "popq %rdx",
+    "popq %rcx",
+    "popq %rbx",
+    "popq %rax",
+    // ベクタ番号とエラーコードを破棄
+    "addq $16, %rsp",
+    "iretq",
+    first = const FIRST_IRQ_VECTOR,
+    count = const IRQ_VECTOR_COUNT,
+    dispatch = sym irq_dispatch,
+    check_resched = sym check_resched_on_interrupt_exit_wrapper,
+    options(att_syntax)
+);
+
+unsafe extern "C" {
+    static irq_stub_table: u8;
+}
+
+/// 指定ベクタのスタブアドレスを取得
+fn stub_address(vector: u8) -> usize {
+    // SAFETY: irq_stub_tableはglobal_asm!で定義されたシンボルで、
+    // アドレスを取得するだけで読み書きは行わない。
+    let base = unsafe { &irq_stub_table as *const u8 as usize };
+    base + handler_index(vector) * IRQ_STUB_SIZE
+}
+
+/// 共通エントリから呼ばれるディスパッチ関数
+extern "C" fn irq_dispatch(frame: *const InterruptFrame) {
+    // SAFETY: frameは共通エントリがスタック上に構築したInterruptFrameを指しており、
+    // この関数の実行中は有効。
+    let frame = unsafe { &*frame };
+    let vector = frame.vector as u8;
+    crate::irq_stats::record(vector);
+
+    let handler = HANDLERS[handler_index(vector)].load(Ordering::Acquire);
+    if handler != 0 {
+        // SAFETY: HANDLERSにはrequest_irq()でIrqHandlerから変換した値のみが格納される。
+        let handler: IrqHandler = unsafe { core::mem::transmute::<usize, IrqHandler>(handler) };
+        handler(frame);
+    } else {
+        crate::warn!("Unhandled interrupt on vector {}", vector);
+    }
+
+    apic::send_eoi();
+}
+
+/// 割り込み復帰時のスケジューリングチェック（ラッパー関数）
+///
+/// need_reschedフラグがセットされている場合、スケジューラを呼び出します。
+/// RFLAGSの保存・復元はswitch_context()内部で自動的に処理されます。
+extern "C" fn check_resched_on_interrupt_exit_wrapper() {
+    crate::sched::check_resched_on_interrupt_exit();
+}
+
+/// 全IRQベクタのIDTエントリを共通スタブに設定
+///
+/// idt::init()から呼び出される。
+pub(crate) fn install_stubs() {
+    for vector in FIRST_IRQ_VECTOR..=LAST_IRQ_VECTOR {
+        crate::idt::set_idt_entry(vector, stub_address(vector));
+    }
+}
+
+/// 割り込みハンドラを登録
+///
+/// # Arguments
+/// * `vector` - 使用するベクタ（`IrqVector::Any`で自動割り当て）
+/// * `handler` - 割り込み発生時に呼ばれるハンドラ（EOIは共通エントリが送信する）
+/// * `name` - 統計表示等に使う名前
+///
+/// # Returns
+/// 成功時は割り当てられたベクタ番号
+pub fn request_irq(
+    vector: IrqVector,
+    handler: IrqHandler,
+    name: &'static str,
+) -> Result<u8, IrqError> {
+    crate::io::without_interrupts(|| {
+        let mut table = IRQ_TABLE.lock();
+        let vector = table.allocator.allocate(vector)?;
+        let index = handler_index(vector);
+        table.names[index] = Some(name);
+        HANDLERS[index].store(handler as *const () as usize, Ordering::Release);
+        Ok(vector)
+    })
+}
+
+/// 割り込みハンドラを解除し、ベクタを解放
+pub fn free_irq(vector: u8) -> Result<(), IrqError> {
+    crate::io::without_interrupts(|| {
+        let mut table = IRQ_TABLE.lock();
+        table.allocator.free(vector)?;
+        let index = handler_index(vector);
+        table.names[index] = None;
+        HANDLERS[index].store(0, Ordering::Release);
+        Ok(())
+    })
+}
+
+/// 指定ベクタに登録されたハンドラ名を取得
+pub fn irq_name(vector: u8) -> Option<&'static str> {
+    if !(FIRST_IRQ_VECTOR..=LAST_IRQ_VECTOR).contains(&vector) {
+        return None;
+    }
+    crate::io::without_interrupts(|| IRQ_TABLE.lock().names[handler_index(vector)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_allocate_any_starts_at_dynamic_range() {
+        let mut allocator = VectorAllocator::new();
+        assert_eq!(allocator.allocate(IrqVector::Any), Ok(FIRST_DYNAMIC_VECTOR));
+        assert_eq!(
+            allocator.allocate(IrqVector::Any),
+            Ok(FIRST_DYNAMIC_VECTOR + 1)
+        );
+    }
+
+    #[test_case]
+    fn test_allocate_fixed_conflict_and_free() {
+        let mut allocator = VectorAllocator::new();
+        assert_eq!(allocator.allocate(IrqVector::Fixed(32)), Ok(32));
+        assert_eq!(
+            allocator.allocate(IrqVector::Fixed(32)),
+            Err(IrqError::VectorInUse { vector: 32 })
+        );
+        assert_eq!(allocator.free(32), Ok(()));
+        assert_eq!(
+            allocator.free(32),
+            Err(IrqError::NotRegistered { vector: 32 })
+        );
+        assert_eq!(allocator.allocate(IrqVector::Fixed(32)), Ok(32));
+    }
+
+    #[test_case]
+    fn test_allocate_rejects_out_of_range() {
+        let mut allocator = VectorAllocator::new();
+        assert_eq!(
+            allocator.allocate(IrqVector::Fixed(31)),
+            Err(IrqError::InvalidVector { vector: 31 })
+        );
+        assert_eq!(
+            allocator.allocate(IrqVector::Fixed(240)),
+            Err(IrqError::InvalidVector { vector: 240 })
+        );
+    }
+
+    #[test_case]
+    fn test_allocate_any_exhaustion() {
+        let mut allocator = VectorAllocator::new();
+        for _ in FIRST_DYNAMIC_VECTOR..=LAST_IRQ_VECTOR {
+            assert!(allocator.allocate(IrqVector::Any).is_ok());
+        }
+        assert_eq!(
+            allocator.allocate(IrqVector::Any),
+            Err(IrqError::NoFreeVector)
+        );
+    }
+}
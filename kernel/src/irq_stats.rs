@@ -0,0 +1,195 @@
+//! 割り込み統計
+//!
+//! CPU毎・ベクタ毎の割り込み発生回数を記録します。
+//! 外部割り込み（irqモジュールの共通エントリ）、CPU例外、スプリアス割り込みの
+//! 全てを同じテーブルで数え、Linuxの`/proc/interrupts`風のテキストで出力できます。
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::apic;
+use crate::cpu::{self, MAX_CPUS};
+use crate::irq;
+use crate::pic;
+
+/// ベクタ数
+const VECTOR_COUNT: usize = 256;
+
+/// CPU例外の数（ベクタ0-31）
+const EXCEPTION_VECTOR_COUNT: u8 = 32;
+
+/// CPU毎・ベクタ毎の発生回数
+static COUNTS: [[AtomicU64; VECTOR_COUNT]; MAX_CPUS] =
+    [const { [const { AtomicU64::new(0) }; VECTOR_COUNT] }; MAX_CPUS];
+
+/// CPU例外の略称（ベクタ0-31）
+const EXCEPTION_NAMES: [&str; EXCEPTION_VECTOR_COUNT as usize] = [
+    "#DE", "#DB", "NMI", "#BP", "#OF", "#BR", "#UD", "#NM", "#DF", "CSO", "#TS", "#NP", "#SS",
+    "#GP", "#PF", "RSV", "#MF", "#AC", "#MC", "#XM", "#VE", "#CP", "RSV", "RSV", "RSV", "RSV",
+    "RSV", "RSV", "#HV", "#VC", "#SX", "RSV",
+];
+
+/// 現在のCPUで指定ベクタの発生を記録
+///
+/// 割り込みコンテキストから呼ばれるため、ロックを取らずアトミック操作のみで更新する。
+pub fn record(vector: u8) {
+    COUNTS[cpu::current_cpu()][vector as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// 指定ベクタがスプリアス割り込みかどうか
+pub fn is_spurious_vector(vector: u8) -> bool {
+    vector == apic::SPURIOUS_VECTOR || vector == pic::MASTER_SPURIOUS_VECTOR
+}
+
+/// 指定ベクタがCPU例外かどうか
+pub fn is_exception_vector(vector: u8) -> bool {
+    vector < EXCEPTION_VECTOR_COUNT
+}
+
+/// 指定CPU・ベクタの発生回数を取得
+pub fn count(cpu: usize, vector: u8) -> u64 {
+    if cpu >= MAX_CPUS {
+        return 0;
+    }
+    COUNTS[cpu][vector as usize].load(Ordering::Relaxed)
+}
+
+/// 全CPU合計の発生回数を取得
+pub fn total(vector: u8) -> u64 {
+    (0..cpu::cpu_count()).map(|cpu| count(cpu, vector)).sum()
+}
+
+/// 全CPU合計のスプリアス割り込み回数を取得
+pub fn spurious_total() -> u64 {
+    (0..=u8::MAX)
+        .filter(|&v| is_spurious_vector(v))
+        .map(total)
+        .sum()
+}
+
+/// 全CPU合計のCPU例外発生回数を取得
+pub fn exception_total() -> u64 {
+    (0..EXCEPTION_VECTOR_COUNT).map(total).sum()
+}
+
+/// ベクタの表示名を取得
+pub fn vector_label(vector: u8) -> &'static str {
+    if is_exception_vector(vector) {
+        EXCEPTION_NAMES[vector as usize]
+    } else if is_spurious_vector(vector) {
+        "spurious"
+    } else {
+        irq::irq_name(vector).unwrap_or("-")
+    }
+}
+
+/// `/proc/interrupts`風のレポートを書き込む
+///
+/// 一度も発生していないベクタは省略する。
+pub fn write_report<W: Write>(w: &mut W) -> fmt::Result {
+    let cpus = cpu::cpu_count();
+
+    write!(w, "     ")?;
+    for cpu in 0..cpus {
+        write!(w, " {:>10}", CpuLabel(cpu))?;
+    }
+    writeln!(w)?;
+
+    for vector in 0..=u8::MAX {
+        if total(vector) == 0 {
+            continue;
+        }
+        write!(w, "{:>4}:", vector)?;
+        for cpu in 0..cpus {
+            write!(w, " {:>10}", count(cpu, vector))?;
+        }
+        writeln!(w, "   {}", vector_label(vector))?;
+    }
+
+    writeln!(w, " SPU: {:>10}   spurious interrupts", spurious_total())?;
+    writeln!(w, " EXC: {:>10}   exceptions", exception_total())
+}
+
+/// 割り込み統計をシリアルに出力
+pub fn dump() {
+    crate::println!("Interrupt statistics:");
+    let _ = write_report(&mut SerialWriter);
+}
+
+/// CPU列ヘッダ（"CPU0"など）
+struct CpuLabel(usize);
+
+impl fmt::Display for CpuLabel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // 幅指定を有効にするため、一度文字列として組み立ててからpadする
+        let mut buf = [0u8; 8];
+        let mut cursor = BufWriter {
+            buf: &mut buf,
+            len: 0,
+        };
+        write!(cursor, "CPU{}", self.0)?;
+        let len = cursor.len;
+        f.pad(core::str::from_utf8(&buf[..len]).unwrap_or("CPU?"))
+    }
+}
+
+/// 固定長バッファへの書き込み
+struct BufWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for BufWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// シリアル出力へのfmt::Writeアダプタ
+struct SerialWriter;
+
+impl Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::print!("{}", s);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_exception_labels() {
+        assert_eq!(vector_label(0), "#DE");
+        assert_eq!(vector_label(14), "#PF");
+        assert!(is_exception_vector(31));
+        assert!(!is_exception_vector(32));
+    }
+
+    #[test_case]
+    fn test_spurious_vectors() {
+        assert!(is_spurious_vector(0xFF));
+        assert!(is_spurious_vector(0xF7));
+        assert!(!is_spurious_vector(apic::TIMER_INTERRUPT_VECTOR));
+        assert_eq!(vector_label(0xFF), "spurious");
+    }
+
+    #[test_case]
+    fn test_cpu_label_padding() {
+        let mut buf = [0u8; 16];
+        let mut w = BufWriter {
+            buf: &mut buf,
+            len: 0,
+        };
+        write!(w, "{:>6}", CpuLabel(3)).unwrap();
+        let len = w.len;
+        assert_eq!(&buf[..len], b"  CPU3");
+    }
+}
@@ -12,27 +12,44 @@ extern crate alloc;
 
 // 全モジュールを公開
 pub mod acpi;
+pub mod acpi_pm;
 pub mod addr;
 pub mod allocator;
 pub mod apic;
+pub mod cpu;
+pub mod debug_console;
 pub mod debug_overlay;
+pub mod exception;
+pub mod executor;
 pub mod gdt;
 pub mod graphics;
 pub mod hpet;
 pub mod idt;
 pub mod io;
+pub mod ioapic;
+pub mod ipi;
+pub mod irq;
+pub mod irq_stats;
 pub mod msi;
 pub mod msr;
 pub mod mtrr;
 pub mod paging;
 pub mod pci;
+pub mod percpu;
+pub mod pic;
 pub mod pit;
 pub mod sched;
 pub mod serial;
+pub mod smp;
 pub mod stack;
 pub mod sync;
+pub mod time;
 pub mod timer;
 pub mod timer_device;
+pub mod timer_wheel;
+pub mod tlb;
+pub mod tsc;
+pub mod workqueue;
 
 // テストフレームワーク
 pub mod test_runner;
@@ -10,18 +10,30 @@ extern crate alloc;
 use vitros_kernel::acpi;
 use vitros_kernel::allocator;
 use vitros_kernel::apic;
+use vitros_kernel::cpu;
+use vitros_kernel::debug_console;
 use vitros_kernel::debug_overlay;
+use vitros_kernel::exception;
 use vitros_kernel::gdt;
 use vitros_kernel::graphics;
 use vitros_kernel::idt;
+use vitros_kernel::ioapic;
+use vitros_kernel::ipi;
 use vitros_kernel::mtrr;
 use vitros_kernel::paging;
 use vitros_kernel::pci;
+use vitros_kernel::percpu;
 use vitros_kernel::sched;
+use vitros_kernel::serial;
+use vitros_kernel::smp;
+use vitros_kernel::time;
 use vitros_kernel::timer;
+use vitros_kernel::tlb;
+use vitros_kernel::tsc;
+use vitros_kernel::workqueue;
 
 // マクロをインポート
-use vitros_kernel::{error, info, print, println, warn};
+use vitros_kernel::{error, info, print, warn};
 
 // 後方互換性のためのエイリアス
 use sched as task;
@@ -46,8 +58,12 @@ const KERNEL_VMA: u64 = 0xFFFF800000000000;
 // パニックハンドラ
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("\n!!! KERNEL PANIC !!!");
-    println!("{}", info);
+    // 他CPUをNMIで停止してから、例外と同じ形式のレポート
+    // （制御レジスタ・現在のタスク・バックトレース）を出力
+    // 停止し損ねたCPUの出力が混ざらないよう、以降はこのCPUの出力のみを送信する
+    ipi::stop_other_cpus();
+    serial::begin_panic_output();
+    exception::report_panic(info);
     loop {
         hlt()
     }
@@ -83,11 +99,8 @@ pub extern "C" fn boot_complete() {
 extern "C" fn idle_task() -> ! {
     info!("[Idle] Idle task started");
     loop {
-        // SAFETY: hlt命令はCPUを低消費電力状態にする特権命令。
-        // 次の割り込みで復帰するため、メモリ安全性に影響しない。
-        unsafe {
-            asm!("hlt");
-        }
+        // 次のイベントまでtickを止めて休止する（tickless idle）
+        time::nohz::idle();
     }
 }
 
@@ -231,6 +244,12 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
     // タスクシステムを初期化
     task::init();
 
+    // IPI（CPU間関数呼び出し）を初期化
+    ipi::init();
+
+    // TLB shootdown用IPIを初期化（APの起動前に必要）
+    tlb::init();
+
     // ACPI を初期化（失敗してもカーネルは継続動作可能）
     if let Err(e) = acpi::init(&boot_info) {
         info!(
@@ -250,10 +269,21 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
     apic::init(apic_base).expect("Failed to initialize Local APIC");
     info!("Local APIC initialized");
 
+    // BSPを論理CPU 0として登録
+    cpu::register_current_cpu().expect("Failed to register BSP");
+
     // APIC Timerをキャリブレーション（割り込み無効状態で実行）
     info!("Calibrating APIC Timer...");
     apic::calibrate_timer().expect("Failed to calibrate APIC Timer");
 
+    // TSCをキャリブレーション（失敗した場合は他のクロックソースを使用）
+    if let Err(e) = tsc::calibrate() {
+        warn!("TSC calibration failed: {}", e);
+    }
+
+    // クロックソース・クロックイベントデバイスを登録し、最適なクロックソースを選択
+    time::init();
+
     // MTRR/PAT設定をダンプ（デバッグ用）
     mtrr::dump();
 
@@ -343,13 +373,34 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
 
         info!("Heap initialized successfully");
 
+        // BSPのPer-CPU領域を初期化（以降、現在のCPUはGSベースから取得される）
+        percpu::init_cpu(0).expect("Failed to initialize per-CPU area");
+
         // タイマーシステムを初期化（ヒープが必要）
         const TIMER_FREQUENCY_HZ: u64 = 250;
         timer::init(TIMER_FREQUENCY_HZ);
 
-        // APIC Timerを初期化（250Hz = 4msタイムスライス）
-        info!("Initializing APIC Timer...");
-        apic::init_timer(TIMER_FREQUENCY_HZ as u32).expect("Failed to initialize APIC Timer");
+        // I/O APICを初期化（全エントリをマスクした状態で待機）
+        info!("Initializing I/O APIC...");
+        match ioapic::init() {
+            Ok(()) => info!("I/O APIC initialized"),
+            Err(e) => warn!("I/O APIC initialization failed: {}", e),
+        }
+
+        // タイマー割り込みを開始（250Hz = 4msタイムスライス）
+        // Local APIC Timerが使えない場合はI/O APIC経由でHPET/PITを使用する
+        info!("Starting timer tick...");
+        time::clockevent::start_periodic(TIMER_FREQUENCY_HZ as u32)
+            .expect("Failed to start timer tick");
+
+        // APを起動（各APはGDT/TSS・IDT・Local APIC・APIC Timerを初期化してアイドル待機）
+        info!("Starting application processors...");
+        if let Err(e) = smp::init(
+            &boot_info.memory_map[..safe_count],
+            TIMER_FREQUENCY_HZ as u32,
+        ) {
+            warn!("SMP initialization failed: {}", e);
+        }
 
         // =================================================================
         // Compositorを初期化
@@ -384,6 +435,18 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
             Box::new(task::Task::new_idle("Idle", idle_task).expect("Failed to create idle task"));
         task::add_task(*idle);
 
+        // 終了したタスクの回収タスク
+        task::start_reaper();
+
+        // 遅延処理用のワーカータスク
+        workqueue::init(workqueue::DEFAULT_WORKERS);
+
+        // シリアルから統計のダンプを要求できるようにする（'?'でコマンド一覧）
+        match debug_console::init() {
+            Ok(()) => info!("Debug console ready on COM1 (press '?' for commands)"),
+            Err(e) => warn!("Debug console unavailable: {}", e),
+        }
+
         // 可視化モード: 専用の初期化処理へ（戻らない）
         #[cfg(feature = "visualize-pipeline")]
         pipeline_visualization::start_visualization();
@@ -524,8 +587,6 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
     info!("Entering main loop");
     boot_complete();
 
-    // KernelMainタスクは自身をブロックして停止
-    loop {
-        task::block_current_task();
-    }
+    // KernelMainタスクの役目は終わったので終了する
+    task::exit(0);
 }
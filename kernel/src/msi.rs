@@ -2,6 +2,9 @@
 //!
 //! PCIデバイスのMSI割り込みを設定・管理します。
 
+use alloc::vec::Vec;
+
+use crate::irq::{self, IrqError, IrqHandler, IrqVector};
 use crate::pci::{PCI_CONFIG, PciConfigAccess, PciDevice, capability_id};
 
 /// MSI Capability レジスタオフセット（Capability先頭からの相対）
@@ -119,6 +122,14 @@ pub enum MsiError {
     TooManyVectors { requested: usize, available: u16 },
     /// MMIOマッピング失敗（MSI-X）
     MappingFailed,
+    /// 割り込みベクタの確保に失敗
+    VectorAllocationFailed(IrqError),
+}
+
+impl From<IrqError> for MsiError {
+    fn from(e: IrqError) -> Self {
+        MsiError::VectorAllocationFailed(e)
+    }
 }
 
 /// MSI設定情報
@@ -561,6 +572,70 @@ pub fn configure_msix(device: &PciDevice, vectors: &[u8]) -> Result<MsixConfig,
     Ok(config)
 }
 
+/// ベクタを動的に割り当て、ハンドラを登録してMSIを設定
+///
+/// # Arguments
+/// * `device` - MSIを設定するPCIデバイス
+/// * `handler` - 割り込みハンドラ
+/// * `name` - 割り込み名
+///
+/// # Returns
+/// 成功時はMsiConfig（割り当てられたベクタを含む）、失敗時はMsiError
+///
+/// # Notes
+/// MSIの設定に失敗した場合、確保したベクタは解放されます。
+pub fn request_msi(
+    device: &PciDevice,
+    handler: IrqHandler,
+    name: &'static str,
+) -> Result<MsiConfig, MsiError> {
+    let vector = irq::request_irq(IrqVector::Any, handler, name)?;
+    configure_msi(device, vector).inspect_err(|_| {
+        let _ = irq::free_irq(vector);
+    })
+}
+
+/// エントリ毎にベクタを動的に割り当て、ハンドラを登録してMSI-Xを設定
+///
+/// # Arguments
+/// * `device` - MSI-Xを設定するPCIデバイス
+/// * `handlers` - 各エントリに対応する（ハンドラ, 名前）のスライス
+///
+/// # Returns
+/// 成功時はMsixConfigと割り当てられたベクタのリスト、失敗時はMsiError
+///
+/// # Notes
+/// 途中で失敗した場合、それまでに確保したベクタは全て解放されます。
+pub fn request_msix(
+    device: &PciDevice,
+    handlers: &[(IrqHandler, &'static str)],
+) -> Result<(MsixConfig, Vec<u8>), MsiError> {
+    let mut vectors = Vec::with_capacity(handlers.len());
+    let free_all = |vectors: &[u8]| {
+        for &v in vectors {
+            let _ = irq::free_irq(v);
+        }
+    };
+
+    for &(handler, name) in handlers {
+        match irq::request_irq(IrqVector::Any, handler, name) {
+            Ok(v) => vectors.push(v),
+            Err(e) => {
+                free_all(&vectors);
+                return Err(e.into());
+            }
+        }
+    }
+
+    match configure_msix(device, &vectors) {
+        Ok(config) => Ok((config, vectors)),
+        Err(e) => {
+            free_all(&vectors);
+            Err(e)
+        }
+    }
+}
+
 /// MSI-Xを無効化
 ///
 /// # Arguments
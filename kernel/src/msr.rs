@@ -23,6 +23,26 @@ pub const IA32_MTRR_PHYSMASK0: u32 = 0x201;
 /// Page Attribute Table - PAT設定
 pub const IA32_PAT: u32 = 0x277;
 
+// =============================================================================
+// セグメントベース関連 MSR アドレス
+// =============================================================================
+
+/// GSセグメントのベースアドレス
+pub const IA32_GS_BASE: u32 = 0xC000_0101;
+
+/// SWAPGSで入れ替えられるGSベースアドレス
+pub const IA32_KERNEL_GS_BASE: u32 = 0xC000_0102;
+
+// =============================================================================
+// APIC 関連 MSR アドレス
+// =============================================================================
+
+/// Local APICのベースアドレスと動作モード（有効化・x2APICモード）
+pub const IA32_APIC_BASE: u32 = 0x1B;
+
+/// TSC-deadlineモードでタイマー割り込みを発生させるTSC値（0で解除）
+pub const IA32_TSC_DEADLINE: u32 = 0x6E0;
+
 /// MSRを読み込む
 ///
 /// # Safety
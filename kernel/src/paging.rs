@@ -5,6 +5,8 @@
 use core::arch::asm;
 use core::ptr::{addr_of, addr_of_mut};
 
+use crate::tlb::{self, TlbFlushBatch};
+
 /// ハイヤーハーフカーネルのベースアドレス（上位カノニカルアドレス空間）
 /// x86_64のカノニカルアドレス空間の上位半分の開始位置
 pub const KERNEL_VIRTUAL_BASE: u64 = 0xFFFF_8000_0000_0000;
@@ -87,7 +89,7 @@ const PHYSICAL_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
 
 /// カーネル空間のPML4エントリインデックス (0xFFFF_8000_0000_0000に対応)
 /// x86_64では仮想アドレスのビット47:39がPML4インデックスとなる
-const PML4_KERNEL_INDEX: usize = 256;
+pub const PML4_KERNEL_INDEX: usize = 256;
 
 /// アドレスが2MB境界にアライメントされているかチェック
 #[inline]
@@ -276,9 +278,7 @@ pub fn write_cr3(pml4_addr: u64) {
 /// CR3レジスタをリロード（TLBフラッシュ）
 ///
 /// 現在のCPUのTLBのみをフラッシュする。
-///
-/// # TODO: マルチコア対応
-/// マルチコア環境では他CPUへのIPIによるTLB shootdownが必要。
+/// 他CPUのTLBも無効化する必要がある場合は`tlb::TlbFlushBatch`を使用すること。
 pub fn reload_cr3() {
     let cr3 = read_cr3();
     write_cr3(cr3);
@@ -722,11 +722,15 @@ pub fn map_mmio(phys_addr: u64, size: u64) -> Result<u64, PagingError> {
                 .entry(page_idx_in_pt)
                 .set(addr, uc_flags);
         }
-
-        // TLBフラッシュ
-        reload_cr3();
     }
 
+    // 既存マッピングの属性を変更した可能性があるため、全CPUのTLBを無効化
+    tlb::flush_range(
+        KERNEL_VIRTUAL_BASE + phys_addr,
+        (page_count * PAGE_SIZE) as u64,
+        PAGE_SIZE as u64,
+    );
+
     // 仮想アドレスを計算して返す
     let virt_addr = phys_to_virt(phys_addr)?;
 
@@ -748,13 +752,19 @@ pub fn map_mmio(phys_addr: u64, size: u64) -> Result<u64, PagingError> {
 /// クリア（Present=0）にする。これにより、PDエントリをヒュージページに
 /// 変更しても、元のPTエントリが不整合な状態にならない。
 ///
+/// クリアした範囲は`batch`に追加されるため、呼び出し元で`flush()`すること。
+///
 /// # Arguments
 /// * `phys_addr` - クリアする2MB範囲の開始物理アドレス（2MB境界）
+/// * `batch` - TLB無効化対象を追加するバッチ
 ///
 /// # Errors
 /// * `PagingError::InvalidAddress` - アドレスが2MB境界にアライメントされていない場合
 /// * `PagingError::AddressOutOfRange` - アドレスがサポート範囲外の場合
-fn clear_4kb_mappings_for_huge_page(phys_addr: u64) -> Result<(), PagingError> {
+fn clear_4kb_mappings_for_huge_page(
+    phys_addr: u64,
+    batch: &mut TlbFlushBatch,
+) -> Result<(), PagingError> {
     use crate::info;
 
     // 2MB境界アライメントチェック
@@ -796,6 +806,12 @@ fn clear_4kb_mappings_for_huge_page(phys_addr: u64) -> Result<(), PagingError> {
         (*pd_high)[pd_array_idx].entry(pd_entry_idx).set(0, 0);
     }
 
+    batch.add(
+        KERNEL_VIRTUAL_BASE + phys_addr,
+        HUGE_PAGE_SIZE_2MB as u64,
+        PAGE_SIZE as u64,
+    );
+
     info!(
         "Cleared 4KB mappings for huge page at 0x{:X} (PT index {}, PD[{}][{}])",
         phys_addr, pt_array_idx, pd_array_idx, pd_entry_idx
@@ -834,6 +850,20 @@ fn clear_4kb_mappings_for_huge_page(phys_addr: u64) -> Result<(), PagingError> {
 /// * `PagingError::AddressOutOfRange` - ページテーブルのインデックスが範囲外の場合
 /// * `PagingError::ExistingMappingConflict` - 既存のPT参照が存在する場合
 pub fn map_huge_2mb(phys_addr: u64, additional_flags: u64) -> Result<u64, PagingError> {
+    let mut batch = TlbFlushBatch::new();
+    let result = map_huge_2mb_batched(phys_addr, additional_flags, &mut batch);
+    batch.flush();
+    result
+}
+
+/// 2MBヒュージページをマッピングし、TLB無効化対象を`batch`に追加する
+///
+/// 複数のページテーブル変更をまとめてTLB shootdownするための`map_huge_2mb`の内部実装。
+fn map_huge_2mb_batched(
+    phys_addr: u64,
+    additional_flags: u64,
+    batch: &mut TlbFlushBatch,
+) -> Result<u64, PagingError> {
     use crate::info;
 
     // 割り込みが無効であることを確認
@@ -874,11 +904,15 @@ pub fn map_huge_2mb(phys_addr: u64, additional_flags: u64) -> Result<u64, Paging
         (*pd_high)[pd_array_idx]
             .entry(pd_entry_idx)
             .set(phys_addr, huge_flags);
-
-        // TLBフラッシュ
-        reload_cr3();
     }
 
+    // 既存のヒュージページを置き換えた可能性があるため、TLB無効化対象に追加
+    batch.add(
+        KERNEL_VIRTUAL_BASE + phys_addr,
+        HUGE_PAGE_SIZE_2MB as u64,
+        HUGE_PAGE_SIZE_2MB as u64,
+    );
+
     // 仮想アドレスを計算して返す
     let virt_addr = phys_to_virt(phys_addr)?;
 
@@ -933,11 +967,15 @@ pub fn unmap_huge_2mb(phys_addr: u64) -> Result<(), PagingError> {
 
         // PDエントリをクリア（Present=0）
         (*pd_high)[pd_array_idx].entry(pd_entry_idx).set(0, 0);
-
-        // TLBフラッシュ
-        reload_cr3();
     }
 
+    // 全CPUのTLBから古いエントリを無効化
+    tlb::flush_range(
+        KERNEL_VIRTUAL_BASE + phys_addr,
+        HUGE_PAGE_SIZE_2MB as u64,
+        HUGE_PAGE_SIZE_2MB as u64,
+    );
+
     info!("Huge 2MB page unmapped: phys=0x{:X}", phys_addr);
 
     Ok(())
@@ -1079,11 +1117,15 @@ pub fn map_huge_1gb(phys_addr: u64, additional_flags: u64) -> Result<u64, Paging
 
         // PDPエントリにHugePageフラグ付きで物理アドレスを設定
         (*pdp_high).entry(pdp_entry_idx).set(phys_addr, huge_flags);
-
-        // TLBフラッシュ
-        reload_cr3();
     }
 
+    // 既存のヒュージページを置き換えた可能性があるため、全CPUのTLBを無効化
+    tlb::flush_range(
+        KERNEL_VIRTUAL_BASE + phys_addr,
+        HUGE_PAGE_SIZE_1GB as u64,
+        HUGE_PAGE_SIZE_1GB as u64,
+    );
+
     // 仮想アドレスを計算して返す
     let virt_addr = phys_to_virt(phys_addr)?;
 
@@ -1136,11 +1178,15 @@ pub fn unmap_huge_1gb(phys_addr: u64) -> Result<(), PagingError> {
 
         // PDPエントリをクリア（Present=0）
         (*pdp_high).entry(pdp_entry_idx).set(0, 0);
-
-        // TLBフラッシュ
-        reload_cr3();
     }
 
+    // 全CPUのTLBから古いエントリを無効化
+    tlb::flush_range(
+        KERNEL_VIRTUAL_BASE + phys_addr,
+        HUGE_PAGE_SIZE_1GB as u64,
+        HUGE_PAGE_SIZE_1GB as u64,
+    );
+
     info!("Huge 1GB page unmapped: phys=0x{:X}", phys_addr);
 
     Ok(())
@@ -1196,15 +1242,22 @@ pub fn map_framebuffer_huge(fb_base: u64, fb_size: u64) -> Result<u64, PagingErr
 
     // 各2MBページをマッピング（MMIO領域のためCacheDisableフラグを設定）
     // 既存の4KBマッピングがある場合は先にクリアする
-    for i in 0..huge_page_count {
+    // TLBの無効化は全ページの変更後にまとめて1回行う
+    let mut batch = TlbFlushBatch::new();
+    let result = (0..huge_page_count).try_for_each(|i| {
         let page_addr = fb_base + (i as u64 * HUGE_PAGE_SIZE_2MB as u64);
         // 既存の4KBマッピングをクリアしてからヒュージページをマッピング
-        clear_4kb_mappings_for_huge_page(page_addr)?;
-        map_huge_2mb(
+        clear_4kb_mappings_for_huge_page(page_addr, &mut batch)?;
+        map_huge_2mb_batched(
             page_addr,
             PageTableFlags::CacheDisable as u64 | PageTableFlags::NoExecute as u64,
+            &mut batch,
         )?;
-    }
+        Ok(())
+    });
+    // 途中で失敗した場合も、それまでの変更を反映させるためにフラッシュする
+    batch.flush();
+    result?;
 
     info!("Framebuffer huge page mapping complete");
     phys_to_virt(fb_base)
@@ -0,0 +1,258 @@
+//! Per-CPUデータ
+//!
+//! CPU毎に1つのデータブロック（`PerCpuBlock`）をヒープに確保し、
+//! IA32_GS_BASE / IA32_KERNEL_GS_BASE にそのアドレスを設定します。
+//! 現在のCPU番号は`gs:[offset]`の1命令で取得できるため、Local APICの
+//! ID読み取り + 線形探索よりも高速に現在のCPUを特定できます。
+//!
+//! # `percpu!`マクロ
+//! `percpu!`で宣言した変数はCPU数分の実体を持ち、`get()`で現在のCPUの実体を参照します。
+//!
+//! ```ignore
+//! percpu! {
+//!     /// 再スケジューリング要求フラグ
+//!     static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+//! }
+//!
+//! NEED_RESCHED.get().store(true, Ordering::Release);
+//! ```
+//!
+//! # 初期化前の動作
+//! BSPの`init_cpu(0)`（ヒープ初期化後）が完了するまでは`PERCPU_READY`がfalseで、
+//! 現在のCPU番号はLocal APIC IDからの探索（`cpu::current_cpu()`の低速パス）で求めます。
+//! APは`ap_main`の先頭で自身のブロックを初期化するため、それ以前にPer-CPU変数へ
+//! アクセスしてはいけません。
+//!
+//! # 注意
+//! `get()`が返す参照は呼び出し時点のCPUのものです。タスクが別CPUへ移動しうる場合、
+//! 複数回のアクセスを一貫させるには割り込みを無効化した状態で使用してください。
+
+use alloc::boxed::Box;
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+use crate::cpu::{self, MAX_CPUS};
+use crate::msr;
+
+/// Per-CPU領域が利用可能かどうか（BSPの初期化完了後にtrue）
+static PERCPU_READY: AtomicBool = AtomicBool::new(false);
+
+/// 各CPUのPer-CPUブロック（論理CPU番号でインデックス）
+static BLOCKS: [AtomicPtr<PerCpuBlock>; MAX_CPUS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CPUS];
+
+/// CPU毎のデータブロック（GSベースが指す）
+///
+/// フィールドのオフセットは`gs:[offset]`アクセスで使用するため、
+/// 並びを変更する場合は`this_cpu_id()`等のオフセットも合わせて変更すること。
+#[repr(C)]
+#[derive(Debug)]
+pub struct PerCpuBlock {
+    /// ブロック自身のアドレス（gs:[0]）
+    self_ptr: u64,
+    /// 論理CPU番号（gs:[8]）
+    cpu_id: usize,
+    /// Local APIC ID（gs:[16]）
+    apic_id: u32,
+}
+
+impl PerCpuBlock {
+    /// 論理CPU番号
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id
+    }
+
+    /// Local APIC ID
+    pub fn apic_id(&self) -> u32 {
+        self.apic_id
+    }
+}
+
+/// Per-CPU関連のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerCpuError {
+    /// 論理CPU番号が範囲外
+    InvalidCpu(usize),
+    /// 指定CPUのAPIC IDが未登録
+    NotRegistered(usize),
+}
+
+impl core::fmt::Display for PerCpuError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            PerCpuError::InvalidCpu(cpu) => write!(f, "Invalid CPU index: {}", cpu),
+            PerCpuError::NotRegistered(cpu) => write!(f, "CPU {} is not registered", cpu),
+        }
+    }
+}
+
+/// 現在のCPUのPer-CPUブロックを確保し、GSベースに設定
+///
+/// BSPはヒープ初期化後に`init_cpu(0)`を、各APは`ap_main`の先頭で呼び出す。
+/// BSPの呼び出しでPer-CPU領域が有効になる。
+///
+/// # Arguments
+/// * `cpu` - 現在のCPUの論理CPU番号（`cpu::register_cpu()`で割り当て済みであること）
+pub fn init_cpu(cpu: usize) -> Result<(), PerCpuError> {
+    if cpu >= MAX_CPUS {
+        return Err(PerCpuError::InvalidCpu(cpu));
+    }
+    let apic_id = cpu::apic_id_of(cpu).ok_or(PerCpuError::NotRegistered(cpu))?;
+
+    let block = Box::leak(Box::new(PerCpuBlock {
+        self_ptr: 0,
+        cpu_id: cpu,
+        apic_id,
+    }));
+    block.self_ptr = block as *mut PerCpuBlock as u64;
+    let addr = block.self_ptr;
+    BLOCKS[cpu].store(block, Ordering::SeqCst);
+
+    // SAFETY: IA32_GS_BASE / IA32_KERNEL_GS_BASE はx86_64で常に存在するMSRで、
+    // addrはリークさせたため以降ずっと有効なPerCpuBlockを指す。
+    // ユーザーモードは存在しないためSWAPGSは使用されず、両方に同じ値を設定する。
+    unsafe {
+        msr::write(msr::IA32_GS_BASE, addr);
+        msr::write(msr::IA32_KERNEL_GS_BASE, addr);
+    }
+
+    if cpu == 0 {
+        PERCPU_READY.store(true, Ordering::Release);
+    }
+    Ok(())
+}
+
+/// Per-CPU領域が利用可能かどうか
+#[inline]
+pub fn is_ready() -> bool {
+    PERCPU_READY.load(Ordering::Acquire)
+}
+
+/// 現在のCPUの論理CPU番号をGSベースから取得（高速パス）
+///
+/// `is_ready()`がtrueで、かつ現在のCPUで`init_cpu()`済みであること。
+/// 通常は`cpu::current_cpu()`を使用する。
+#[inline]
+pub fn this_cpu_id() -> usize {
+    let cpu: usize;
+    // SAFETY: GSベースはinit_cpu()で設定したPerCpuBlockを指しており、
+    // gs:[8]はcpu_idフィールド。読み取りのみで副作用はない。
+    unsafe {
+        asm!("mov {}, gs:[8]", out(reg) cpu, options(nostack, preserves_flags, readonly));
+    }
+    cpu
+}
+
+/// 指定CPUのPer-CPUブロックを取得
+pub fn block(cpu: usize) -> Option<&'static PerCpuBlock> {
+    if cpu >= MAX_CPUS {
+        return None;
+    }
+    let ptr = BLOCKS[cpu].load(Ordering::Acquire);
+    // SAFETY: BLOCKSにはinit_cpu()でリークさせた（解放されない）ブロックのみが格納される
+    unsafe { ptr.as_ref() }
+}
+
+/// キャッシュラインのサイズ（バイト）
+pub const CACHE_LINE_SIZE: usize = 64;
+
+/// キャッシュライン境界に配置した値
+///
+/// 各CPUの実体を別々のキャッシュラインに置き、あるCPUの書き込みが
+/// 他CPUの実体を含むキャッシュラインを無効化する（false sharing）ことを防ぐ。
+#[repr(C, align(64))]
+pub struct CacheAligned<T>(T);
+
+const _: () = assert!(core::mem::align_of::<CacheAligned<u8>>() == CACHE_LINE_SIZE);
+
+impl<T> CacheAligned<T> {
+    /// 値をキャッシュライン境界に配置する
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+/// CPU毎の実体を持つ変数（`percpu!`マクロで宣言する）
+///
+/// 各CPUの実体はキャッシュライン単位で分けて配置される。
+pub struct PerCpu<T> {
+    slots: [CacheAligned<T>; MAX_CPUS],
+}
+
+impl<T> PerCpu<T> {
+    /// 各CPUの初期値を指定して作成
+    pub const fn new(slots: [CacheAligned<T>; MAX_CPUS]) -> Self {
+        Self { slots }
+    }
+
+    /// 現在のCPUの実体を取得
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.slots[cpu::current_cpu()].0
+    }
+
+    /// 指定CPUの実体を取得
+    ///
+    /// # Panics
+    /// `cpu`がMAX_CPUS以上の場合
+    #[inline]
+    pub fn get_for(&self, cpu: usize) -> &T {
+        &self.slots[cpu].0
+    }
+
+    /// 全CPUの実体を列挙
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().map(|slot| &slot.0)
+    }
+}
+
+/// Per-CPU変数を宣言する
+///
+/// 初期化式はconst文脈で評価され、全CPU分に同じ初期値が設定される。
+/// 各CPUの実体は`CacheAligned`で別々のキャッシュラインに配置される。
+#[macro_export]
+macro_rules! percpu {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::percpu::PerCpu<$ty> =
+                $crate::percpu::PerCpu::new(
+                    [const { $crate::percpu::CacheAligned::new($init) }; $crate::cpu::MAX_CPUS],
+                );
+        )*
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::CACHE_LINE_SIZE;
+    use crate::cpu::MAX_CPUS;
+
+    crate::percpu! {
+        static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+    }
+
+    #[test_case]
+    fn test_percpu_slots_are_independent() {
+        TEST_COUNTER.get_for(1).fetch_add(5, Ordering::Relaxed);
+        assert_eq!(TEST_COUNTER.get_for(1).load(Ordering::Relaxed), 5);
+        assert_eq!(TEST_COUNTER.get_for(2).load(Ordering::Relaxed), 0);
+        assert_eq!(TEST_COUNTER.iter().count(), MAX_CPUS);
+    }
+
+    #[test_case]
+    fn test_percpu_slots_do_not_share_cache_lines() {
+        let cpu0 = TEST_COUNTER.get_for(0) as *const AtomicU64 as usize;
+        let cpu1 = TEST_COUNTER.get_for(1) as *const AtomicU64 as usize;
+        assert_eq!(cpu0 % CACHE_LINE_SIZE, 0);
+        assert_eq!(cpu1 - cpu0, CACHE_LINE_SIZE);
+    }
+
+    #[test_case]
+    fn test_percpu_get_before_init_uses_cpu0() {
+        // Per-CPU領域の初期化前はBSP（CPU 0）の実体を返す
+        assert!(core::ptr::eq(TEST_COUNTER.get(), TEST_COUNTER.get_for(0)));
+    }
+}
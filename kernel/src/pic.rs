@@ -0,0 +1,106 @@
+//! 8259 PIC (Programmable Interrupt Controller) 制御
+//!
+//! APIC/I/O APICを使用するため、レガシーPICは初期化後に全IRQをマスクします。
+//! マスク前にベクタを例外領域（0-31）の外へ再マップしておくことで、
+//! マスク中に発生しうるスプリアス割り込み（IRQ7/IRQ15）がCPU例外と
+//! 誤認されることを防ぎます。
+
+use crate::io::{port_read_u8, port_write_u8};
+
+/// マスターPICの再マップ先ベクタベース（IRQ0-7 → 0xF0-0xF7）
+pub const MASTER_VECTOR_BASE: u8 = 0xF0;
+
+/// スレーブPICの再マップ先ベクタベース（IRQ8-15 → 0xF8-0xFF）
+pub const SLAVE_VECTOR_BASE: u8 = 0xF8;
+
+/// マスターPICのスプリアス割り込みベクタ（IRQ7）
+pub const MASTER_SPURIOUS_VECTOR: u8 = MASTER_VECTOR_BASE + 7;
+
+/// スレーブPICのスプリアス割り込みベクタ（IRQ15）
+pub const SLAVE_SPURIOUS_VECTOR: u8 = SLAVE_VECTOR_BASE + 7;
+
+/// PICのI/Oポート
+mod ports {
+    /// Master PIC command port
+    pub const MASTER_COMMAND: u16 = 0x20;
+    /// Master PIC data port (IMR)
+    pub const MASTER_DATA: u16 = 0x21;
+    /// Slave PIC command port
+    pub const SLAVE_COMMAND: u16 = 0xA0;
+    /// Slave PIC data port (IMR)
+    pub const SLAVE_DATA: u16 = 0xA1;
+    /// POSTコード出力ポート（I/O待機用のダミー書き込み先）
+    pub const IO_WAIT: u16 = 0x80;
+}
+
+/// 初期化コマンドワード
+mod icw {
+    /// ICW1: 初期化開始 + ICW4が必要
+    pub const ICW1_INIT_ICW4: u8 = 0x11;
+    /// ICW3（マスター）: IRQ2にスレーブが接続されている
+    pub const ICW3_MASTER_SLAVE_ON_IRQ2: u8 = 0x04;
+    /// ICW3（スレーブ）: カスケードID = 2
+    pub const ICW3_SLAVE_CASCADE_ID: u8 = 0x02;
+    /// ICW4: 8086/88モード
+    pub const ICW4_8086: u8 = 0x01;
+}
+
+/// PICへのコマンド間に必要な短い待機
+///
+/// # Safety
+/// ポート0x80への書き込みが副作用を持たない環境であること（x86標準）
+unsafe fn io_wait() {
+    // SAFETY: ポート0x80はPOSTコード用で、書き込みは無害
+    unsafe {
+        port_write_u8(ports::IO_WAIT, 0);
+    }
+}
+
+/// レガシーPICを再マップした上で全IRQをマスクする
+///
+/// APIC初期化前に呼び出す必要があります。
+pub fn disable() {
+    // SAFETY:
+    // - I/Oポート0x20/0x21/0xA0/0xA1は8259 PICのコマンド/データポートとして定義されている
+    // - ICW1-ICW4の初期化シーケンスは8259のデータシートに従った標準的な手順
+    // - Ring 0で実行されることが前提
+    unsafe {
+        // ICW1: 初期化シーケンス開始
+        port_write_u8(ports::MASTER_COMMAND, icw::ICW1_INIT_ICW4);
+        io_wait();
+        port_write_u8(ports::SLAVE_COMMAND, icw::ICW1_INIT_ICW4);
+        io_wait();
+
+        // ICW2: ベクタオフセット
+        port_write_u8(ports::MASTER_DATA, MASTER_VECTOR_BASE);
+        io_wait();
+        port_write_u8(ports::SLAVE_DATA, SLAVE_VECTOR_BASE);
+        io_wait();
+
+        // ICW3: カスケード構成
+        port_write_u8(ports::MASTER_DATA, icw::ICW3_MASTER_SLAVE_ON_IRQ2);
+        io_wait();
+        port_write_u8(ports::SLAVE_DATA, icw::ICW3_SLAVE_CASCADE_ID);
+        io_wait();
+
+        // ICW4: 8086モード
+        port_write_u8(ports::MASTER_DATA, icw::ICW4_8086);
+        io_wait();
+        port_write_u8(ports::SLAVE_DATA, icw::ICW4_8086);
+        io_wait();
+
+        // OCW1: 全IRQをマスク
+        port_write_u8(ports::MASTER_DATA, 0xFF);
+        port_write_u8(ports::SLAVE_DATA, 0xFF);
+
+        let master_mask = port_read_u8(ports::MASTER_DATA);
+        let slave_mask = port_read_u8(ports::SLAVE_DATA);
+        crate::info!(
+            "Legacy PIC remapped to 0x{:02X}/0x{:02X} and masked (IMR: 0x{:02X}/0x{:02X})",
+            MASTER_VECTOR_BASE,
+            SLAVE_VECTOR_BASE,
+            master_mask,
+            slave_mask
+        );
+    }
+}
@@ -100,6 +100,8 @@ const COLOR_HIGHLIGHT: u32 = 0x00FFFF;
 const COLOR_ARROW: u32 = 0xFFFF00;
 /// Compositor枠色（紫）
 const COLOR_COMPOSITOR_BORDER: u32 = 0xAA60AA;
+/// 優先度継承の表示色（オレンジ）
+const COLOR_PI_BOOST: u32 = 0xFF9020;
 
 // =============================================================================
 // アニメーション速度定数
@@ -187,6 +189,14 @@ pub fn get_owner_task_id(buffer_index: usize) -> Option<u64> {
     }
 }
 
+/// 各バッファの所有タスクについて、優先度継承による現在の引き上げを取得
+fn owner_pi_boosts() -> [Option<crate::sched::TaskPriority>; 4] {
+    core::array::from_fn(|index| {
+        get_owner_task_id(index)
+            .and_then(|id| crate::sched::task_pi_boost(crate::sched::TaskId::from_u64(id)))
+    })
+}
+
 /// 保存されたフレームバッファベースアドレスを取得
 pub fn fb_base() -> u64 {
     FB_BASE.load(Ordering::Relaxed)
@@ -240,6 +250,8 @@ pub struct BufferQueueInfo {
     pub processed_count: usize,
     /// 総コマンド数（処理開始時のコマンド数）
     pub total_commands: usize,
+    /// 所有タスクの優先度継承による引き上げ（UIタスクが描画前に設定）
+    pub pi_boost: Option<crate::sched::TaskPriority>,
 }
 
 /// パイプ内を流れるコマンド（アニメーション用）
@@ -323,6 +335,7 @@ impl MiniVisualizationState {
                     is_processing: false,
                     processed_count: 0,
                     total_commands: 0,
+                    pi_boost: None,
                 }
             }; 4],
             buffer_count: 0,
@@ -1305,6 +1318,7 @@ pub extern "C" fn visualization_ui_task() -> ! {
             is_processing: false,
             processed_count: 0,
             total_commands: 0,
+            pi_boost: None,
         }
     }; 4];
     let mut local_blit_anim: Option<BlitAnimation> = None;
@@ -1340,6 +1354,11 @@ pub extern "C" fn visualization_ui_task() -> ! {
             }
         };
 
+        // 所有タスクの優先度継承の状態（タスク一覧の走査は可視化状態のロック外で行う）
+        for (queue, boost) in local_buffer_queues.iter_mut().zip(owner_pi_boosts()) {
+            queue.pi_boost = boost;
+        }
+
         // バックバッファをクリア
         // SAFETY: back_baseは上で確保したback_buffer配列を指しており、
         // screen_width * screen_height のサイズが保証されている。
@@ -1955,6 +1974,19 @@ fn draw_task_boxes_with_queues(
                     COLOR_TEXT,
                 );
 
+                // 優先度継承で引き上げられている場合は継承した優先度を右上に表示
+                if let Some(boost) = info.pi_boost {
+                    let label = alloc::format!("PI {}", boost);
+                    draw_string(
+                        fb_base,
+                        fb_width,
+                        TASK_BOX_X + TASK_BOX_WIDTH - 5 - label.len() * 8,
+                        y + 3,
+                        &label,
+                        COLOR_PI_BOOST,
+                    );
+                }
+
                 // バッファ枠（内部）
                 draw_rect_outline(
                     fb_base,
@@ -2,17 +2,25 @@
 //!
 //! 8254 PITチップを使用してタイミング制御を行います。
 //! 主にAPIC Timerのキャリブレーションに使用します。
+//!
+//! Channel 2はフリーランニングのカウンタとして動作させ、他のタイマーが使えない場合の
+//! クロックソースと、`sleep_ms()`/`udelay()`の遅延の計測に使用します。
+//! Channel 0はクロックイベント（周期割り込み）に使用できるため、遅延では変更しません。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
 
-use crate::io::{port_read_u8, port_write_u8};
+use crate::io::{port_read_u8, port_write_u8, without_interrupts};
+use crate::time::{ClockEventDevice, ClockEventError, ClockSource, clockevent};
 
 /// PIT周波数（Hz）
 const PIT_FREQUENCY: u32 = 1193182;
 
-/// ポーリングループの最大イテレーション数（タイムアウト用）
+/// カウンタが進まない読み取りの最大連続回数（タイムアウト用）
 ///
-/// PITカウンタは約1.19MHzで動作し、1回のループで数サイクルを消費する。
-/// 100,000回のイテレーションは、通常の動作では十分な待機時間を提供し、
-/// ハードウェア障害時の無限ループを防止する。
+/// PITカウンタは約1.19MHzで動作し、1回の読み取りにはI/Oポートへの3回のアクセスが必要なため、
+/// 正常なら数回の読み取りでカウンタが進む。100,000回続けて進まない場合は
+/// ハードウェア障害とみなし、無限ループを防止する。
 const MAX_POLL_ITERATIONS: u32 = 100_000;
 
 /// PITのI/Oポート
@@ -20,75 +28,57 @@ mod ports {
     /// Channel 0 data port (read/write)
     pub const CHANNEL_0: u16 = 0x40;
     /// Channel 2 data port (read/write)
-    #[allow(dead_code)]
     pub const CHANNEL_2: u16 = 0x42;
     /// Mode/Command register (write only)
     pub const COMMAND: u16 = 0x43;
+    /// NMI Status and Control (bit 0: Channel 2 gate, bit 1: スピーカー出力)
+    pub const NMI_STATUS_CONTROL: u16 = 0x61;
 }
 
+/// Channel 2のカウンタが動作しているかどうか
+static COUNTER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Channel 2のラッチと読み取りを直列化するロック
+///
+/// ラッチ後の2回の読み取り（下位・上位バイト）の間に他の読み取りが
+/// 割り込むと値が壊れるため、読み取り全体を保護する。
+static COUNTER_LOCK: Mutex<()> = Mutex::new(());
+
 /// PITを使って指定ミリ秒待機
 ///
 /// # Arguments
 /// * `ms` - 待機時間（ミリ秒）
 pub fn sleep_ms(ms: u32) {
-    // 1msずつ待機することで精度を上げる
-    for _ in 0..ms {
-        sleep_1ms();
-    }
+    delay_counts(PIT_FREQUENCY as u64 * ms as u64 / 1000);
 }
 
-/// 1ミリ秒待機（内部関数）
-fn sleep_1ms() {
-    // SAFETY:
-    // - PITのI/Oポート(0x40-0x43)はx86標準のハードウェアポート
-    // - カーネルモードでは特権I/O命令が許可されている
-    // - port_write_u8/port_read_u8はio.rsで定義された安全なI/Oラッパー
-    unsafe {
-        // 1ms = 1193 カウント（PIT_FREQUENCY / 1000）
-        let count: u16 = (PIT_FREQUENCY / 1000) as u16;
-
-        // Channel 0, Mode 0 (Interrupt on terminal count), binary counter
-        // Command: 0x30 = 0011 0000
-        // - Channel 0 (bits 6-7: 00)
-        // - Access mode: lobyte/hibyte (bits 4-5: 11)
-        // - Operating mode 0: interrupt on terminal count (bits 1-3: 000)
-        // - Binary counter (bit 0: 0)
-        port_write_u8(ports::COMMAND, 0x30);
-
-        // カウント値を設定（下位バイト、上位バイト）
-        port_write_u8(ports::CHANNEL_0, (count & 0xFF) as u8);
-        port_write_u8(ports::CHANNEL_0, ((count >> 8) & 0xFF) as u8);
-
-        // 初回の読み取り
-        port_write_u8(ports::COMMAND, 0x00);
-        let mut last_count = read_current_count();
+/// Channel 2のカウンタで指定カウント数だけ待機（内部関数）
+///
+/// Channel 0はクロックイベントが使用している場合があるため変更しない。
+/// カウンタは約55msでラップアラウンドするため、読み取り毎の差分を積算する。
+/// カウンタが`MAX_POLL_ITERATIONS`回続けて進まない場合はハードウェア障害とみなして諦める。
+fn delay_counts(counts: u64) {
+    if !counter_running() {
+        start_counter();
+    }
 
-        // カウントダウンが完了するまで待つ（タイムアウト付き）
-        let mut iterations = 0u32;
-        loop {
-            if iterations >= MAX_POLL_ITERATIONS {
+    let mut last = read_counter();
+    let mut elapsed = 0u64;
+    let mut stalled = 0u32;
+    while elapsed < counts {
+        let now = read_counter();
+        let delta = now.wrapping_sub(last) & counter_mask();
+        if delta == 0 {
+            stalled += 1;
+            if stalled >= MAX_POLL_ITERATIONS {
                 break; // タイムアウト
             }
-            iterations += 1;
-
-            port_write_u8(ports::COMMAND, 0x00); // latch command
-            let current_count = read_current_count();
-
-            // Mode 0: カウンタが0になるか、再ロードされて大きくなったら終了
-            if current_count == 0 || current_count > last_count {
-                break;
-            }
-            last_count = current_count;
+            core::hint::spin_loop();
+            continue;
         }
-    }
-}
-
-/// 現在のPITカウント値を読み取る
-unsafe fn read_current_count() -> u16 {
-    unsafe {
-        let low = port_read_u8(ports::CHANNEL_0) as u16;
-        let high = port_read_u8(ports::CHANNEL_0) as u16;
-        (high << 8) | low
+        stalled = 0;
+        elapsed += delta;
+        last = now;
     }
 }
 
@@ -119,37 +109,87 @@ pub fn oneshot(count: u16) {
 /// * `us` - 待機時間（マイクロ秒）
 #[allow(dead_code)]
 pub fn udelay(us: u32) {
-    // 1マイクロ秒 = PIT_FREQUENCY / 1_000_000 カウント
-    let count = ((PIT_FREQUENCY as u64 * us as u64) / 1_000_000) as u16;
+    // 1マイクロ秒 = PIT_FREQUENCY / 1_000_000 カウント（切り上げ）
+    delay_counts((PIT_FREQUENCY as u64 * us as u64).div_ceil(1_000_000));
+}
+
+/// Channel 0を周期モードで開始
+///
+/// # Arguments
+/// * `hz` - 割り込み周波数（19Hz未満は最低周波数に切り上げられる）
+pub fn start_periodic(hz: u32) {
+    let divisor = (PIT_FREQUENCY / hz.max(1)).clamp(1, u16::MAX as u32) as u16;
 
     // SAFETY:
     // - PITのI/Oポート(0x40-0x43)はx86標準のハードウェアポート
     // - カーネルモードでは特権I/O命令が許可されている
-    // - port_write_u8/port_read_u8はio.rsで定義された安全なI/Oラッパー
     unsafe {
-        // One-shot mode
-        port_write_u8(ports::COMMAND, 0x30);
-        port_write_u8(ports::CHANNEL_0, (count & 0xFF) as u8);
-        port_write_u8(ports::CHANNEL_0, ((count >> 8) & 0xFF) as u8);
+        // Channel 0, lobyte/hibyte, Mode 2 (Rate generator), binary counter
+        // Command: 0x34 = 0011 0100
+        port_write_u8(ports::COMMAND, 0x34);
+        port_write_u8(ports::CHANNEL_0, (divisor & 0xFF) as u8);
+        port_write_u8(ports::CHANNEL_0, ((divisor >> 8) & 0xFF) as u8);
+    }
+}
 
-        // カウントが0になるまで待つ（タイムアウト付き）
-        let mut iterations = 0u32;
-        loop {
-            if iterations >= MAX_POLL_ITERATIONS {
-                break; // タイムアウト
-            }
-            iterations += 1;
+/// Channel 0の割り込みを止める
+///
+/// Mode 0でカウント値を書き込まずに待機させ、割り込みの発生を止める。
+pub fn stop() {
+    // SAFETY: PITのコマンドポートへの書き込みは常に安全
+    unsafe {
+        port_write_u8(ports::COMMAND, 0x30);
+    }
+}
 
-            port_write_u8(ports::COMMAND, 0x00); // latch
-            let low = port_read_u8(ports::CHANNEL_0) as u16;
-            let high = port_read_u8(ports::CHANNEL_0) as u16;
-            let current = (high << 8) | low;
+/// Channel 2をフリーランニングのカウンタとして開始
+///
+/// 最大カウント（65536）のRate generatorとして動作させ、スピーカー出力は無効にする。
+pub fn start_counter() {
+    // SAFETY:
+    // - PITのI/Oポート(0x42, 0x43)とポート0x61はx86標準のハードウェアポート
+    // - ポート0x61のbit 0-1以外は変更しない
+    unsafe {
+        let control = port_read_u8(ports::NMI_STATUS_CONTROL);
+        // gateを有効化、スピーカー出力を無効化
+        port_write_u8(ports::NMI_STATUS_CONTROL, (control & !0x02) | 0x01);
 
-            if current == 0 {
-                break;
-            }
-        }
+        // Channel 2, lobyte/hibyte, Mode 2 (Rate generator), binary counter
+        // Command: 0xB4 = 1011 0100
+        port_write_u8(ports::COMMAND, 0xB4);
+        // カウント値0は65536として扱われる
+        port_write_u8(ports::CHANNEL_2, 0);
+        port_write_u8(ports::CHANNEL_2, 0);
     }
+    COUNTER_RUNNING.store(true, Ordering::Release);
+}
+
+/// Channel 2のカウンタが動作しているかどうか
+pub fn counter_running() -> bool {
+    COUNTER_RUNNING.load(Ordering::Acquire)
+}
+
+/// Channel 2のカウンタ値を増加方向の値として読み取る（16ビット幅）
+///
+/// PITのカウンタは減少方向に動くため、反転した値を返す。
+pub fn read_counter() -> u64 {
+    without_interrupts(|| {
+        let _guard = COUNTER_LOCK.lock();
+        // SAFETY: PITのI/Oポートへのアクセスは常に安全。ラッチと読み取りはロックで保護される
+        let count = unsafe {
+            // Channel 2のカウンタをラッチ（Command: 0x80 = 1000 0000）
+            port_write_u8(ports::COMMAND, 0x80);
+            let low = port_read_u8(ports::CHANNEL_2) as u16;
+            let high = port_read_u8(ports::CHANNEL_2) as u16;
+            (high << 8) | low
+        };
+        0u16.wrapping_sub(count) as u64
+    })
+}
+
+/// PITの入力クロック周波数（Hz）
+pub fn frequency() -> u64 {
+    PIT_FREQUENCY as u64
 }
 
 // ============================================================================
@@ -185,3 +225,45 @@ impl TimerDevice for Pit {
 
 /// グローバルPITインスタンス
 pub static PIT: Pit = Pit;
+
+// ============================================================================
+// クロックソース / クロックイベント
+// ============================================================================
+
+/// Channel 2カウンタのマスク（16ビット幅）
+fn counter_mask() -> u64 {
+    0xFFFF
+}
+
+/// PIT Channel 2クロックソース（約55msでラップアラウンドする）
+pub static PIT_CLOCKSOURCE: ClockSource =
+    ClockSource::new("pit", 100, read_counter, counter_mask, frequency);
+
+/// Channel 0を周期モードで開始し、ISA IRQ0をBSPに配送する
+fn clockevent_set_periodic(hz: u32) -> Result<(), ClockEventError> {
+    start_periodic(hz);
+    clockevent::route_legacy_timer_irq().inspect_err(|_| stop())
+}
+
+/// Channel 0で指定ナノ秒後に割り込みを発生させる（最大約55ms）
+fn clockevent_set_oneshot(ns: u64) -> Result<(), ClockEventError> {
+    let count = (ns as u128 * PIT_FREQUENCY as u128 / 1_000_000_000).clamp(1, u16::MAX as u128);
+    oneshot(count as u16);
+    clockevent::route_legacy_timer_irq().inspect_err(|_| stop())
+}
+
+/// Channel 0を停止し、ISA IRQ0をマスクする
+fn clockevent_shutdown() {
+    stop();
+    clockevent::mask_legacy_timer_irq();
+}
+
+/// PIT Channel 0クロックイベントデバイス
+pub static PIT_CLOCKEVENT: ClockEventDevice = ClockEventDevice::new(
+    "pit",
+    100,
+    false,
+    clockevent_set_periodic,
+    Some(clockevent_set_oneshot),
+    clockevent_shutdown,
+);
@@ -10,7 +10,7 @@ use spin::Mutex;
 
 use crate::io::without_interrupts;
 
-use super::scheduler::{CURRENT_TASK, current_task_id, schedule};
+use super::scheduler::{CURRENT_TASK, schedule};
 use super::task::{Task, TaskId, TaskState};
 
 lazy_static! {
@@ -59,17 +59,17 @@ pub fn is_interrupt_context() -> bool {
 /// # アトミック性保証
 /// WAKEUP_PENDINGのチェックとBlocked状態設定を同一のクリティカルセクション内で
 /// 実行し、その間に起床シグナルが失われることを防ぎます。
-/// ロック順序: WAKEUP_PENDING → CURRENT_TASK（デッドロック防止）
+/// ロック順序: CURRENT_TASK → WAKEUP_PENDING（デッドロック防止）
 ///
 /// # Note
 /// 割り込みを無効化してからロックを取得し、デッドロックを防ぎます。
 pub fn block_current_task() {
     // Lost Wakeup防止: WAKEUP_PENDINGチェックとBlocked設定をアトミックに実行
     let should_block = without_interrupts(|| {
-        // ロック順序: WAKEUP_PENDING → CURRENT_TASK
-        // この順序を維持することでデッドロックを防ぐ
+        // ロック順序: CURRENT_TASK → WAKEUP_PENDING
+        // この順序を維持することでデッドロックを防ぐ（for_each_task()の走査と同じ順序）
+        let mut current = CURRENT_TASK.get().lock();
         let mut wakeup_pending = WAKEUP_PENDING.lock();
-        let mut current = CURRENT_TASK.lock();
 
         if let Some(task) = current.as_mut() {
             let id = task.id().as_u64();
@@ -99,6 +99,8 @@ pub fn block_current_task() {
 /// 指定タスクをアンブロック（Ready状態に戻す）
 ///
 /// BLOCKED_TASKSから取り出して、スケジューリングクラスに応じたキューに追加します。
+/// `for_each_task()`の走査中にタスクが見えなくなることを防ぐため、
+/// キューへの追加が完了するまでBLOCKED_TASKSのロックを保持します。
 /// タスクがまだBLOCKED_TASKSに登録されていない場合（Lost Wakeup防止）、
 /// WAKEUP_PENDINGセットに追加し、block_current_task()で検出できるようにします。
 ///
@@ -114,10 +116,11 @@ pub fn unblock_task(task_id: TaskId) {
         if let Some(mut task) = blocked_tasks.remove(&task_id.as_u64()) {
             // Ready状態に戻す
             task.set_state(TaskState::Ready);
+            task.record_wakeup(crate::time::monotonic_ns());
             let sched_class = task.sched_class();
-            drop(blocked_tasks); // ロックを早期に解放
 
             // スケジューリングクラスに応じて適切なキューに追加
+            // ロック順序: BLOCKED_TASKS → ランキュー
             super::scheduler::enqueue_to_appropriate_queue(task, sched_class);
         } else {
             // タスクがBLOCKED_TASKSにない場合、まだblock_current_task()が
@@ -133,8 +136,8 @@ pub fn unblock_task(task_id: TaskId) {
 /// 指定したミリ秒数だけ現在のタスクをスリープさせる
 ///
 /// Linux の `schedule_timeout()` に倣った実装です。
-/// タイマーを登録し、タスクをブロック状態にしてスケジューラに譲ります。
-/// タイマー期限切れ時にコールバックでタスクを起床させます。
+/// 誰からも起床されない待機キューでタイムアウト付きの待機を行い、
+/// タイマー期限切れ時にタイムアウトとして起床します。
 ///
 /// # Arguments
 /// * `ms` - スリープ時間（ミリ秒）
@@ -159,21 +162,6 @@ pub fn sleep_ms(ms: u64) {
         return;
     }
 
-    // 現在のタスクIDを取得（TaskId は Copy なのでクロージャにキャプチャ可能）
-    let task_id = current_task_id();
-
-    // ミリ秒をtick数に変換（最小1tickを保証）
-    let ticks = crate::timer::ms_to_ticks(ms).max(1);
-
-    // タイマーを登録: 期限切れ時に unblock_task を呼び出す
-    crate::timer::register_timer(
-        ticks,
-        Box::new(move || {
-            unblock_task(task_id);
-        }),
-    );
-
-    // タスクをブロック状態にしてスケジュール
-    // タイマーが起床するまで他のタスクが実行される
-    block_current_task();
+    // タイムアウト（最小1tick）まで他のタスクが実行される
+    crate::sync::WaitQueue::new().wait_timeout(ms);
 }
@@ -2,6 +2,8 @@
 //!
 //! このモジュールはCPUコンテキストの保存・復元とコンテキストスイッチを担当します。
 
+use super::exit::exit_from_entry;
+use super::scheduler::finish_task_switch;
 use super::task::TaskError;
 
 /// CPUコンテキスト（レジスタ状態）
@@ -20,19 +22,24 @@ impl Context {
     /// 新しいコンテキストを作成（Linux方式）
     ///
     /// スタックに以下の順序でレジスタを配置（switch_context()のpush順序に合わせる）:
-    /// 1. 戻りアドレス（entry_point） - 最上位
-    /// 2. rbp, rbx, r12, r13, r14, r15（callee-savedレジスタ）
-    /// 3. rflags
+    /// 1. 戻りアドレス（task_entry_trampoline） - 最上位
+    /// 2. rbp, rbx, r12（= entry_point）, r13（= arg）, r14, r15（callee-savedレジスタ）
+    /// 3. rflags（割り込み無効）
     /// 4. fxsave領域（512バイト、16バイトアライメント） - 最下位、rspがここを指す
     ///
+    /// 新しいタスクはtask_entry_trampolineから開始し、finish_task_switch()で
+    /// 直前のタスクの後処理を行ってから割り込みを有効化し、`arg`を第1引数（RDI）として
+    /// entry_pointを呼び出す。
+    ///
     /// # Arguments
     /// * `entry_point` - タスクのエントリポイント
+    /// * `arg` - エントリポイントに渡す引数（不要なら0）
     /// * `stack_top` - スタックの最上位アドレス
     ///
     /// # Errors
     /// * `TaskError::InvalidStackAddress` - スタックアドレスが無効（null、アラインメント不正、範囲不正）
     /// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗
-    pub fn new(entry_point: u64, stack_top: u64) -> Result<Self, TaskError> {
+    pub fn new(entry_point: u64, arg: u64, stack_top: u64) -> Result<Self, TaskError> {
         const FXSAVE_SIZE: u64 = 512;
         const FXSAVE_ALIGN: u64 = 16;
         const MIN_REQUIRED_STACK: u64 = 1024; // 最小スタックサイズ
@@ -59,13 +66,13 @@ impl Context {
         // 初期コンテキストを書き込む。呼び出し元がstack_topが有効なスタック領域の
         // 最上位アドレスであることを保証する。
 
-        // 1. 戻りアドレス（entry_point）- switch_context()のret用
+        // 1. 戻りアドレス（task_entry_trampoline）- switch_context()のret用
         rsp -= 8;
         if rsp == 0 {
             return Err(TaskError::InvalidStackAddress);
         }
         unsafe {
-            *(rsp as *mut u64) = entry_point;
+            *(rsp as *mut u64) = task_entry_trampoline as *const () as u64;
         }
 
         // 2. callee-savedレジスタ（switch_context()のpush順序に合わせる）
@@ -79,15 +86,15 @@ impl Context {
         unsafe {
             *(rsp as *mut u64) = 0;
         }
-        // r12（push r12で積まれる）
+        // r12（push r12で積まれる）- task_entry_trampolineのジャンプ先
         rsp -= 8;
         unsafe {
-            *(rsp as *mut u64) = 0;
+            *(rsp as *mut u64) = entry_point;
         }
-        // r13（push r13で積まれる）
+        // r13（push r13で積まれる）- エントリポイントの引数
         rsp -= 8;
         unsafe {
-            *(rsp as *mut u64) = 0;
+            *(rsp as *mut u64) = arg;
         }
         // r14（push r14で積まれる）
         rsp -= 8;
@@ -100,10 +107,11 @@ impl Context {
             *(rsp as *mut u64) = 0;
         }
 
-        // 3. rflags（pushfqで積まれる）- 割り込み有効
+        // 3. rflags（pushfqで積まれる）- 割り込み無効
+        // finish_task_switch()の完了後にtask_entry_trampolineが割り込みを有効化する
         rsp -= 8;
         unsafe {
-            *(rsp as *mut u64) = 0x202; // IF (Interrupt Flag) を有効化
+            *(rsp as *mut u64) = 0x002; // 予約ビット(bit 1)のみ
         }
 
         // 4. fxsave領域を確保（512バイト）
@@ -146,8 +154,9 @@ impl Context {
 /// * `new_context` - 切り替え先のコンテキスト（rspのみ）
 ///
 /// # Note
-/// 保存されるRFLAGSは割り込み有効フラグ(IF)が強制的にセットされます。
-/// これにより、タスク復帰時に必ず割り込み有効状態になることが保証されます。
+/// RFLAGSはそのまま保存・復元されます（schedule()から呼ばれるため通常IF=0）。
+/// 割り込みの再有効化は、切り替え先でfinish_task_switch()を実行した後に
+/// schedule()または task_entry_trampoline が行います。
 #[unsafe(naked)]
 pub unsafe extern "C" fn switch_context(old_context: *mut Context, new_context: *const Context) {
     core::arch::naked_asm!(
@@ -159,10 +168,8 @@ pub unsafe extern "C" fn switch_context(old_context: *mut Context, new_context:
         "push r13",
         "push r14",
         "push r15",
-        // RFLAGSを保存し、IFフラグを強制的にセット
-        // これにより、タスク復帰時に必ず割り込みが有効になる
+        // RFLAGSを保存
         "pushfq",
-        "or qword ptr [rsp], 0x200", // IF (bit 9) を強制的に1にする
         // fxsave用の領域を確保し、16バイトアラインを保証
         // call命令で8バイトプッシュされているため、アラインメント調整が必要
         "mov r11, rsp", // アラインメント前のRSPを保存
@@ -181,7 +188,7 @@ pub unsafe extern "C" fn switch_context(old_context: *mut Context, new_context:
         "fxrstor [rsp]",
         // アラインメント前のRSPを復元
         "mov rsp, [rsp + 504]",
-        // RFLAGSを復元（IFは保存時に強制セット済みなので、割り込み有効で復帰）
+        // RFLAGSを復元
         "popfq",
         // callee-savedレジスタを復元（保存と逆順）
         "pop r15",
@@ -194,3 +201,25 @@ pub unsafe extern "C" fn switch_context(old_context: *mut Context, new_context:
         "ret",
     )
 }
+
+/// 新しいタスクの開始地点
+///
+/// 初回のswitch_context()の`ret`でここに到達する（RSP = スタックトップ、R12 = エントリポイント、
+/// R13 = エントリポイントの引数）。
+/// 切り替え元タスクの後処理（finish_task_switch）を行ってから割り込みを有効化し、
+/// タスクのエントリポイントを呼び出す。エントリポイントから戻った場合は
+/// 戻り値（EAX）を終了コードとしてタスクを終了する。
+#[unsafe(naked)]
+extern "C" fn task_entry_trampoline() -> ! {
+    core::arch::naked_asm!(
+        "call {finish}",
+        "sti",
+        "mov rdi, r13",
+        "call r12",
+        "mov edi, eax",
+        "call {exit}",
+        "ud2",
+        finish = sym finish_task_switch,
+        exit = sym exit_from_entry,
+    )
+}
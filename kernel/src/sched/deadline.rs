@@ -0,0 +1,468 @@
+//! 期限（Deadline）スケジューリングクラス
+//!
+//! 「周期`period`毎に`runtime`だけ実行し、各周期の開始から`deadline`以内に終える」という
+//! 形でCPU時間を予約するクラスです（Linuxの`SCHED_DEADLINE`に相当）。
+//!
+//! - 受け入れ制御: CPU毎の利用率（runtime / period）の合計が`MAX_DL_BANDWIDTH`を超える
+//!   タスクは追加できない（`TaskError::AdmissionDenied`）
+//! - EDF: 期限クラスのタスクはRealtime/Normalより先に、絶対期限の早い順に選択される
+//! - スロットリング: 周期内の予算（runtime）を使い切ったタスクは次の周期まで実行されない
+//!   （CBS: Constant Bandwidth Server）
+//!
+//! # 配置
+//! 期限クラスのタスクは追加時に予約に余裕のあるCPUに配置され、以後移動しない
+//! （パーティション方式のEDF）。CPU毎の利用率が上限以下であれば、そのCPUの
+//! 期限クラスのタスクはすべて期限を守れる。残りの帯域はRT/CFSのタスクが使う。
+//!
+//! # 予算の監視
+//! 期限クラスのタスクに切り替える時に、残りの予算を使い切る時刻に高精度タイマーを設定し、
+//! 期限切れでそのCPUに再スケジューリングを要求する。同じタスクが予算切れ時刻を変えずに
+//! 実行を続ける間（tick毎の再スケジューリングなど）は、タイマーを設定し直さない。
+//! スロットリングしたタスクは
+//! 次の周期の開始時刻のタイマーで再スケジューリングを要求し、schedule()で予算を補充する。
+//! タイマー割り込みの遅延による予算の超過分は、次の周期の予算から差し引く。
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::cpu;
+use crate::io::without_interrupts;
+use crate::percpu;
+use crate::time::hrtimer::{self, HrTimerContext, HrTimerId};
+
+use super::runqueue;
+use super::scheduler::{CURRENT_TASK, set_need_resched};
+use super::task::{TaskError, TaskId};
+
+/// 帯域（利用率）の固定小数点のシフト量（1 << BW_SHIFT が利用率100%）
+const BW_SHIFT: u32 = 20;
+
+/// CPU毎に期限クラスへ予約できる帯域の上限（95%、残りはRT/CFSのために空けておく）
+const MAX_DL_BANDWIDTH: u64 = (95 << BW_SHIFT) / 100;
+
+percpu! {
+    /// CPU毎の予約済み帯域（期限クラスのタスクの利用率の合計）
+    static DL_BANDWIDTH: AtomicU64 = AtomicU64::new(0);
+
+    /// 実行中の期限クラスのタスクの予算切れを通知するタイマー
+    static BUDGET_TIMER: Mutex<Option<BudgetTimer>> = Mutex::new(None);
+}
+
+/// 予算切れ時刻の変化がこの範囲内なら、予算切れタイマーを設定し直さない（ナノ秒）
+///
+/// 同じタスクが実行を続ける間の予算切れ時刻は、実行時間を計上した時刻と
+/// タイマーを設定する時刻のずれの分だけ揺れる。
+const BUDGET_TIMER_SLACK_NS: u64 = 10_000;
+
+/// 設定中の予算切れタイマー
+struct BudgetTimer {
+    /// 予算を監視しているタスク
+    task: TaskId,
+    /// 予算を使い切る時刻（`time::monotonic_ns()`基準）
+    expires_at: u64,
+    /// 高精度タイマー
+    id: HrTimerId,
+}
+
+/// 期限クラスのパラメータ（ナノ秒単位）
+///
+/// `0 < runtime <= deadline <= period`を満たす値のみ作成できる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineParams {
+    runtime_ns: u64,
+    deadline_ns: u64,
+    period_ns: u64,
+}
+
+impl DeadlineParams {
+    /// パラメータを作成
+    ///
+    /// # Arguments
+    /// * `runtime_ns` - 周期毎の実行時間の予算
+    /// * `deadline_ns` - 周期の開始からの相対期限
+    /// * `period_ns` - 周期
+    ///
+    /// # Errors
+    /// * `TaskError::InvalidDeadlineParams` - `0 < runtime <= deadline <= period`を満たさない場合
+    pub const fn new(runtime_ns: u64, deadline_ns: u64, period_ns: u64) -> Result<Self, TaskError> {
+        if runtime_ns == 0 || runtime_ns > deadline_ns || deadline_ns > period_ns {
+            return Err(TaskError::InvalidDeadlineParams);
+        }
+        Ok(Self {
+            runtime_ns,
+            deadline_ns,
+            period_ns,
+        })
+    }
+
+    /// 周期毎の実行時間の予算（ナノ秒）
+    pub fn runtime_ns(&self) -> u64 {
+        self.runtime_ns
+    }
+
+    /// 周期の開始からの相対期限（ナノ秒）
+    pub fn deadline_ns(&self) -> u64 {
+        self.deadline_ns
+    }
+
+    /// 周期（ナノ秒）
+    pub fn period_ns(&self) -> u64 {
+        self.period_ns
+    }
+
+    /// 利用率（runtime / period、`1 << BW_SHIFT`が100%、切り上げ）
+    pub fn bandwidth(&self) -> u64 {
+        ((self.runtime_ns as u128) << BW_SHIFT).div_ceil(self.period_ns as u128) as u64
+    }
+}
+
+/// 期限クラスのタスクの実行状態（CBS）
+pub(super) struct DeadlineState {
+    params: DeadlineParams,
+    /// 現在のジョブの開始時刻（`time::monotonic_ns()`基準）
+    period_start: u64,
+    /// 現在のジョブの絶対期限
+    abs_deadline: u64,
+    /// 現在の周期の残り予算（超過した場合は負）
+    remaining_ns: i64,
+    /// スロットリング中なら予算を補充する時刻
+    throttled_until: Option<u64>,
+    /// 帯域を予約したCPU（受け入れ前はNone）
+    reserved_cpu: Option<usize>,
+}
+
+impl DeadlineState {
+    pub(super) fn new(params: DeadlineParams) -> Self {
+        Self {
+            params,
+            period_start: 0,
+            abs_deadline: 0,
+            remaining_ns: params.runtime_ns as i64,
+            throttled_until: None,
+            reserved_cpu: None,
+        }
+    }
+
+    pub(super) fn params(&self) -> DeadlineParams {
+        self.params
+    }
+
+    /// 現在のジョブの絶対期限
+    pub(super) fn abs_deadline(&self) -> u64 {
+        self.abs_deadline
+    }
+
+    /// 現在の周期の残り予算（ナノ秒、使い切っていれば0）
+    pub(super) fn remaining_ns(&self) -> u64 {
+        self.remaining_ns.max(0) as u64
+    }
+
+    /// スロットリング中なら予算を補充する時刻
+    pub(super) fn throttled_until(&self) -> Option<u64> {
+        self.throttled_until
+    }
+
+    /// 帯域を予約してCPUを選び、最初のジョブを開始する
+    ///
+    /// # Errors
+    /// * `TaskError::AdmissionDenied` - どのCPUにも帯域の空きがない場合
+    pub(super) fn admit(&mut self, now: u64) -> Result<usize, TaskError> {
+        let cpu = reserve_bandwidth(self.params.bandwidth())?;
+        self.reserved_cpu = Some(cpu);
+        self.start_job(now);
+        Ok(cpu)
+    }
+
+    /// 現在時刻を開始時刻として新しいジョブを開始（予算を満たす）
+    pub(super) fn start_job(&mut self, now: u64) {
+        self.period_start = now;
+        self.abs_deadline = now.saturating_add(self.params.deadline_ns);
+        self.remaining_ns = self.params.runtime_ns as i64;
+        self.throttled_until = None;
+    }
+
+    /// 実行時間を予算から差し引く
+    ///
+    /// # Returns
+    /// 予算を使い切って新たにスロットリングした場合true
+    pub(super) fn charge(&mut self, runtime_ns: u64) -> bool {
+        self.remaining_ns = self
+            .remaining_ns
+            .saturating_sub(runtime_ns.min(i64::MAX as u64) as i64);
+        if self.remaining_ns > 0 || self.throttled_until.is_some() {
+            return false;
+        }
+        self.throttled_until = Some(self.next_period());
+        true
+    }
+
+    /// 現在のジョブを完了し、次の周期まで実行を止める
+    ///
+    /// # Returns
+    /// ジョブの完了が絶対期限を過ぎていた場合true
+    pub(super) fn complete_job(&mut self, now: u64) -> bool {
+        if self.throttled_until.is_none() {
+            self.throttled_until = Some(self.next_period());
+        }
+        now > self.abs_deadline
+    }
+
+    /// スロットリングの期限が過ぎていれば、次の周期のジョブを開始して予算を補充する
+    ///
+    /// 周期の開始時刻は前回の周期から続けて決め、補充の遅れを累積させない
+    /// （1周期以上遅れた場合は現在時刻から開始する）。超過した予算は持ち越す。
+    pub(super) fn replenish(&mut self, now: u64) {
+        let Some(release) = self.throttled_until else {
+            return;
+        };
+        if now < release {
+            return;
+        }
+        self.period_start = if now < release.saturating_add(self.params.period_ns) {
+            release
+        } else {
+            now
+        };
+        self.abs_deadline = self.period_start.saturating_add(self.params.deadline_ns);
+        self.remaining_ns = self
+            .remaining_ns
+            .saturating_add(self.params.runtime_ns as i64)
+            .min(self.params.runtime_ns as i64);
+        self.throttled_until = (self.remaining_ns <= 0).then(|| self.next_period());
+    }
+
+    /// ブロックからの起床時に、現在のジョブを続けるか新しいジョブを開始するかを決める
+    ///
+    /// 残りの予算を期限までに使うと予約した帯域を超える場合（期限を過ぎた場合を含む）は、
+    /// 現在時刻から新しいジョブを開始する（CBSの起床規則）。
+    pub(super) fn wakeup(&mut self, now: u64) {
+        if self.throttled_until.is_some() {
+            return;
+        }
+        let laxity = self.abs_deadline.saturating_sub(now);
+        let remaining = self.remaining_ns.max(0) as u128;
+        if laxity == 0
+            || remaining * self.params.period_ns as u128
+                > laxity as u128 * self.params.runtime_ns as u128
+        {
+            self.start_job(now);
+        }
+    }
+
+    /// 次の周期の開始時刻
+    fn next_period(&self) -> u64 {
+        self.period_start.saturating_add(self.params.period_ns)
+    }
+}
+
+impl Drop for DeadlineState {
+    fn drop(&mut self) {
+        if let Some(cpu) = self.reserved_cpu {
+            DL_BANDWIDTH
+                .get_for(cpu)
+                .fetch_sub(self.params.bandwidth(), Ordering::AcqRel);
+        }
+    }
+}
+
+/// 上限を超えない場合のみ帯域を予約する
+fn try_reserve(reserved: &AtomicU64, bandwidth: u64) -> bool {
+    reserved
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            used.checked_add(bandwidth)
+                .filter(|&total| total <= MAX_DL_BANDWIDTH)
+        })
+        .is_ok()
+}
+
+/// 帯域に空きのあるCPUを選んで予約する（予約の少ないCPUから試す）
+fn reserve_bandwidth(bandwidth: u64) -> Result<usize, TaskError> {
+    let this_cpu = cpu::current_cpu();
+    let mut cpus: Vec<usize> = (0..cpu::cpu_count())
+        .filter(|&cpu| cpu == this_cpu || cpu::is_online(cpu))
+        .collect();
+    cpus.sort_by_key(|&cpu| {
+        (
+            DL_BANDWIDTH.get_for(cpu).load(Ordering::Acquire),
+            cpu != this_cpu,
+        )
+    });
+    cpus.into_iter()
+        .find(|&cpu| try_reserve(DL_BANDWIDTH.get_for(cpu), bandwidth))
+        .ok_or(TaskError::AdmissionDenied)
+}
+
+/// 指定CPUの予約済み帯域（パーセント）
+pub fn dl_bandwidth_percent(cpu: usize) -> u64 {
+    (DL_BANDWIDTH.get_for(cpu).load(Ordering::Acquire) * 100) >> BW_SHIFT
+}
+
+/// 指定CPUに再スケジューリングを要求（高精度タイマーのコールバックから呼び出す）
+fn resched_cpu(cpu: usize) {
+    if cpu == cpu::current_cpu() {
+        set_need_resched();
+    } else {
+        runqueue::send_resched_ipi(cpu);
+    }
+}
+
+/// 現在のCPUの予算切れタイマーを設定し直す
+///
+/// 同じタスクの予算切れ時刻が変わっていなければ、設定済みのタイマーをそのまま使う。
+/// 割り込み無効状態で、CURRENT_TASKをロックしていない状態で呼び出すこと。
+///
+/// # Arguments
+/// * `budget` - これから実行する期限クラスのタスクとその残り予算（期限クラス以外ならNone）
+pub(super) fn arm_budget_timer(budget: Option<(TaskId, u64)>) {
+    let mut timer = BUDGET_TIMER.get().lock();
+    let next = budget
+        .map(|(task, budget_ns)| (task, crate::time::monotonic_ns().saturating_add(budget_ns)));
+    if let (Some(armed), Some((task, expires_at))) = (timer.as_ref(), next)
+        && armed.task == task
+        && armed.expires_at.abs_diff(expires_at) <= BUDGET_TIMER_SLACK_NS
+    {
+        return;
+    }
+    if let Some(armed) = timer.take() {
+        hrtimer::cancel(armed.id);
+    }
+    if let Some((task, expires_at)) = next {
+        let cpu = cpu::current_cpu();
+        let id = hrtimer::start(
+            expires_at,
+            HrTimerContext::HardIrq,
+            alloc::boxed::Box::new(move || budget_expired(cpu, task, expires_at)),
+        );
+        *timer = Some(BudgetTimer {
+            task,
+            expires_at,
+            id,
+        });
+    }
+}
+
+/// 予算切れタイマーのコールバック
+///
+/// 期限切れになったタイマーの設定を消し、次の`arm_budget_timer()`で設定し直させてから、
+/// そのCPUに再スケジューリングを要求する。
+fn budget_expired(cpu: usize, task: TaskId, expires_at: u64) {
+    without_interrupts(|| {
+        let mut timer = BUDGET_TIMER.get_for(cpu).lock();
+        if timer
+            .as_ref()
+            .is_some_and(|armed| armed.task == task && armed.expires_at == expires_at)
+        {
+            *timer = None;
+        }
+    });
+    resched_cpu(cpu);
+}
+
+/// スロットリングしたタスクの予算を補充する時刻に、現在のCPUへ再スケジューリングを要求する
+///
+/// 割り込み無効状態で、CURRENT_TASKをロックしていない状態で呼び出すこと。
+pub(super) fn arm_replenish_timer(at_ns: u64) {
+    let cpu = cpu::current_cpu();
+    hrtimer::start(
+        at_ns,
+        HrTimerContext::HardIrq,
+        alloc::boxed::Box::new(move || resched_cpu(cpu)),
+    );
+}
+
+/// 期限クラスの現在のタスクの今回のジョブを完了し、次の周期まで待つ
+///
+/// 周期的な処理（1フレームの描画など）を終えた時に呼び出す。残りの予算は破棄され、
+/// 次の周期の開始時に予算が補充されて実行を再開する。期限クラス以外のタスクから
+/// 呼び出した場合は`yield_now()`と同じ。
+pub fn wait_next_period() {
+    without_interrupts(|| {
+        let mut current = CURRENT_TASK.get().lock();
+        if let Some(task) = current.as_mut() {
+            task.complete_deadline_job(crate::time::monotonic_ns());
+        }
+    });
+    super::scheduler::schedule();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test_case]
+    fn test_deadline_params_validation() {
+        assert!(DeadlineParams::new(2 * MS, 10 * MS, 16 * MS).is_ok());
+        assert!(DeadlineParams::new(2 * MS, 2 * MS, 2 * MS).is_ok());
+        assert_eq!(
+            DeadlineParams::new(0, 10 * MS, 16 * MS),
+            Err(TaskError::InvalidDeadlineParams)
+        );
+        assert_eq!(
+            DeadlineParams::new(11 * MS, 10 * MS, 16 * MS),
+            Err(TaskError::InvalidDeadlineParams)
+        );
+        assert_eq!(
+            DeadlineParams::new(2 * MS, 17 * MS, 16 * MS),
+            Err(TaskError::InvalidDeadlineParams)
+        );
+    }
+
+    #[test_case]
+    fn test_admission_rejects_overcommit() {
+        let reserved = AtomicU64::new(0);
+        let half = DeadlineParams::new(5 * MS, 10 * MS, 10 * MS).unwrap();
+        let small = DeadlineParams::new(MS, 20 * MS, 20 * MS).unwrap();
+        assert!(try_reserve(&reserved, half.bandwidth()));
+        assert!(try_reserve(&reserved, small.bandwidth()));
+        // 50% + 5% + 50% は上限(95%)を超える
+        assert!(!try_reserve(&reserved, half.bandwidth()));
+        assert_eq!(
+            reserved.load(Ordering::Relaxed),
+            half.bandwidth() + small.bandwidth()
+        );
+    }
+
+    #[test_case]
+    fn test_cbs_throttle_and_replenish() {
+        let params = DeadlineParams::new(2 * MS, 8 * MS, 16 * MS).unwrap();
+        let mut dl = DeadlineState::new(params);
+        dl.start_job(100 * MS);
+        assert_eq!(dl.abs_deadline(), 108 * MS);
+
+        assert!(!dl.charge(MS));
+        // 予算を1ms超過してスロットリング（次の周期の開始まで）
+        assert!(dl.charge(2 * MS));
+        assert_eq!(dl.throttled_until(), Some(116 * MS));
+        dl.replenish(115 * MS);
+        assert_eq!(dl.throttled_until(), Some(116 * MS));
+
+        // 超過分は次の周期の予算から差し引かれる
+        dl.replenish(116 * MS);
+        assert_eq!(dl.throttled_until(), None);
+        assert_eq!(dl.abs_deadline(), 124 * MS);
+        assert_eq!(dl.remaining_ns(), MS);
+
+        // 期限内に完了したジョブは期限超過ではない
+        assert!(!dl.complete_job(120 * MS));
+        assert_eq!(dl.throttled_until(), Some(132 * MS));
+    }
+
+    #[test_case]
+    fn test_cbs_wakeup_rule() {
+        let params = DeadlineParams::new(2 * MS, 10 * MS, 10 * MS).unwrap();
+        let mut dl = DeadlineState::new(params);
+        dl.start_job(0);
+        assert!(!dl.charge(MS));
+
+        // 残り1msを期限まで5msで使っても帯域(20%)以内なので現在のジョブを続ける
+        dl.wakeup(5 * MS);
+        assert_eq!(dl.abs_deadline(), 10 * MS);
+        // 期限の直前では帯域を超えるため、新しいジョブを開始する
+        dl.wakeup(9 * MS);
+        assert_eq!(dl.abs_deadline(), 19 * MS);
+        assert_eq!(dl.remaining_ns(), 2 * MS);
+    }
+}
@@ -0,0 +1,232 @@
+//! タスクの終了と回収
+//!
+//! タスクは`exit()`を呼ぶか、エントリポイントから戻ることで終了します
+//! （`extern "C" fn() -> i32`やクロージャの戻り値が終了コードになる）。
+//!
+//! 終了したタスクはコンテキストスイッチ後の`finish_task_switch()`で
+//! 回収待ちリストに移され、回収タスク（reaper）がスタックと`Box<Task>`を解放します。
+//! 回収タスクの起動前に終了したタスクは、その場で解放されます。
+//!
+//! 終了コードは`JoinHandle::join()`で待ち合わせて受け取れます。
+//! `JoinHandle`を破棄してもタスクには影響しません（detach）。
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::io::without_interrupts;
+
+use super::blocking::{block_current_task, is_interrupt_context, unblock_task};
+use super::scheduler::{CURRENT_TASK, add_task, schedule};
+use super::task::{Task, TaskId, TaskState};
+
+/// 終了コードの型
+pub type ExitCode = i32;
+
+/// タスクとして実行するクロージャの戻り値から終了コードへの変換
+pub trait IntoExitCode {
+    fn into_exit_code(self) -> ExitCode;
+}
+
+impl IntoExitCode for () {
+    fn into_exit_code(self) -> ExitCode {
+        0
+    }
+}
+
+impl IntoExitCode for ExitCode {
+    fn into_exit_code(self) -> ExitCode {
+        self
+    }
+}
+
+/// 終了を待つタスクの状態
+struct ExitInner {
+    /// 終了コード（終了前はNone）
+    code: Option<ExitCode>,
+    /// `join()`で待機中のタスク
+    waiters: Vec<TaskId>,
+}
+
+/// タスクの終了状態（タスクと`JoinHandle`で共有）
+pub(super) struct ExitState {
+    inner: Mutex<ExitInner>,
+}
+
+impl ExitState {
+    pub(super) fn new() -> Self {
+        Self {
+            inner: Mutex::new(ExitInner {
+                code: None,
+                waiters: Vec::new(),
+            }),
+        }
+    }
+
+    /// 終了コードを記録し、待機中のタスクを起床させる
+    fn complete(&self, code: ExitCode) {
+        let waiters = without_interrupts(|| {
+            let mut inner = self.inner.lock();
+            inner.code = Some(code);
+            core::mem::take(&mut inner.waiters)
+        });
+        for id in waiters {
+            unblock_task(id);
+        }
+    }
+
+    fn code(&self) -> Option<ExitCode> {
+        without_interrupts(|| self.inner.lock().code)
+    }
+}
+
+/// タスクの終了を待ち合わせるハンドル
+///
+/// `add_task()`が返す。破棄してもタスクは実行を続ける。
+pub struct JoinHandle {
+    id: TaskId,
+    exit: Arc<ExitState>,
+}
+
+impl JoinHandle {
+    pub(super) fn new(task: &Task) -> Self {
+        Self {
+            id: task.id(),
+            exit: task.exit_state().clone(),
+        }
+    }
+
+    /// 対象タスクのID
+    pub fn task_id(&self) -> TaskId {
+        self.id
+    }
+
+    /// 対象タスクが終了済みかどうか
+    pub fn is_finished(&self) -> bool {
+        self.exit.code().is_some()
+    }
+
+    /// 終了済みなら終了コードを返す（ブロックしない）
+    pub fn try_join(&self) -> Option<ExitCode> {
+        self.exit.code()
+    }
+
+    /// 対象タスクの終了を待ち、終了コードを返す
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn join(self) -> ExitCode {
+        debug_assert!(
+            !is_interrupt_context(),
+            "JoinHandle::join() cannot be called from interrupt context"
+        );
+        let me = super::scheduler::current_task_id();
+        loop {
+            // 終了コードの確認と待機登録を同じロック内で行い、起床の取りこぼしを防ぐ
+            // （登録から実際にブロックするまでの間の起床はWAKEUP_PENDINGで検出される）
+            let code = without_interrupts(|| {
+                let mut inner = self.exit.inner.lock();
+                if inner.code.is_none() {
+                    inner.waiters.push(me);
+                }
+                inner.code
+            });
+            if let Some(code) = code {
+                return code;
+            }
+            block_current_task();
+        }
+    }
+}
+
+/// 回収待ちの終了したタスク
+static DEAD_TASKS: Mutex<VecDeque<Box<Task>>> = Mutex::new(VecDeque::new());
+
+/// 回収タスクのID（起動前はNone）
+static REAPER: Mutex<Option<TaskId>> = Mutex::new(None);
+
+/// 現在のタスクを終了する
+///
+/// `join()`で待機中のタスクを起床させてから、二度とスケジュールされない状態にする。
+/// タスクのスタックとタスク構造体は回収タスクが解放する。
+///
+/// # Arguments
+/// * `code` - 終了コード（`JoinHandle::join()`の戻り値）
+pub fn exit(code: ExitCode) -> ! {
+    let exit = without_interrupts(|| {
+        let current = CURRENT_TASK.get().lock();
+        current.as_ref().map(|task| task.exit_state().clone())
+    });
+    if let Some(exit) = exit {
+        exit.complete(code);
+    }
+
+    without_interrupts(|| {
+        if let Some(task) = CURRENT_TASK.get().lock().as_mut() {
+            task.set_state(TaskState::Terminated);
+        }
+    });
+    // Terminatedのタスクはランキューに戻されないため、ここには戻らない
+    // （実行可能なタスクが一時的にない場合に備えて繰り返す）
+    loop {
+        schedule();
+    }
+}
+
+/// エントリポイントから戻ったタスクの終了処理（task_entry_trampolineから呼ばれる）
+pub(super) extern "C" fn exit_from_entry(code: ExitCode) -> ! {
+    exit(code)
+}
+
+/// 終了したタスクを回収待ちリストに移す
+///
+/// `finish_task_switch()`から割り込み無効状態で呼び出される。
+/// 回収タスクが起動していなければその場で解放する。
+pub(super) fn reap_later(task: Box<Task>) {
+    let Some(reaper) = *REAPER.lock() else {
+        drop(task);
+        return;
+    };
+    DEAD_TASKS.lock().push_back(task);
+    unblock_task(reaper);
+}
+
+/// 回収タスク：終了したタスクのスタックとタスク構造体を解放する
+extern "C" fn reaper_task() -> ! {
+    loop {
+        let dead = without_interrupts(|| core::mem::take(&mut *DEAD_TASKS.lock()));
+        // 割り込み有効状態で解放する
+        drop(dead);
+
+        // 回収待ちがなければ次の終了まで待機する
+        // （確認後に追加された場合の起床はWAKEUP_PENDINGで検出される）
+        if without_interrupts(|| DEAD_TASKS.lock().is_empty()) {
+            block_current_task();
+        }
+    }
+}
+
+/// 回収タスクを起動する
+///
+/// ヒープの初期化後に1度だけ呼び出す。
+pub fn start_reaper() {
+    let task = Task::new("Reaper", super::task::nice::DEFAULT, reaper_task)
+        .expect("Failed to create reaper task");
+    without_interrupts(|| *REAPER.lock() = Some(task.id()));
+    add_task(task);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_exit_state_records_code_once_completed() {
+        let exit = ExitState::new();
+        assert_eq!(exit.code(), None);
+        exit.complete(-3);
+        assert_eq!(exit.code(), Some(-3));
+    }
+}
@@ -5,30 +5,77 @@
 //! # モジュール構成
 //! - `task`: タスク構造体、状態、優先度の定義
 //! - `context`: CPUコンテキストとコンテキストスイッチ
-//! - `scheduler`: スケジューラとキュー管理
+//! - `scheduler`: スケジューラとコンテキストスイッチの制御
+//! - `runqueue`: CPU毎のランキューと負荷分散
 //! - `blocking`: タスクのブロッキングとスリープ機能
+//! - `exit`: タスクの終了・回収と終了の待ち合わせ
+//! - `stats`: タスク一覧のスナップショットと実行統計の出力
+//! - `pi`: BlockingMutexの優先度継承
+//! - `deadline`: 期限クラス（EDF・受け入れ制御・スロットリング）
 
 mod blocking;
 mod context;
+mod deadline;
+mod exit;
+mod pi;
+mod runqueue;
 mod scheduler;
+mod stats;
 mod task;
 
 // 公開API: タスク関連
+pub use task::SchedulingClass;
+pub use task::SpawnOptions;
 pub use task::Task;
+pub use task::TaskError;
 pub use task::TaskId;
+pub use task::TaskPriority;
+pub use task::TaskState;
+pub use task::TaskStats;
 pub use task::nice;
 pub use task::rt_priority;
+pub use task::stack_size;
 
 // 公開API: スケジューラ関連
+pub use scheduler::account_tick;
 pub use scheduler::add_task;
+pub use scheduler::can_stop_tick;
 pub use scheduler::check_resched_on_interrupt_exit;
 pub use scheduler::current_task_id;
 pub use scheduler::init;
 pub use scheduler::schedule;
 pub use scheduler::set_current_task;
 pub use scheduler::set_need_resched;
+pub use scheduler::spawn;
+pub use scheduler::trigger_load_balance;
+pub use scheduler::try_add_task;
+pub use scheduler::try_with_current_task;
 pub use scheduler::update_current_task_vruntime;
 
+// 公開API: 終了関連
+pub use exit::ExitCode;
+pub use exit::IntoExitCode;
+pub use exit::JoinHandle;
+pub use exit::exit;
+pub use exit::start_reaper;
+
+// 公開API: タスク一覧・統計関連
+pub use stats::TaskInfo;
+pub use stats::dump_tasks;
+pub use stats::for_each_task;
+pub use stats::state_label;
+pub use stats::task_snapshot;
+
+// 公開API: 優先度継承関連
+pub use pi::current_priority;
+pub use pi::set_pi_boost;
+pub use pi::task_pi_boost;
+
+// 公開API: 期限クラス関連
+pub use deadline::DeadlineParams;
+pub use deadline::dl_bandwidth_percent;
+pub use deadline::wait_next_period;
+
 // 公開API: ブロッキング関連
 pub use blocking::block_current_task;
 pub use blocking::is_interrupt_context;
@@ -0,0 +1,163 @@
+//! 優先度継承（Priority Inheritance）
+//!
+//! 低優先度のタスクが保持する`BlockingMutex`を高優先度のタスクが待つと、
+//! 中間の優先度のタスクに保持タスクが追い越され続け、高優先度のタスクが
+//! 無期限に待たされる（優先度逆転）。これを防ぐため、ロックの保持タスクを
+//! 待機タスクのうち最も高いスケジューリングクラス・優先度まで一時的に引き上げ、
+//! ロックの解放時に元に戻します。
+//!
+//! # 制限
+//! 引き上げは1段階のみで、保持タスク自身が別のBlockingMutexで待機している場合に
+//! その保持タスクへ引き上げを伝播させることはしません。
+//!
+//! # タスクの探索
+//! 対象のタスクは、手掛かりのCPU（保持タスクがロックを取得したCPU）から順に
+//! 1CPUずつ探します。全CPUのキューを同時にロックすることはしません。
+
+use crate::cpu;
+use crate::io::without_interrupts;
+
+use super::blocking::BLOCKED_TASKS;
+use super::runqueue::{self, RunQueue};
+use super::scheduler::{CURRENT_TASK, PREV_TASK, set_need_resched};
+use super::task::{Task, TaskId, TaskPriority};
+
+/// タスクを探す際に全CPUを調べ直す回数の上限
+///
+/// CPU毎に順に調べるため、調べ終えたCPUへ移動したタスクは見落とす。
+/// 移動は短時間で完了するため、数回調べ直せば見つかる。
+const LOOKUP_SWEEPS: usize = 3;
+
+/// タスクが見つかった場所
+enum TaskLocation<'a> {
+    /// CPUで実行中（CURRENT_TASK）
+    Running(usize, &'a mut Task),
+    /// 切り替え中（PREV_TASK）
+    Switching(&'a mut Task),
+    /// ブロック中（BLOCKED_TASKS）
+    Blocked(&'a mut Task),
+    /// CPUのランキューで実行待ち
+    Queued(usize, &'a mut RunQueue),
+}
+
+/// 現在のタスクの実効優先度を取得（タスクが存在しない場合はNone）
+pub fn current_priority() -> Option<TaskPriority> {
+    without_interrupts(|| {
+        CURRENT_TASK
+            .get()
+            .lock()
+            .as_ref()
+            .map(|task| task.priority())
+    })
+}
+
+/// タスクの優先度継承による引き上げを設定・解除
+///
+/// タスクがどのキューにいても（実行中・切り替え中・ブロック中・実行待ち）反映します。
+/// ランキュー内のタスクはキューのキーが変わるため、取り出してから再投入します。
+/// 実行中・実行待ちのタスクの優先度が変わった場合は、そのCPUに再スケジューリングを要求します。
+///
+/// # Arguments
+/// * `task_id` - 対象のタスク
+/// * `cpu_hint` - タスクを最初に探すCPU（BlockingMutexでは保持タスクがロックを取得したCPU）
+/// * `key` - 引き上げの原因（BlockingMutexのアドレス）
+/// * `boost` - 継承する優先度（Noneなら`key`による引き上げを解除）
+pub fn set_pi_boost(task_id: TaskId, cpu_hint: usize, key: usize, boost: Option<TaskPriority>) {
+    without_interrupts(|| {
+        let Some(cpu) = update_boost(task_id, cpu_hint, key, boost) else {
+            return;
+        };
+        if cpu == cpu::current_cpu() {
+            set_need_resched();
+        } else {
+            runqueue::send_resched_ipi(cpu);
+        }
+    });
+}
+
+/// タスクの優先度継承による現在の引き上げを取得
+///
+/// タスクが存在しないか、引き上げられていなければNoneを返す。
+pub fn task_pi_boost(task_id: TaskId) -> Option<TaskPriority> {
+    without_interrupts(|| {
+        with_task(task_id, cpu::current_cpu(), |location| match location {
+            TaskLocation::Running(_, task)
+            | TaskLocation::Switching(task)
+            | TaskLocation::Blocked(task) => task.stats().pi_boost,
+            TaskLocation::Queued(_, rq) => rq
+                .tasks()
+                .find(|task| task.id() == task_id)
+                .and_then(|task| task.stats().pi_boost),
+        })
+        .flatten()
+    })
+}
+
+/// タスクを探して引き上げを反映し、再スケジューリングが必要なCPUを返す
+///
+/// 割り込み無効状態で呼び出すこと。
+fn update_boost(
+    task_id: TaskId,
+    cpu_hint: usize,
+    key: usize,
+    boost: Option<TaskPriority>,
+) -> Option<usize> {
+    with_task(task_id, cpu_hint, |location| match location {
+        TaskLocation::Running(cpu, task) => {
+            task.set_pi_boost(key, boost);
+            Some(cpu)
+        }
+        // 切り替え中のタスクは優先度のみを変更し、コンテキストには触れない
+        // （キューへの移動はfinish_task_switch()が新しい優先度で行う）
+        TaskLocation::Switching(task) | TaskLocation::Blocked(task) => {
+            task.set_pi_boost(key, boost);
+            None
+        }
+        // ランキュー内のタスクはキューのキーが変わるため、取り出してから再投入する
+        TaskLocation::Queued(cpu, rq) => {
+            let mut task = rq.dequeue(task_id)?;
+            task.set_pi_boost(key, boost);
+            rq.enqueue(task);
+            Some(cpu)
+        }
+    })
+    .flatten()
+}
+
+/// `cpu_hint`のCPUから順にタスクを探し、見つかった場所で`f`を実行
+///
+/// 各CPUについて、そのCPUのCURRENT_TASK → PREV_TASK → BLOCKED_TASKS → ランキューの順
+/// （ロック順序どおり）にロックし、同時に保持したまま探す。タスクはこれらの間を
+/// 移動する間も必ずいずれかに見えるため、他のCPUへ移動していなければ見落とさない。
+/// 他のCPUのロックは同時に保持しない。
+///
+/// 割り込み無効状態で、いずれのキューもロックしていない状態で呼び出すこと。
+fn with_task<R>(
+    task_id: TaskId,
+    cpu_hint: usize,
+    f: impl FnOnce(TaskLocation<'_>) -> R,
+) -> Option<R> {
+    let cpus = cpu::cpu_count();
+    let start = if cpu_hint < cpus { cpu_hint } else { 0 };
+    for _ in 0..LOOKUP_SWEEPS {
+        for cpu in (start..cpus).chain(0..start) {
+            let mut current = CURRENT_TASK.get_for(cpu).lock();
+            if let Some(task) = current.as_deref_mut().filter(|task| task.id() == task_id) {
+                return Some(f(TaskLocation::Running(cpu, task)));
+            }
+            let mut prev = PREV_TASK.get_for(cpu).lock();
+            if let Some(task) = prev.as_deref_mut().filter(|task| task.id() == task_id) {
+                return Some(f(TaskLocation::Switching(task)));
+            }
+            let mut blocked = BLOCKED_TASKS.lock();
+            if let Some(task) = blocked.get_mut(&task_id.as_u64()) {
+                return Some(f(TaskLocation::Blocked(task)));
+            }
+            let mut rq = runqueue::cpu_rq(cpu);
+            if rq.tasks().any(|task| task.id() == task_id) {
+                return Some(f(TaskLocation::Queued(cpu, &mut rq)));
+            }
+        }
+    }
+    None
+}
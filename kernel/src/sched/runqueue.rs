@@ -0,0 +1,495 @@
+//! CPU毎のランキューと負荷分散
+//!
+//! 各CPUは自身のランキュー（Deadline/RT/CFS/Idle）を持ち、自身のAPICタイマーで
+//! 独立してschedule()を実行します。
+//!
+//! 期限クラスのタスクは帯域を予約したCPUに固定され、負荷分散の対象になりません。
+//!
+//! # 負荷分散
+//! - 定期分散: 各CPUは`BALANCE_INTERVAL_TICKS`毎に最も負荷の高いCPUから
+//!   CFSタスクを引き抜く（pull方式）
+//! - アイドル分散: ローカルにRT/CFSタスクがない場合、schedule()内で
+//!   他CPUからCFSタスクを1つ引き抜く
+//!
+//! 移動するタスクのvruntimeは移動元の`min_vruntime`からの相対値として
+//! 移動先の`min_vruntime`に付け替える（CPU間でvruntimeの基準が異なるため）。
+//!
+//! # ロック順序
+//! 2つのランキューを同時にロックする場合は、必ず論理CPU番号の小さい方から取得する。
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicU8, Ordering};
+use spin::{Mutex, MutexGuard};
+
+use crate::cpu;
+use crate::irq::{self, InterruptFrame, IrqVector};
+use crate::{ipi, percpu};
+
+use super::task::{SchedulingClass, Task, TaskId, rt_priority};
+
+/// 定期負荷分散の間隔（tick数、250Hzで約100ms）
+pub(super) const BALANCE_INTERVAL_TICKS: u64 = 25;
+
+/// 再スケジューリングIPIのベクタ（0は未登録）
+static RESCHED_VECTOR: AtomicU8 = AtomicU8::new(0);
+
+/// CPU毎のランキュー
+pub(super) struct RunQueue {
+    /// 期限キュー (Deadlineクラスのタスク、EDF方式)
+    /// キー: (絶対期限, task_id) - 期限が早い順にソート
+    dl: BTreeMap<(u64, u64), Box<Task>>,
+    /// スロットリング中の期限クラスのタスク
+    /// キー: (予算を補充する時刻, task_id)
+    dl_throttled: BTreeMap<(u64, u64), Box<Task>>,
+    /// リアルタイムキュー (Realtimeクラスのタスク)
+    /// キー: (255 - priority, task_id) - 優先度が高い順にソート
+    rt: BTreeMap<(u8, u64), Box<Task>>,
+    /// 通常キュー (Normalクラスのタスク、CFS方式)
+    /// キー: (vruntime, task_id) - vruntimeでソートされ、同じvruntimeの場合はtask_idで区別
+    cfs: BTreeMap<(u64, u64), Box<Task>>,
+    /// アイドルキュー (Idleクラスのタスク、FIFO順)
+    idle: VecDeque<Box<Task>>,
+    /// このキューのvruntimeの基準値（単調増加）
+    min_vruntime: u64,
+    /// 現在実行中のタスクがNormalクラスかどうか（負荷計算用）
+    curr_is_cfs: bool,
+}
+
+impl RunQueue {
+    const fn new() -> Self {
+        Self {
+            dl: BTreeMap::new(),
+            dl_throttled: BTreeMap::new(),
+            rt: BTreeMap::new(),
+            cfs: BTreeMap::new(),
+            idle: VecDeque::new(),
+            min_vruntime: 0,
+            curr_is_cfs: false,
+        }
+    }
+
+    /// タスクをスケジューリングクラスに応じたキューに追加
+    pub(super) fn enqueue(&mut self, mut task: Box<Task>) {
+        match task.sched_class() {
+            SchedulingClass::Deadline => {
+                let id = task.id().as_u64();
+                match task
+                    .deadline()
+                    .map(|dl| (dl.throttled_until(), dl.abs_deadline()))
+                {
+                    Some((Some(until), _)) => {
+                        self.dl_throttled.insert((until, id), task);
+                    }
+                    Some((None, abs_deadline)) => {
+                        self.dl.insert((abs_deadline, id), task);
+                    }
+                    None => unreachable!("Deadline class task without deadline state"),
+                }
+            }
+            SchedulingClass::Realtime => {
+                let key = (rt_priority::MAX - task.rt_priority(), task.id().as_u64());
+                self.rt.insert(key, task);
+            }
+            SchedulingClass::Normal => {
+                // 新規・起床タスクが長時間CPUを独占しないよう、基準値未満のvruntimeは切り上げる
+                if task.vruntime() < self.min_vruntime {
+                    task.set_vruntime(self.min_vruntime);
+                }
+                let key = (task.vruntime(), task.id().as_u64());
+                self.cfs.insert(key, task);
+            }
+            SchedulingClass::Idle => {
+                self.idle.push_back(task);
+            }
+        }
+    }
+
+    /// 次に実行するタスクを取り出す（Deadline > Realtime > Normal > Idle）
+    pub(super) fn pick_next(&mut self) -> Option<Box<Task>> {
+        let next = self
+            .dl
+            .pop_first()
+            .map(|(_, task)| task)
+            .or_else(|| self.rt.pop_first().map(|(_, task)| task))
+            .or_else(|| self.cfs.pop_first().map(|(_, task)| task))
+            .or_else(|| self.idle.pop_front())?;
+
+        self.curr_is_cfs = next.sched_class() == SchedulingClass::Normal;
+        if self.curr_is_cfs {
+            self.update_min_vruntime(next.vruntime());
+        }
+        Some(next)
+    }
+
+    /// min_vruntimeを更新（実行中タスクとキュー先頭の小さい方、ただし単調増加）
+    fn update_min_vruntime(&mut self, curr_vruntime: u64) {
+        let leftmost = self
+            .cfs
+            .first_key_value()
+            .map_or(u64::MAX, |(&(v, _), _)| v);
+        self.min_vruntime = self.min_vruntime.max(curr_vruntime.min(leftmost));
+    }
+
+    /// スロットリングの期限が過ぎた期限クラスのタスクの予算を補充し、期限キューに戻す
+    pub(super) fn replenish_deadline(&mut self, now: u64) {
+        while let Some(entry) = self.dl_throttled.first_entry()
+            && entry.key().0 <= now
+        {
+            let mut task = entry.remove();
+            if let Some(dl) = task.deadline_mut() {
+                dl.replenish(now);
+            }
+            self.enqueue(task);
+        }
+    }
+
+    /// 実行中のタスクより先に実行すべきタスクがキューにあるかどうか
+    ///
+    /// 期限クラスのタスクは、より早い絶対期限を持つ期限クラスのタスクにのみ譲る。
+    /// それ以外のクラスでは、実行可能なタスクがあれば譲る（tick毎のラウンドロビン）。
+    pub(super) fn should_preempt(&self, curr: &Task) -> bool {
+        match curr.deadline() {
+            Some(dl) => self
+                .dl
+                .first_key_value()
+                .is_some_and(|(&(abs_deadline, _), _)| abs_deadline < dl.abs_deadline()),
+            None => self.has_runnable(),
+        }
+    }
+
+    /// Deadline/RT/CFSの実行可能タスクがキューにあるかどうか
+    pub(super) fn has_runnable(&self) -> bool {
+        !self.dl.is_empty() || !self.rt.is_empty() || !self.cfs.is_empty()
+    }
+
+    /// 負荷（キュー内のDeadline/RT/CFSタスク数 + 実行中のCFSタスク）
+    pub(super) fn load(&self) -> usize {
+        self.dl.len() + self.rt.len() + self.cfs.len() + self.curr_is_cfs as usize
+    }
+
+    /// キュー内の全タスクを列挙
+    pub(super) fn tasks(&self) -> impl Iterator<Item = &Task> {
+        self.dl
+            .values()
+            .chain(self.dl_throttled.values())
+            .chain(self.rt.values())
+            .chain(self.cfs.values())
+            .chain(self.idle.iter())
+            .map(|task| &**task)
+    }
+
+    /// 指定IDのタスクをキューから取り出す（優先度の変更用）
+    pub(super) fn dequeue(&mut self, id: TaskId) -> Option<Box<Task>> {
+        let id = id.as_u64();
+        for queue in [&mut self.dl, &mut self.dl_throttled] {
+            if let Some(key) = queue.keys().find(|&&(_, task_id)| task_id == id).copied() {
+                return queue.remove(&key);
+            }
+        }
+        if let Some(key) = self.rt.keys().find(|&&(_, task_id)| task_id == id).copied() {
+            return self.rt.remove(&key);
+        }
+        if let Some(key) = self
+            .cfs
+            .keys()
+            .find(|&&(_, task_id)| task_id == id)
+            .copied()
+        {
+            return self.cfs.remove(&key);
+        }
+        let index = self.idle.iter().position(|task| task.id().as_u64() == id)?;
+        self.idle.remove(index)
+    }
+
+    /// 移動用にCFSタスクを1つ取り出す（vruntimeが最大のもの）
+    ///
+    /// vruntimeが大きいタスクは直近で多く実行されておらず、次の実行まで
+    /// 最も時間があるため、移動によるキャッシュへの影響が小さい。
+    fn detach_cfs_task(&mut self) -> Option<Box<Task>> {
+        self.cfs.pop_last().map(|(_, task)| task)
+    }
+}
+
+percpu! {
+    /// CPU毎のランキュー
+    static RUN_QUEUES: Mutex<RunQueue> = Mutex::new(RunQueue::new());
+}
+
+/// 現在のCPUのランキューをロック
+///
+/// 割り込み無効状態で呼び出すこと。
+pub(super) fn this_rq() -> MutexGuard<'static, RunQueue> {
+    RUN_QUEUES.get().lock()
+}
+
+/// 指定CPUのランキューをロック
+///
+/// 割り込み無効状態で呼び出すこと。
+pub(super) fn cpu_rq(cpu: usize) -> MutexGuard<'static, RunQueue> {
+    RUN_QUEUES.get_for(cpu).lock()
+}
+
+/// 移動元キュー基準のvruntimeを移動先キュー基準に変換
+fn normalize_vruntime(vruntime: u64, from_min: u64, to_min: u64) -> u64 {
+    vruntime.saturating_sub(from_min).saturating_add(to_min)
+}
+
+/// 負荷差から移動すべきタスク数を計算
+///
+/// 差が2以上ある場合に、差の半分を移動する（1つ差では移動しても偏りが逆転するだけ）。
+fn imbalance(this_load: usize, busiest_load: usize) -> usize {
+    if busiest_load >= this_load + 2 {
+        (busiest_load - this_load) / 2
+    } else {
+        0
+    }
+}
+
+/// 負荷最大のオンラインCPUを探す（自CPUを除く）
+fn find_busiest_cpu(this_cpu: usize) -> Option<(usize, usize)> {
+    (0..cpu::cpu_count())
+        .filter(|&cpu| cpu != this_cpu && cpu::is_online(cpu))
+        .map(|cpu| (cpu, cpu_rq(cpu).load()))
+        .max_by_key(|&(_, load)| load)
+}
+
+/// 2つのランキューを論理CPU番号順にロックし、(this, other)の順で返す
+fn lock_pair(
+    this_cpu: usize,
+    other_cpu: usize,
+) -> (MutexGuard<'static, RunQueue>, MutexGuard<'static, RunQueue>) {
+    if this_cpu < other_cpu {
+        let this = cpu_rq(this_cpu);
+        let other = cpu_rq(other_cpu);
+        (this, other)
+    } else {
+        let other = cpu_rq(other_cpu);
+        let this = cpu_rq(this_cpu);
+        (this, other)
+    }
+}
+
+/// `src`から`dst`へCFSタスクを最大`count`個移動
+fn migrate_cfs_tasks(
+    src: &mut RunQueue,
+    dst: &mut RunQueue,
+    dst_cpu: usize,
+    count: usize,
+) -> usize {
+    let mut moved = 0;
+    while moved < count {
+        let Some(mut task) = src.detach_cfs_task() else {
+            break;
+        };
+        let vruntime = normalize_vruntime(task.vruntime(), src.min_vruntime, dst.min_vruntime);
+        task.set_vruntime(vruntime);
+        task.set_cpu(dst_cpu);
+        dst.enqueue(task);
+        moved += 1;
+    }
+    moved
+}
+
+/// 定期負荷分散: 最も負荷の高いCPUから差の半分のCFSタスクを引き抜く
+///
+/// 割り込み無効状態で呼び出すこと。
+pub(super) fn load_balance() {
+    let this_cpu = cpu::current_cpu();
+    let Some((busiest, _)) = find_busiest_cpu(this_cpu) else {
+        return;
+    };
+
+    let (mut this, mut other) = lock_pair(this_cpu, busiest);
+    let count = imbalance(this.load(), other.load());
+    if count > 0 {
+        migrate_cfs_tasks(&mut other, &mut this, this_cpu, count);
+    }
+}
+
+/// アイドル分散: ローカルに実行可能タスクがない場合、他CPUのキューからCFSタスクを1つ引き抜く
+///
+/// 割り込み無効状態で、ローカルのランキューをロックしていない状態で呼び出すこと。
+pub(super) fn idle_balance() {
+    let this_cpu = cpu::current_cpu();
+    let Some((busiest, load)) = find_busiest_cpu(this_cpu) else {
+        return;
+    };
+    // 実行中の1タスクのみのCPUからは奪わない
+    if load < 2 {
+        return;
+    }
+
+    let (mut this, mut other) = lock_pair(this_cpu, busiest);
+    if !this.has_runnable() {
+        migrate_cfs_tasks(&mut other, &mut this, this_cpu, 1);
+    }
+}
+
+/// 新しいタスクを配置するCPUを選択
+///
+/// Normalクラスは負荷最小のオンラインCPUへ、それ以外（Realtime/Idle）は現在のCPUへ配置する。
+/// Deadlineクラスは帯域を予約したCPUへ配置するため、この関数は使わない。
+pub(super) fn select_task_rq(task: &Task) -> usize {
+    let this_cpu = cpu::current_cpu();
+    if task.sched_class() != SchedulingClass::Normal {
+        return this_cpu;
+    }
+    (0..cpu::cpu_count())
+        .filter(|&cpu| cpu == this_cpu || cpu::is_online(cpu))
+        .min_by_key(|&cpu| (cpu_rq(cpu).load(), cpu != this_cpu))
+        .unwrap_or(this_cpu)
+}
+
+/// タスクを指定CPUのランキューに追加し、リモートCPUなら再スケジューリングIPIを送る
+///
+/// 期限クラスのタスクを現在のCPUに追加した場合は、次のtickを待たずに切り替えられるよう
+/// 再スケジューリングを要求する。割り込み無効状態で呼び出すこと。
+pub(super) fn enqueue_on(cpu: usize, mut task: Box<Task>) {
+    task.set_cpu(cpu);
+    let is_deadline = task.sched_class() == SchedulingClass::Deadline;
+    cpu_rq(cpu).enqueue(task);
+    if cpu != cpu::current_cpu() {
+        send_resched_ipi(cpu);
+    } else if is_deadline {
+        super::scheduler::set_need_resched();
+    }
+}
+
+/// 再スケジューリングIPIハンドラ
+///
+/// need_reschedをセットするだけで、実際のschedule()は割り込み復帰時に行われる。
+fn resched_ipi_handler(_frame: &InterruptFrame) {
+    super::scheduler::set_need_resched();
+}
+
+/// 再スケジューリングIPIのベクタを登録
+pub(super) fn init_resched_ipi() {
+    match irq::request_irq(IrqVector::Any, resched_ipi_handler, "resched-ipi") {
+        Ok(vector) => RESCHED_VECTOR.store(vector, Ordering::Release),
+        Err(e) => crate::warn!("Failed to register reschedule IPI: {}", e),
+    }
+}
+
+/// 指定CPUへ再スケジューリングIPIを送信
+pub(super) fn send_resched_ipi(cpu: usize) {
+    let vector = RESCHED_VECTOR.load(Ordering::Acquire);
+    if vector == 0 {
+        return;
+    }
+    // 宛先がまだオンラインでない場合は、起動後の最初のschedule()でキューが処理される
+    let _ = ipi::send_to_cpu(cpu, vector);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sched::deadline::DeadlineParams;
+    use crate::sched::task::SpawnOptions;
+
+    #[test_case]
+    fn test_normalize_vruntime_keeps_relative_lag() {
+        // 移動元で基準値より300進んでいたタスクは、移動先でも基準値より300進んだ位置になる
+        assert_eq!(normalize_vruntime(1300, 1000, 5000), 5300);
+        // 基準値より小さい場合は移動先の基準値に揃える
+        assert_eq!(normalize_vruntime(900, 1000, 5000), 5000);
+    }
+
+    #[test_case]
+    fn test_imbalance() {
+        assert_eq!(imbalance(0, 0), 0);
+        assert_eq!(imbalance(0, 1), 0);
+        assert_eq!(imbalance(0, 2), 1);
+        assert_eq!(imbalance(1, 5), 2);
+        assert_eq!(imbalance(5, 1), 0);
+    }
+
+    const MS: u64 = 1_000_000;
+
+    fn test_task(options: SpawnOptions) -> Box<Task> {
+        Box::new(Task::from_closure("rq-test", options, || ()).unwrap())
+    }
+
+    fn deadline_task(params: DeadlineParams, now: u64) -> Box<Task> {
+        let mut task = test_task(SpawnOptions::deadline(params));
+        task.deadline_mut().unwrap().start_job(now);
+        task
+    }
+
+    #[test_case]
+    fn test_deadline_tasks_run_first_in_edf_order() {
+        let mut rq = RunQueue::new();
+        let late = deadline_task(DeadlineParams::new(MS, 20 * MS, 20 * MS).unwrap(), 0);
+        let early = deadline_task(DeadlineParams::new(MS, 10 * MS, 20 * MS).unwrap(), 0);
+        let (late_id, early_id) = (late.id(), early.id());
+        rq.enqueue(test_task(SpawnOptions::realtime(rt_priority::MAX)));
+        rq.enqueue(late);
+        rq.enqueue(early);
+
+        let first = rq.pick_next().unwrap();
+        assert_eq!(first.id(), early_id);
+        // 実行中の期限クラスのタスクは、より遅い期限のタスクやRTタスクには譲らない
+        assert!(!rq.should_preempt(&first));
+        assert_eq!(rq.pick_next().unwrap().id(), late_id);
+        assert_eq!(
+            rq.pick_next().unwrap().sched_class(),
+            SchedulingClass::Realtime
+        );
+    }
+
+    /// CFSタスクで常に埋まったCPUで、期限クラスのタスクが毎周期期限内に予算を得ることを
+    /// schedule()と同じ手順（計上 → 補充 → プリエンプション判定 → 選択）で模擬する
+    #[test_case]
+    fn test_deadline_task_meets_deadlines_under_cfs_load() {
+        const TICK: u64 = MS / 10;
+        const JOBS: usize = 10;
+        let params = DeadlineParams::new(2 * MS, 8 * MS, 16_600_000).unwrap();
+
+        let mut rq = RunQueue::new();
+        let dl_task = deadline_task(params, 0);
+        let dl_id = dl_task.id();
+        rq.enqueue(dl_task);
+        for nice in [-5, 0, 0, 5] {
+            rq.enqueue(test_task(SpawnOptions::normal(nice)));
+        }
+
+        let mut served = [0u64; JOBS];
+        let mut cfs_time = 0;
+        let mut current = rq.pick_next().unwrap();
+        let mut now = 0;
+        while now < JOBS as u64 * params.period_ns() {
+            // 現在のタスクを1tick実行
+            let job = (now / params.period_ns()) as usize;
+            let job_deadline = job as u64 * params.period_ns() + params.deadline_ns();
+            now += TICK;
+            if current.id() == dl_id {
+                if now <= job_deadline {
+                    served[job] += TICK;
+                }
+                current.charge_deadline(TICK);
+            } else {
+                current.update_vruntime(TICK);
+                cfs_time += TICK;
+            }
+
+            rq.replenish_deadline(now);
+            if current.is_throttled() || rq.should_preempt(&current) {
+                let next = rq.pick_next().unwrap();
+                rq.enqueue(core::mem::replace(&mut current, next));
+            }
+        }
+
+        // 各周期で期限までにちょうど予算分だけ実行され、超過分はスロットリングされている
+        assert!(served.iter().all(|&ns| ns == params.runtime_ns()));
+        assert_eq!(cfs_time, now - JOBS as u64 * params.runtime_ns());
+        let stats = rq
+            .tasks()
+            .chain(core::iter::once(&*current))
+            .find(|task| task.id() == dl_id)
+            .map(|task| *task.stats())
+            .unwrap();
+        assert_eq!(stats.dl_throttles, JOBS as u64);
+        assert_eq!(stats.dl_misses, 0);
+    }
+}
@@ -1,98 +1,69 @@
 //! スケジューラとタスクキュー管理
 //!
 //! このモジュールはマルチレベルキュースケジューリングとタスク管理を担当します。
+//! 各CPUは自身のランキュー（runqueueモジュール）からタスクを選択し、
+//! 自身のAPICタイマー割り込みを契機に独立してschedule()を実行します。
 
 use alloc::boxed::Box;
-use alloc::collections::BTreeMap;
-use alloc::collections::VecDeque;
+use alloc::string::String;
 use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use lazy_static::lazy_static;
 use spin::Mutex;
 
+use crate::cpu::{self, MAX_CPUS};
 use crate::io::without_interrupts;
+use crate::{percpu, time};
 
 use super::blocking::{BLOCKED_TASKS, WAKEUP_PENDING};
 use super::context::{Context, switch_context};
-use super::task::{SchedulingClass, Task, TaskError, TaskId, TaskState, rt_priority};
+use super::deadline;
+use super::exit::{self, IntoExitCode, JoinHandle};
+use super::runqueue::{self, BALANCE_INTERVAL_TICKS};
+use super::task::{SchedulingClass, SpawnOptions, Task, TaskError, TaskId, TaskState};
 
-/// スケジューリングが必要かどうかを示すフラグ
-/// 割り込みハンドラがこのフラグをセットし、割り込み復帰時にチェックされる
-static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+percpu! {
+    /// スケジューリングが必要かどうかを示すフラグ
+    /// 割り込みハンドラがこのフラグをセットし、割り込み復帰時にチェックされる
+    static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
 
-/// 現在のタスクの蓄積実行時間（ナノ秒）
-/// タイマー割り込みで加算され、schedule()でvruntimeに反映される
-/// これにより、ロックを取得せずに実行時間を記録できる
-static ACCUMULATED_RUNTIME: AtomicU64 = AtomicU64::new(0);
+    /// 現在のタスクの蓄積実行時間（ナノ秒）
+    /// タイマー割り込みで加算され、schedule()でvruntimeに反映される
+    /// これにより、ロックを取得せずに実行時間を記録できる
+    static ACCUMULATED_RUNTIME: AtomicU64 = AtomicU64::new(0);
 
-/// 初回起動時に使用するダミーコンテキスト
-/// 現在のタスクが存在しない場合、このコンテキストに「保存」する（実際には捨てられる）
-static mut DUMMY_CONTEXT: Context = Context { rsp: 0 };
+    /// 前回実行時間を計上した時刻（time::monotonic_ns()基準、0は未計上）
+    static LAST_ACCOUNT_NS: AtomicU64 = AtomicU64::new(0);
 
-// グローバルタスクキュー（マルチレベル）
-lazy_static! {
-    /// リアルタイムキュー (Realtimeクラスのタスク)
-    /// キー: (255 - priority, task_id) - 優先度が高い順にソート
-    /// 値: タスク
-    static ref RT_QUEUE: Mutex<BTreeMap<(u8, u64), Box<Task>>> = Mutex::new(BTreeMap::new());
+    /// 現在実行中のタスク
+    pub(super) static CURRENT_TASK: Mutex<Option<Box<Task>>> = Mutex::new(None);
 
-    /// 通常キュー (Normalクラスのタスク、CFS方式)
-    /// キー: (vruntime, task_id) - vruntimeでソートされ、同じvruntimeの場合はtask_idで区別
-    /// 値: タスク
-    static ref CFS_QUEUE: Mutex<BTreeMap<(u64, u64), Box<Task>>> = Mutex::new(BTreeMap::new());
+    /// 切り替え直前まで実行していたタスク
+    ///
+    /// switch_context()でコンテキストが保存されるまで他CPUから参照されないよう、
+    /// ランキューやBLOCKED_TASKSへの移動は切り替え先のfinish_task_switch()で行う。
+    pub(super) static PREV_TASK: Mutex<Option<Box<Task>>> = Mutex::new(None);
 
-    /// アイドルキュー (Idleクラスのタスク)
-    /// FIFO順で管理
-    static ref IDLE_QUEUE: Mutex<VecDeque<Box<Task>>> = Mutex::new(VecDeque::new());
+    /// 負荷分散用のローカルtickカウンタ
+    static BALANCE_TICKS: AtomicU64 = AtomicU64::new(0);
 
-    /// 現在実行中のタスク
-    pub(super) static ref CURRENT_TASK: Mutex<Option<Box<Task>>> = Mutex::new(None);
+    /// 定期負荷分散が要求されているかどうか
+    static BALANCE_PENDING: AtomicBool = AtomicBool::new(false);
 }
 
+/// 初回起動時に使用するダミーコンテキスト（CPU毎）
+/// 現在のタスクが存在しない場合、このコンテキストに「保存」する（実際には捨てられる）
+static mut DUMMY_CONTEXTS: [Context; MAX_CPUS] = [Context { rsp: 0 }; MAX_CPUS];
+
 /// タスク管理システムの初期化
 pub fn init() {
+    runqueue::init_resched_ipi();
     crate::info!("Task system initialized");
 }
 
-/// タスクを適切なキューに追加する共通実装
-///
-/// スケジューリングクラスに応じて、適切なキュー（RT/CFS/IDLE）にタスクを追加します。
-/// 必要なキューのみをロックすることで、ロック保持時間を最小化します。
-#[inline]
-fn enqueue_task_impl(task: Box<Task>) {
-    match task.sched_class() {
-        SchedulingClass::Realtime => {
-            let key = (rt_priority::MAX - task.rt_priority(), task.id().as_u64());
-            let mut rt_queue = RT_QUEUE.lock();
-            rt_queue.insert(key, task);
-        }
-        SchedulingClass::Normal => {
-            let key = (task.vruntime(), task.id().as_u64());
-            let mut cfs_queue = CFS_QUEUE.lock();
-            cfs_queue.insert(key, task);
-        }
-        SchedulingClass::Idle => {
-            let mut idle_queue = IDLE_QUEUE.lock();
-            idle_queue.push_back(task);
-        }
-    }
-}
-
-/// タスクを適切なキューに追加（単一キューロック版）
-///
-/// schedule()の最適化用。必要なキューのみをロックしてエンキューします。
+/// タスクを所属CPUのランキューに追加（blocking.rsから呼び出される）
 ///
-/// # Safety Contract
-/// この関数は割り込み無効状態（cli実行後）でのみ呼び出すこと。
-/// schedule()の内部ヘルパーとして設計されており、割り込み有効状態で
-/// 呼び出すとデッドロックの可能性があります。
-#[inline]
-fn enqueue_task_single(task: Box<Task>) {
-    enqueue_task_impl(task);
-}
-
-/// タスクを適切なキューに追加（blocking.rsから呼び出される）
+/// 所属CPUがリモートの場合は再スケジューリングIPIで通知する。
 pub(super) fn enqueue_to_appropriate_queue(task: Box<Task>, _sched_class: SchedulingClass) {
-    enqueue_task_impl(task);
+    runqueue::enqueue_on(task.cpu(), task);
 }
 
 /// 新しいタスクをタスクキューに追加（エラーハンドリング版）
@@ -100,47 +71,42 @@ pub(super) fn enqueue_to_appropriate_queue(task: Box<Task>, _sched_class: Schedu
 /// # Arguments
 /// * `task` - 追加するタスク
 ///
+/// # Returns
+/// タスクの終了を待ち合わせるハンドル（破棄してもタスクは実行を続ける）
+///
 /// # Errors
-/// * `TaskError::QueueFull` - タスクキューが満杯の場合（現在は常に成功）
+/// * `TaskError::AdmissionDenied` - Deadlineクラスのタスクの帯域をどのCPUにも予約できない場合
 ///
 /// # Note
 /// 割り込みを無効化してからロックを取得し、デッドロックを防ぎます。
-/// スケジューリングクラスに応じて、適切なキュー（RT/CFS/IDLE）に追加します。
-pub fn try_add_task(task: Task) -> Result<(), TaskError> {
+/// Normalクラスのタスクは負荷が最小のオンラインCPUに、
+/// Deadlineクラスのタスクは帯域を予約したCPUに、
+/// Realtime/Idleクラスのタスクは現在のCPUに配置されます。
+pub fn try_add_task(task: Task) -> Result<JoinHandle, TaskError> {
     let task_id = task.id().as_u64();
+    let handle = JoinHandle::new(&task);
     let sched_class = task.sched_class();
     // 名前を所有型として取得（借用を終わらせるため）
-    let name = alloc::format!("{}", task.name());
-
-    without_interrupts(|| {
-        let boxed_task = Box::new(task);
-
-        // スケジューリングクラスに応じて適切なキューに追加
-        match sched_class {
-            SchedulingClass::Realtime => {
-                let mut rt = RT_QUEUE.lock();
-                let key = (rt_priority::MAX - boxed_task.rt_priority(), task_id);
-                rt.insert(key, boxed_task);
-            }
-            SchedulingClass::Normal => {
-                let mut cfs = CFS_QUEUE.lock();
-                let key = (boxed_task.vruntime(), task_id);
-                cfs.insert(key, boxed_task);
-            }
-            SchedulingClass::Idle => {
-                let mut idle = IDLE_QUEUE.lock();
-                idle.push_back(boxed_task);
-            }
-        }
-    });
+    let name = String::from(task.name());
+
+    let cpu = without_interrupts(|| {
+        let mut boxed_task = Box::new(task);
+        let cpu = match boxed_task.deadline_mut() {
+            Some(dl) => dl.admit(time::monotonic_ns())?,
+            None => runqueue::select_task_rq(&boxed_task),
+        };
+        runqueue::enqueue_on(cpu, boxed_task);
+        Ok(cpu)
+    })?;
 
     crate::info!(
-        "Task added to queue: ID={}, name={}, class={:?}",
+        "Task added to queue: ID={}, name={}, class={:?}, cpu={}",
         task_id,
         name,
-        sched_class
+        sched_class,
+        cpu
     );
-    Ok(())
+    Ok(handle)
 }
 
 /// 新しいタスクをタスクキューに追加（後方互換性のため残す）
@@ -148,10 +114,43 @@ pub fn try_add_task(task: Task) -> Result<(), TaskError> {
 /// # Arguments
 /// * `task` - 追加するタスク
 ///
+/// # Returns
+/// タスクの終了を待ち合わせるハンドル（破棄してもタスクは実行を続ける）
+///
 /// # Panics
-/// タスク追加に失敗した場合（現在は発生しない）
-pub fn add_task(task: Task) {
-    try_add_task(task).expect("Failed to add task to queue");
+/// タスク追加に失敗した場合（Deadlineクラスのタスクの受け入れ制御で拒否された場合）
+pub fn add_task(task: Task) -> JoinHandle {
+    try_add_task(task).expect("Failed to add task to queue")
+}
+
+/// クロージャを新しいタスクとして起動する
+///
+/// クロージャはキャプチャした値ごとタスクに移され、新しいタスク上で1度だけ実行される。
+/// クロージャから戻るとタスクは終了し、戻り値（`()`なら0）が終了コードになる。
+///
+/// # Arguments
+/// * `name` - タスク名（`format!("Worker-{}", i)`のように実行時に生成してよい）
+/// * `options` - スケジューリングクラス・優先度・スタックサイズ
+/// * `f` - タスクとして実行するクロージャ
+///
+/// # Returns
+/// タスクの終了を待ち合わせるハンドル（破棄してもタスクは実行を続ける）
+///
+/// # Errors
+/// * `TaskError::InvalidPriority` - Realtimeクラスでrt_priorityが0の場合
+/// * `TaskError::InvalidStackSize` - スタックサイズが範囲外の場合
+/// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗した場合
+/// * `TaskError::AdmissionDenied` - Deadlineクラスの帯域をどのCPUにも予約できない場合
+pub fn spawn<F, R>(
+    name: impl Into<String>,
+    options: SpawnOptions,
+    f: F,
+) -> Result<JoinHandle, TaskError>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: IntoExitCode,
+{
+    try_add_task(Task::from_closure(name, options, f)?)
 }
 
 /// 現在のタスクが自発的にCPUを手放す
@@ -171,9 +170,10 @@ pub fn yield_now() {
 ///
 /// # Note
 /// 割り込みを無効化してからロックを取得し、デッドロックを防ぎます。
-pub fn set_current_task(task: Task) {
+pub fn set_current_task(mut task: Task) {
     without_interrupts(|| {
-        let mut current = CURRENT_TASK.lock();
+        task.set_cpu(cpu::current_cpu());
+        let mut current = CURRENT_TASK.get().lock();
         *current = Some(Box::new(task));
     });
 }
@@ -191,7 +191,40 @@ pub fn set_current_task(task: Task) {
 /// デッドロックを回避しつつ、確実に実行時間を記録します。
 /// schedule()が呼ばれた時に、蓄積された時間がvruntimeに反映されます。
 pub fn update_current_task_vruntime(delta: u64) {
-    ACCUMULATED_RUNTIME.fetch_add(delta, Ordering::Relaxed);
+    ACCUMULATED_RUNTIME
+        .get()
+        .fetch_add(delta, Ordering::Relaxed);
+}
+
+/// 指定CPUで実行中のタスクの、まだタスクに計上していない実行時間（ナノ秒）
+pub(super) fn pending_runtime(cpu: usize) -> u64 {
+    ACCUMULATED_RUNTIME.get_for(cpu).load(Ordering::Relaxed)
+}
+
+/// タイマー割り込み周期（250Hz = 4ms）
+///
+/// クロックソースが使えない場合は1tick分をそのまま実行時間として計上する。
+const TIMER_PERIOD_NS: u64 = 4_000_000;
+
+/// 前回の計上から現在までの経過時間を現在のタスクの実行時間として蓄積
+fn account_elapsed_runtime() {
+    let now = time::monotonic_ns();
+    let last = LAST_ACCOUNT_NS.get().swap(now, Ordering::Relaxed);
+    if last != 0 {
+        update_current_task_vruntime(now.saturating_sub(last));
+    }
+}
+
+/// タイマー割り込み毎の実行時間の計上
+///
+/// クロックソースが利用可能なら前回の計上からの実測時間を、
+/// そうでなければ1tick分の時間を現在のタスクの実行時間として蓄積します。
+pub fn account_tick() {
+    if time::has_clocksource() {
+        account_elapsed_runtime();
+    } else {
+        update_current_task_vruntime(TIMER_PERIOD_NS);
+    }
 }
 
 /// スケジューリングが必要であることを示すフラグをセット
@@ -199,7 +232,28 @@ pub fn update_current_task_vruntime(delta: u64) {
 /// タイマー割り込みハンドラから呼び出されます。
 /// 実際のスケジューリングは割り込み復帰時に行われます。
 pub fn set_need_resched() {
-    NEED_RESCHED.store(true, Ordering::Release);
+    NEED_RESCHED.get().store(true, Ordering::Release);
+}
+
+/// 現在のCPUのtickを止めてよいかどうか（tickless idle用）
+///
+/// 再スケジューリングや負荷分散が保留中、またはランキューに実行可能なタスクが
+/// ある場合はtickが必要。割り込み無効状態で呼び出すこと。
+pub fn can_stop_tick() -> bool {
+    !NEED_RESCHED.get().load(Ordering::Acquire)
+        && !BALANCE_PENDING.get().load(Ordering::Acquire)
+        && !runqueue::this_rq().has_runnable()
+}
+
+/// 定期負荷分散のタイミングを判定
+///
+/// 各CPUのタイマー割り込みハンドラから呼び出されます。
+/// `BALANCE_INTERVAL_TICKS`毎に負荷分散を要求し、実際の分散は割り込み復帰時に行われます。
+pub fn trigger_load_balance() {
+    let ticks = BALANCE_TICKS.get().fetch_add(1, Ordering::Relaxed) + 1;
+    if ticks.is_multiple_of(BALANCE_INTERVAL_TICKS) {
+        BALANCE_PENDING.get().store(true, Ordering::Release);
+    }
 }
 
 /// 割り込み復帰時にsoftirq処理とスケジューリングをチェック
@@ -222,6 +276,9 @@ pub fn set_need_resched() {
 /// schedule()の呼び出しが遅延し、sleep_msの精度が悪化する可能性がある。
 /// 詳細は timer.rs の process_pending_timers() のTODOコメントを参照。
 pub fn check_resched_on_interrupt_exit() {
+    // tickless idle中に割り込みで起床した場合は、スケジューリングの前にtickを再開する
+    crate::time::nohz::restart_tick();
+
     // 1. softirq処理（タイマーコールバック実行）
     // schedule()の前に実行することで、unblockされたタスクが即座にスケジューリング対象になる
     if crate::timer::softirq_pending() {
@@ -246,8 +303,13 @@ pub fn check_resched_on_interrupt_exit() {
         return;
     }
 
+    // 定期負荷分散（他CPUのランキューからCFSタスクを引き抜く）
+    if BALANCE_PENDING.get().swap(false, Ordering::Acquire) {
+        runqueue::load_balance();
+    }
+
     // softirq処理でunblockされたタスクも含めてスケジューリング
-    if NEED_RESCHED.swap(false, Ordering::Acquire) {
+    if NEED_RESCHED.get().swap(false, Ordering::Acquire) {
         // 割り込みは無効のままschedule()を呼び出す
         // これにより、schedule()実行中に再度タイマー割り込みが入ることを防ぐ
         schedule();
@@ -264,97 +326,151 @@ pub fn check_resched_on_interrupt_exit() {
 /// 割り込みを無効化してからロックを取得し、デッドロックを防ぎます。
 pub fn current_task_id() -> TaskId {
     without_interrupts(|| {
-        let current = CURRENT_TASK.lock();
+        let current = CURRENT_TASK.get().lock();
         current.as_ref().map(|t| t.id()).unwrap_or_else(TaskId::new)
     })
 }
 
+/// 現在のタスクを参照してクロージャを実行（ロックを待たない）
+///
+/// 例外ハンドラやパニックハンドラなど、CURRENT_TASKのロック保持中に
+/// 呼ばれうる文脈から使用する。ロックを取得できない場合はNoneを返す。
+pub fn try_with_current_task<R>(f: impl FnOnce(&Task) -> R) -> Option<R> {
+    let current = CURRENT_TASK.get().try_lock()?;
+    current.as_deref().map(f)
+}
+
 /// 次に実行するタスクを選択してコンテキストスイッチ
 ///
-/// マルチレベルキュースケジューリングを行います。
-/// - 優先順位: Realtime > Normal (CFS) > Idle
+/// 現在のCPUのランキューでマルチレベルキュースケジューリングを行います。
+/// - 優先順位: Deadline > Realtime > Normal (CFS) > Idle
 /// - 上位クラスのキューが空になるまで、下位クラスのタスクは実行されません
-/// - Realtimeクラス内では優先度順、Normalクラス内ではvruntime順
-///
-/// RFLAGSの保存・復元はswitch_context()内部で自動的に行われます。
-/// switch_context()でRFLAGSのIFフラグが強制セットされるため、
-/// タスク復帰時は必ず割り込み有効状態になります。
+/// - Deadlineクラス内では絶対期限順（EDF）、Realtimeクラス内では優先度順、
+///   Normalクラス内ではvruntime順
+/// - 予算を使い切ったDeadlineクラスのタスクは次の周期までスロットリングされます
+/// - ローカルにRT/CFSタスクがない場合は、他CPUからCFSタスクを引き抜きます（アイドル分散）
+/// - 実行可能な現在のタスクをアイドルタスクで置き換えることはありません
 ///
-/// # ロック順序（段階的取得）
-/// 1. RT_QUEUE → 即解放
-/// 2. CFS_QUEUE → 即解放
-/// 3. IDLE_QUEUE → 即解放
-/// 4. CURRENT_TASK → 処理後解放
-/// 5. BLOCKED_TASKS または 各キュー（単一）
+/// # 切り替えの流れ
+/// 1. ランキューから次のタスクを取り出す（ランキューのロックのみ）
+/// 2. CURRENT_TASKを次のタスクに差し替え、前のタスクをPREV_TASKに退避
+/// 3. switch_context()でコンテキストを保存・復元
+/// 4. 切り替え先でfinish_task_switch()を実行し、前のタスクを
+///    ランキューまたはBLOCKED_TASKSへ移動
 ///
-/// # 前提条件
-/// この関数は内部で cli を実行するため、割り込み有効状態で呼び出すこと。
-/// シングルコア環境を前提としており、cli による割り込み無効化が
-/// フェーズ間のレース条件を防いでいます。
+/// 前のタスクのキューへの移動をコンテキスト保存後まで遅らせることで、
+/// 保存途中のタスクを他CPUが選択して実行してしまうことを防ぎます。
 ///
-/// # Note
-/// この関数は割り込みを無効化してからロックを取得します。
-/// これにより、タイマー割り込みハンドラとのデッドロックを防ぎます。
+/// # 割り込み状態
+/// 内部で割り込みを無効化し、復帰時に呼び出し時の割り込み状態を復元します。
+/// 割り込み復帰処理（割り込み無効）からも、タスク（割り込み有効）からも呼び出せます。
 pub fn schedule() {
-    // SAFETY: cli はRFLAGSの割り込みフラグを無効化するのみで、メモリ安全性に影響しない。
-    // カーネルモードで実行されることが前提であり、ユーザーモードからの呼び出しはCPUが拒否する。
-    // 割り込み無効化により、フェーズ間でのレース条件を防ぎ、データ整合性を保証する。
+    let rflags: u64;
+    // SAFETY: RFLAGSの読み取りとcliはRFLAGSの割り込みフラグを変更するのみで、
+    // メモリ安全性に影響しない。割り込み無効化により、フェーズ間でのレース条件を防ぐ。
     unsafe {
-        core::arch::asm!("cli", options(nomem, nostack));
+        core::arch::asm!("pushfq; pop {}; cli", out(reg) rflags, options(nomem));
+    }
+
+    switch_to_next();
+
+    if rflags & 0x200 != 0 {
+        // SAFETY: sti は割り込みフラグを有効化するのみで、メモリ安全性に影響しない。
+        // 呼び出し時に有効だった割り込みを復元する。
+        unsafe {
+            core::arch::asm!("sti", options(nomem, nostack));
+        }
+    }
+}
+
+/// schedule()の本体（割り込み無効状態で呼び出す）
+///
+/// 次タスクの選択からCURRENT_TASKへの設定までCURRENT_TASKのロックを保持し、
+/// `for_each_task()`の走査中にタスクがどのキューにも見えなくなることを防ぐ。
+/// ロック順序: CURRENT_TASK → ランキュー, CURRENT_TASK → PREV_TASK
+fn switch_to_next() {
+    let mut current = CURRENT_TASK.get().lock();
+    let now = time::monotonic_ns();
+
+    // 期限クラスの現在のタスクは、選択の前に実行時間を予算から差し引く
+    // （予算を使い切ったタスクはスロットリングされ、現在のタスクとして継続しない）
+    let mut replenish_at = None;
+    if let Some(task) = current.as_mut()
+        && task.sched_class() == SchedulingClass::Deadline
+    {
+        if time::has_clocksource() {
+            account_elapsed_runtime();
+        }
+        let accumulated = ACCUMULATED_RUNTIME.get().swap(0, Ordering::Relaxed);
+        task.charge_deadline(accumulated);
+        replenish_at = task.deadline().and_then(|dl| dl.throttled_until());
+    }
+
+    // ===== フェーズ1: 次タスクの選択 =====
+    let keep_current = current.as_ref().is_some_and(|task| {
+        task.state() == TaskState::Running
+            && task.sched_class() != SchedulingClass::Idle
+            && !task.is_throttled()
+    });
+
+    runqueue::this_rq().replenish_deadline(now);
+    if !runqueue::this_rq().has_runnable() {
+        runqueue::idle_balance();
     }
 
-    // ===== フェーズ1: 次タスクの選択（段階的ロック取得） =====
-    // 優先度順にキューをチェックし、見つかったらすぐにロック解放
-    // これにより、複数のキューを同時にロックする必要がなくなる
     let next_task = {
-        // 1. リアルタイムキューをチェック（最優先）
-        let mut rt_queue = RT_QUEUE.lock();
-        if let Some(entry) = rt_queue.pop_first() {
-            drop(rt_queue);
-            Some(entry.1)
+        let mut rq = runqueue::this_rq();
+        let preempt = current
+            .as_deref()
+            .is_none_or(|task| rq.should_preempt(task));
+        if keep_current && !preempt {
+            None
         } else {
-            drop(rt_queue);
-            // 2. CFSキューをチェック
-            let mut cfs_queue = CFS_QUEUE.lock();
-            if let Some(entry) = cfs_queue.pop_first() {
-                drop(cfs_queue);
-                Some(entry.1)
-            } else {
-                drop(cfs_queue);
-                // 3. アイドルキューをチェック
-                let mut idle_queue = IDLE_QUEUE.lock();
-                let task = idle_queue.pop_front();
-                drop(idle_queue);
-                task
-            }
+            rq.pick_next()
         }
     };
 
-    // タスクがない場合は早期リターン
+    // タスクがない場合は現在のタスクを継続
     let Some(mut next_task) = next_task else {
-        // SAFETY: sti は割り込みフラグを有効化するのみで、メモリ安全性に影響しない。
-        // cli で無効化した割り込みを復元する。
-        unsafe {
-            core::arch::asm!("sti", options(nomem, nostack));
-        }
+        let budget = current
+            .as_ref()
+            .and_then(|task| Some((task.id(), task.deadline()?.remaining_ns())));
+        drop(current);
+        arm_deadline_timers(replenish_at, budget);
         return;
     };
+    let budget = next_task
+        .deadline()
+        .map(|dl| (next_task.id(), dl.remaining_ns()));
 
     next_task.set_state(TaskState::Running);
+    next_task.set_cpu(cpu::current_cpu());
+    next_task.stats_mut().last_cpu = cpu::current_cpu();
     let new_context_ptr = next_task.context() as *const Context;
 
-    // ===== フェーズ2: 現在のタスクの処理（CURRENT_TASKのみロック） =====
+    // ===== フェーズ2: 現在のタスクの処理 =====
     let old_context_ptr = {
-        let mut current = CURRENT_TASK.lock();
         if let Some(mut old_task) = current.take() {
+            // tick途中での切り替えでも実行した分だけを計上する
+            if time::has_clocksource() {
+                account_elapsed_runtime();
+            }
+
             // 蓄積された実行時間でvruntimeを更新（Normalクラスのみ有効）
             // accumulatedが0でも最小値(1)を加算して、同じタスクが連続選択されることを防ぐ
-            let accumulated = ACCUMULATED_RUNTIME.swap(0, Ordering::Relaxed);
+            // （Deadlineクラスのタスクは選択前に計上済みのため0になる）
+            let accumulated = ACCUMULATED_RUNTIME.get().swap(0, Ordering::Relaxed);
             if old_task.sched_class() == SchedulingClass::Normal {
                 let delta = if accumulated > 0 { accumulated } else { 1 };
                 old_task.update_vruntime(delta);
             }
 
+            // 実行中のまま切り替えられた場合（プリエンプション・yield）は非自発的なスイッチ
+            let voluntary = old_task.state() != TaskState::Running;
+            old_task
+                .stats_mut()
+                .record_switch_out(accumulated, voluntary);
+
             // 実行中だった場合は準備完了状態に変更
             if old_task.state() == TaskState::Running {
                 old_task.set_state(TaskState::Ready);
@@ -363,70 +479,99 @@ pub fn schedule() {
             // 古いタスクのコンテキストへのポインタを取得
             // （Box内のTaskは移動しても同じアドレスに留まる）
             let old_ctx_ptr = old_task.context_mut() as *mut Context;
-            let state = old_task.state();
 
-            // 新しいタスクを現在のタスクに設定
+            // 新しいタスクを現在のタスクに設定し、古いタスクはfinish_task_switch()まで退避
             *current = Some(next_task);
-            drop(current); // CURRENT_TASKのロック解放
-
-            // ===== フェーズ3: 古いタスクを適切な場所に移動（単一キューロック） =====
-            // 各キューを個別にロックすることで、ロック競合を最小化
-            match state {
-                TaskState::Terminated => {
-                    // 終了したタスクは破棄
-                }
-                TaskState::Blocked => {
-                    // ブロック中のタスクはBLOCKED_TASKSに移動
-                    // ただし、既にWAKEUP_PENDINGに追加されている場合（Lost Wakeup）は
-                    // Ready状態に戻してキューに追加
-                    let task_id = old_task.id().as_u64();
-
-                    // ロック順序: BLOCKED_TASKS → WAKEUP_PENDING (unblock_task()と同じ順序)
-                    let mut blocked = BLOCKED_TASKS.lock();
-                    let mut wakeup_pending = WAKEUP_PENDING.lock();
-
-                    if wakeup_pending.remove(&task_id) {
-                        // Lost Wakeup検出: 既にunblock_task()が呼ばれている
-                        // BLOCKED_TASKSに追加せず、Ready状態に戻してキューに追加
-                        drop(wakeup_pending);
-                        drop(blocked);
-                        old_task.set_state(TaskState::Ready);
-                        enqueue_task_single(old_task);
-                    } else {
-                        // 通常通りBLOCKED_TASKSに追加
-                        drop(wakeup_pending);
-                        blocked.insert(task_id, old_task);
-                        // blockedのロックはスコープ終了で自動解放
-                    }
-                }
-                _ => {
-                    // Ready状態のタスクは適切なキューにエンキュー（単一キューロック版）
-                    enqueue_task_single(old_task);
-                }
-            }
+            *PREV_TASK.get().lock() = Some(old_task);
 
             old_ctx_ptr
         } else {
-            // 現在のタスクがない場合（初回起動時）
+            // 現在のタスクがない場合（各CPUの初回起動時）
             // 新しいタスクを現在のタスクに設定
             *current = Some(next_task);
-            drop(current);
-            // staticなダミーコンテキストを使用
-            // SAFETY: DUMMY_CONTEXTへの可変参照は、初回スケジュール時にのみ使用される。
-            // その後は使用されないため、競合は発生しない。
-            &raw mut DUMMY_CONTEXT as *mut Context
+            // このCPUのダミーコンテキストを使用
+            // SAFETY: DUMMY_CONTEXTS[cpu]への書き込みは、そのCPUの初回スケジュール時にのみ行われる。
+            // 各CPUは自身のスロットのみを使用するため、競合は発生しない。
+            unsafe { &raw mut DUMMY_CONTEXTS[cpu::current_cpu()] }
         }
     };
+    drop(current);
+    arm_deadline_timers(replenish_at, budget);
 
     // コンテキストスイッチを実行
     // old_context_ptrに現在の状態を保存し、new_context_ptrの状態を復元
-    // RFLAGSの保存・復元もswitch_context()内部で自動的に処理される
     // SAFETY: old_context_ptrとnew_context_ptrは、それぞれ有効なContext構造体を指す。
-    // old_context_ptrは現在実行中のタスクまたはDUMMY_CONTEXT、
-    // new_context_ptrはキューから取得した次のタスクのコンテキスト。
+    // old_context_ptrはPREV_TASKに退避したタスクまたはDUMMY_CONTEXTS、
+    // new_context_ptrはCURRENT_TASKに設定した次のタスクのコンテキスト。
+    // PREV_TASKのタスクはfinish_task_switch()まで他CPUから参照されない。
     unsafe {
         switch_context(old_context_ptr, new_context_ptr);
     }
 
     // ここに戻ってくるのは、このタスクが再度スケジュールされた時
+    // （このCPUで直前に実行していたタスクの後処理を行う）
+    finish_task_switch();
+}
+
+/// 期限クラスのタイマーを設定（CURRENT_TASKのロック解放後に呼び出す）
+///
+/// # Arguments
+/// * `replenish_at` - スロットリングした現在のタスクの予算を補充する時刻
+/// * `budget` - これから実行する期限クラスのタスクとその残り予算
+fn arm_deadline_timers(replenish_at: Option<u64>, budget: Option<(TaskId, u64)>) {
+    if let Some(at) = replenish_at {
+        deadline::arm_replenish_timer(at);
+    }
+    deadline::arm_budget_timer(budget);
+}
+
+/// コンテキストスイッチ後の後処理
+///
+/// 切り替え先（schedule()から復帰したタスク、または新しいタスクの
+/// task_entry_trampoline）で、割り込み無効状態のまま呼び出されます。
+/// PREV_TASKに退避したタスクを状態に応じて移動します。
+/// - Terminated: 回収タスクに渡す（既に別のスタックで実行中のため安全に解放できる）
+/// - Blocked: BLOCKED_TASKSへ移動（起床済みならランキューへ戻す）
+/// - Ready: このCPUのランキューへ戻す
+///
+/// 移動が完了するまでPREV_TASKのロックを保持し、`for_each_task()`の走査中に
+/// タスクがどのキューにも見えなくなることを防ぐ。
+pub(super) extern "C" fn finish_task_switch() {
+    let mut prev_slot = PREV_TASK.get().lock();
+    let Some(mut prev) = prev_slot.take() else {
+        return;
+    };
+
+    match prev.state() {
+        TaskState::Terminated => {
+            // 終了したタスクのスタックとタスク構造体は回収タスクが解放する
+            exit::reap_later(prev);
+        }
+        TaskState::Blocked => {
+            // ブロック中のタスクはBLOCKED_TASKSに移動
+            // ただし、既にWAKEUP_PENDINGに追加されている場合（Lost Wakeup）は
+            // Ready状態に戻してキューに追加
+            let task_id = prev.id().as_u64();
+
+            // ロック順序: BLOCKED_TASKS → WAKEUP_PENDING (unblock_task()と同じ順序)
+            let mut blocked = BLOCKED_TASKS.lock();
+            let mut wakeup_pending = WAKEUP_PENDING.lock();
+
+            if wakeup_pending.remove(&task_id) {
+                // Lost Wakeup検出: 既にunblock_task()が呼ばれている
+                drop(wakeup_pending);
+                prev.set_state(TaskState::Ready);
+                prev.record_wakeup(time::monotonic_ns());
+                runqueue::this_rq().enqueue(prev);
+            } else {
+                drop(wakeup_pending);
+                prev.stats_mut().record_block(time::monotonic_ns());
+                blocked.insert(task_id, prev);
+            }
+        }
+        _ => {
+            // Ready状態のタスクはこのCPUのランキューに戻す
+            runqueue::this_rq().enqueue(prev);
+        }
+    }
 }
@@ -0,0 +1,246 @@
+//! タスク一覧のスナップショットと実行統計の出力
+//!
+//! `for_each_task()`は全CPUのCURRENT_TASK・PREV_TASK、BLOCKED_TASKS、全CPUのランキューを
+//! 同時にロックして走査し、ある時点の全タスクの一覧を作成します。
+//!
+//! # ロック順序
+//! スケジューラ全体で以下の順序を守ることで、走査中にタスクがキュー間を移動して
+//! 見えなくなる（または2回数えられる）ことを防ぎます。
+//!
+//! CURRENT_TASK → PREV_TASK → BLOCKED_TASKS → WAKEUP_PENDING → ランキュー
+//!
+//! 同じ種類のロックを複数のCPUについて取得する場合は、論理CPU番号の小さい方から取得します。
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::MutexGuard;
+
+use crate::cpu;
+use crate::io::without_interrupts;
+
+use super::blocking::BLOCKED_TASKS;
+use super::deadline::DeadlineParams;
+use super::runqueue::{self, RunQueue};
+use super::scheduler::{CURRENT_TASK, PREV_TASK, pending_runtime};
+use super::task::{
+    Nice, RtPriority, SchedulingClass, Task, TaskId, TaskPriority, TaskState, TaskStats,
+};
+
+/// タスクを保持する全キューのロック（ロック順序に従って取得したもの）
+struct TaskLists {
+    /// 各CPUのCURRENT_TASK
+    current: Vec<MutexGuard<'static, Option<Box<Task>>>>,
+    /// 各CPUのPREV_TASK
+    prev: Vec<MutexGuard<'static, Option<Box<Task>>>>,
+    /// BLOCKED_TASKS
+    blocked: MutexGuard<'static, BTreeMap<u64, Box<Task>>>,
+    /// 各CPUのランキュー
+    rqs: Vec<MutexGuard<'static, RunQueue>>,
+}
+
+/// タスクを保持する全キューをロック順序に従ってロック
+///
+/// 割り込み無効状態で、いずれのキューもロックしていない状態で呼び出すこと。
+fn lock_task_lists() -> TaskLists {
+    let cpus = cpu::cpu_count();
+    let current = (0..cpus).map(|c| CURRENT_TASK.get_for(c).lock()).collect();
+    let prev = (0..cpus).map(|c| PREV_TASK.get_for(c).lock()).collect();
+    let blocked = BLOCKED_TASKS.lock();
+    let rqs = (0..cpus).map(runqueue::cpu_rq).collect();
+    TaskLists {
+        current,
+        prev,
+        blocked,
+        rqs,
+    }
+}
+
+/// タスクのスナップショット
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    /// タスクID
+    pub id: TaskId,
+    /// タスク名
+    pub name: String,
+    /// スケジューリングクラス
+    pub sched_class: SchedulingClass,
+    /// Nice値（Normalクラス用）
+    pub nice: Nice,
+    /// Realtime優先度（Realtimeクラス用）
+    pub rt_priority: RtPriority,
+    /// 期限クラスのパラメータ（Deadlineクラスでは常にSome）
+    pub deadline: Option<DeadlineParams>,
+    /// タスクの状態
+    pub state: TaskState,
+    /// 実行統計
+    pub stats: TaskStats,
+}
+
+impl TaskInfo {
+    fn from_task(task: &Task) -> Self {
+        Self {
+            id: task.id(),
+            name: String::from(task.name()),
+            sched_class: task.sched_class(),
+            nice: task.nice(),
+            rt_priority: task.rt_priority(),
+            deadline: task.deadline_params(),
+            state: task.state(),
+            stats: *task.stats(),
+        }
+    }
+
+    /// 現在の実効優先度（優先度継承による引き上げを含む）
+    pub fn priority(&self) -> TaskPriority {
+        match (self.sched_class, self.deadline) {
+            (SchedulingClass::Deadline, Some(params)) => TaskPriority::Deadline(params),
+            (SchedulingClass::Deadline | SchedulingClass::Realtime, _) => {
+                TaskPriority::Realtime(self.rt_priority)
+            }
+            (SchedulingClass::Normal, _) => TaskPriority::Normal(self.nice),
+            (SchedulingClass::Idle, _) => TaskPriority::Idle,
+        }
+    }
+
+    /// 表示用の優先度（Deadlineは"D<runtime>/<period>"(ms)、Realtimeは"R<優先度>"、
+    /// Normalはnice値、Idleは"idle"）
+    ///
+    /// 優先度継承で引き上げられている場合は末尾に"*"を付ける。
+    pub fn priority_label(&self) -> String {
+        let mut label = format!("{}", self.priority());
+        if self.stats.pi_boost.is_some() {
+            label.push('*');
+        }
+        label
+    }
+}
+
+/// 全タスクのスナップショットを作成（タスクID順）
+///
+/// 実行中のタスクのCPU時間には、まだタスクに計上していない実行時間も含める。
+pub fn task_snapshot() -> Vec<TaskInfo> {
+    let mut tasks = without_interrupts(|| {
+        // PREV_TASKのタスクは切り替え中のCPUがコンテキストを保存している最中の場合があるが、
+        // 参照するのは名前・状態・統計のみでコンテキストには触れない
+        let lists = lock_task_lists();
+
+        let mut tasks = Vec::new();
+        for (cpu, slot) in lists.current.iter().enumerate() {
+            if let Some(task) = slot.as_deref() {
+                let mut info = TaskInfo::from_task(task);
+                info.stats.cpu_time_ns += pending_runtime(cpu);
+                tasks.push(info);
+            }
+        }
+        tasks.extend(
+            lists
+                .prev
+                .iter()
+                .filter_map(|slot| slot.as_deref())
+                .map(TaskInfo::from_task),
+        );
+        tasks.extend(lists.blocked.values().map(|task| TaskInfo::from_task(task)));
+        for rq in &lists.rqs {
+            tasks.extend(rq.tasks().map(TaskInfo::from_task));
+        }
+        tasks
+    });
+    tasks.sort_unstable_by_key(|info| info.id);
+    tasks
+}
+
+/// 全タスクのスナップショットを作成し、タスクID順にクロージャを呼び出す
+///
+/// スナップショットの作成後、ロックを解放してからクロージャを呼び出すため、
+/// クロージャ内でブロックやログ出力を行ってもよい。
+pub fn for_each_task(mut f: impl FnMut(&TaskInfo)) {
+    for info in &task_snapshot() {
+        f(info);
+    }
+}
+
+/// タスク一覧をシリアルに出力
+pub fn dump_tasks() {
+    crate::println!("Tasks:");
+    crate::println!(
+        "{:>4} {:<16} {:>6} {:<7} {:>3} {:>10} {:>8} {:>8} {:>8} {:>10} {:>6} {:>6}",
+        "ID",
+        "NAME",
+        "PRI",
+        "STATE",
+        "CPU",
+        "TIME(ms)",
+        "VCSW",
+        "IVCSW",
+        "WAKEUPS",
+        "BLOCK(ms)",
+        "PI",
+        "DLMISS"
+    );
+    for_each_task(|info| {
+        crate::println!(
+            "{:>4} {:<16.16} {:>6} {:<7} {:>3} {:>10} {:>8} {:>8} {:>8} {:>10} {:>6} {:>6}",
+            info.id.as_u64(),
+            info.name,
+            info.priority_label(),
+            state_label(info.state),
+            info.stats.last_cpu,
+            info.stats.cpu_time_ns / 1_000_000,
+            info.stats.voluntary_switches,
+            info.stats.involuntary_switches,
+            info.stats.wakeups,
+            info.stats.blocked_ns / 1_000_000,
+            info.stats.pi_boosts,
+            info.stats.dl_misses
+        );
+    });
+}
+
+/// タスク状態の表示名
+pub fn state_label(state: TaskState) -> &'static str {
+    match state {
+        TaskState::Running => "running",
+        TaskState::Ready => "ready",
+        TaskState::Blocked => "blocked",
+        TaskState::Terminated => "dead",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(sched_class: SchedulingClass, nice: Nice, rt_priority: RtPriority) -> TaskInfo {
+        TaskInfo {
+            id: TaskId::new(),
+            name: String::from("test"),
+            sched_class,
+            nice,
+            rt_priority,
+            deadline: None,
+            state: TaskState::Ready,
+            stats: TaskStats::default(),
+        }
+    }
+
+    #[test_case]
+    fn test_priority_label() {
+        assert_eq!(
+            info(SchedulingClass::Realtime, 0, 99).priority_label(),
+            "R99"
+        );
+        assert_eq!(info(SchedulingClass::Normal, -5, 0).priority_label(), "-5");
+        assert_eq!(info(SchedulingClass::Idle, 19, 0).priority_label(), "idle");
+
+        let mut boosted = info(SchedulingClass::Realtime, 0, 99);
+        boosted.stats.pi_boost = Some(TaskPriority::Realtime(99));
+        assert_eq!(boosted.priority_label(), "R99*");
+
+        let mut deadline = info(SchedulingClass::Deadline, 0, 0);
+        deadline.deadline = Some(DeadlineParams::new(2_000_000, 16_000_000, 16_000_000).unwrap());
+        assert_eq!(deadline.priority_label(), "D2/16");
+    }
+}
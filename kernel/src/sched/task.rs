@@ -3,11 +3,18 @@
 //! このモジュールはタスクの基本的な構造体、状態、優先度を定義します。
 
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, Ordering};
 
+use crate::io::without_interrupts;
 use crate::paging::KERNEL_VIRTUAL_BASE;
 
 use super::context::Context;
+use super::deadline::{DeadlineParams, DeadlineState};
+use super::exit::{ExitCode, ExitState, IntoExitCode};
 
 /// タスク操作のエラー型
 #[allow(dead_code)]
@@ -23,6 +30,12 @@ pub enum TaskError {
     ContextInitFailed,
     /// タスクキューが満杯
     QueueFull,
+    /// 無効なスタックサイズ
+    InvalidStackSize,
+    /// 無効な期限クラスのパラメータ
+    InvalidDeadlineParams,
+    /// 期限クラスの帯域の予約に空きがない（受け入れ制御）
+    AdmissionDenied,
 }
 
 impl core::fmt::Display for TaskError {
@@ -39,6 +52,21 @@ impl core::fmt::Display for TaskError {
             TaskError::InvalidStackAddress => write!(f, "Invalid stack address"),
             TaskError::ContextInitFailed => write!(f, "Failed to initialize task context"),
             TaskError::QueueFull => write!(f, "Task queue is full"),
+            TaskError::InvalidStackSize => {
+                write!(
+                    f,
+                    "Invalid stack size (must be {}..={} bytes)",
+                    stack_size::MIN,
+                    stack_size::MAX
+                )
+            }
+            TaskError::InvalidDeadlineParams => {
+                write!(
+                    f,
+                    "Invalid deadline parameters (must be 0 < runtime <= deadline <= period)"
+                )
+            }
+            TaskError::AdmissionDenied => write!(f, "Deadline bandwidth exhausted on all CPUs"),
         }
     }
 }
@@ -55,8 +83,7 @@ impl TaskId {
         TaskId(id)
     }
 
-    /// u64からTaskIdを作成（可視化モード用）
-    #[cfg(feature = "visualize-pipeline")]
+    /// u64からTaskIdを作成
     pub fn from_u64(id: u64) -> Self {
         TaskId(id)
     }
@@ -106,7 +133,10 @@ pub mod rt_priority {
 /// 下位クラスのタスクは実行されません。
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SchedulingClass {
-    /// リアルタイムクラス（最高優先度）
+    /// 期限クラス（最高優先度、EDF方式）
+    /// 周期毎に決まった実行時間を期限までに必要とするタスク用
+    Deadline = 3,
+    /// リアルタイムクラス
     /// Compositor、マウス描画など即座に応答が必要なタスク用
     Realtime = 2,
     /// 通常クラス（CFS方式）
@@ -152,7 +182,6 @@ pub fn nice_to_weight(nice: Nice) -> u32 {
 }
 
 /// タスクの状態
-#[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TaskState {
     /// 実行中（CPUを使用中）
@@ -165,21 +194,103 @@ pub enum TaskState {
     Terminated,
 }
 
-/// タスクスタック
-const STACK_SIZE: usize = 16384; // 16KB
+/// タスク毎の実行統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaskStats {
+    /// 合計CPU時間（ナノ秒）
+    pub cpu_time_ns: u64,
+    /// 自発的なコンテキストスイッチの回数（ブロック・終了によるもの）
+    pub voluntary_switches: u64,
+    /// 非自発的なコンテキストスイッチの回数（プリエンプション・yieldによるもの）
+    pub involuntary_switches: u64,
+    /// ブロック状態から起床した回数
+    pub wakeups: u64,
+    /// 最後に実行された論理CPU番号
+    pub last_cpu: usize,
+    /// ブロック状態で過ごした合計時間（ナノ秒）
+    pub blocked_ns: u64,
+    /// 優先度継承で引き上げられた回数
+    pub pi_boosts: u64,
+    /// 優先度継承による現在の実効優先度（引き上げられていなければNone）
+    pub pi_boost: Option<TaskPriority>,
+    /// 期限クラスで予算を使い切ってスロットリングされた回数
+    pub dl_throttles: u64,
+    /// 期限クラスでジョブの完了が期限を過ぎた回数
+    pub dl_misses: u64,
+    /// ブロックを開始した時刻（ブロック中でなければNone）
+    blocked_since: Option<u64>,
+}
+
+impl TaskStats {
+    /// CPUから切り替えられたことを記録
+    pub(super) fn record_switch_out(&mut self, runtime_ns: u64, voluntary: bool) {
+        self.cpu_time_ns = self.cpu_time_ns.saturating_add(runtime_ns);
+        if voluntary {
+            self.voluntary_switches += 1;
+        } else {
+            self.involuntary_switches += 1;
+        }
+    }
+
+    /// ブロックの開始を記録
+    pub(super) fn record_block(&mut self, now_ns: u64) {
+        self.blocked_since = Some(now_ns);
+    }
 
-#[repr(align(16))]
-pub(super) struct TaskStack([u8; STACK_SIZE]);
+    /// 起床を記録し、ブロックしていた時間を加算
+    pub(super) fn record_wakeup(&mut self, now_ns: u64) {
+        self.wakeups += 1;
+        if let Some(since) = self.blocked_since.take() {
+            self.blocked_ns = self.blocked_ns.saturating_add(now_ns.saturating_sub(since));
+        }
+    }
+}
+
+/// タスクスタックのサイズ（バイト単位）の定数
+pub mod stack_size {
+    /// 最小サイズ
+    pub const MIN: usize = 4 * 1024;
+    /// デフォルトサイズ
+    pub const DEFAULT: usize = 16 * 1024;
+    /// 最大サイズ
+    pub const MAX: usize = 1024 * 1024;
+}
+
+/// スタックの割り当て単位（16バイトアラインメントを保証する）
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct StackChunk([u8; 16]);
+
+/// タスクスタック
+pub(super) struct TaskStack(Box<[StackChunk]>);
 
 impl TaskStack {
-    pub(super) const fn new() -> Self {
-        Self([0; STACK_SIZE])
+    /// 指定サイズのスタックを割り当てる（16バイト単位に切り上げ）
+    ///
+    /// # Errors
+    /// * `TaskError::InvalidStackSize` - サイズが`stack_size::MIN`〜`stack_size::MAX`の範囲外の場合
+    pub(super) fn new(size: usize) -> Result<Self, TaskError> {
+        if !(stack_size::MIN..=stack_size::MAX).contains(&size) {
+            return Err(TaskError::InvalidStackSize);
+        }
+        let chunks = size.div_ceil(core::mem::size_of::<StackChunk>());
+        Ok(Self(vec![StackChunk([0; 16]); chunks].into_boxed_slice()))
+    }
+
+    /// スタックのサイズ（バイト単位）
+    pub(super) fn size(&self) -> usize {
+        core::mem::size_of_val(&*self.0)
+    }
+
+    /// スタックの最下位アドレスを取得（仮想アドレス）
+    pub(super) fn bottom(&self) -> u64 {
+        self.top() - self.size() as u64
     }
 
     /// スタックの最上位アドレスを取得（仮想アドレス）
     pub(super) fn top(&self) -> u64 {
         let base = self.0.as_ptr() as u64;
-        let physical_top = base + STACK_SIZE as u64;
+        let physical_top = base + self.size() as u64;
 
         // ヒープは物理アドレスで割り当てられるため、カーネル仮想アドレスに変換
         // カーネルは KERNEL_VIRTUAL_BASE (0xFFFF800000000000) 以降で動作
@@ -193,12 +304,136 @@ impl TaskStack {
     }
 }
 
+/// タスクの優先度指定（スケジューリングクラスとクラス内の優先度）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskPriority {
+    /// 期限クラス（周期毎の実行時間・期限）
+    Deadline(DeadlineParams),
+    /// Realtimeクラス（1-99、大きいほど高優先度）
+    Realtime(RtPriority),
+    /// Normalクラス（nice値 -20〜+19、小さいほど高優先度）
+    Normal(Nice),
+    /// Idleクラス
+    Idle,
+}
+
+impl TaskPriority {
+    /// 優先度の順位（大きいほど高優先度）
+    ///
+    /// クラス間ではDeadline > Realtime > Normal > Idle、Realtime内では優先度が大きいほど、
+    /// Normal内ではnice値が小さいほど高い（Deadline内の順位は絶対期限で決まるため同順位）。
+    fn rank(self) -> (u8, i16) {
+        match self {
+            TaskPriority::Deadline(_) => (3, 0),
+            TaskPriority::Realtime(rt_priority) => (2, rt_priority as i16),
+            TaskPriority::Normal(nice) => (1, -(nice as i16)),
+            TaskPriority::Idle => (0, 0),
+        }
+    }
+
+    /// `other`より高い優先度かどうか
+    pub fn outranks(self, other: TaskPriority) -> bool {
+        self.rank() > other.rank()
+    }
+
+    /// 優先度継承で引き継ぐ優先度
+    ///
+    /// 期限クラスの帯域の予約は引き継げないため、Realtimeクラスの最高優先度として継承する。
+    fn inheritable(self) -> Self {
+        match self {
+            TaskPriority::Deadline(_) => TaskPriority::Realtime(rt_priority::MAX),
+            priority => priority,
+        }
+    }
+}
+
+impl core::fmt::Display for TaskPriority {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            TaskPriority::Deadline(params) => write!(
+                f,
+                "D{}/{}",
+                params.runtime_ns() / 1_000_000,
+                params.period_ns() / 1_000_000
+            ),
+            TaskPriority::Realtime(rt_priority) => write!(f, "R{}", rt_priority),
+            TaskPriority::Normal(nice) => write!(f, "{}", nice),
+            TaskPriority::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// タスク作成時のオプション（`sched::spawn()`用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnOptions {
+    /// スケジューリングクラスと優先度
+    pub priority: TaskPriority,
+    /// スタックサイズ（バイト単位）
+    pub stack_size: usize,
+}
+
+impl SpawnOptions {
+    /// Normalクラス、指定nice値、デフォルトスタックサイズのオプション
+    pub const fn normal(nice: Nice) -> Self {
+        Self {
+            priority: TaskPriority::Normal(nice),
+            stack_size: stack_size::DEFAULT,
+        }
+    }
+
+    /// Realtimeクラス、指定優先度、デフォルトスタックサイズのオプション
+    pub const fn realtime(rt_priority: RtPriority) -> Self {
+        Self {
+            priority: TaskPriority::Realtime(rt_priority),
+            stack_size: stack_size::DEFAULT,
+        }
+    }
+
+    /// 期限クラス、指定パラメータ、デフォルトスタックサイズのオプション
+    pub const fn deadline(params: DeadlineParams) -> Self {
+        Self {
+            priority: TaskPriority::Deadline(params),
+            stack_size: stack_size::DEFAULT,
+        }
+    }
+
+    /// スタックサイズを変更する
+    pub const fn with_stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = stack_size;
+        self
+    }
+}
+
+impl Default for SpawnOptions {
+    fn default() -> Self {
+        Self::normal(nice::DEFAULT)
+    }
+}
+
+/// `spawn()`で渡されたクロージャ（実行を開始するまでタスクが所有する）
+type TaskClosure = Box<dyn FnOnce() -> ExitCode + Send>;
+
+/// クロージャから作成したタスクのエントリポイント
+///
+/// 現在のタスクからクロージャを取り出して実行する。取り出した後はタスクを破棄しても
+/// クロージャは解放されず、実行を終えた時点で解放される。
+extern "C" fn closure_entry() -> ExitCode {
+    let closure = without_interrupts(|| {
+        super::scheduler::CURRENT_TASK
+            .get()
+            .lock()
+            .as_mut()
+            .and_then(|task| task.closure.take())
+    });
+    closure.expect("closure task started without its closure")()
+}
+
 /// タスク制御ブロック (Task Control Block)
 pub struct Task {
     /// タスクID
     id: TaskId,
     /// タスク名（デバッグ用）
-    name: &'static str,
+    name: String,
     /// スケジューリングクラス（Realtime, Normal, Idle）
     sched_class: SchedulingClass,
     /// Normalクラス用のnice値（-20〜+19）
@@ -214,13 +449,29 @@ pub struct Task {
     /// 仮想実行時間（CFS風スケジューリング、Normalクラスで使用）
     /// この値が小さいタスクが優先的に実行される
     vruntime: u64,
+    /// 所属するランキューの論理CPU番号（最後に実行された、または配置されたCPU）
+    cpu: usize,
     /// CPUコンテキスト
     context: Context,
     /// タスクの状態
     state: TaskState,
     /// タスク専用スタック（ヒープに割り当て）
-    #[allow(dead_code)]
-    stack: Box<TaskStack>,
+    stack: TaskStack,
+    /// 終了状態（JoinHandleと共有）
+    exit: Arc<ExitState>,
+    /// 本来の優先度（優先度継承による引き上げを含まない）
+    base_priority: TaskPriority,
+    /// 優先度継承による引き上げ（(BlockingMutexのアドレス, 継承した優先度)）
+    pi_boosts: Vec<(usize, TaskPriority)>,
+    /// 期限クラスの実行状態（Deadlineクラス以外はNone）
+    deadline: Option<DeadlineState>,
+    /// 実行を開始していないクロージャ（`from_closure()`で作成したタスクのみ）
+    ///
+    /// 実行前にタスクが破棄された場合（受け入れ制御で拒否された場合など）は、
+    /// タスクと共に解放され、キャプチャした値も破棄される。
+    closure: Option<TaskClosure>,
+    /// 実行統計
+    stats: TaskStats,
 }
 
 impl Task {
@@ -238,32 +489,41 @@ impl Task {
     /// # Note
     /// nice値は自動的に有効範囲（-20〜+19）にクランプされます。
     pub fn new(
-        name: &'static str,
+        name: impl Into<String>,
         nice: Nice,
         entry_point: extern "C" fn() -> !,
     ) -> Result<Self, TaskError> {
-        // スタックをヒープに割り当て
-        let stack = Box::new(TaskStack::new());
-        let stack_top = stack.top();
-
-        let context = Context::new(entry_point as u64, stack_top)?;
-
-        // nice値から重みを計算
-        let clamped_nice = nice.clamp(nice::MIN, nice::MAX);
-        let weight = nice_to_weight(clamped_nice);
+        Self::with_entry(
+            name.into(),
+            SpawnOptions::normal(nice),
+            entry_point as u64,
+            0,
+        )
+    }
 
-        Ok(Self {
-            id: TaskId::new(),
-            name,
-            sched_class: SchedulingClass::Normal,
-            nice: clamped_nice,
-            rt_priority: 0, // Normalクラスでは使用しない
-            weight,
-            vruntime: 0, // 初期値は0
-            context,
-            state: TaskState::Ready,
-            stack,
-        })
+    /// 終了コードを返すエントリポイントからNormalクラスのタスクを作成
+    ///
+    /// エントリポイントから戻るとタスクは終了し、戻り値が終了コードになる。
+    ///
+    /// # Arguments
+    /// * `name` - タスク名
+    /// * `nice` - Nice値（-20〜+19、小さいほど高優先度）
+    /// * `entry_point` - エントリポイント関数のアドレス
+    ///
+    /// # Errors
+    /// * `TaskError::StackAllocationFailed` - スタック割り当てに失敗した場合
+    /// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗した場合
+    pub fn new_returning(
+        name: impl Into<String>,
+        nice: Nice,
+        entry_point: extern "C" fn() -> i32,
+    ) -> Result<Self, TaskError> {
+        Self::with_entry(
+            name.into(),
+            SpawnOptions::normal(nice),
+            entry_point as usize as u64,
+            0,
+        )
     }
 
     /// Realtimeクラスのタスクを作成
@@ -278,34 +538,16 @@ impl Task {
     /// * `TaskError::StackAllocationFailed` - スタック割り当てに失敗した場合
     /// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗した場合
     pub fn new_realtime(
-        name: &'static str,
+        name: impl Into<String>,
         rt_priority: RtPriority,
         entry_point: extern "C" fn() -> !,
     ) -> Result<Self, TaskError> {
-        // rt_priority 0は無効（Normalクラスと区別するため）
-        if rt_priority < rt_priority::MIN {
-            return Err(TaskError::InvalidPriority);
-        }
-
-        // スタックをヒープに割り当て
-        let stack = Box::new(TaskStack::new());
-        let stack_top = stack.top();
-
-        let context = Context::new(entry_point as u64, stack_top)?;
-
-        // Realtimeクラスではweightとvruntimeは使用しない
-        Ok(Self {
-            id: TaskId::new(),
-            name,
-            sched_class: SchedulingClass::Realtime,
-            nice: 0, // Realtimeクラスでは使用しない
-            rt_priority: rt_priority.min(rt_priority::MAX),
-            weight: 0,   // Realtimeクラスでは使用しない
-            vruntime: 0, // Realtimeクラスでは使用しない
-            context,
-            state: TaskState::Ready,
-            stack,
-        })
+        Self::with_entry(
+            name.into(),
+            SpawnOptions::realtime(rt_priority),
+            entry_point as u64,
+            0,
+        )
     }
 
     /// アイドルタスク専用の作成関数
@@ -318,27 +560,186 @@ impl Task {
     /// * `TaskError::StackAllocationFailed` - スタック割り当てに失敗した場合
     /// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗した場合
     pub fn new_idle(
-        name: &'static str,
+        name: impl Into<String>,
         entry_point: extern "C" fn() -> !,
     ) -> Result<Self, TaskError> {
-        // スタックをヒープに割り当て
-        let stack = Box::new(TaskStack::new());
-        let stack_top = stack.top();
+        let options = SpawnOptions {
+            priority: TaskPriority::Idle,
+            stack_size: stack_size::DEFAULT,
+        };
+        Self::with_entry(name.into(), options, entry_point as u64, 0)
+    }
+
+    /// クロージャからタスクを作成
+    ///
+    /// クロージャから戻るとタスクは終了し、戻り値が終了コードになる。
+    ///
+    /// # Arguments
+    /// * `name` - タスク名
+    /// * `options` - スケジューリングクラス・優先度・スタックサイズ
+    /// * `f` - タスクとして実行するクロージャ
+    ///
+    /// # Errors
+    /// * `TaskError::InvalidPriority` - Realtimeクラスでrt_priorityが0の場合
+    /// * `TaskError::InvalidStackSize` - スタックサイズが範囲外の場合
+    /// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗した場合
+    pub fn from_closure<F, R>(
+        name: impl Into<String>,
+        options: SpawnOptions,
+        f: F,
+    ) -> Result<Self, TaskError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: IntoExitCode,
+    {
+        let entry_point = closure_entry as extern "C" fn() -> ExitCode;
+        let mut task = Self::with_entry(name.into(), options, entry_point as usize as u64, 0)?;
+        task.closure = Some(Box::new(move || f().into_exit_code()));
+        Ok(task)
+    }
+
+    /// タスクを作成（エントリポイントのアドレスと引数を指定）
+    ///
+    /// Normalクラスのnice値は有効範囲にクランプし、Realtimeクラスの優先度は最大値で制限する。
+    fn with_entry(
+        name: String,
+        options: SpawnOptions,
+        entry_point: u64,
+        arg: u64,
+    ) -> Result<Self, TaskError> {
+        // rt_priority 0は無効（Normalクラスと区別するため）
+        if let TaskPriority::Realtime(rt_priority) = options.priority
+            && rt_priority < rt_priority::MIN
+        {
+            return Err(TaskError::InvalidPriority);
+        }
 
-        let context = Context::new(entry_point as u64, stack_top)?;
+        // スタックをヒープに割り当て
+        let stack = TaskStack::new(options.stack_size)?;
+        let context = Context::new(entry_point, arg, stack.top())?;
 
-        Ok(Self {
+        let mut task = Self {
             id: TaskId::new(),
             name,
             sched_class: SchedulingClass::Idle,
-            nice: nice::MAX, // Idleは最低優先度相当
+            nice: nice::MAX,
             rt_priority: 0,
-            weight: nice_to_weight(nice::MAX), // 参考値
-            vruntime: 0,
+            weight: 0,
+            vruntime: 0, // 初期値は0
+            cpu: 0,
             context,
             state: TaskState::Ready,
             stack,
-        })
+            exit: Arc::new(ExitState::new()),
+            base_priority: options.priority,
+            pi_boosts: Vec::new(),
+            deadline: None,
+            closure: None,
+            stats: TaskStats::default(),
+        };
+        task.apply_priority(options.priority);
+        // 範囲外の優先度は制限した値を本来の優先度とする
+        task.base_priority = task.priority();
+        Ok(task)
+    }
+
+    /// スケジューリングクラスと優先度を設定
+    ///
+    /// Deadline/Realtimeクラスではnice・weight・vruntimeは使用せず、
+    /// Normal/Idleクラスではrt_priorityは使用しない。
+    /// 期限クラスの実行状態は、パラメータが変わった場合のみ作り直す。
+    fn apply_priority(&mut self, priority: TaskPriority) {
+        match priority {
+            TaskPriority::Deadline(params) => {
+                if self.deadline_params() != Some(params) {
+                    self.deadline = Some(DeadlineState::new(params));
+                }
+            }
+            _ => self.deadline = None,
+        }
+        let (sched_class, nice, rt_priority, weight) = match priority {
+            TaskPriority::Deadline(_) => (SchedulingClass::Deadline, 0, 0, 0),
+            TaskPriority::Realtime(rt_priority) => (
+                SchedulingClass::Realtime,
+                0,
+                rt_priority.min(rt_priority::MAX),
+                0,
+            ),
+            TaskPriority::Normal(nice) => {
+                let clamped_nice = nice.clamp(nice::MIN, nice::MAX);
+                (
+                    SchedulingClass::Normal,
+                    clamped_nice,
+                    0,
+                    nice_to_weight(clamped_nice),
+                )
+            }
+            // Idleは最低優先度相当（weightは参考値）
+            TaskPriority::Idle => (
+                SchedulingClass::Idle,
+                nice::MAX,
+                0,
+                nice_to_weight(nice::MAX),
+            ),
+        };
+        self.sched_class = sched_class;
+        self.nice = nice;
+        self.rt_priority = rt_priority;
+        self.weight = weight;
+    }
+
+    /// 現在の実効優先度を取得（優先度継承による引き上げを含む）
+    pub fn priority(&self) -> TaskPriority {
+        match self.sched_class {
+            SchedulingClass::Deadline => TaskPriority::Deadline(
+                self.deadline_params()
+                    .expect("Deadline class task without deadline parameters"),
+            ),
+            SchedulingClass::Realtime => TaskPriority::Realtime(self.rt_priority),
+            SchedulingClass::Normal => TaskPriority::Normal(self.nice),
+            SchedulingClass::Idle => TaskPriority::Idle,
+        }
+    }
+
+    /// 本来の優先度を取得（優先度継承による引き上げを含まない）
+    pub fn base_priority(&self) -> TaskPriority {
+        self.base_priority
+    }
+
+    /// 優先度継承による引き上げを設定・解除し、実効優先度を再計算
+    ///
+    /// 実効優先度は本来の優先度とすべての引き上げのうち最も高いもの。
+    /// ランキュー内のタスクはキーが変わるため、呼び出し前にキューから取り出しておくこと。
+    ///
+    /// # Arguments
+    /// * `key` - 引き上げの原因（BlockingMutexのアドレス）
+    /// * `boost` - 継承する優先度（Noneなら`key`による引き上げを解除）
+    pub(super) fn set_pi_boost(&mut self, key: usize, boost: Option<TaskPriority>) {
+        self.pi_boosts.retain(|&(k, _)| k != key);
+        if let Some(priority) = boost {
+            self.pi_boosts.push((key, priority.inheritable()));
+        }
+
+        let effective = self.pi_boosts.iter().map(|&(_, priority)| priority).fold(
+            self.base_priority,
+            |max, priority| {
+                if priority.outranks(max) {
+                    priority
+                } else {
+                    max
+                }
+            },
+        );
+        if effective == self.priority() {
+            return;
+        }
+
+        let was_boosted = self.stats.pi_boost.is_some();
+        self.apply_priority(effective);
+        self.stats.pi_boost = (effective != self.base_priority).then_some(effective);
+        if !was_boosted && self.stats.pi_boost.is_some() {
+            self.stats.pi_boosts += 1;
+        }
     }
 
     /// タスクIDを取得
@@ -348,7 +749,12 @@ impl Task {
 
     /// タスク名を取得
     pub fn name(&self) -> &str {
-        self.name
+        &self.name
+    }
+
+    /// タスクスタックの範囲（最下位アドレス, 最上位アドレス）を取得
+    pub fn stack_bounds(&self) -> (u64, u64) {
+        (self.stack.bottom(), self.stack.top())
     }
 
     /// Nice値を取得（Normalクラス用）
@@ -395,6 +801,21 @@ impl Task {
         self.vruntime = self.vruntime.saturating_add(increment);
     }
 
+    /// タスクの仮想実行時間を設定（CPU間移動時の正規化用）
+    pub(super) fn set_vruntime(&mut self, vruntime: u64) {
+        self.vruntime = vruntime;
+    }
+
+    /// 所属するランキューの論理CPU番号を取得
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// 所属するランキューの論理CPU番号を設定
+    pub(super) fn set_cpu(&mut self, cpu: usize) {
+        self.cpu = cpu;
+    }
+
     /// タスクの状態を取得
     pub fn state(&self) -> TaskState {
         self.state
@@ -405,6 +826,77 @@ impl Task {
         self.state = state;
     }
 
+    /// 終了状態を取得
+    pub(super) fn exit_state(&self) -> &Arc<ExitState> {
+        &self.exit
+    }
+
+    /// 期限クラスのパラメータを取得（Deadlineクラス以外はNone）
+    pub fn deadline_params(&self) -> Option<DeadlineParams> {
+        self.deadline.as_ref().map(DeadlineState::params)
+    }
+
+    /// 期限クラスの実行状態を取得
+    pub(super) fn deadline(&self) -> Option<&DeadlineState> {
+        self.deadline.as_ref()
+    }
+
+    /// 期限クラスの実行状態への可変参照を取得
+    pub(super) fn deadline_mut(&mut self) -> Option<&mut DeadlineState> {
+        self.deadline.as_mut()
+    }
+
+    /// 期限クラスで予算を使い切り、次の周期まで実行できない状態かどうか
+    pub(super) fn is_throttled(&self) -> bool {
+        self.deadline
+            .as_ref()
+            .is_some_and(|dl| dl.throttled_until().is_some())
+    }
+
+    /// 期限クラスのタスクの実行時間を予算とCPU時間に計上する
+    ///
+    /// 期限クラス以外のタスクでは何もしない（CPU時間は切り替え時に計上される）。
+    ///
+    /// # Returns
+    /// 計上した場合true
+    pub(super) fn charge_deadline(&mut self, runtime_ns: u64) -> bool {
+        let Some(dl) = self.deadline.as_mut() else {
+            return false;
+        };
+        if dl.charge(runtime_ns) {
+            self.stats.dl_throttles += 1;
+        }
+        self.stats.cpu_time_ns = self.stats.cpu_time_ns.saturating_add(runtime_ns);
+        true
+    }
+
+    /// 期限クラスの現在のジョブを完了する（期限を過ぎていれば記録する）
+    pub(super) fn complete_deadline_job(&mut self, now_ns: u64) {
+        if let Some(dl) = self.deadline.as_mut()
+            && dl.complete_job(now_ns)
+        {
+            self.stats.dl_misses += 1;
+        }
+    }
+
+    /// ブロック状態からの起床を記録し、期限クラスなら起床規則を適用する
+    pub(super) fn record_wakeup(&mut self, now_ns: u64) {
+        self.stats.record_wakeup(now_ns);
+        if let Some(dl) = self.deadline.as_mut() {
+            dl.wakeup(now_ns);
+        }
+    }
+
+    /// 実行統計を取得
+    pub fn stats(&self) -> &TaskStats {
+        &self.stats
+    }
+
+    /// 実行統計への可変参照を取得
+    pub(super) fn stats_mut(&mut self) -> &mut TaskStats {
+        &mut self.stats
+    }
+
     /// コンテキストへの参照を取得
     pub fn context(&self) -> &Context {
         &self.context
@@ -446,4 +938,88 @@ mod tests {
         // nice +30 -> クランプされて +19 -> 重み15
         assert_eq!(nice_to_weight(30), nice_to_weight(19));
     }
+
+    #[test_case]
+    fn test_task_stats_accumulates_blocked_time() {
+        let mut stats = TaskStats::default();
+        stats.record_block(1_000);
+        stats.record_wakeup(4_000);
+        // ブロックしていない状態での起床（Lost Wakeup）は時間を加算しない
+        stats.record_wakeup(9_000);
+        stats.record_switch_out(500, true);
+        stats.record_switch_out(700, false);
+        assert_eq!(stats.blocked_ns, 3_000);
+        assert_eq!(stats.wakeups, 2);
+        assert_eq!(stats.cpu_time_ns, 1_200);
+        assert_eq!(stats.voluntary_switches, 1);
+        assert_eq!(stats.involuntary_switches, 1);
+    }
+
+    #[test_case]
+    fn test_task_priority_outranks() {
+        assert!(TaskPriority::Realtime(1).outranks(TaskPriority::Normal(nice::MIN)));
+        assert!(TaskPriority::Realtime(99).outranks(TaskPriority::Realtime(50)));
+        assert!(TaskPriority::Normal(-5).outranks(TaskPriority::Normal(0)));
+        assert!(TaskPriority::Normal(nice::MAX).outranks(TaskPriority::Idle));
+        assert!(!TaskPriority::Normal(0).outranks(TaskPriority::Normal(0)));
+        let params = DeadlineParams::new(2_000_000, 16_000_000, 16_000_000).unwrap();
+        assert!(TaskPriority::Deadline(params).outranks(TaskPriority::Realtime(99)));
+        assert_eq!(
+            TaskPriority::Deadline(params).inheritable(),
+            TaskPriority::Realtime(99)
+        );
+    }
+
+    #[test_case]
+    fn test_set_pi_boost_takes_highest_and_restores_base() {
+        extern "C" fn entry() -> ! {
+            loop {
+                core::hint::spin_loop();
+            }
+        }
+        let mut task = Task::new("pi-test", 10, entry).unwrap();
+        task.set_pi_boost(1, Some(TaskPriority::Normal(-5)));
+        task.set_pi_boost(2, Some(TaskPriority::Realtime(99)));
+        assert_eq!(task.priority(), TaskPriority::Realtime(99));
+        assert_eq!(task.stats().pi_boost, Some(TaskPriority::Realtime(99)));
+
+        task.set_pi_boost(2, None);
+        assert_eq!(task.priority(), TaskPriority::Normal(-5));
+        assert_eq!(task.weight(), nice_to_weight(-5));
+
+        task.set_pi_boost(1, None);
+        assert_eq!(task.priority(), TaskPriority::Normal(10));
+        assert_eq!(task.stats().pi_boost, None);
+        assert_eq!(task.stats().pi_boosts, 1);
+    }
+
+    #[test_case]
+    fn test_task_stack_size_is_rounded_and_bounded() {
+        let stack = TaskStack::new(stack_size::MIN + 1).unwrap();
+        assert_eq!(stack.size(), stack_size::MIN + 16);
+        assert_eq!(stack.top() % 16, 0);
+        assert_eq!(stack.top() - stack.bottom(), stack.size() as u64);
+        assert!(matches!(
+            TaskStack::new(stack_size::MIN - 1),
+            Err(TaskError::InvalidStackSize)
+        ));
+        assert!(matches!(
+            TaskStack::new(stack_size::MAX + 1),
+            Err(TaskError::InvalidStackSize)
+        ));
+    }
+
+    #[test_case]
+    fn test_unstarted_closure_is_dropped_with_task() {
+        let captured = Arc::new(0);
+        let moved = captured.clone();
+        // 利用率100%は上限(95%)を超えるため、どのCPUにも受け入れられない
+        let params = DeadlineParams::new(10_000_000, 10_000_000, 10_000_000).unwrap();
+        let result = crate::sched::spawn("overcommit", SpawnOptions::deadline(params), move || {
+            drop(moved);
+        });
+        assert!(matches!(result, Err(TaskError::AdmissionDenied)));
+        // 実行されなかったクロージャはキャプチャした値ごと破棄されている
+        assert_eq!(Arc::strong_count(&captured), 1);
+    }
 }
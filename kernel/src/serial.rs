@@ -1,9 +1,39 @@
 // シリアルポート（COM1）ドライバ
+//
+// 出力はロックを取らずにポートへ直接書き込むため、パニック時に他CPUを停止しても
+// 出力が止まることはない。パニック出力中（`begin_panic_output()`以降）は
+// パニックしたCPU以外の出力を捨て、送信待ちに上限を設けて、停止し損ねたCPUの出力が
+// 混ざることやUARTの異常で出力が止まることを防ぐ。
 use crate::io::{port_read_u8, port_write_u8};
 use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-#[allow(dead_code)]
-const COM1: u16 = 0x3F8;
+pub const COM1: u16 = 0x3F8;
+
+/// パニック出力中でないことを示す値
+const NO_PANIC_CPU: usize = usize::MAX;
+
+/// パニック出力中のCPU（パニック出力中でなければNO_PANIC_CPU）
+static PANIC_CPU: AtomicUsize = AtomicUsize::new(NO_PANIC_CPU);
+
+/// パニック出力中の送信待ちの上限（ループ回数、38400bpsの1バイト分より十分長い）
+const PANIC_TRANSMIT_SPINS: usize = 1_000_000;
+
+/// パニック出力を開始する
+///
+/// 以降は呼び出したCPUの出力のみを送信し、送信待ちには上限を設ける。
+/// `ipi::stop_other_cpus()`の後、パニックレポートの出力前に呼び出す。
+pub fn begin_panic_output() {
+    PANIC_CPU.store(crate::cpu::current_cpu(), Ordering::Release);
+    // 停止したCPUが出力していた行の途中から始まらないよう改行する
+    SerialPort::new(COM1).write_str("\n");
+}
+
+/// 現在のCPUからの出力を送信してよいかどうか
+fn may_write() -> bool {
+    let panic_cpu = PANIC_CPU.load(Ordering::Acquire);
+    panic_cpu == NO_PANIC_CPU || panic_cpu == crate::cpu::current_cpu()
+}
 
 pub struct SerialPort {
     base: u16,
@@ -28,17 +58,26 @@ impl SerialPort {
         }
     }
 
-    // 送信準備完了を待つ
+    // 送信準備完了を待つ（パニック出力中は上限まで待って諦める）
     fn wait_for_transmit(&self) {
+        let bounded = PANIC_CPU.load(Ordering::Acquire) != NO_PANIC_CPU;
+        let mut spins = 0;
         unsafe {
             while (port_read_u8(self.base + 5) & 0x20) == 0 {
+                if bounded && spins >= PANIC_TRANSMIT_SPINS {
+                    return;
+                }
+                spins += 1;
                 core::hint::spin_loop();
             }
         }
     }
 
-    // 1バイト送信
+    // 1バイト送信（パニック出力中はパニックしたCPUからのみ）
     pub fn write_byte(&self, byte: u8) {
+        if !may_write() {
+            return;
+        }
         self.wait_for_transmit();
         unsafe {
             port_write_u8(self.base, byte);
@@ -51,6 +90,25 @@ impl SerialPort {
             self.write_byte(byte);
         }
     }
+
+    // 受信済みのバイトがあれば1バイト読み出す（ブロックしない）
+    pub fn read_byte(&self) -> Option<u8> {
+        unsafe {
+            if (port_read_u8(self.base + 5) & 0x01) == 0 {
+                return None;
+            }
+            Some(port_read_u8(self.base))
+        }
+    }
+
+    // 受信データの割り込みを有効化（OUT2を立ててIRQ線へ出力する）
+    pub fn enable_receive_interrupt(&self) {
+        unsafe {
+            let mcr = port_read_u8(self.base + 4);
+            port_write_u8(self.base + 4, mcr | 0x08);
+            port_write_u8(self.base + 1, 0x01);
+        }
+    }
 }
 
 // SerialPort に対して fmt::Write を実装
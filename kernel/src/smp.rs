@@ -0,0 +1,530 @@
+//! SMP（Symmetric Multi-Processing）: APの起動
+//!
+//! MADTに列挙されたAP（Application Processor）をINIT-SIPI-SIPIシーケンスで起動します。
+//!
+//! # 起動の流れ
+//! 1. BSPが1MB未満の空きメモリ（2ページ）にリアルモード用トランポリンをコピー
+//! 2. 2ページ目に一時PML4を作成（カーネルのPML4のコピー + PML4[0]に低位の恒等マップ）
+//! 3. INIT IPI → 10ms待機 → SIPI（必要に応じて2回）でAPを起動
+//! 4. APはリアルモード → プロテクトモード → ロングモードと遷移し、
+//!    BSPと同じCR0/CR4/EFERを設定して高位アドレスの`ap_entry`へジャンプ
+//! 5. `ap_entry`でカーネルのCR3へ切り替え（恒等マップが消える）、
+//!    GDT/TSS・IDT・Local APIC・APIC Timerを初期化し、自身のアイドルタスクを
+//!    登録してスケジューラに参加
+//!
+//! APは1つずつ起動し、オンラインになるのを待ってから次のAPを起動します
+//! （トランポリンのデータ領域を共有するため）。
+
+use alloc::vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+use vitros_common::boot_info::MemoryRegion;
+use vitros_common::uefi::EFI_CONVENTIONAL_MEMORY;
+
+use crate::cpu::{self, MAX_CPUS};
+use crate::paging::{self, KERNEL_VIRTUAL_BASE, PAGE_SIZE};
+use crate::sched::{self, Task};
+use crate::{apic, gdt, hpet, idt, info, percpu, pit, time, warn};
+
+/// APのカーネルスタックサイズ
+const AP_STACK_SIZE: usize = 32 * 1024;
+
+/// トランポリンに必要な領域（コード/データ1ページ + 一時PML4 1ページ）
+const TRAMPOLINE_REGION_SIZE: u64 = 2 * PAGE_SIZE as u64;
+
+/// トランポリン配置の下限（0ページはリアルモードIVT/BDAのため避ける）
+const TRAMPOLINE_MIN_ADDR: u64 = 0x1000;
+
+/// トランポリン配置の上限（EBDA・VGA領域より下）
+const TRAMPOLINE_MAX_ADDR: u64 = 0x9F000;
+
+/// INIT IPI後の待機時間（マイクロ秒）
+const INIT_DELAY_US: u64 = 10_000;
+
+/// SIPI後の待機時間（マイクロ秒）
+const SIPI_DELAY_US: u64 = 200;
+
+/// APがオンラインになるまでの最大待機時間（ミリ秒）
+const AP_ONLINE_TIMEOUT_MS: u64 = 100;
+
+/// IA32_EFER MSR
+const IA32_EFER: u32 = 0xC000_0080;
+
+/// CR4.PCIDE（CR3の上位ビットが0でないと設定できないため、トランポリンでは除外する）
+const CR4_PCIDE: u64 = 1 << 17;
+
+/// EFER.LMA（読み取り専用。ロングモード有効化時にCPUが設定する）
+const EFER_LMA: u64 = 1 << 10;
+
+/// APのAPICタイマー周波数（BSPと同じ値を使用）
+static AP_TIMER_HZ: AtomicU32 = AtomicU32::new(0);
+
+/// SMP関連のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmpError {
+    /// トランポリンを配置できる低位メモリが見つからない
+    NoTrampolineMemory,
+    /// トランポリンが1ページに収まらない
+    TrampolineTooLarge,
+    /// 論理CPU番号の上限に達した
+    TooManyCpus,
+    /// 指定時間内にAPがオンラインにならなかった
+    ApTimeout { apic_id: u32 },
+}
+
+impl core::fmt::Display for SmpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            SmpError::NoTrampolineMemory => write!(f, "No low memory for AP trampoline"),
+            SmpError::TrampolineTooLarge => write!(f, "AP trampoline does not fit in a page"),
+            SmpError::TooManyCpus => write!(f, "Too many CPUs (max {})", MAX_CPUS),
+            SmpError::ApTimeout { apic_id } => {
+                write!(f, "AP (APIC ID {}) did not come online", apic_id)
+            }
+        }
+    }
+}
+
+/// MADTから登録されたプロセッサ（Local APIC ID）の一覧
+struct ProcessorTable {
+    apic_ids: [u32; MAX_CPUS],
+    count: usize,
+}
+
+static PROCESSORS: Mutex<ProcessorTable> = Mutex::new(ProcessorTable {
+    apic_ids: [0; MAX_CPUS],
+    count: 0,
+});
+
+/// MADTのProcessor Local APICエントリ（有効なもの）を登録（acpi.rsから呼ばれる）
+pub fn register_processor(apic_id: u32) {
+    let mut table = PROCESSORS.lock();
+    // ファームウェアによってはLocal APICとLocal x2APICの両方に同じプロセッサを列挙する
+    if table.apic_ids[..table.count].contains(&apic_id) {
+        return;
+    }
+    if table.count >= MAX_CPUS {
+        warn!("Too many processors, ignoring APIC ID={}", apic_id);
+        return;
+    }
+    let index = table.count;
+    table.apic_ids[index] = apic_id;
+    table.count += 1;
+}
+
+/// トランポリンのデータ領域（global_asm!内のレイアウトと一致させること）
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, Default)]
+struct TrampolineData {
+    /// 一時GDTのGDTR（limit, base）
+    gdtr_limit: u16,
+    gdtr_base: u32,
+    /// プロテクトモードへのfar jump先（offset, selector）
+    pm_entry: u32,
+    pm_selector: u16,
+    /// ロングモードへのfar jump先（offset, selector）
+    lm_entry: u32,
+    lm_selector: u16,
+    /// BSPから引き継ぐ制御レジスタ
+    cr0: u32,
+    cr4: u32,
+    /// 一時PML4の物理アドレス（4GB未満）
+    pml4: u32,
+    efer: u64,
+    /// カーネルのCR3
+    kernel_cr3: u64,
+    /// APのスタックトップ（高位仮想アドレス）
+    stack_top: u64,
+    /// 高位アドレスのエントリポイント
+    entry: u64,
+    /// 論理CPU番号
+    cpu_index: u64,
+}
+
+// リアルモードトランポリン
+//
+// BSPがこのコードを1MB未満のページにコピーし、SIPIでAPに実行させる。
+// コピー先で動作するため、全てのアドレスはsmp_trampoline_startからの相対で扱い、
+// 絶対アドレスが必要な箇所（GDTR・far jump先）はBSPがデータ領域に書き込む。
+core::arch::global_asm!(
+    ".pushsection .rodata.smp_trampoline, \"a\"",
+    ".global smp_trampoline_start",
+    ".global smp_trampoline_end",
+    ".global smp_trampoline_data",
+    ".global smp_trampoline_gdt",
+    ".global smp_trampoline_pm_entry",
+    ".global smp_trampoline_lm_entry",
+    ".balign 16",
+    ".code16",
+    "smp_trampoline_start:",
+    "cli",
+    "cld",
+    // EBX = トランポリンの物理ベースアドレス（CS << 4）
+    "xorl %ebx, %ebx",
+    "movw %cs, %bx",
+    "shll $4, %ebx",
+    "movw %cs, %ax",
+    "movw %ax, %ds",
+    // 一時GDTをロードしてプロテクトモードへ
+    "lgdtl (smp_trampoline_data - smp_trampoline_start)",
+    "movl %cr0, %eax",
+    "orl $1, %eax",
+    "movl %eax, %cr0",
+    "ljmpl *(smp_trampoline_data + 6 - smp_trampoline_start)",
+    ".code32",
+    "smp_trampoline_pm_entry:",
+    "movw $0x10, %ax",
+    "movw %ax, %ds",
+    "movw %ax, %es",
+    "movw %ax, %ss",
+    // CR4（PAE等）→ CR3（一時PML4）→ EFER（LME/NXE）→ CR0（PG）の順に設定
+    "movl (smp_trampoline_data + 22 - smp_trampoline_start)(%ebx), %eax",
+    "movl %eax, %cr4",
+    "movl (smp_trampoline_data + 26 - smp_trampoline_start)(%ebx), %eax",
+    "movl %eax, %cr3",
+    "movl $0xC0000080, %ecx",
+    "movl (smp_trampoline_data + 30 - smp_trampoline_start)(%ebx), %eax",
+    "movl (smp_trampoline_data + 34 - smp_trampoline_start)(%ebx), %edx",
+    "wrmsr",
+    "movl (smp_trampoline_data + 18 - smp_trampoline_start)(%ebx), %eax",
+    "movl %eax, %cr0",
+    "ljmpl *(smp_trampoline_data + 12 - smp_trampoline_start)(%ebx)",
+    ".code64",
+    "smp_trampoline_lm_entry:",
+    // 上位32ビットをクリア
+    "movl %ebx, %ebx",
+    "movq (smp_trampoline_data + 38 - smp_trampoline_start)(%rbx), %rsi",
+    "movq (smp_trampoline_data + 46 - smp_trampoline_start)(%rbx), %rsp",
+    "movq (smp_trampoline_data + 62 - smp_trampoline_start)(%rbx), %rdi",
+    "movq (smp_trampoline_data + 54 - smp_trampoline_start)(%rbx), %rax",
+    "jmpq *%rax",
+    // 一時GDT: NULL, 32bitコード(0x08), データ(0x10), 64bitコード(0x18)
+    ".balign 8",
+    "smp_trampoline_gdt:",
+    ".quad 0",
+    ".quad 0x00CF9A000000FFFF",
+    ".quad 0x00CF92000000FFFF",
+    ".quad 0x00AF9A000000FFFF",
+    "smp_trampoline_gdt_end:",
+    // データ領域（TrampolineDataと同じレイアウト、BSPが書き込む）
+    ".balign 8",
+    "smp_trampoline_data:",
+    ".fill 70, 1, 0",
+    "smp_trampoline_end:",
+    ".popsection",
+    options(att_syntax)
+);
+
+unsafe extern "C" {
+    static smp_trampoline_start: u8;
+    static smp_trampoline_end: u8;
+    static smp_trampoline_data: u8;
+    static smp_trampoline_gdt: u8;
+    static smp_trampoline_pm_entry: u8;
+    static smp_trampoline_lm_entry: u8;
+}
+
+/// トランポリン内シンボルの先頭からのオフセット
+fn trampoline_offset(symbol: *const u8) -> u64 {
+    let start = core::ptr::addr_of!(smp_trampoline_start) as u64;
+    symbol as u64 - start
+}
+
+/// 1MB未満でトランポリン（2ページ）を配置できる物理アドレスを探す
+///
+/// # Arguments
+/// * `regions` - UEFIメモリマップ
+///
+/// # Returns
+/// 4KB境界にアラインされた物理アドレス
+fn find_trampoline_base(regions: &[MemoryRegion]) -> Option<u64> {
+    regions
+        .iter()
+        .filter(|r| r.region_type == EFI_CONVENTIONAL_MEMORY)
+        .find_map(|r| {
+            let start = r
+                .start
+                .max(TRAMPOLINE_MIN_ADDR)
+                .next_multiple_of(PAGE_SIZE as u64);
+            let end = r.start.saturating_add(r.size).min(TRAMPOLINE_MAX_ADDR);
+            (start + TRAMPOLINE_REGION_SIZE <= end).then_some(start)
+        })
+}
+
+/// 指定マイクロ秒待機（HPETが利用可能ならHPET、なければPIT）
+fn delay_us(us: u64) {
+    if hpet::is_available() {
+        hpet::delay_us(us);
+    } else {
+        pit::udelay(us as u32);
+    }
+}
+
+/// 制御レジスタとEFERを読み取る
+fn read_boot_state() -> (u64, u64, u64) {
+    let (cr0, cr4): (u64, u64);
+    // SAFETY: CR0/CR4の読み取りはRing 0では副作用がない。
+    unsafe {
+        core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+    }
+    // SAFETY: IA32_EFERはx86_64で常に存在するMSR
+    let efer = unsafe { crate::msr::read(IA32_EFER) };
+    (cr0, cr4, efer)
+}
+
+/// トランポリンと一時PML4を低位メモリに配置
+///
+/// # Returns
+/// トランポリンデータ領域の仮想アドレス
+///
+/// # Safety
+/// `base`から2ページが未使用の物理メモリで、高位アドレスにマッピングされていること
+unsafe fn install_trampoline(base: u64) -> Result<*mut TrampolineData, SmpError> {
+    let start = core::ptr::addr_of!(smp_trampoline_start);
+    let end = core::ptr::addr_of!(smp_trampoline_end);
+    let data = core::ptr::addr_of!(smp_trampoline_data);
+    let gdt = core::ptr::addr_of!(smp_trampoline_gdt);
+    let pm_entry = core::ptr::addr_of!(smp_trampoline_pm_entry);
+    let lm_entry = core::ptr::addr_of!(smp_trampoline_lm_entry);
+    let size = trampoline_offset(end) as usize;
+    if size > PAGE_SIZE {
+        return Err(SmpError::TrampolineTooLarge);
+    }
+
+    let virt = KERNEL_VIRTUAL_BASE + base;
+    let pml4_phys = base + PAGE_SIZE as u64;
+    let pml4_virt = KERNEL_VIRTUAL_BASE + pml4_phys;
+
+    // SAFETY: 呼び出し元がbaseから2ページの書き込み可能性を保証する。
+    // カーネルPML4はCR3が指す有効なページテーブルで、高位アドレスから読み取れる。
+    unsafe {
+        // トランポリンコードをコピー
+        core::ptr::copy_nonoverlapping(start, virt as *mut u8, size);
+
+        // 一時PML4: カーネルのPML4をコピーし、PML4[0]に高位と同じPDPを設定して
+        // 低位アドレスを恒等マップする（トランポリンがページング有効化直後に実行を続けるため）
+        let kernel_pml4 = (KERNEL_VIRTUAL_BASE + (paging::read_cr3() & !0xFFF)) as *const u64;
+        let temp_pml4 = pml4_virt as *mut u64;
+        core::ptr::copy_nonoverlapping(kernel_pml4, temp_pml4, 512);
+        *temp_pml4 = *temp_pml4.add(paging::PML4_KERNEL_INDEX);
+    }
+
+    let (cr0, cr4, efer) = read_boot_state();
+    let data_ptr = (virt + trampoline_offset(data)) as *mut TrampolineData;
+    let template = TrampolineData {
+        gdtr_limit: (4 * 8 - 1) as u16,
+        gdtr_base: (base + trampoline_offset(gdt)) as u32,
+        pm_entry: (base + trampoline_offset(pm_entry)) as u32,
+        pm_selector: 0x08,
+        lm_entry: (base + trampoline_offset(lm_entry)) as u32,
+        lm_selector: 0x18,
+        cr0: cr0 as u32,
+        cr4: (cr4 & !CR4_PCIDE) as u32,
+        pml4: pml4_phys as u32,
+        efer: efer & !EFER_LMA,
+        kernel_cr3: paging::read_cr3(),
+        stack_top: 0,
+        entry: ap_entry as *const () as u64,
+        cpu_index: 0,
+    };
+    // SAFETY: data_ptrはコピーしたトランポリン内のデータ領域（packed構造体）を指す
+    unsafe { core::ptr::write_unaligned(data_ptr, template) };
+    Ok(data_ptr)
+}
+
+/// APの高位アドレスエントリポイント
+///
+/// トランポリンから RDI = 論理CPU番号、RSI = カーネルCR3、RSP = APスタックで呼ばれる。
+#[unsafe(naked)]
+extern "C" fn ap_entry() -> ! {
+    core::arch::naked_asm!(
+        // 一時PML4からカーネルのページテーブルへ切り替え
+        "mov cr3, rsi",
+        // バックトレースの終端
+        "xor ebp, ebp",
+        "call {ap_main}",
+        "2:",
+        "cli",
+        "hlt",
+        "jmp 2b",
+        ap_main = sym ap_main,
+    )
+}
+
+/// APの初期化処理（Rust側）
+extern "C" fn ap_main(cpu: usize) -> ! {
+    // Per-CPU変数（cpu::current_cpu()を含む）を使用する前にGSベースを設定する
+    percpu::init_cpu(cpu).expect("Failed to initialize AP per-CPU area");
+    gdt::init_ap(cpu).expect("Failed to initialize AP GDT");
+    idt::load();
+    apic::init_ap();
+    if let Err(e) = time::clockevent::start_local_periodic(AP_TIMER_HZ.load(Ordering::SeqCst)) {
+        warn!("CPU {}: timer initialization failed: {}", cpu, e);
+    }
+
+    cpu::set_online(cpu);
+    info!("CPU {} online (APIC ID {})", cpu, apic::local_apic_id());
+
+    // このCPU専用のアイドルタスクを登録してスケジューラに参加する
+    // （Idleクラスのタスクは現在のCPUのランキューに配置され、他CPUへ移動しない）
+    let idle = Task::new_idle("Idle", ap_idle_task).expect("Failed to create AP idle task");
+    sched::add_task(idle);
+
+    // 初回のschedule()はダミーコンテキストから切り替えるため、ここには戻らない
+    sched::schedule();
+    unreachable!("AP bootstrap context was rescheduled");
+}
+
+/// APのアイドルタスク
+extern "C" fn ap_idle_task() -> ! {
+    loop {
+        // 次のイベントまでtickを止めて休止する（tickless idle）
+        time::nohz::idle();
+    }
+}
+
+/// 1つのAPを起動し、オンラインになるまで待つ
+///
+/// # Safety
+/// `data`はinstall_trampoline()が返した有効なデータ領域であること
+unsafe fn boot_ap(
+    data: *mut TrampolineData,
+    trampoline_base: u64,
+    apic_id: u32,
+) -> Result<usize, SmpError> {
+    let cpu = cpu::register_cpu(apic_id).ok_or(SmpError::TooManyCpus)?;
+
+    // APスタックを確保（APが使い続けるためリークさせる）
+    let stack = vec![0u8; AP_STACK_SIZE].leak();
+    let stack_top = (stack.as_ptr() as u64 + AP_STACK_SIZE as u64) & !0xF;
+
+    // SAFETY: 呼び出し元がdataの有効性を保証する
+    unsafe {
+        let mut d = core::ptr::read_unaligned(data);
+        d.stack_top = stack_top;
+        d.cpu_index = cpu as u64;
+        core::ptr::write_unaligned(data, d);
+    }
+    core::sync::atomic::fence(Ordering::SeqCst);
+
+    let start_page = (trampoline_base >> 12) as u8;
+    apic::send_init_ipi(apic_id);
+    delay_us(INIT_DELAY_US);
+    for _ in 0..2 {
+        apic::send_startup_ipi(apic_id, start_page);
+        delay_us(SIPI_DELAY_US);
+        if cpu::is_online(cpu) {
+            break;
+        }
+    }
+
+    for _ in 0..AP_ONLINE_TIMEOUT_MS {
+        if cpu::is_online(cpu) {
+            return Ok(cpu);
+        }
+        delay_us(1000);
+    }
+    Err(SmpError::ApTimeout { apic_id })
+}
+
+/// MADTに列挙された全APを起動
+///
+/// ヒープ・Local APIC・APIC Timerのキャリブレーションが完了した後に、
+/// 割り込み無効状態のBSPから呼び出す。
+///
+/// # Arguments
+/// * `memory_map` - UEFIメモリマップ（トランポリン配置先の探索に使用）
+/// * `timer_hz` - APのAPICタイマー周波数
+///
+/// # Returns
+/// 起動に成功したAPの数
+pub fn init(memory_map: &[MemoryRegion], timer_hz: u32) -> Result<usize, SmpError> {
+    let (apic_ids, count) = {
+        let table = PROCESSORS.lock();
+        (table.apic_ids, table.count)
+    };
+    let bsp_apic_id = cpu::apic_id_of(0).unwrap_or_else(apic::local_apic_id);
+    if apic_ids[..count].iter().all(|&id| id == bsp_apic_id) {
+        info!("SMP: no application processors found");
+        return Ok(0);
+    }
+
+    AP_TIMER_HZ.store(timer_hz, Ordering::SeqCst);
+
+    let base = find_trampoline_base(memory_map).ok_or(SmpError::NoTrampolineMemory)?;
+    info!("SMP: trampoline at physical 0x{:X}", base);
+
+    // SAFETY: baseはメモリマップ上の空き領域（1MB未満）で、高位アドレスに直接マッピングされている
+    let data = unsafe { install_trampoline(base)? };
+
+    let mut started = 0;
+    for &apic_id in apic_ids[..count].iter().filter(|&&id| id != bsp_apic_id) {
+        // xAPICモードのICRは8ビットの宛先しか指定できない
+        if !apic::is_x2apic() && apic_id > 0xFF {
+            warn!("SMP: APIC ID {} requires x2APIC mode, skipping", apic_id);
+            continue;
+        }
+        // SAFETY: dataはinstall_trampoline()が返した領域
+        match unsafe { boot_ap(data, base, apic_id) } {
+            Ok(_) => started += 1,
+            Err(e) => warn!("SMP: {}", e),
+        }
+    }
+
+    info!("SMP: {} of {} CPU(s) online", cpu::online_cpus(), count);
+    Ok(started)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(start: u64, size: u64, region_type: u32) -> MemoryRegion {
+        MemoryRegion {
+            start,
+            size,
+            region_type,
+        }
+    }
+
+    #[test_case]
+    fn test_trampoline_data_layout() {
+        // global_asm!内のオフセットと一致していること
+        assert_eq!(core::mem::size_of::<TrampolineData>(), 70);
+        assert_eq!(core::mem::offset_of!(TrampolineData, pm_entry), 6);
+        assert_eq!(core::mem::offset_of!(TrampolineData, lm_entry), 12);
+        assert_eq!(core::mem::offset_of!(TrampolineData, cr0), 18);
+        assert_eq!(core::mem::offset_of!(TrampolineData, cr4), 22);
+        assert_eq!(core::mem::offset_of!(TrampolineData, pml4), 26);
+        assert_eq!(core::mem::offset_of!(TrampolineData, efer), 30);
+        assert_eq!(core::mem::offset_of!(TrampolineData, kernel_cr3), 38);
+        assert_eq!(core::mem::offset_of!(TrampolineData, stack_top), 46);
+        assert_eq!(core::mem::offset_of!(TrampolineData, entry), 54);
+        assert_eq!(core::mem::offset_of!(TrampolineData, cpu_index), 62);
+    }
+
+    #[test_case]
+    fn test_find_trampoline_base_skips_page_zero() {
+        let regions = [region(0, 0x9F000, EFI_CONVENTIONAL_MEMORY)];
+        assert_eq!(find_trampoline_base(&regions), Some(0x1000));
+    }
+
+    #[test_case]
+    fn test_find_trampoline_base_requires_two_pages_below_1mb() {
+        let regions = [
+            // 1ページしかない
+            region(0x8000, 0x1000, EFI_CONVENTIONAL_MEMORY),
+            // 使用中
+            region(0x10000, 0x10000, 0),
+            // 上限を超える
+            region(0x9E000, 0x10000, EFI_CONVENTIONAL_MEMORY),
+            // 1MB以上
+            region(0x100000, 0x100000, EFI_CONVENTIONAL_MEMORY),
+        ];
+        assert_eq!(find_trampoline_base(&regions), None);
+
+        let regions = [region(0x20800, 0x3000, EFI_CONVENTIONAL_MEMORY)];
+        assert_eq!(find_trampoline_base(&regions), Some(0x21000));
+    }
+}
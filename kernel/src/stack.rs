@@ -9,6 +9,7 @@ use crate::paging::PAGE_SIZE;
 #[cfg(not(test))]
 unsafe extern "C" {
     static __stack_top: u8;
+    static __stack_bottom: u8;
     static __stack_guard: u8;
 }
 
@@ -18,6 +19,12 @@ pub fn stack_top() -> u64 {
     core::ptr::addr_of!(__stack_top) as u64
 }
 
+/// スタック底アドレスを取得
+#[cfg(not(test))]
+pub fn stack_bottom() -> u64 {
+    core::ptr::addr_of!(__stack_bottom) as u64
+}
+
 /// ガードページアドレスを取得
 #[cfg(not(test))]
 pub fn guard_page_address() -> Option<u64> {
@@ -38,6 +45,11 @@ pub fn stack_top() -> u64 {
     0
 }
 
+#[cfg(test)]
+pub fn stack_bottom() -> u64 {
+    0
+}
+
 #[cfg(test)]
 pub fn guard_page_address() -> Option<u64> {
     None
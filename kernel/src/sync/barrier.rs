@@ -0,0 +1,119 @@
+//! バリア
+//!
+//! 指定数のタスクが揃うまで、到着したタスクをブロックする
+
+use super::wait_queue::WaitQueue;
+use crate::io::without_interrupts;
+use spin::Mutex as SpinMutex;
+
+/// バリアの状態
+struct BarrierState {
+    /// 現在の世代で到着済みのタスク数
+    arrived: usize,
+    /// 世代番号（全員が揃う毎に増加）
+    generation: u64,
+}
+
+impl BarrierState {
+    const fn new() -> Self {
+        Self {
+            arrived: 0,
+            generation: 0,
+        }
+    }
+
+    /// 1タスクの到着を記録
+    ///
+    /// # Returns
+    /// (待つべき世代番号, `n`個目の到着かどうか)。`n`個目なら次の世代に進める
+    fn arrive(&mut self, n: usize) -> (u64, bool) {
+        self.arrived += 1;
+        if self.arrived == n {
+            self.arrived = 0;
+            self.generation += 1;
+            (self.generation, true)
+        } else {
+            (self.generation, false)
+        }
+    }
+}
+
+/// バリア
+///
+/// `n`個のタスクが`wait()`を呼ぶまで全員をブロックし、揃った時点で全員を起床させます。
+/// 揃った後は再び使用できます。
+pub struct Barrier {
+    /// 揃うべきタスク数
+    n: usize,
+    /// 到着状況
+    state: SpinMutex<BarrierState>,
+    /// 揃うのを待つタスクの待機キュー
+    wait_queue: WaitQueue,
+}
+
+/// `Barrier::wait()`の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    is_leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// 最後に到着したタスク（1世代につき1つだけ）かどうか
+    pub fn is_leader(&self) -> bool {
+        self.is_leader
+    }
+}
+
+impl Barrier {
+    /// `n`個のタスクを待ち合わせるバリアを作成（`n`が0の場合は1として扱う）
+    pub const fn new(n: usize) -> Self {
+        Self {
+            n: if n == 0 { 1 } else { n },
+            state: SpinMutex::new(BarrierState::new()),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// 全員が揃うまでブロック
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn wait(&self) -> BarrierWaitResult {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Barrier::wait() cannot be called from interrupt context"
+        );
+        let (generation, is_leader) = without_interrupts(|| self.state.lock().arrive(self.n));
+
+        if is_leader {
+            self.wait_queue.wake_all();
+        } else {
+            self.wait_queue
+                .wait_event(|| without_interrupts(|| self.state.lock().generation != generation));
+        }
+        BarrierWaitResult { is_leader }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_barrier_of_one_does_not_block() {
+        let barrier = Barrier::new(1);
+        assert!(barrier.wait().is_leader());
+        // 揃った後は再利用できる
+        assert!(barrier.wait().is_leader());
+    }
+
+    #[test_case]
+    fn test_barrier_state_counts_arrivals() {
+        let mut state = BarrierState::new();
+        assert_eq!(state.arrive(2), (0, false));
+        // 2つ目の到着でリーダーになり、世代が進む
+        assert_eq!(state.arrive(2), (1, true));
+        assert_eq!(state.arrive(2), (1, false));
+        assert_eq!(state.arrive(2), (2, true));
+    }
+}
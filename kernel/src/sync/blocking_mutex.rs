@@ -1,11 +1,48 @@
 //! ブロッキングMutex
 //!
 //! スピンロックではなく、タスクをブロックすることで排他制御を行うMutex
+//!
+//! # 優先度継承
+//! ロックを保持しているタスクと待機中のタスクの優先度を記録し、保持タスクを
+//! 待機タスクのうち最も高いスケジューリングクラス・優先度まで一時的に引き上げます
+//! （`sched::set_pi_boost()`）。引き上げはロックの解放時に解除され、次にロックを
+//! 取得したタスクが残りの待機タスクの優先度を引き継ぎます。
 
 use super::wait_queue::WaitQueue;
+use crate::io::without_interrupts;
+use crate::sched::{TaskId, TaskPriority};
+use alloc::vec::Vec;
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
-use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex as SpinMutex;
+
+/// ロックの状態
+struct LockState {
+    /// ロックを保持しているタスク（Noneなら未ロック）
+    owner: Option<TaskId>,
+    /// 保持タスクがロックを取得したCPU（優先度の引き上げ時にタスクを探す起点）
+    owner_cpu: usize,
+    /// ロックを待っているタスクとその優先度
+    waiters: Vec<(TaskId, TaskPriority)>,
+    /// 保持タスクに設定している優先度の引き上げ
+    boost: Option<TaskPriority>,
+}
+
+impl LockState {
+    /// 待機タスクのうち最も高い優先度
+    fn top_waiter_priority(&self) -> Option<TaskPriority> {
+        self.waiters
+            .iter()
+            .map(|&(_, priority)| priority)
+            .reduce(|max, priority| {
+                if priority.outranks(max) {
+                    priority
+                } else {
+                    max
+                }
+            })
+    }
+}
 
 /// ブロッキングMutex
 ///
@@ -15,8 +52,8 @@ use core::sync::atomic::{AtomicBool, Ordering};
 /// # Safety
 /// 内部でロックにより排他アクセスを保証します。
 pub struct BlockingMutex<T: ?Sized> {
-    /// ロック状態（true = ロック中）
-    locked: AtomicBool,
+    /// ロック状態（保持タスク・待機タスク・優先度の引き上げ）
+    state: SpinMutex<LockState>,
     /// 待機キュー
     wait_queue: WaitQueue,
     /// 保護対象データ
@@ -31,41 +68,79 @@ impl<T> BlockingMutex<T> {
     /// 新しいBlockingMutexを作成
     pub const fn new(value: T) -> Self {
         Self {
-            locked: AtomicBool::new(false),
+            state: SpinMutex::new(LockState {
+                owner: None,
+                owner_cpu: 0,
+                waiters: Vec::new(),
+                boost: None,
+            }),
             wait_queue: WaitQueue::new(),
             data: UnsafeCell::new(value),
         }
     }
+}
 
+impl<T: ?Sized> BlockingMutex<T> {
     /// ロックを取得
     ///
-    /// 他のタスクがロックを保持している場合、現在のタスクをブロックします。
-    /// 割り込みコンテキストではスピンにフォールバックします。
+    /// 他のタスクがロックを保持している場合、保持タスクの優先度を引き上げてから
+    /// 現在のタスクをブロックします。
+    /// 割り込みコンテキストではスピンにフォールバックします（優先度継承は行わない）。
     ///
     /// # Returns
     /// ロックガード
     pub fn lock(&self) -> MutexGuard<'_, T> {
-        loop {
-            // 楽観的にロック取得を試みる
-            if self
-                .locked
-                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
-                .is_ok()
-            {
-                return MutexGuard { mutex: self };
-            }
+        let me = crate::sched::current_task_id();
+        // 楽観的にロック取得を試みる
+        if self.try_acquire(me) {
+            return MutexGuard { mutex: self };
+        }
 
-            // 取得失敗時、割り込みコンテキストでないことを確認
-            if crate::sched::is_interrupt_context() {
-                // 割り込み中はスピンにフォールバック
-                while self.locked.load(Ordering::Relaxed) {
-                    core::hint::spin_loop();
-                }
-            } else {
-                // 通常コンテキストではブロック
-                self.wait_queue.wait();
+        // 取得失敗時、割り込みコンテキストでないことを確認
+        if crate::sched::is_interrupt_context() {
+            // 割り込み中はスピンにフォールバック
+            while !self.try_acquire(me) {
+                core::hint::spin_loop();
             }
+        } else {
+            // 通常コンテキストではロックが解放されるまでブロック
+            // （確認から待機までの間の解放はwait_event()が検出する）
+            self.register_waiter(me);
+            self.wait_queue.wait_event(|| self.try_acquire(me));
+        }
+        MutexGuard { mutex: self }
+    }
+
+    /// タイムアウト付きでロックを取得
+    ///
+    /// 指定時間内にロックを取得できなければNoneを返します。
+    /// 待機中は`lock()`と同様に保持タスクの優先度を引き上げます。
+    /// 割り込みコンテキストでは待機できない（tickも進まない）ため、`try_lock()`と同じ動作になります。
+    ///
+    /// # Arguments
+    /// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+    ///
+    /// # Returns
+    /// ロックが取得できた場合はSome(MutexGuard)、タイムアウトした場合はNone
+    pub fn lock_timeout(&self, timeout_ms: u64) -> Option<MutexGuard<'_, T>> {
+        if crate::sched::is_interrupt_context() {
+            return self.try_lock();
         }
+        let me = crate::sched::current_task_id();
+        if self.try_acquire(me) {
+            return Some(MutexGuard { mutex: self });
+        }
+
+        self.register_waiter(me);
+        let result = self
+            .wait_queue
+            .wait_until(|| self.try_acquire(me), timeout_ms);
+        if result.timed_out() {
+            // 待機をやめたので、保持タスクへの引き上げを残りの待機タスクで計算し直す
+            self.unregister_waiter(me);
+            return None;
+        }
+        Some(MutexGuard { mutex: self })
     }
 
     /// try_lock: ノンブロッキングでロック取得を試みる
@@ -75,14 +150,82 @@ impl<T> BlockingMutex<T> {
     /// # Returns
     /// ロックが取得できた場合はSome(MutexGuard)、できなかった場合はNone
     pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
-        if self
-            .locked
-            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok()
-        {
-            Some(MutexGuard { mutex: self })
-        } else {
-            None
+        self.try_acquire(crate::sched::current_task_id())
+            .then_some(MutexGuard { mutex: self })
+    }
+
+    /// ロックを保持しているタスクを取得
+    pub fn owner(&self) -> Option<TaskId> {
+        without_interrupts(|| self.state.lock().owner)
+    }
+
+    /// 優先度継承の引き上げを識別するキー（このMutexのアドレス）
+    fn key(&self) -> usize {
+        self as *const Self as *const u8 as usize
+    }
+
+    /// ロックが空いていれば`task`を保持タスクとして取得する
+    ///
+    /// `task`が待機タスクとして登録されていれば登録を解除し、
+    /// 残りの待機タスクの優先度を`task`に引き継ぐ。
+    fn try_acquire(&self, task: TaskId) -> bool {
+        without_interrupts(|| {
+            let mut state = self.state.lock();
+            if state.owner.is_some() {
+                return false;
+            }
+            state.owner = Some(task);
+            state.owner_cpu = crate::cpu::current_cpu();
+            state.waiters.retain(|&(id, _)| id != task);
+            self.update_boost(&mut state);
+            true
+        })
+    }
+
+    /// `task`を待機タスクとして登録し、保持タスクの優先度を引き上げる
+    fn register_waiter(&self, task: TaskId) {
+        let Some(priority) = crate::sched::current_priority() else {
+            return;
+        };
+        without_interrupts(|| {
+            let mut state = self.state.lock();
+            state.waiters.push((task, priority));
+            self.update_boost(&mut state);
+        });
+    }
+
+    /// `task`の待機タスクとしての登録を解除する（タイムアウト時）
+    fn unregister_waiter(&self, task: TaskId) {
+        without_interrupts(|| {
+            let mut state = self.state.lock();
+            state.waiters.retain(|&(id, _)| id != task);
+            self.update_boost(&mut state);
+        });
+    }
+
+    /// ロックを解放し、保持タスクの優先度の引き上げを解除する
+    fn unlock(&self) {
+        without_interrupts(|| {
+            let mut state = self.state.lock();
+            let owner = state.owner.take();
+            if let (Some(owner), Some(_)) = (owner, state.boost.take()) {
+                crate::sched::set_pi_boost(owner, state.owner_cpu, self.key(), None);
+            }
+        });
+    }
+
+    /// 保持タスクの優先度の引き上げを待機タスクの最高優先度に合わせる
+    ///
+    /// 状態のロックを保持したまま呼び出すことで、引き上げと解除の順序を
+    /// ロックの取得・解放の順序と一致させる（ロック順序: 状態 → スケジューラ）。
+    fn update_boost(&self, state: &mut LockState) {
+        let Some(owner) = state.owner else {
+            return;
+        };
+        let boost = state.top_waiter_priority();
+        if boost != state.boost {
+            state.boost = boost;
+            crate::sched::set_pi_boost(owner, state.owner_cpu, self.key(), boost);
         }
     }
 }
@@ -94,6 +237,15 @@ pub struct MutexGuard<'a, T: ?Sized> {
     mutex: &'a BlockingMutex<T>,
 }
 
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    /// ガードが保持しているMutexを取得（Condvar用）
+    ///
+    /// `T`のメソッドと衝突しないよう、`MutexGuard::mutex(&guard)`の形で呼び出す。
+    pub(super) fn mutex(guard: &Self) -> &'a BlockingMutex<T> {
+        guard.mutex
+    }
+}
+
 impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
     type Target = T;
     fn deref(&self) -> &T {
@@ -109,8 +261,8 @@ impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
 
 impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
     fn drop(&mut self) {
-        // ロックを解放
-        self.mutex.locked.store(false, Ordering::Release);
+        // ロックを解放（優先度の引き上げも解除される）
+        self.mutex.unlock();
         // 待機中のタスクを1つ起床
         self.mutex.wait_queue.wake_one();
     }
@@ -160,6 +312,45 @@ mod tests {
         assert_eq!(*guard, 100);
     }
 
+    #[test_case]
+    fn test_mutex_lock_timeout_uncontended() {
+        let mutex = BlockingMutex::new(42);
+        let guard = mutex.lock_timeout(10).expect("Lock should succeed");
+        assert_eq!(*guard, 42);
+    }
+
+    #[test_case]
+    fn test_mutex_tracks_owner() {
+        let mutex = BlockingMutex::new(42);
+        assert_eq!(mutex.owner(), None);
+        let guard = mutex.try_lock().unwrap();
+        assert_eq!(mutex.owner(), Some(crate::sched::current_task_id()));
+        drop(guard);
+        assert_eq!(mutex.owner(), None);
+    }
+
+    #[test_case]
+    fn test_top_waiter_priority() {
+        let mut state = LockState {
+            owner: None,
+            owner_cpu: 0,
+            waiters: Vec::new(),
+            boost: None,
+        };
+        assert_eq!(state.top_waiter_priority(), None);
+        state.waiters.push((TaskId::new(), TaskPriority::Normal(0)));
+        state
+            .waiters
+            .push((TaskId::new(), TaskPriority::Realtime(99)));
+        state
+            .waiters
+            .push((TaskId::new(), TaskPriority::Normal(-20)));
+        assert_eq!(
+            state.top_waiter_priority(),
+            Some(TaskPriority::Realtime(99))
+        );
+    }
+
     #[test_case]
     fn test_mutex_unlock_on_drop() {
         let mutex = BlockingMutex::new(42);
@@ -0,0 +1,645 @@
+//! チャネル（タスク間のメッセージパッシング）
+//!
+//! 複数の送信側（`Sender`）から1つの受信側（`Receiver`）へ値を送るMPSCチャネル。
+//! 容量を指定する有界チャネル（`channel()`）と、容量に上限のない非有界チャネル
+//! （`unbounded_channel()`）があります。
+//!
+//! # 待機
+//! 受信側はチャネルが空の間、有界チャネルの送信側はチャネルが満杯の間、
+//! それぞれの`WaitQueue`でブロックします。`select()`は待機中のタスク専用の
+//! `WaitQueue`を各チャネルに登録し、いずれかのチャネルへの送信で起床します。
+//! 登録された待機キューは、一覧を複製せずにチャネルのロックを保持したまま起床させます
+//! （ロック順序: チャネルの状態 → 待機キュー → スケジューラ）。
+//!
+//! # 切断
+//! すべての`Sender`が破棄されると、受信側はキューに残った値を受信し終えた後に
+//! `Disconnected`を受け取ります。`Receiver`が破棄されると、送信は値を返して失敗します。
+//!
+//! # 割り込みコンテキスト
+//! `Sender::send_from_irq()`はブロックしないため、デバイスドライバの割り込みハンドラから
+//! タスクへイベントを渡すのに使用できます。有界チャネルはキューを作成時に確保するため、
+//! 割り込みハンドラからの送信でメモリ割り当ては発生しません。
+
+use super::wait_queue::{WaitEventFuture, WaitQueue, WaitResult};
+use crate::io::without_interrupts;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex as SpinMutex;
+
+/// 受信側が破棄されたため送信できなかった（送信しようとした値を返す）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> core::fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "Sending on a disconnected channel")
+    }
+}
+
+/// `try_send()`/`send_from_irq()`のエラー（送信しようとした値を返す）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// チャネルが満杯
+    Full(T),
+    /// 受信側が破棄されている
+    Disconnected(T),
+}
+
+impl<T> core::fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "Sending on a full channel"),
+            TrySendError::Disconnected(_) => write!(f, "Sending on a disconnected channel"),
+        }
+    }
+}
+
+/// `send_timeout()`のエラー（送信しようとした値を返す）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendTimeoutError<T> {
+    /// 期限までにチャネルに空きができなかった
+    Timeout(T),
+    /// 受信側が破棄されている
+    Disconnected(T),
+}
+
+impl<T> core::fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => write!(f, "Timed out sending on a full channel"),
+            SendTimeoutError::Disconnected(_) => write!(f, "Sending on a disconnected channel"),
+        }
+    }
+}
+
+/// すべての送信側が破棄され、チャネルが空になった
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl core::fmt::Display for RecvError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "Receiving on a disconnected channel")
+    }
+}
+
+/// `try_recv()`のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// チャネルが空
+    Empty,
+    /// すべての送信側が破棄され、チャネルが空
+    Disconnected,
+}
+
+impl core::fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "Receiving on an empty channel"),
+            TryRecvError::Disconnected => write!(f, "Receiving on a disconnected channel"),
+        }
+    }
+}
+
+/// `recv_timeout()`/`select_timeout()`のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    /// 期限までに値が届かなかった
+    Timeout,
+    /// すべての送信側が破棄され、チャネルが空
+    Disconnected,
+}
+
+impl core::fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => write!(f, "Timed out receiving on an empty channel"),
+            RecvTimeoutError::Disconnected => write!(f, "Receiving on a disconnected channel"),
+        }
+    }
+}
+
+/// チャネルの状態（送信側と受信側で共有）
+struct State<T> {
+    /// 未受信の値
+    queue: VecDeque<T>,
+    /// 生存している`Sender`の数
+    senders: usize,
+    /// `Receiver`が生存しているかどうか
+    receiver_alive: bool,
+    /// `select()`で待機中のタスクの待機キュー
+    selectors: Vec<Arc<WaitQueue>>,
+}
+
+/// チャネルの共有部分
+struct Shared<T> {
+    /// チャネルの状態
+    state: SpinMutex<State<T>>,
+    /// 容量（Noneなら非有界）
+    capacity: Option<usize>,
+    /// 受信待ちのタスク（チャネルが空の間待機）
+    recv_queue: WaitQueue,
+    /// 送信待ちのタスク（有界チャネルが満杯の間待機）
+    send_queue: WaitQueue,
+}
+
+impl<T> Shared<T> {
+    fn new(capacity: Option<usize>) -> Self {
+        Self {
+            state: SpinMutex::new(State {
+                queue: VecDeque::with_capacity(capacity.unwrap_or(0)),
+                senders: 1,
+                receiver_alive: true,
+                selectors: Vec::new(),
+            }),
+            capacity,
+            recv_queue: WaitQueue::new(),
+            send_queue: WaitQueue::new(),
+        }
+    }
+
+    /// ブロックせずに送信し、受信待ちのタスクを起床させる
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        without_interrupts(|| {
+            let mut state = self.state.lock();
+            if !state.receiver_alive {
+                return Err(TrySendError::Disconnected(value));
+            }
+            if self
+                .capacity
+                .is_some_and(|capacity| state.queue.len() >= capacity)
+            {
+                return Err(TrySendError::Full(value));
+            }
+            state.queue.push_back(value);
+            // select()で待機中のタスクは、一覧を複製しないようロック保持中に起床させる
+            for selector in &state.selectors {
+                selector.wake_one();
+            }
+            Ok(())
+        })?;
+
+        // ロック解放後に起床させる
+        self.recv_queue.wake_one();
+        Ok(())
+    }
+
+    /// ブロックせずに受信し、送信待ちのタスクを起床させる
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        let result = without_interrupts(|| {
+            let mut state = self.state.lock();
+            match state.queue.pop_front() {
+                Some(value) => Ok(value),
+                None if state.senders == 0 => Err(TryRecvError::Disconnected),
+                None => Err(TryRecvError::Empty),
+            }
+        });
+        if result.is_ok() && self.capacity.is_some() {
+            self.send_queue.wake_one();
+        }
+        result
+    }
+
+    /// 受信側の待機（`recv()`・`select()`）をすべて起床させる（送信側の切断時）
+    fn wake_receivers(&self) {
+        without_interrupts(|| {
+            for selector in &self.state.lock().selectors {
+                selector.wake_one();
+            }
+        });
+        self.recv_queue.wake_all();
+    }
+}
+
+/// 有界チャネルを作成
+///
+/// キューは作成時に確保するため、送信時（割り込みハンドラからの送信を含む）に
+/// メモリ割り当ては発生しない。
+///
+/// # Arguments
+/// * `capacity` - チャネルに溜められる値の数
+///
+/// # Panics
+/// `capacity`が0の場合
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be at least 1");
+    new_channel(Some(capacity))
+}
+
+/// 非有界チャネルを作成
+///
+/// 送信はブロックしないが、受信されない値の分だけヒープを消費する。
+pub fn unbounded_channel<T>() -> (Sender<T>, Receiver<T>) {
+    new_channel(None)
+}
+
+fn new_channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared::new(capacity));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// チャネルの送信側（複製して複数のタスクから送信できる）
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// 値を送信
+    ///
+    /// 有界チャネルが満杯の場合は、空きができるまでブロックする。
+    ///
+    /// # Errors
+    /// 受信側が破棄されている場合は、送信しようとした値を`SendError`で返す
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Sender::send() cannot be called from interrupt context"
+        );
+        let mut value = Some(value);
+        let mut disconnected = None;
+        self.shared
+            .send_queue
+            .wait_event(|| self.send_step(&mut value, &mut disconnected));
+        match disconnected {
+            Some(value) => Err(SendError(value)),
+            None => Ok(()),
+        }
+    }
+
+    /// 値を送信（タイムアウト付き）
+    ///
+    /// # Arguments
+    /// * `value` - 送信する値
+    /// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+    ///
+    /// # Errors
+    /// 期限までに空きができなかった場合、または受信側が破棄されている場合は、
+    /// 送信しようとした値を返す
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn send_timeout(&self, value: T, timeout_ms: u64) -> Result<(), SendTimeoutError<T>> {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Sender::send_timeout() cannot be called from interrupt context"
+        );
+        let mut value = Some(value);
+        let mut disconnected = None;
+        let result = self
+            .shared
+            .send_queue
+            .wait_until(|| self.send_step(&mut value, &mut disconnected), timeout_ms);
+        match (disconnected, value) {
+            (Some(value), _) => Err(SendTimeoutError::Disconnected(value)),
+            (None, Some(value)) if result.timed_out() => Err(SendTimeoutError::Timeout(value)),
+            _ => Ok(()),
+        }
+    }
+
+    /// `send()`/`send_timeout()`の待機条件（送信できたか、受信側が破棄されていればtrue）
+    fn send_step(&self, value: &mut Option<T>, disconnected: &mut Option<T>) -> bool {
+        let Some(v) = value.take() else {
+            return true;
+        };
+        match self.shared.try_send(v) {
+            Ok(()) => true,
+            Err(TrySendError::Full(v)) => {
+                *value = Some(v);
+                false
+            }
+            Err(TrySendError::Disconnected(v)) => {
+                *disconnected = Some(v);
+                true
+            }
+        }
+    }
+
+    /// ブロックせずに値を送信
+    ///
+    /// # Errors
+    /// * `TrySendError::Full` - 有界チャネルが満杯の場合
+    /// * `TrySendError::Disconnected` - 受信側が破棄されている場合
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.shared.try_send(value)
+    }
+
+    /// 割り込みハンドラから値を送信（ブロックしない）
+    ///
+    /// 満杯の場合は待たずに値を返すため、割り込みハンドラ側で破棄するか
+    /// 取りこぼしとして数えること。有界チャネルではメモリ割り当ても発生しない。
+    ///
+    /// # Errors
+    /// * `TrySendError::Full` - 有界チャネルが満杯の場合
+    /// * `TrySendError::Disconnected` - 受信側が破棄されている場合
+    pub fn send_from_irq(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.shared.try_send(value)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        without_interrupts(|| self.shared.state.lock().senders += 1);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let last = without_interrupts(|| {
+            let mut state = self.shared.state.lock();
+            state.senders -= 1;
+            state.senders == 0
+        });
+        if last {
+            // 受信待ちのタスクに切断を知らせる
+            self.shared.wake_receivers();
+        }
+    }
+}
+
+/// チャネルの受信側
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// 値を受信
+    ///
+    /// チャネルが空の場合は、値が届くまでブロックする。
+    ///
+    /// # Errors
+    /// すべての送信側が破棄され、チャネルが空の場合は`RecvError`
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn recv(&self) -> Result<T, RecvError> {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Receiver::recv() cannot be called from interrupt context"
+        );
+        let mut result = None;
+        self.shared
+            .recv_queue
+            .wait_event(|| recv_step(self, &mut result));
+        match result {
+            Some(Ok(value)) => Ok(value),
+            _ => Err(RecvError),
+        }
+    }
+
+    /// 値を受信（タイムアウト付き）
+    ///
+    /// # Arguments
+    /// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+    ///
+    /// # Errors
+    /// * `RecvTimeoutError::Timeout` - 期限までに値が届かなかった場合
+    /// * `RecvTimeoutError::Disconnected` - すべての送信側が破棄され、チャネルが空の場合
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn recv_timeout(&self, timeout_ms: u64) -> Result<T, RecvTimeoutError> {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Receiver::recv_timeout() cannot be called from interrupt context"
+        );
+        let mut result = None;
+        self.shared
+            .recv_queue
+            .wait_until(|| recv_step(self, &mut result), timeout_ms);
+        match result {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(RecvError)) => Err(RecvTimeoutError::Disconnected),
+            None => Err(RecvTimeoutError::Timeout),
+        }
+    }
+
+    /// 値を受信するフューチャーを返す（タスクはブロックしない）
+    ///
+    /// `recv()`の非同期版。すべての送信側が破棄され、チャネルが空の場合は`RecvError`で完了する。
+    pub fn recv_async(
+        &self,
+    ) -> WaitEventFuture<'_, impl FnMut() -> Option<Result<T, RecvError>> + Unpin + '_> {
+        self.shared
+            .recv_queue
+            .wait_event_async(|| match self.try_recv() {
+                Ok(value) => Some(Ok(value)),
+                Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+                Err(TryRecvError::Empty) => None,
+            })
+    }
+
+    /// ブロックせずに値を受信
+    ///
+    /// # Errors
+    /// * `TryRecvError::Empty` - チャネルが空の場合
+    /// * `TryRecvError::Disconnected` - すべての送信側が破棄され、チャネルが空の場合
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.shared.try_recv()
+    }
+
+    /// `select()`の待機キューを登録
+    fn register_selector(&self, selector: &Arc<WaitQueue>) {
+        without_interrupts(|| self.shared.state.lock().selectors.push(selector.clone()));
+    }
+
+    /// `select()`の待機キューの登録を解除
+    fn unregister_selector(&self, selector: &Arc<WaitQueue>) {
+        without_interrupts(|| {
+            self.shared
+                .state
+                .lock()
+                .selectors
+                .retain(|s| !Arc::ptr_eq(s, selector));
+        });
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // 未受信の値はロック解放後に破棄する
+        let pending = without_interrupts(|| {
+            let mut state = self.shared.state.lock();
+            state.receiver_alive = false;
+            core::mem::take(&mut state.queue)
+        });
+        drop(pending);
+        // 送信待ちのタスクに切断を知らせる
+        self.shared.send_queue.wake_all();
+    }
+}
+
+/// 受信の待機条件（受信できたか、切断されていればtrue）
+fn recv_step<T>(receiver: &Receiver<T>, result: &mut Option<Result<T, RecvError>>) -> bool {
+    match receiver.try_recv() {
+        Ok(value) => *result = Some(Ok(value)),
+        Err(TryRecvError::Disconnected) => *result = Some(Err(RecvError)),
+        Err(TryRecvError::Empty) => return false,
+    }
+    true
+}
+
+/// 複数のチャネルのいずれかから値を受信
+///
+/// 複数のチャネルに値がある場合は、`receivers`の先頭に近いものを優先する。
+///
+/// # Returns
+/// 受信したチャネルの`receivers`内の添字と、受信した値
+///
+/// # Errors
+/// すべてのチャネルで送信側が破棄され、空になっている場合は`RecvError`
+///
+/// # Panics
+/// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+pub fn select<T>(receivers: &[&Receiver<T>]) -> Result<(usize, T), RecvError> {
+    debug_assert!(
+        !crate::sched::is_interrupt_context(),
+        "select() cannot be called from interrupt context"
+    );
+    match select_inner(receivers, None) {
+        Ok(received) => Ok(received),
+        Err(_) => Err(RecvError),
+    }
+}
+
+/// 複数のチャネルのいずれかから値を受信（タイムアウト付き）
+///
+/// # Arguments
+/// * `receivers` - 受信するチャネル（先頭に近いものを優先）
+/// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+///
+/// # Errors
+/// * `RecvTimeoutError::Timeout` - 期限までにいずれのチャネルにも値が届かなかった場合
+/// * `RecvTimeoutError::Disconnected` - すべてのチャネルで送信側が破棄され、空になっている場合
+///
+/// # Panics
+/// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+pub fn select_timeout<T>(
+    receivers: &[&Receiver<T>],
+    timeout_ms: u64,
+) -> Result<(usize, T), RecvTimeoutError> {
+    debug_assert!(
+        !crate::sched::is_interrupt_context(),
+        "select_timeout() cannot be called from interrupt context"
+    );
+    select_inner(receivers, Some(timeout_ms))
+}
+
+/// `select()`/`select_timeout()`の本体
+///
+/// 各チャネルに専用の待機キューを登録してから受信を試みるため、
+/// 確認と待機の間に届いた値も取りこぼさない。
+fn select_inner<T>(
+    receivers: &[&Receiver<T>],
+    timeout_ms: Option<u64>,
+) -> Result<(usize, T), RecvTimeoutError> {
+    let selector = Arc::new(WaitQueue::new());
+    for receiver in receivers {
+        receiver.register_selector(&selector);
+    }
+
+    let mut result = None;
+    let condition = || {
+        let mut disconnected = 0;
+        for (index, receiver) in receivers.iter().enumerate() {
+            match receiver.try_recv() {
+                Ok(value) => {
+                    result = Some(Ok((index, value)));
+                    return true;
+                }
+                Err(TryRecvError::Disconnected) => disconnected += 1,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+        if disconnected == receivers.len() {
+            result = Some(Err(RecvTimeoutError::Disconnected));
+            return true;
+        }
+        false
+    };
+    let wait = match timeout_ms {
+        Some(timeout_ms) => selector.wait_until(condition, timeout_ms),
+        None => {
+            selector.wait_event(condition);
+            WaitResult::Woken
+        }
+    };
+
+    for receiver in receivers {
+        receiver.unregister_selector(&selector);
+    }
+    match result {
+        Some(result) => result,
+        None => {
+            debug_assert!(wait.timed_out());
+            Err(RecvTimeoutError::Timeout)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_bounded_channel_try_send_full() {
+        let (tx, rx) = channel(2);
+        assert_eq!(tx.try_send(1), Ok(()));
+        assert_eq!(tx.send_from_irq(2), Ok(()));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(tx.try_send(3), Ok(()));
+        assert_eq!(rx.recv(), Ok(2));
+        assert_eq!(rx.recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test_case]
+    fn test_channel_disconnect() {
+        let (tx, rx) = unbounded_channel();
+        let tx2 = tx.clone();
+        tx.send(1).unwrap();
+        drop(tx);
+        tx2.send(2).unwrap();
+        drop(tx2);
+        // 送信側がすべて破棄されても、残っている値は受信できる
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.recv_timeout(10), Ok(2));
+        assert_eq!(rx.recv(), Err(RecvError));
+
+        let (tx, rx) = channel(1);
+        drop(rx);
+        assert_eq!(tx.send(5), Err(SendError(5)));
+        assert_eq!(tx.try_send(6), Err(TrySendError::Disconnected(6)));
+    }
+
+    #[test_case]
+    fn test_select_prefers_first_ready_channel() {
+        let (tx1, rx1) = unbounded_channel();
+        let (tx2, rx2) = unbounded_channel();
+        tx2.send("b").unwrap();
+        tx1.send("a").unwrap();
+        assert_eq!(select(&[&rx1, &rx2]), Ok((0, "a")));
+        assert_eq!(select(&[&rx1, &rx2]), Ok((1, "b")));
+        drop(tx1);
+        drop(tx2);
+        assert_eq!(select(&[&rx1, &rx2]), Err(RecvError));
+        // 登録した待機キューは解除されている
+        assert!(without_interrupts(|| rx1
+            .shared
+            .state
+            .lock()
+            .selectors
+            .is_empty()));
+    }
+}
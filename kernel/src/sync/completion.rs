@@ -0,0 +1,131 @@
+//! 完了通知
+//!
+//! デバイスI/Oなどの非同期な処理の完了と結果を、割り込みハンドラから待機側へ渡すための
+//! 1回限りの通知。待機側はタスクとしてブロックする（`wait()`）か、
+//! フューチャーとして待機する（`wait_async()`）ことができます。
+
+use super::wait_queue::{WaitEventFuture, WaitQueue, WaitResult};
+use crate::io::without_interrupts;
+use spin::Mutex as SpinMutex;
+
+/// 完了通知
+///
+/// `complete()`で格納された結果は、いずれか1つの待機が受け取ります。
+/// 受け取った後は未完了の状態に戻るため、同じ`Completion`を次の要求に再利用できます。
+pub struct Completion<T> {
+    /// 受け取られていない結果
+    result: SpinMutex<Option<T>>,
+    /// 完了を待つタスク・フューチャーの待機キュー
+    wait_queue: WaitQueue,
+}
+
+impl<T> Completion<T> {
+    /// 未完了の完了通知を作成
+    pub const fn new() -> Self {
+        Self {
+            result: SpinMutex::new(None),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// 結果を格納して待機を1つ起床させる（ブロックしない）
+    ///
+    /// 割り込みハンドラから呼び出せる。
+    ///
+    /// # Errors
+    /// 前回の結果がまだ受け取られていない場合は、格納しようとした値を返す
+    pub fn complete(&self, value: T) -> Result<(), T> {
+        without_interrupts(|| {
+            let mut result = self.result.lock();
+            if result.is_some() {
+                return Err(value);
+            }
+            *result = Some(value);
+            Ok(())
+        })?;
+        self.wait_queue.wake_one();
+        Ok(())
+    }
+
+    /// 結果が格納されていれば受け取る（ブロックしない）
+    pub fn try_take(&self) -> Option<T> {
+        without_interrupts(|| self.result.lock().take())
+    }
+
+    /// 完了するまでブロックし、結果を受け取る
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn wait(&self) -> T {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Completion::wait() cannot be called from interrupt context"
+        );
+        let mut result = None;
+        self.wait_queue.wait_event(|| {
+            result = self.try_take();
+            result.is_some()
+        });
+        result.expect("Completion::wait() returned without a result")
+    }
+
+    /// 完了するか、指定時間が経過するまでブロックする
+    ///
+    /// デバイスが応答しない場合でもタスクが永久に止まらないようにするために使用する。
+    ///
+    /// # Arguments
+    /// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+    ///
+    /// # Returns
+    /// 完了した場合は結果、タイムアウトした場合はNone
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn wait_timeout(&self, timeout_ms: u64) -> Option<T> {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Completion::wait_timeout() cannot be called from interrupt context"
+        );
+        let mut result = None;
+        let wait = self.wait_queue.wait_until(
+            || {
+                result = self.try_take();
+                result.is_some()
+            },
+            timeout_ms,
+        );
+        debug_assert!(result.is_some() || wait == WaitResult::TimedOut);
+        result
+    }
+
+    /// 完了すると結果を返すフューチャーを返す（タスクはブロックしない）
+    pub fn wait_async(&self) -> WaitEventFuture<'_, impl FnMut() -> Option<T> + Unpin + '_> {
+        self.wait_queue.wait_event_async(|| self.try_take())
+    }
+}
+
+impl<T> Default for Completion<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_completion_complete_and_take() {
+        let completion = Completion::new();
+        assert_eq!(completion.try_take(), None);
+        assert_eq!(completion.complete(1), Ok(()));
+        // 受け取られるまでは次の結果を格納できない
+        assert_eq!(completion.complete(2), Err(2));
+        assert_eq!(completion.wait(), 1);
+        assert_eq!(completion.complete(3), Ok(()));
+        assert_eq!(crate::executor::block_on(completion.wait_async()), 3);
+        // 完了済みなら待機せずに結果を受け取る（タイムアウトはwait_queueのテストで確認）
+        assert_eq!(completion.complete(4), Ok(()));
+        assert_eq!(completion.wait_timeout(10), Some(4));
+    }
+}
@@ -0,0 +1,132 @@
+//! 条件変数
+//!
+//! `BlockingMutex`のガードと組み合わせて、条件が成立するまでタスクをブロックする
+
+use super::blocking_mutex::MutexGuard;
+use super::wait_queue::{WaitQueue, WaitResult};
+
+/// 条件変数
+///
+/// `wait()`はMutexを解放してブロックし、起床後にMutexを再取得して戻ります。
+/// 起床は`notify_one()`/`notify_all()`以外でも起こり得る（spurious wakeup）ため、
+/// 条件は`wait_while()`またはループで確認してください。
+pub struct Condvar {
+    /// 通知を待つタスクの待機キュー
+    wait_queue: WaitQueue,
+}
+
+impl Condvar {
+    /// 新しい条件変数を作成
+    pub const fn new() -> Self {
+        Self {
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// Mutexを解放して通知を待ち、Mutexを再取得して戻る
+    ///
+    /// 待機キューへの登録後にMutexを解放するため、解放から
+    /// ブロックまでの間の通知も取りこぼさない（WAKEUP_PENDINGで検出される）。
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Condvar::wait() cannot be called from interrupt context"
+        );
+        self.wait_inner(guard, None).0
+    }
+
+    /// Mutexを解放して通知を待ち、Mutexを再取得して戻る（タイムアウト付き）
+    ///
+    /// # Arguments
+    /// * `guard` - Mutexガード
+    /// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+    ///
+    /// # Returns
+    /// 再取得したMutexガードと、タイムアウトしたかどうか
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn wait_timeout<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout_ms: u64,
+    ) -> (MutexGuard<'a, T>, WaitResult) {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Condvar::wait_timeout() cannot be called from interrupt context"
+        );
+        let ticks = crate::timer::ms_to_ticks(timeout_ms).max(1);
+        self.wait_inner(guard, Some(ticks))
+    }
+
+    /// `wait()`/`wait_timeout()`の本体
+    fn wait_inner<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout_ticks: Option<u64>,
+    ) -> (MutexGuard<'a, T>, WaitResult) {
+        let mutex = MutexGuard::mutex(&guard);
+        let token = self.wait_queue.prepare_to_wait();
+        drop(guard);
+        let result = self.wait_queue.finish_wait(token, timeout_ticks);
+        (mutex.lock(), result)
+    }
+
+    /// `condition`がtrueを返す間、通知を待ち続ける
+    ///
+    /// # Returns
+    /// `condition`がfalseを返した時点のMutexガード
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// 待機中のタスクを1つ起床させる
+    ///
+    /// # Returns
+    /// 起床させたタスクがあればtrue
+    pub fn notify_one(&self) -> bool {
+        self.wait_queue.wake_one()
+    }
+
+    /// 待機中のすべてのタスクを起床させる
+    pub fn notify_all(&self) {
+        self.wait_queue.wake_all();
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::BlockingMutex;
+
+    #[test_case]
+    fn test_condvar_wait_while_returns_when_condition_is_false() {
+        let mutex = BlockingMutex::new(3);
+        let condvar = Condvar::new();
+        let guard = condvar.wait_while(mutex.lock(), |value| *value == 0);
+        assert_eq!(*guard, 3);
+    }
+
+    #[test_case]
+    fn test_condvar_notify_without_waiters() {
+        let condvar = Condvar::new();
+        assert!(!condvar.notify_one());
+        condvar.notify_all();
+    }
+}
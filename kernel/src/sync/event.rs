@@ -0,0 +1,139 @@
+//! イベント
+//!
+//! タスク間で「何かが起きた」ことを通知するためのフラグ。
+//! 一度セットされると解除されないワンショット型と、
+//! 待機タスク1つの起床で自動的に解除される自動リセット型があります。
+
+use super::wait_queue::{WaitQueue, WaitResult};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// イベントの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// 一度セットされるとリセットされるまで全ての待機を通過させる（全タスクを起床）
+    OneShot,
+    /// セット毎に1つの待機だけを通過させ、自動的に解除される
+    AutoReset,
+}
+
+/// イベント
+pub struct Event {
+    /// イベントの種類
+    kind: EventKind,
+    /// セットされているかどうか
+    signaled: AtomicBool,
+    /// セットを待つタスクの待機キュー
+    wait_queue: WaitQueue,
+}
+
+impl Event {
+    /// 未セット状態のイベントを作成
+    pub const fn new(kind: EventKind) -> Self {
+        Self {
+            kind,
+            signaled: AtomicBool::new(false),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// イベントがセットされるまでブロック
+    ///
+    /// 自動リセット型では、戻った時点でイベントは解除されている。
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn wait(&self) {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Event::wait() cannot be called from interrupt context"
+        );
+        self.wait_queue.wait_event(|| self.try_wait());
+    }
+
+    /// イベントがセットされるか、指定時間が経過するまでブロック
+    ///
+    /// デバイスの割り込みを待つ場合などに、デバイスが応答しなくても
+    /// タスクが永久に止まらないようにするために使用する。
+    ///
+    /// # Arguments
+    /// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn wait_timeout(&self, timeout_ms: u64) -> WaitResult {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Event::wait_timeout() cannot be called from interrupt context"
+        );
+        self.wait_queue.wait_until(|| self.try_wait(), timeout_ms)
+    }
+
+    /// ノンブロッキングでイベントを確認
+    ///
+    /// 自動リセット型でセットされていた場合は解除する。
+    ///
+    /// # Returns
+    /// セットされていた場合はtrue
+    pub fn try_wait(&self) -> bool {
+        match self.kind {
+            EventKind::OneShot => self.signaled.load(Ordering::Acquire),
+            EventKind::AutoReset => self
+                .signaled
+                .compare_exchange(true, false, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok(),
+        }
+    }
+
+    /// イベントをセットし、待機中のタスクを起床させる
+    ///
+    /// ワンショット型は全ての待機タスクを、自動リセット型は1つを起床させる。
+    /// 割り込みコンテキストからも呼び出せます。
+    pub fn set(&self) {
+        self.signaled.store(true, Ordering::Release);
+        match self.kind {
+            EventKind::OneShot => self.wait_queue.wake_all(),
+            EventKind::AutoReset => {
+                self.wait_queue.wake_one();
+            }
+        }
+    }
+
+    /// イベントを解除する
+    pub fn reset(&self) {
+        self.signaled.store(false, Ordering::Release);
+    }
+
+    /// イベントがセットされているかどうか
+    pub fn is_set(&self) -> bool {
+        self.signaled.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_one_shot_event_stays_set() {
+        let event = Event::new(EventKind::OneShot);
+        assert!(!event.try_wait());
+        event.set();
+        event.wait();
+        assert!(event.try_wait());
+        assert!(event.is_set());
+        event.reset();
+        assert!(!event.try_wait());
+    }
+
+    #[test_case]
+    fn test_auto_reset_event_passes_one_waiter_per_set() {
+        let event = Event::new(EventKind::AutoReset);
+        event.set();
+        event.wait();
+        assert!(!event.is_set());
+        assert!(!event.try_wait());
+        event.set();
+        assert!(event.try_wait());
+        assert!(!event.try_wait());
+    }
+}
@@ -1,8 +1,27 @@
 //! 同期プリミティブ
 //!
 //! このモジュールはブロッキング同期プリミティブを提供します。
+//! いずれも`WaitQueue`の上に構築され、待機中のタスクはスピンせずにブロックします。
 
+pub mod barrier;
 pub mod blocking_mutex;
+pub mod channel;
+pub mod completion;
+pub mod condvar;
+pub mod event;
+pub mod rwlock;
+pub mod semaphore;
 pub mod wait_queue;
 
+pub use barrier::Barrier;
 pub use blocking_mutex::BlockingMutex;
+pub use channel::{
+    Receiver, RecvError, RecvTimeoutError, SendError, SendTimeoutError, Sender, TryRecvError,
+    TrySendError, channel, select, select_timeout, unbounded_channel,
+};
+pub use completion::Completion;
+pub use condvar::Condvar;
+pub use event::{Event, EventKind};
+pub use rwlock::RwLock;
+pub use semaphore::Semaphore;
+pub use wait_queue::{WaitEventFuture, WaitFuture, WaitQueue, WaitResult};
@@ -0,0 +1,259 @@
+//! ブロッキングRwLock（書き込み優先）
+//!
+//! 複数の読み取りタスク、または1つの書き込みタスクにアクセスを許可するロック。
+//! 書き込みを待つタスクがいる間は新たな読み取りを許可しないため、
+//! 読み取りが続いても書き込みタスクが飢餓状態になりません。
+
+use super::wait_queue::WaitQueue;
+use crate::io::without_interrupts;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use spin::Mutex as SpinMutex;
+
+/// ロックの状態
+struct RwState {
+    /// 読み取り中のタスク数
+    readers: usize,
+    /// 書き込み中かどうか
+    writer: bool,
+    /// 書き込みを待っているタスク数
+    waiting_writers: usize,
+}
+
+impl RwState {
+    const fn new() -> Self {
+        Self {
+            readers: 0,
+            writer: false,
+            waiting_writers: 0,
+        }
+    }
+
+    /// 読み取りの取得を試みる（書き込み中・書き込み待ちがあれば失敗）
+    fn try_read(&mut self) -> bool {
+        if !self.writer && self.waiting_writers == 0 {
+            self.readers += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 書き込みの取得を試みる（読み取り中・書き込み中なら失敗）
+    fn try_write(&mut self) -> bool {
+        if self.readers == 0 && !self.writer {
+            self.writer = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 書き込み待ちを登録（以降の新たな読み取りを止める）
+    fn begin_write_wait(&mut self) {
+        self.waiting_writers += 1;
+    }
+
+    /// 書き込み待ちのタスクとして書き込みの取得を試みる（成功すれば待ちを解除）
+    fn try_finish_write_wait(&mut self) -> bool {
+        let acquired = self.try_write();
+        if acquired {
+            self.waiting_writers -= 1;
+        }
+        acquired
+    }
+
+    /// 読み取りを解放し、最後の読み取りだったかどうかを返す
+    fn read_unlock(&mut self) -> bool {
+        self.readers -= 1;
+        self.readers == 0
+    }
+
+    /// 書き込みを解放し、書き込み待ちがいるかどうかを返す
+    fn write_unlock(&mut self) -> bool {
+        self.writer = false;
+        self.waiting_writers > 0
+    }
+}
+
+/// ブロッキングRwLock（書き込み優先）
+pub struct RwLock<T: ?Sized> {
+    /// ロック状態
+    state: SpinMutex<RwState>,
+    /// 読み取りを待つタスクの待機キュー
+    readers: WaitQueue,
+    /// 書き込みを待つタスクの待機キュー
+    writers: WaitQueue,
+    /// 保護対象データ
+    data: UnsafeCell<T>,
+}
+
+// Safety: 内部でロックにより排他アクセスを保証
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// 新しいRwLockを作成
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: SpinMutex::new(RwState::new()),
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+            data: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// 読み取りロックを取得（書き込み中・書き込み待ちがあればブロック）
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "RwLock::read() cannot be called from interrupt context"
+        );
+        self.readers.wait_event(|| self.try_read_raw());
+        RwLockReadGuard { lock: self }
+    }
+
+    /// 書き込みロックを取得（他に読み取り中・書き込み中のタスクがあればブロック）
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "RwLock::write() cannot be called from interrupt context"
+        );
+        // 待機中の書き込みがある間は新たな読み取りを止める
+        without_interrupts(|| self.state.lock().begin_write_wait());
+        self.writers
+            .wait_event(|| without_interrupts(|| self.state.lock().try_finish_write_wait()));
+        RwLockWriteGuard { lock: self }
+    }
+
+    /// ノンブロッキングで読み取りロックの取得を試みる
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.try_read_raw()
+            .then_some(RwLockReadGuard { lock: self })
+    }
+
+    /// ノンブロッキングで書き込みロックの取得を試みる
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        without_interrupts(|| self.state.lock().try_write())
+            .then_some(RwLockWriteGuard { lock: self })
+    }
+
+    /// 読み取りロックの取得を試み、取得できたかどうかを返す
+    fn try_read_raw(&self) -> bool {
+        without_interrupts(|| self.state.lock().try_read())
+    }
+
+    /// 読み取りロックを解放（最後の読み取りなら書き込み待ちを1つ起床）
+    fn read_unlock(&self) {
+        let last = without_interrupts(|| self.state.lock().read_unlock());
+        if last {
+            self.writers.wake_one();
+        }
+    }
+
+    /// 書き込みロックを解放（書き込み待ちを優先して起床）
+    fn write_unlock(&self) {
+        let writers_waiting = without_interrupts(|| self.state.lock().write_unlock());
+        if writers_waiting {
+            self.writers.wake_one();
+        } else {
+            self.readers.wake_all();
+        }
+    }
+}
+
+/// 読み取りロックのガード（RAII）
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: 読み取りロック保持中は書き込みガードが存在しない
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+/// 書き込みロックのガード（RAII）
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: 書き込みロック保持中は他のガードが存在しない
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: 書き込みロック保持中は他のガードが存在しない
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_rwlock_allows_multiple_readers() {
+        let lock = RwLock::new(7);
+        let r1 = lock.read();
+        let r2 = lock.try_read().expect("second reader should succeed");
+        assert_eq!(*r1 + *r2, 14);
+        // 読み取り中は書き込めない
+        assert!(lock.try_write().is_none());
+    }
+
+    #[test_case]
+    fn test_rwlock_writer_excludes_readers() {
+        let lock = RwLock::new(1);
+        {
+            let mut w = lock.write();
+            *w = 2;
+            assert!(lock.try_read().is_none());
+            assert!(lock.try_write().is_none());
+        }
+        assert_eq!(*lock.read(), 2);
+    }
+
+    #[test_case]
+    fn test_rw_state_waiting_writer_blocks_new_readers() {
+        let mut state = RwState::new();
+        assert!(state.try_read());
+        state.begin_write_wait();
+        // 書き込み待ちがいる間は新たな読み取りを許可しない
+        assert!(!state.try_read());
+        assert!(!state.try_finish_write_wait());
+        assert!(state.read_unlock());
+        assert!(state.try_finish_write_wait());
+        assert!(!state.try_read());
+        // 他に書き込み待ちがいなければ読み取りを再開できる
+        assert!(!state.write_unlock());
+        assert!(state.try_read());
+    }
+}
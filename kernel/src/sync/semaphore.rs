@@ -0,0 +1,94 @@
+//! カウンティングセマフォ
+//!
+//! 許可（permit）の数を数え、許可がない場合はタスクをブロックするセマフォ
+
+use super::wait_queue::{WaitQueue, WaitResult};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// カウンティングセマフォ
+///
+/// `acquire()`で許可を1つ獲得し（なければブロック）、`release()`で1つ返却します。
+pub struct Semaphore {
+    /// 残りの許可数
+    permits: AtomicUsize,
+    /// 許可を待つタスクの待機キュー
+    wait_queue: WaitQueue,
+}
+
+impl Semaphore {
+    /// 指定した許可数でセマフォを作成
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// 許可を1つ獲得する（許可がなければブロック）
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn acquire(&self) {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Semaphore::acquire() cannot be called from interrupt context"
+        );
+        self.wait_queue.wait_event(|| self.try_acquire());
+    }
+
+    /// 許可を1つ獲得する（指定時間内に獲得できなければタイムアウト）
+    ///
+    /// # Arguments
+    /// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+    ///
+    /// # Panics
+    /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+    pub fn acquire_timeout(&self, timeout_ms: u64) -> WaitResult {
+        debug_assert!(
+            !crate::sched::is_interrupt_context(),
+            "Semaphore::acquire_timeout() cannot be called from interrupt context"
+        );
+        self.wait_queue
+            .wait_until(|| self.try_acquire(), timeout_ms)
+    }
+
+    /// ノンブロッキングで許可を1つ獲得する
+    ///
+    /// # Returns
+    /// 獲得できた場合はtrue、許可がなかった場合はfalse
+    pub fn try_acquire(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |p| p.checked_sub(1))
+            .is_ok()
+    }
+
+    /// 許可を1つ返却し、待機中のタスクを1つ起床させる
+    ///
+    /// 割り込みコンテキストからも呼び出せます。
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+        self.wait_queue.wake_one();
+    }
+
+    /// 現在の許可数を取得
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_semaphore_counts_permits() {
+        let sem = Semaphore::new(2);
+        assert!(sem.try_acquire());
+        sem.acquire(); // 許可が残っているのでブロックしない
+        assert_eq!(sem.available_permits(), 0);
+        assert!(!sem.try_acquire());
+        sem.release();
+        assert_eq!(sem.available_permits(), 1);
+        assert!(sem.try_acquire());
+    }
+}
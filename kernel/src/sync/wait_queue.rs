@@ -4,16 +4,119 @@
 //! シングルCPU環境でのデッドロック防止のため、スピンロック保持中は
 //! 割り込みを無効化します。これにより、ロック保持中にプリエンプションが
 //! 発生して別タスクが同じロックを取得しようとする問題を防ぎます。
+//!
+//! # 起床の取りこぼし防止
+//! - 待機キューへの登録からブロックまでの間の起床は、`block_current_task()`が
+//!   `WAKEUP_PENDING`で検出する
+//! - 条件の確認から待機キューへの登録までの間の起床は、`wait_event()`/`wait_until()`が
+//!   登録後に条件を再確認することで検出する
+//!
+//! # タイムアウト
+//! 待機キューの各エントリは「待機中・起床済み・タイムアウト・取り消し」の状態を持ち、
+//! 起床（`wake_one()`/`wake_all()`）とタイムアウト（タイマーのコールバック）のうち
+//! 先に状態を遷移させた方だけが`unblock_task()`を呼び出します。
+//! タイムアウトしたエントリはキューに残りますが、`wake_one()`はそれを読み飛ばして
+//! 次の待機タスクを起床させるため、起床は失われません。
+//! タイマーのコールバックはエントリのみを参照するため、待機キューより長く生存しても安全です。
+//!
+//! # 非同期の待機
+//! `wait_async()`/`wait_event_async()`はタスクをブロックせずに待機するフューチャーを返します。
+//! エントリはタスクIDの代わりに`Waker`を持ち、起床時には`unblock_task()`の代わりに
+//! `Waker::wake()`が呼ばれます（エグゼキュータのタスクが起床する）。
 
 use crate::io::without_interrupts;
 use crate::sched::TaskId;
+use alloc::boxed::Box;
 use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
 use spin::Mutex as SpinMutex;
 
+/// 待機中
+const WAITING: u8 = 0;
+/// `wake_one()`/`wake_all()`で起床済み
+const WOKEN: u8 = 1;
+/// タイムアウト済み
+const TIMED_OUT: u8 = 2;
+/// 待機タスク自身が取り消した
+const CANCELLED: u8 = 3;
+
+/// 待機の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitResult {
+    /// 起床された（または条件が成立した）
+    Woken,
+    /// タイムアウトした
+    TimedOut,
+}
+
+impl WaitResult {
+    /// タイムアウトしたかどうか
+    pub fn timed_out(self) -> bool {
+        self == WaitResult::TimedOut
+    }
+}
+
+/// 起床させる対象
+enum WaitTarget {
+    /// ブロックしているタスク
+    Task(TaskId),
+    /// 非同期に待機しているフューチャー（最後にポーリングされた時のWaker）
+    Future(SpinMutex<Waker>),
+}
+
+/// 待機キューのエントリ（待機タスクとタイマーで共有）
+struct Waiter {
+    target: WaitTarget,
+    state: AtomicU8,
+}
+
+impl Waiter {
+    fn new(target: WaitTarget) -> Arc<Self> {
+        Arc::new(Self {
+            target,
+            state: AtomicU8::new(WAITING),
+        })
+    }
+
+    /// 待機中から指定状態へ遷移させる（遷移できた場合のみtrue）
+    fn finish(&self, state: u8) -> bool {
+        self.state
+            .compare_exchange(WAITING, state, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// 待機しているタスクまたはフューチャーを起床させる
+    fn wake(&self) {
+        match &self.target {
+            WaitTarget::Task(task_id) => crate::sched::unblock_task(*task_id),
+            WaitTarget::Future(waker) => without_interrupts(|| waker.lock().clone()).wake(),
+        }
+    }
+
+    /// フューチャーのWakerを更新（ポーリング毎に呼ばれる）
+    fn set_waker(&self, new: &Waker) {
+        if let WaitTarget::Future(waker) = &self.target {
+            without_interrupts(|| {
+                let mut waker = waker.lock();
+                if !waker.will_wake(new) {
+                    *waker = new.clone();
+                }
+            });
+        }
+    }
+}
+
+/// `prepare_to_wait()`で登録した待機
+pub struct WaitToken(Arc<Waiter>);
+
 /// ブロックされたタスクを管理するキュー
 pub struct WaitQueue {
-    /// 待機中のタスクIDリスト
-    waiters: SpinMutex<VecDeque<TaskId>>,
+    /// 待機中のタスクのリスト（タイムアウト済みのエントリを含む）
+    waiters: SpinMutex<VecDeque<Arc<Waiter>>>,
 }
 
 impl WaitQueue {
@@ -32,22 +135,173 @@ impl WaitQueue {
     /// スピンロック保持中は割り込みを無効化し、シングルCPU環境での
     /// デッドロックを防止します。
     pub fn wait(&self) {
-        let task_id = crate::sched::current_task_id();
+        let token = self.prepare_to_wait();
+        self.finish_wait(token, None);
+    }
 
-        // スピンロック保持中は割り込みを無効化
-        // これにより、ロック保持中のプリエンプションを防ぐ
-        without_interrupts(|| {
-            let mut waiters = self.waiters.lock();
-            waiters.push_back(task_id);
+    /// 起床されるか、指定時間が経過するまで現在のタスクをブロック
+    ///
+    /// # Arguments
+    /// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+    pub fn wait_timeout(&self, timeout_ms: u64) -> WaitResult {
+        let token = self.prepare_to_wait();
+        self.finish_wait(token, Some(timeout_ticks(timeout_ms)))
+    }
+
+    /// 条件が成立するまで現在のタスクをブロック
+    ///
+    /// `condition`は成立時に必要な状態変更（セマフォの獲得など）まで行い、
+    /// 成立したかどうかを返すこと。起床しても条件が成立していなければ再び待機する。
+    ///
+    /// 待機キューへの登録後に条件を再確認するため、確認と登録の間に
+    /// `wake_one()`が空振りしても起床を取りこぼさない。
+    /// 再確認で条件が成立した時に既に`wake_one()`で起床されていた場合は、
+    /// その起床を消費したうえで次の待機タスクに譲る。
+    pub fn wait_event(&self, condition: impl FnMut() -> bool) {
+        self.wait_event_inner(condition, None);
+    }
+
+    /// 条件が成立するか、指定時間が経過するまで現在のタスクをブロック
+    ///
+    /// 条件の扱いは`wait_event()`と同じ。
+    ///
+    /// # Arguments
+    /// * `condition` - 待機する条件
+    /// * `timeout_ms` - タイムアウト（ミリ秒、最小1tick）
+    ///
+    /// # Returns
+    /// 条件が成立した場合は`WaitResult::Woken`、成立しないまま期限を過ぎた場合は
+    /// `WaitResult::TimedOut`
+    pub fn wait_until(&self, condition: impl FnMut() -> bool, timeout_ms: u64) -> WaitResult {
+        let deadline = crate::timer::current_tick() + timeout_ticks(timeout_ms);
+        self.wait_event_inner(condition, Some(deadline))
+    }
+
+    /// `wait_event()`/`wait_until()`の本体（`deadline`はtick単位の期限）
+    fn wait_event_inner(
+        &self,
+        mut condition: impl FnMut() -> bool,
+        deadline: Option<u64>,
+    ) -> WaitResult {
+        while !condition() {
+            let remaining = match deadline {
+                Some(deadline) => {
+                    let now = crate::timer::current_tick();
+                    if now >= deadline {
+                        return WaitResult::TimedOut;
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+
+            let token = self.prepare_to_wait();
+            if condition() {
+                self.cancel_wait(token);
+                return WaitResult::Woken;
+            }
+            self.finish_wait(token, remaining);
+        }
+        WaitResult::Woken
+    }
+
+    /// 現在のタスクを待機キューに登録（ブロックはしない）
+    ///
+    /// 登録後に`finish_wait()`でブロックする。登録からブロックまでの間の起床は
+    /// `WAKEUP_PENDING`で検出されるため、その間にロックの解放などを行ってよい。
+    pub fn prepare_to_wait(&self) -> WaitToken {
+        WaitToken(self.enqueue(WaitTarget::Task(crate::sched::current_task_id())))
+    }
+
+    /// エントリを作成して待機キューの末尾に登録
+    fn enqueue(&self, target: WaitTarget) -> Arc<Waiter> {
+        let waiter = Waiter::new(target);
+        without_interrupts(|| self.waiters.lock().push_back(waiter.clone()));
+        waiter
+    }
+
+    /// `prepare_to_wait()`で登録した待機について、起床されるかタイムアウトするまでブロック
+    ///
+    /// # Arguments
+    /// * `token` - `prepare_to_wait()`の戻り値
+    /// * `timeout_ticks` - タイムアウト（tick数、Noneなら無期限）
+    pub fn finish_wait(&self, token: WaitToken, timeout_ticks: Option<u64>) -> WaitResult {
+        let waiter = token.0;
+        let timer = timeout_ticks.map(|ticks| {
+            let waiter = waiter.clone();
+            crate::timer::register_timer(
+                ticks,
+                Box::new(move || {
+                    if waiter.finish(TIMED_OUT) {
+                        waiter.wake();
+                    }
+                }),
+            )
         });
 
-        // waitersロック解放後にブロック
-        // block_current_task()は内部で適切にロックを管理する
+        // 起床またはタイムアウトのどちらかが状態を遷移させ、unblock_task()を呼ぶまで待つ
+        // （状態が待機中のままの起床は、以前の待機から残った起床なので無視する）
+        loop {
+            crate::sched::block_current_task();
+            if waiter.state.load(Ordering::Acquire) != WAITING {
+                break;
+            }
+        }
+
+        if let Some(timer) = timer {
+            // 既に実行待ちの場合は取り消せないが、コールバックは状態の遷移に失敗して何もしない
+            crate::timer::cancel_timer(timer);
+        }
+
+        if waiter.state.load(Ordering::Acquire) == TIMED_OUT {
+            self.remove(&waiter);
+            WaitResult::TimedOut
+        } else {
+            WaitResult::Woken
+        }
+    }
+
+    /// `prepare_to_wait()`で登録した待機を取り消す（ブロックはしない）
+    ///
+    /// 取り消す前に起床・タイムアウトしていた場合は、その`unblock_task()`を消費する。
+    /// 起床されていた場合は、その起床を次の待機タスクに譲る。
+    pub fn cancel_wait(&self, token: WaitToken) {
+        let waiter = token.0;
+        if waiter.finish(CANCELLED) {
+            self.remove(&waiter);
+            return;
+        }
+        // 自分宛ての起床（unblock_task）を消費する
         crate::sched::block_current_task();
+        match waiter.state.load(Ordering::Acquire) {
+            WOKEN => {
+                self.wake_one();
+            }
+            _ => self.remove(&waiter),
+        }
+    }
+
+    /// エントリを待機キューから取り除く
+    fn remove(&self, waiter: &Arc<Waiter>) {
+        without_interrupts(|| {
+            self.waiters.lock().retain(|w| !Arc::ptr_eq(w, waiter));
+        });
+    }
+
+    /// 待機中のタスクがあるかどうか
+    pub fn has_waiters(&self) -> bool {
+        without_interrupts(|| {
+            self.waiters
+                .lock()
+                .iter()
+                .any(|w| w.state.load(Ordering::Acquire) == WAITING)
+        })
     }
 
     /// 1つのタスクを起床させる
     ///
+    /// タイムアウト済みのエントリは取り除いて読み飛ばす。
+    ///
     /// # Returns
     /// 起床させたタスクがあればtrue、キューが空ならfalse
     ///
@@ -55,40 +309,215 @@ impl WaitQueue {
     /// スピンロック保持中は割り込みを無効化し、シングルCPU環境での
     /// デッドロックを防止します。unblock_task()はロック解放後に呼び出します。
     pub fn wake_one(&self) -> bool {
-        // スピンロック操作を割り込み無効で実行
-        let task_id = without_interrupts(|| {
-            let mut waiters = self.waiters.lock();
-            waiters.pop_front()
-        });
+        loop {
+            // スピンロック操作を割り込み無効で実行
+            let Some(waiter) = without_interrupts(|| self.waiters.lock().pop_front()) else {
+                return false;
+            };
 
-        if let Some(id) = task_id {
-            // ロック解放後にunblock_task()を呼び出す
-            crate::sched::unblock_task(id);
-            true
-        } else {
-            false
+            if waiter.finish(WOKEN) {
+                // ロック解放後にunblock_task()を呼び出す
+                waiter.wake();
+                return true;
+            }
         }
     }
 
     /// すべてのタスクを起床させる
     ///
     /// # 実装詳細
-    /// 各タスクの起床処理を個別に行い、ロック保持時間を最小化します。
-    #[allow(dead_code)]
+    /// 呼び出し時点の待機リストをまとめて取り出してから起床させるため、
+    /// 起床したタスクが再び待機しても、この呼び出しで繰り返し起床させることはありません。
     pub fn wake_all(&self) {
-        loop {
-            // 1つずつタスクIDを取得（割り込み無効で）
-            let task_id = without_interrupts(|| {
-                let mut waiters = self.waiters.lock();
-                waiters.pop_front()
-            });
+        let waiters = without_interrupts(|| core::mem::take(&mut *self.waiters.lock()));
+        for waiter in waiters {
+            if waiter.finish(WOKEN) {
+                waiter.wake();
+            }
+        }
+    }
 
-            if let Some(id) = task_id {
-                // ロック解放後にunblock_task()を呼び出す
-                crate::sched::unblock_task(id);
-            } else {
-                break;
+    /// 起床されるまで待機するフューチャーを返す（タスクはブロックしない）
+    ///
+    /// `wait()`の非同期版。最初のポーリングで待機キューに登録し、
+    /// `wake_one()`/`wake_all()`で起床されると完了する。
+    pub fn wait_async(&self) -> WaitFuture<'_> {
+        WaitFuture {
+            queue: self,
+            waiter: None,
+        }
+    }
+
+    /// 条件が成立するまで待機するフューチャーを返す（タスクはブロックしない）
+    ///
+    /// `wait_event()`の非同期版。`condition`は成立時に必要な状態変更（受信など）まで行い、
+    /// 成立した場合は結果を`Some`で返すこと。待機キューへの登録後に条件を再確認するため、
+    /// 確認と登録の間の起床を取りこぼさない。
+    pub fn wait_event_async<F, R>(&self, condition: F) -> WaitEventFuture<'_, F>
+    where
+        F: FnMut() -> Option<R> + Unpin,
+    {
+        WaitEventFuture {
+            wait: self.wait_async(),
+            condition,
+        }
+    }
+}
+
+/// `wait_async()`が返すフューチャー
+///
+/// 完了前に破棄された場合は待機を取り消し、既に起床されていた場合は
+/// その起床を次の待機に譲る。
+pub struct WaitFuture<'a> {
+    queue: &'a WaitQueue,
+    /// 登録中のエントリ（未登録・起床済みならNone）
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl WaitFuture<'_> {
+    /// 登録中のエントリが起床されたかどうかを確認する
+    ///
+    /// 未登録なら登録してfalseを返す。起床済みならエントリを手放してtrueを返す。
+    fn poll_woken(&mut self, waker: &Waker) -> bool {
+        match &self.waiter {
+            Some(waiter) => {
+                // Wakerを更新してから状態を確認し、更新前の起床も取りこぼさない
+                waiter.set_waker(waker);
+                if waiter.state.load(Ordering::Acquire) == WAITING {
+                    return false;
+                }
+                self.waiter = None;
+                true
+            }
+            None => {
+                self.register(waker);
+                false
             }
         }
     }
+
+    /// 待機キューに登録する
+    fn register(&mut self, waker: &Waker) {
+        self.waiter = Some(
+            self.queue
+                .enqueue(WaitTarget::Future(SpinMutex::new(waker.clone()))),
+        );
+    }
+
+    /// 登録中のエントリを取り消す（`forward`なら消費しなかった起床を次の待機に譲る）
+    fn cancel(&mut self, forward: bool) {
+        let Some(waiter) = self.waiter.take() else {
+            return;
+        };
+        if waiter.finish(CANCELLED) {
+            self.queue.remove(&waiter);
+        } else if forward && waiter.state.load(Ordering::Acquire) == WOKEN {
+            self.queue.wake_one();
+        }
+    }
+}
+
+impl Future for WaitFuture<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.get_mut().poll_woken(cx.waker()) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for WaitFuture<'_> {
+    fn drop(&mut self) {
+        self.cancel(true);
+    }
+}
+
+/// `wait_event_async()`が返すフューチャー
+pub struct WaitEventFuture<'a, F> {
+    wait: WaitFuture<'a>,
+    condition: F,
+}
+
+impl<F, R> Future for WaitEventFuture<'_, F>
+where
+    F: FnMut() -> Option<R> + Unpin,
+{
+    type Output = R;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<R> {
+        let this = self.get_mut();
+        loop {
+            if let Some(result) = (this.condition)() {
+                // 条件が成立したので、登録中の待機は不要（起床されていても消費してよい）
+                this.wait.cancel(false);
+                return Poll::Ready(result);
+            }
+            // 登録済みで起床されていなければ待機を続ける
+            if this.wait.waiter.is_some() && !this.wait.poll_woken(cx.waker()) {
+                return Poll::Pending;
+            }
+            // 未登録、または起床されたが条件が成立していない場合は登録し、条件を再確認する
+            this.wait.register(cx.waker());
+        }
+    }
+}
+
+/// タイムアウト（ミリ秒）をtick数に変換（最小1tick）
+fn timeout_ticks(timeout_ms: u64) -> u64 {
+    crate::timer::ms_to_ticks(timeout_ms).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_wait_event_returns_without_blocking_when_condition_holds() {
+        let queue = WaitQueue::new();
+        let mut checks = 0;
+        queue.wait_event(|| {
+            checks += 1;
+            true
+        });
+        assert_eq!(checks, 1);
+        assert!(!queue.has_waiters());
+    }
+
+    #[test_case]
+    fn test_cancel_wait() {
+        let queue = WaitQueue::new();
+        let token = queue.prepare_to_wait();
+        assert!(queue.has_waiters());
+        queue.cancel_wait(token);
+        assert!(!queue.has_waiters());
+        assert!(!queue.wake_one());
+    }
+
+    #[test_case]
+    fn test_wake_one_skips_timed_out_waiters() {
+        let queue = WaitQueue::new();
+        let timed_out = queue.prepare_to_wait();
+        // タイムアウトのコールバックが先に状態を遷移させた状態を再現
+        assert!(timed_out.0.finish(TIMED_OUT));
+        assert!(!queue.has_waiters());
+        assert!(!queue.wake_one());
+        // タイムアウトしたエントリを起床させようとはしない
+        assert_eq!(timed_out.0.state.load(Ordering::Relaxed), TIMED_OUT);
+    }
+
+    #[test_case]
+    fn test_finish_wait_reports_timeout() {
+        let queue = WaitQueue::new();
+        let token = queue.prepare_to_wait();
+        // タイムアウトのコールバックが先に状態を遷移させた状態を再現
+        // （テストカーネルにはtickがないため、実際のタイマーでは待たない）
+        assert!(token.0.finish(TIMED_OUT));
+        assert!(!token.0.finish(WOKEN));
+        assert_eq!(queue.finish_wait(token, None), WaitResult::TimedOut);
+        // タイムアウトしたエントリは取り除かれている
+        assert!(!queue.wake_one());
+    }
 }
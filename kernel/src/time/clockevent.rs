@@ -0,0 +1,393 @@
+//! クロックイベント（タイマー割り込みの発生源）の登録と選択
+//!
+//! 各ドライバは割り込みを発生させるデバイスを`ClockEventDevice`として登録し、
+//! 最も評価値（rating）の高い信頼できるデバイスがスケジューラのtickに使用されます。
+//!
+//! - CPUごとに独立したデバイス（Local APIC Timer）は各CPUで個別に開始する
+//! - システムに1つのデバイス（HPET, PIT）はISA IRQ0をI/O APIC経由でBSPに配送する
+//!
+//! # 実行時の切り替え
+//! BSPのtickの間隔をクロックソースで測り、期待値から大きくずれる状態が続いた場合は
+//! デバイスを信頼できないと判定して次のデバイスに切り替える。
+
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::io::without_interrupts;
+use crate::{apic, ioapic, percpu};
+
+/// 登録できるクロックイベントデバイスの最大数
+const MAX_CLOCKEVENTS: usize = 8;
+
+/// tick間隔の検査期間（ナノ秒）
+const CHECK_WINDOW_NS: u64 = 1_000_000_000;
+
+/// デバイスを信頼できないと判定するまでの連続した異常検出回数
+///
+/// 割り込み無効区間が長く続いた場合などの一時的なずれで切り替えないようにする。
+const CHECK_MAX_STRIKES: u32 = 3;
+
+/// クロックイベント操作のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockEventError {
+    /// 使用できるデバイスがない
+    NoDevice,
+    /// 登録数の上限に達した
+    TooManyDevices,
+    /// 周波数が0
+    InvalidFrequency,
+    /// デバイスが要求されたモードをサポートしていない
+    Unsupported,
+    /// デバイスの設定に失敗した
+    DeviceFailed(&'static str),
+}
+
+impl core::fmt::Display for ClockEventError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ClockEventError::NoDevice => write!(f, "No clock event device available"),
+            ClockEventError::TooManyDevices => write!(f, "Too many clock event devices"),
+            ClockEventError::InvalidFrequency => write!(f, "Invalid tick frequency"),
+            ClockEventError::Unsupported => write!(f, "Mode not supported by device"),
+            ClockEventError::DeviceFailed(name) => write!(f, "Failed to program {}", name),
+        }
+    }
+}
+
+/// 1つのクロックイベントデバイス
+pub struct ClockEventDevice {
+    /// 名前（ログ表示用）
+    pub name: &'static str,
+    /// 評価値（大きいほど優先される）
+    pub rating: u32,
+    /// CPUごとに独立したデバイスかどうか
+    pub per_cpu: bool,
+    /// 指定周波数の周期割り込みを開始する
+    pub set_periodic: fn(u32) -> Result<(), ClockEventError>,
+    /// 指定ナノ秒後に1回だけ割り込みを発生させる（非対応ならNone）
+    pub set_oneshot: Option<fn(u64) -> Result<(), ClockEventError>>,
+    /// 割り込みを止める
+    pub shutdown: fn(),
+    /// 実行時の検査により信頼できないと判定されたかどうか
+    unreliable: AtomicBool,
+}
+
+impl ClockEventDevice {
+    /// クロックイベントデバイスを定義
+    pub const fn new(
+        name: &'static str,
+        rating: u32,
+        per_cpu: bool,
+        set_periodic: fn(u32) -> Result<(), ClockEventError>,
+        set_oneshot: Option<fn(u64) -> Result<(), ClockEventError>>,
+        shutdown: fn(),
+    ) -> Self {
+        Self {
+            name,
+            rating,
+            per_cpu,
+            set_periodic,
+            set_oneshot,
+            shutdown,
+            unreliable: AtomicBool::new(false),
+        }
+    }
+
+    /// 信頼できないと判定されたかどうか
+    pub fn is_unreliable(&self) -> bool {
+        self.unreliable.load(Ordering::Acquire)
+    }
+}
+
+/// 登録済みのデバイス
+static DEVICES: Mutex<[Option<&'static ClockEventDevice>; MAX_CLOCKEVENTS]> =
+    Mutex::new([None; MAX_CLOCKEVENTS]);
+
+percpu! {
+    /// このCPUのtickに使用しているデバイス（nullは未開始）
+    static LOCAL_DEVICE: AtomicPtr<ClockEventDevice> = AtomicPtr::new(core::ptr::null_mut());
+
+    /// このCPUのtick周波数（Hz、0は未開始）
+    static LOCAL_HZ: AtomicU32 = AtomicU32::new(0);
+}
+
+/// tick間隔の検査期間の開始時刻（0は未開始）
+static CHECK_START_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 検査期間中のtick数
+static CHECK_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// 連続した異常検出回数
+static CHECK_STRIKES: AtomicU32 = AtomicU32::new(0);
+
+/// 実際のtick数が期待値から25%以上ずれているかどうか
+fn rate_deviates(ticks: u64, expected: u64) -> bool {
+    ticks.abs_diff(expected) > expected / 4
+}
+
+/// デバイスを登録
+///
+/// # Errors
+/// * `ClockEventError::TooManyDevices` - 登録数の上限に達した場合
+pub fn register(dev: &'static ClockEventDevice) -> Result<(), ClockEventError> {
+    without_interrupts(|| {
+        let mut devices = DEVICES.lock();
+        let slot = devices
+            .iter_mut()
+            .find(|d| d.is_none())
+            .ok_or(ClockEventError::TooManyDevices)?;
+        *slot = Some(dev);
+        crate::info!(
+            "clockevent: registered {} (rating {}{})",
+            dev.name,
+            dev.rating,
+            if dev.per_cpu { ", per-CPU" } else { "" }
+        );
+        Ok(())
+    })
+}
+
+/// 条件を満たす信頼できるデバイスを評価の高い順に列挙
+fn candidates(
+    filter: impl Fn(&ClockEventDevice) -> bool,
+) -> [Option<&'static ClockEventDevice>; MAX_CLOCKEVENTS] {
+    let mut list = *DEVICES.lock();
+    for dev in list.iter_mut() {
+        if dev.is_some_and(|d| d.is_unreliable() || !filter(d)) {
+            *dev = None;
+        }
+    }
+    list.sort_unstable_by_key(|dev| core::cmp::Reverse(dev.map_or(0, |d| d.rating)));
+    list
+}
+
+/// 指定CPUのtickに使用しているデバイス
+fn device_of(cpu: usize) -> Option<&'static ClockEventDevice> {
+    let ptr = LOCAL_DEVICE.get_for(cpu).load(Ordering::Acquire);
+    // SAFETY: LOCAL_DEVICEには'staticなClockEventDeviceへの参照のみが格納される
+    unsafe { ptr.as_ref() }
+}
+
+/// 現在のCPUのtickに使用しているデバイス
+fn local_device() -> Option<&'static ClockEventDevice> {
+    let ptr = LOCAL_DEVICE.get().load(Ordering::Acquire);
+    // SAFETY: LOCAL_DEVICEには'staticなClockEventDeviceへの参照のみが格納される
+    unsafe { ptr.as_ref() }
+}
+
+/// 現在のCPUのtickに使用するデバイスを記録
+fn set_local_device(dev: Option<&'static ClockEventDevice>, hz: u32) {
+    let ptr = dev.map_or(core::ptr::null_mut(), |d| {
+        d as *const ClockEventDevice as *mut ClockEventDevice
+    });
+    LOCAL_HZ.get().store(hz, Ordering::Release);
+    LOCAL_DEVICE.get().store(ptr, Ordering::Release);
+}
+
+/// 評価の高い順に周期モードを試し、開始できたデバイスを返す
+fn start_best_periodic(
+    hz: u32,
+    filter: impl Fn(&ClockEventDevice) -> bool,
+) -> Result<&'static ClockEventDevice, ClockEventError> {
+    for dev in candidates(filter).into_iter().flatten() {
+        match (dev.set_periodic)(hz) {
+            Ok(()) => return Ok(dev),
+            Err(e) => crate::warn!("clockevent: {} unavailable: {}", dev.name, e),
+        }
+    }
+    Err(ClockEventError::NoDevice)
+}
+
+/// BSPのtick（周期割り込み）を開始
+///
+/// 評価の最も高いデバイスから順に試し、最初に開始できたものを使用する。
+///
+/// # Errors
+/// * `ClockEventError::InvalidFrequency` - 周波数が0の場合
+/// * `ClockEventError::NoDevice` - どのデバイスも開始できなかった場合
+pub fn start_periodic(hz: u32) -> Result<(), ClockEventError> {
+    if hz == 0 {
+        return Err(ClockEventError::InvalidFrequency);
+    }
+    without_interrupts(|| {
+        let dev = start_best_periodic(hz, |_| true)?;
+        set_local_device(Some(dev), hz);
+        crate::info!("clockevent: using {} at {} Hz", dev.name, hz);
+        Ok(())
+    })
+}
+
+/// APのtick（周期割り込み）を開始
+///
+/// システムに1つのデバイスはBSPが使用するため、CPUごとのデバイスのみを使用する。
+///
+/// # Errors
+/// * `ClockEventError::InvalidFrequency` - 周波数が0の場合
+/// * `ClockEventError::NoDevice` - どのデバイスも開始できなかった場合
+pub fn start_local_periodic(hz: u32) -> Result<(), ClockEventError> {
+    if hz == 0 {
+        return Err(ClockEventError::InvalidFrequency);
+    }
+    without_interrupts(|| {
+        let dev = start_best_periodic(hz, |dev| dev.per_cpu)?;
+        set_local_device(Some(dev), hz);
+        Ok(())
+    })
+}
+
+/// 現在のCPUのデバイスがワンショットモードに対応しているかどうか
+pub fn local_supports_oneshot() -> bool {
+    local_device().is_some_and(|dev| dev.set_oneshot.is_some())
+}
+
+/// 現在のCPUのデバイスで指定ナノ秒後に1回だけ割り込みを発生させる
+///
+/// 周期割り込みは止まるため、再開するには`resume_periodic()`を呼び出す。
+///
+/// # Errors
+/// * `ClockEventError::NoDevice` - このCPUのtickが開始されていない場合
+/// * `ClockEventError::Unsupported` - デバイスがワンショットモードに対応していない場合
+pub fn program_oneshot_ns(ns: u64) -> Result<(), ClockEventError> {
+    let dev = local_device().ok_or(ClockEventError::NoDevice)?;
+    let set_oneshot = dev.set_oneshot.ok_or(ClockEventError::Unsupported)?;
+    set_oneshot(ns)
+}
+
+/// 現在のCPUのデバイスを元の周波数の周期モードに戻す
+///
+/// # Errors
+/// * `ClockEventError::NoDevice` - このCPUのtickが開始されていない場合
+pub fn resume_periodic() -> Result<(), ClockEventError> {
+    let dev = local_device().ok_or(ClockEventError::NoDevice)?;
+    (dev.set_periodic)(LOCAL_HZ.get().load(Ordering::Acquire))
+}
+
+/// BSPが使用中のデバイスの名前
+pub fn current_name() -> Option<&'static str> {
+    device_of(0).map(|dev| dev.name)
+}
+
+/// 使用中のデバイスを信頼できないと判定し、次のデバイスに切り替える
+///
+/// BSPのタイマー割り込みから呼び出される。
+fn fall_back(hz: u32) {
+    let Some(old) = local_device() else {
+        return;
+    };
+    set_local_device(None, 0);
+    old.unreliable.store(true, Ordering::Release);
+    (old.shutdown)();
+
+    match start_best_periodic(hz, |_| true) {
+        Ok(dev) => {
+            crate::warn!(
+                "clockevent: {} is unreliable, switched to {}",
+                old.name,
+                dev.name
+            );
+            set_local_device(Some(dev), hz);
+        }
+        Err(e) => {
+            // 代わりがなければ元のデバイスを使い続ける
+            crate::warn!(
+                "clockevent: {} is unreliable but no fallback: {}",
+                old.name,
+                e
+            );
+            old.unreliable.store(false, Ordering::Release);
+            if (old.set_periodic)(hz).is_ok() {
+                set_local_device(Some(old), hz);
+            }
+        }
+    }
+}
+
+/// BSPのtick間隔を検査する
+///
+/// BSPのタイマー割り込みから呼び出す。
+///
+/// # Arguments
+/// * `now_ns` - 現在時刻（monotonic_ns()、クロックソースがなければ0）
+pub(super) fn check_tick_rate(now_ns: u64) {
+    let hz = LOCAL_HZ.get().load(Ordering::Acquire);
+    if hz == 0 || now_ns == 0 {
+        return;
+    }
+    let start = CHECK_START_NS.load(Ordering::Relaxed);
+    if start == 0 {
+        CHECK_START_NS.store(now_ns, Ordering::Relaxed);
+        CHECK_TICKS.store(0, Ordering::Relaxed);
+        return;
+    }
+    let ticks = CHECK_TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    let elapsed = now_ns.saturating_sub(start);
+    if elapsed < CHECK_WINDOW_NS {
+        return;
+    }
+    CHECK_START_NS.store(now_ns, Ordering::Relaxed);
+    CHECK_TICKS.store(0, Ordering::Relaxed);
+
+    let expected = elapsed * hz as u64 / 1_000_000_000;
+    if !rate_deviates(ticks, expected) {
+        CHECK_STRIKES.store(0, Ordering::Relaxed);
+        return;
+    }
+    if CHECK_STRIKES.fetch_add(1, Ordering::Relaxed) + 1 >= CHECK_MAX_STRIKES {
+        CHECK_STRIKES.store(0, Ordering::Relaxed);
+        crate::warn!(
+            "clockevent: {} ticks in {} ms (expected {})",
+            ticks,
+            elapsed / 1_000_000,
+            expected
+        );
+        fall_back(hz);
+    }
+}
+
+/// tick間隔の検査期間をリセットする
+///
+/// tickを止めている間（tickless idle）は間隔が不規則になるため、再開時に呼び出す。
+pub(super) fn reset_tick_rate_check() {
+    CHECK_START_NS.store(0, Ordering::Relaxed);
+    CHECK_TICKS.store(0, Ordering::Relaxed);
+}
+
+/// ISA IRQ0（HPET Legacy Replacement / PIT）をBSPのタイマーベクタに配送する
+///
+/// # Errors
+/// * `ClockEventError::DeviceFailed` - I/O APICの設定に失敗した場合
+pub fn route_legacy_timer_irq() -> Result<(), ClockEventError> {
+    let (gsi, trigger, polarity) = ioapic::legacy_irq_to_gsi(0);
+    let bsp_apic_id = crate::cpu::apic_id_of(0).unwrap_or_else(apic::local_apic_id);
+    ioapic::route_irq(
+        gsi,
+        apic::TIMER_INTERRUPT_VECTOR,
+        bsp_apic_id as u8,
+        trigger,
+        polarity,
+    )
+    .and_then(|()| ioapic::unmask(gsi))
+    .map_err(|_| ClockEventError::DeviceFailed("I/O APIC"))
+}
+
+/// ISA IRQ0をマスクする
+pub fn mask_legacy_timer_irq() {
+    let (gsi, _, _) = ioapic::legacy_irq_to_gsi(0);
+    let _ = ioapic::mask(gsi);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_rate_deviates() {
+        assert!(!rate_deviates(250, 250));
+        assert!(!rate_deviates(200, 250));
+        assert!(!rate_deviates(300, 250));
+        // 停止・半分の速度・倍速はずれとみなす
+        assert!(rate_deviates(0, 250));
+        assert!(rate_deviates(125, 250));
+        assert!(rate_deviates(500, 250));
+    }
+}
@@ -0,0 +1,423 @@
+//! クロックソース（時刻の読み取り元）の登録と選択
+//!
+//! 各ドライバは自身のカウンタを`ClockSource`として登録し、最も評価値（rating）の
+//! 高い安定したソースが`monotonic_ns()`の計算に使用されます。
+//!
+//! # 時刻の計算
+//! 選択中のソースについて、基準時刻`base_ns`とその時点のカウンタ値`last_cycles`を保持し、
+//! `base_ns + (現在値 - last_cycles) * mult >> 32`で現在時刻を求める。
+//! カウンタ幅の狭いソース（ACPI PM timer: 24ビット等）がラップアラウンドしないよう、
+//! `accumulate()`で定期的に経過分を`base_ns`へ繰り込む。
+//!
+//! 状態の更新はシーケンスロック（`SEQ`が奇数の間は更新中）で行うため、
+//! 読み取り側はロックを取得しない。
+//!
+//! # ウォッチドッグ
+//! 選択中のソースを次に評価の高いソースと定期的に比較し、進み方が大きくずれた場合は
+//! 不安定と判定して次のソースに切り替える。
+
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::io::without_interrupts;
+
+/// 登録できるクロックソースの最大数
+const MAX_CLOCKSOURCES: usize = 8;
+
+/// 選択中のソースがないことを示す値
+const NO_SOURCE: usize = usize::MAX;
+
+/// 1秒あたりのナノ秒数
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// ナノ秒変換倍率の固定小数点シフト量
+const NS_SHIFT: u32 = 32;
+
+/// ウォッチドッグの比較間隔（ナノ秒、ウォッチドッグ側の経過時間）
+const WATCHDOG_INTERVAL_NS: u64 = 500_000_000;
+
+/// ウォッチドッグに使用できるソースのラップアラウンド周期の下限（ナノ秒）
+///
+/// 比較間隔の間にカウンタが一周してしまうソース（PIT: 約55ms）は使用しない。
+const WATCHDOG_MIN_WRAP_NS: u64 = 4 * WATCHDOG_INTERVAL_NS;
+
+/// 1つのクロックソース
+pub struct ClockSource {
+    /// 名前（ログ表示用）
+    pub name: &'static str,
+    /// 評価値（大きいほど優先される）
+    pub rating: u32,
+    /// カウンタの現在値を読み取る（単調増加、maskでラップアラウンド）
+    pub read: fn() -> u64,
+    /// カウンタの有効ビットマスク
+    pub mask: fn() -> u64,
+    /// カウンタの周波数（Hz）
+    pub frequency: fn() -> u64,
+    /// ウォッチドッグにより不安定と判定されたかどうか
+    unstable: AtomicBool,
+}
+
+impl ClockSource {
+    /// クロックソースを定義
+    pub const fn new(
+        name: &'static str,
+        rating: u32,
+        read: fn() -> u64,
+        mask: fn() -> u64,
+        frequency: fn() -> u64,
+    ) -> Self {
+        Self {
+            name,
+            rating,
+            read,
+            mask,
+            frequency,
+            unstable: AtomicBool::new(false),
+        }
+    }
+
+    /// 不安定と判定されたかどうか
+    pub fn is_unstable(&self) -> bool {
+        self.unstable.load(Ordering::Acquire)
+    }
+
+    /// 前回値からの経過サイクル数（ラップアラウンドを考慮）
+    fn delta(&self, now: u64, last: u64) -> u64 {
+        now.wrapping_sub(last) & (self.mask)()
+    }
+
+    /// カウンタが一周するまでの時間（ナノ秒）
+    fn wrap_ns(&self) -> u64 {
+        cycles_to_ns((self.mask)(), ns_mult((self.frequency)()))
+    }
+}
+
+/// 登録済みのクロックソース
+static SOURCES: [AtomicPtr<ClockSource>; MAX_CLOCKSOURCES] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CLOCKSOURCES];
+
+/// 登録済みのクロックソース数
+static SOURCE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 状態更新を直列化するロック
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// シーケンスカウンタ（奇数の間は更新中）
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// 選択中のソースのインデックス
+static CURRENT: AtomicUsize = AtomicUsize::new(NO_SOURCE);
+
+/// 基準時刻（ナノ秒）
+static BASE_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 基準時刻におけるカウンタ値
+static LAST_CYCLES: AtomicU64 = AtomicU64::new(0);
+
+/// サイクル数→ナノ秒の変換倍率（2^NS_SHIFT固定小数点）
+static MULT: AtomicU64 = AtomicU64::new(0);
+
+/// ウォッチドッグに使用しているソースのインデックス
+static WATCHDOG: AtomicUsize = AtomicUsize::new(NO_SOURCE);
+
+/// 前回のウォッチドッグ比較時のウォッチドッグ側カウンタ値
+static WATCHDOG_LAST: AtomicU64 = AtomicU64::new(0);
+
+/// 前回のウォッチドッグ比較時の時刻（monotonic_ns()基準）
+static WATCHDOG_CS_LAST_NS: AtomicU64 = AtomicU64::new(0);
+
+/// クロックソース操作のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSourceError {
+    /// 登録数の上限に達した
+    TooManySources,
+    /// 周波数が0
+    InvalidFrequency,
+}
+
+impl core::fmt::Display for ClockSourceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ClockSourceError::TooManySources => write!(f, "Too many clock sources"),
+            ClockSourceError::InvalidFrequency => write!(f, "Clock source frequency is zero"),
+        }
+    }
+}
+
+/// 周波数からサイクル数→ナノ秒の変換倍率を計算
+fn ns_mult(freq_hz: u64) -> u64 {
+    (((NS_PER_SEC as u128) << NS_SHIFT) / freq_hz.max(1) as u128) as u64
+}
+
+/// 変換倍率を使ってサイクル数をナノ秒に変換
+fn cycles_to_ns(cycles: u64, mult: u64) -> u64 {
+    ((cycles as u128 * mult as u128) >> NS_SHIFT) as u64
+}
+
+/// 選択中ソースとウォッチドッグの経過時間のずれが許容範囲を超えているか
+///
+/// ずれがウォッチドッグ側の経過時間の1/8（12.5%）を超えた場合に不安定とみなす。
+fn skew_exceeds(cs_ns: u64, watchdog_ns: u64) -> bool {
+    cs_ns.abs_diff(watchdog_ns) > watchdog_ns / 8
+}
+
+/// 指定インデックスのソースを取得
+fn source(index: usize) -> Option<&'static ClockSource> {
+    if index >= MAX_CLOCKSOURCES {
+        return None;
+    }
+    let ptr = SOURCES[index].load(Ordering::Acquire);
+    // SAFETY: SOURCESには'staticなClockSourceへの参照のみが格納される
+    unsafe { ptr.as_ref() }
+}
+
+/// 登録済みのソースを列挙
+fn sources() -> impl Iterator<Item = (usize, &'static ClockSource)> {
+    (0..SOURCE_COUNT.load(Ordering::Acquire)).filter_map(|i| source(i).map(|cs| (i, cs)))
+}
+
+/// 安定したソースのうち最も評価の高いものを探す（`exclude`は除外）
+fn best_source(exclude: usize) -> Option<usize> {
+    sources()
+        .filter(|&(i, cs)| i != exclude && !cs.is_unstable())
+        .max_by_key(|&(_, cs)| cs.rating)
+        .map(|(i, _)| i)
+}
+
+/// 選択中のソースと比較するウォッチドッグを探す
+fn best_watchdog(current: usize) -> Option<usize> {
+    sources()
+        .filter(|&(i, cs)| {
+            i != current && !cs.is_unstable() && cs.wrap_ns() >= WATCHDOG_MIN_WRAP_NS
+        })
+        .max_by_key(|&(_, cs)| cs.rating)
+        .map(|(i, _)| i)
+}
+
+/// クロックソースを登録し、より評価が高ければ選択する
+///
+/// # Errors
+/// * `ClockSourceError::InvalidFrequency` - 周波数が0の場合
+/// * `ClockSourceError::TooManySources` - 登録数の上限に達した場合
+pub fn register(cs: &'static ClockSource) -> Result<(), ClockSourceError> {
+    if (cs.frequency)() == 0 {
+        return Err(ClockSourceError::InvalidFrequency);
+    }
+    without_interrupts(|| {
+        let _guard = UPDATE_LOCK.lock();
+        let index = SOURCE_COUNT.load(Ordering::Acquire);
+        if index >= MAX_CLOCKSOURCES {
+            return Err(ClockSourceError::TooManySources);
+        }
+        SOURCES[index].store(
+            cs as *const ClockSource as *mut ClockSource,
+            Ordering::Release,
+        );
+        SOURCE_COUNT.store(index + 1, Ordering::Release);
+        crate::info!(
+            "clocksource: registered {} (rating {}, {} Hz)",
+            cs.name,
+            cs.rating,
+            (cs.frequency)()
+        );
+        select_best_locked();
+        Ok(())
+    })
+}
+
+/// 最も評価の高い安定したソースに切り替える（UPDATE_LOCK保持中に呼び出す）
+fn select_best_locked() {
+    let current = CURRENT.load(Ordering::Acquire);
+    let Some(best) = best_source(NO_SOURCE) else {
+        return;
+    };
+    if best == current {
+        return;
+    }
+    let Some(cs) = source(best) else {
+        return;
+    };
+
+    // 切り替え前の時刻を新しいソースの基準時刻とし、時刻が巻き戻らないようにする
+    let now_ns = monotonic_ns();
+    let cycles = (cs.read)();
+
+    SEQ.fetch_add(1, Ordering::AcqRel);
+    BASE_NS.store(now_ns, Ordering::Relaxed);
+    LAST_CYCLES.store(cycles, Ordering::Relaxed);
+    MULT.store(ns_mult((cs.frequency)()), Ordering::Relaxed);
+    CURRENT.store(best, Ordering::Relaxed);
+    SEQ.fetch_add(1, Ordering::Release);
+
+    // ウォッチドッグは選び直す
+    WATCHDOG.store(NO_SOURCE, Ordering::Relaxed);
+
+    match source(current) {
+        Some(old) => crate::info!("clocksource: switched from {} to {}", old.name, cs.name),
+        None => crate::info!("clocksource: using {}", cs.name),
+    }
+}
+
+/// 選択中のクロックソースの名前
+pub fn current_name() -> Option<&'static str> {
+    source(CURRENT.load(Ordering::Acquire)).map(|cs| cs.name)
+}
+
+/// 経過分の繰り込み（`accumulate()`）なしで時刻を保てる最大の時間（ナノ秒）
+///
+/// カウンタがラップアラウンドしないよう、tickを止める時間はこれ以下にする。
+pub fn max_idle_ns() -> u64 {
+    source(CURRENT.load(Ordering::Acquire)).map_or(0, |cs| cs.wrap_ns() / 2)
+}
+
+/// クロックソースが選択されているかどうか
+pub fn is_available() -> bool {
+    CURRENT.load(Ordering::Acquire) != NO_SOURCE
+}
+
+/// 起動からの単調増加時刻（ナノ秒）
+///
+/// クロックソースが登録されていない場合は0を返す。
+pub fn monotonic_ns() -> u64 {
+    loop {
+        let seq = SEQ.load(Ordering::Acquire);
+        if seq & 1 != 0 {
+            core::hint::spin_loop();
+            continue;
+        }
+        let Some(cs) = source(CURRENT.load(Ordering::Relaxed)) else {
+            return 0;
+        };
+        let base = BASE_NS.load(Ordering::Relaxed);
+        let last = LAST_CYCLES.load(Ordering::Relaxed);
+        let mult = MULT.load(Ordering::Relaxed);
+        let now = (cs.read)();
+        if SEQ.load(Ordering::Acquire) == seq {
+            return base + cycles_to_ns(cs.delta(now, last), mult);
+        }
+    }
+}
+
+/// 経過分を基準時刻へ繰り込む（カウンタのラップアラウンド対策）
+///
+/// カウンタ幅の1/4以上進んでいる場合のみ更新する。
+/// タイマー割り込み等から定期的に、割り込み無効状態で呼び出す。
+pub fn accumulate() {
+    let Some(_guard) = UPDATE_LOCK.try_lock() else {
+        return;
+    };
+    let Some(cs) = source(CURRENT.load(Ordering::Acquire)) else {
+        return;
+    };
+    let last = LAST_CYCLES.load(Ordering::Relaxed);
+    let now = (cs.read)();
+    let delta = cs.delta(now, last);
+    if delta < (cs.mask)() / 4 {
+        return;
+    }
+    let elapsed = cycles_to_ns(delta, MULT.load(Ordering::Relaxed));
+
+    SEQ.fetch_add(1, Ordering::AcqRel);
+    BASE_NS.fetch_add(elapsed, Ordering::Relaxed);
+    LAST_CYCLES.store(now, Ordering::Relaxed);
+    SEQ.fetch_add(1, Ordering::Release);
+}
+
+/// 選択中のソースをウォッチドッグと比較し、ずれていれば切り替える
+///
+/// タイマー割り込み等から定期的に、割り込み無効状態で呼び出す。
+pub fn watchdog_check() {
+    let Some(_guard) = UPDATE_LOCK.try_lock() else {
+        return;
+    };
+    let current = CURRENT.load(Ordering::Acquire);
+    let Some(cs) = source(current) else {
+        return;
+    };
+
+    let watchdog_index = WATCHDOG.load(Ordering::Relaxed);
+    if watchdog_index == NO_SOURCE {
+        // 比較対象を選び、次回から比較を開始する
+        let Some(index) = best_watchdog(current) else {
+            return;
+        };
+        let Some(wd) = source(index) else {
+            return;
+        };
+        WATCHDOG.store(index, Ordering::Relaxed);
+        WATCHDOG_LAST.store((wd.read)(), Ordering::Relaxed);
+        WATCHDOG_CS_LAST_NS.store(monotonic_ns(), Ordering::Relaxed);
+        return;
+    }
+    let Some(wd) = source(watchdog_index) else {
+        return;
+    };
+
+    // 選択中のソースの経過時間はmonotonic_ns()で測り、ラップアラウンドの影響を受けないようにする
+    let wd_now = (wd.read)();
+    let cs_now_ns = monotonic_ns();
+    let wd_ns = cycles_to_ns(
+        wd.delta(wd_now, WATCHDOG_LAST.load(Ordering::Relaxed)),
+        ns_mult((wd.frequency)()),
+    );
+    if wd_ns < WATCHDOG_INTERVAL_NS {
+        return;
+    }
+    let cs_ns = cs_now_ns.saturating_sub(WATCHDOG_CS_LAST_NS.load(Ordering::Relaxed));
+    WATCHDOG_LAST.store(wd_now, Ordering::Relaxed);
+    WATCHDOG_CS_LAST_NS.store(cs_now_ns, Ordering::Relaxed);
+
+    if skew_exceeds(cs_ns, wd_ns) {
+        crate::warn!(
+            "clocksource: {} is unstable ({} ns elapsed, {} measured {} ns)",
+            cs.name,
+            cs_ns,
+            wd.name,
+            wd_ns
+        );
+        cs.unstable.store(true, Ordering::Release);
+        select_best_locked();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_cycles_to_ns_for_common_frequencies() {
+        // ACPI PM timer: 3.579545MHz → 3579545サイクルで1秒
+        let mult = ns_mult(3_579_545);
+        assert!(cycles_to_ns(3_579_545, mult).abs_diff(NS_PER_SEC) <= 1);
+        // HPET (QEMU): 100MHz → 1サイクル10ns
+        let mult = ns_mult(100_000_000);
+        assert_eq!(cycles_to_ns(1_000, mult), 10_000);
+    }
+
+    #[test_case]
+    fn test_skew_exceeds() {
+        assert!(!skew_exceeds(500_000_000, 500_000_000));
+        // 12.5%以内のずれは許容する
+        assert!(!skew_exceeds(560_000_000, 500_000_000));
+        assert!(!skew_exceeds(440_000_000, 500_000_000));
+        // 止まっている・倍速で進むソースは不安定
+        assert!(skew_exceeds(0, 500_000_000));
+        assert!(skew_exceeds(1_000_000_000, 500_000_000));
+    }
+
+    #[test_case]
+    fn test_delta_handles_wraparound() {
+        fn read() -> u64 {
+            0
+        }
+        fn mask() -> u64 {
+            0xFF_FFFF
+        }
+        fn frequency() -> u64 {
+            3_579_545
+        }
+        let cs = ClockSource::new("test", 0, read, mask, frequency);
+        assert_eq!(cs.delta(0x10, 0xFF_FFF0), 0x20);
+        assert_eq!(cs.delta(0x20, 0x10), 0x10);
+    }
+}
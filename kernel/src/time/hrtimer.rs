@@ -0,0 +1,433 @@
+//! 高精度タイマー（hrtimer）
+//!
+//! `time::monotonic_ns()`基準のナノ秒単位の期限を持つタイマーです。
+//! `timer::register_timer()`（tick単位・相対時間・ワンショットのみ）と異なり、
+//! 次の機能を提供します。
+//!
+//! - 絶対時刻・相対時間での開始
+//! - IDによる取り消し（`cancel`）と期限の変更（`modify`）
+//! - 周期タイマー（前回の期限に周期を加算して再設定するため、遅延が累積しない）
+//! - コールバックの実行コンテキストの選択（割り込みハンドラ内 / softirq）
+//!
+//! # 期限の処理
+//! タイマーキューはシステムで1つで、BSPのタイマー割り込みで期限切れを処理する。
+//! BSPは次のtickより前に期限のあるタイマーがあれば、クロックイベントを
+//! ワンショットに切り替えてその期限に割り込みを発生させる（`nohz`参照）。
+//!
+//! # コールバックの制約
+//! `HrTimerContext::HardIrq`のコールバックは割り込み無効状態で実行されるため、
+//! ブロックしてはならない。`SoftIrq`のコールバックは割り込み復帰時に
+//! 割り込み有効状態で実行される。
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+use super::nohz;
+use crate::io::without_interrupts;
+use crate::timer;
+
+/// ワンショットタイマーのコールバック型
+pub type HrTimerCallback = Box<dyn FnOnce() + Send + 'static>;
+
+/// 周期タイマーのコールバック型
+pub type HrTimerPeriodicCallback = Box<dyn FnMut() + Send + 'static>;
+
+/// タイマーIDカウンタ
+static HRTIMER_ID_COUNTER: AtomicU64 = AtomicU64::new(1);
+
+/// 高精度タイマーのID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HrTimerId(u64);
+
+/// コールバックを実行するコンテキスト
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HrTimerContext {
+    /// タイマー割り込みハンドラ内（割り込み無効、最小の遅延）
+    HardIrq,
+    /// 割り込み復帰時のsoftirq処理（割り込み有効）
+    SoftIrq,
+}
+
+/// 高精度タイマー操作のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HrTimerError {
+    /// 指定IDのタイマーが存在しない（期限切れ済み・取り消し済み）
+    NotFound,
+    /// 周期が0
+    InvalidPeriod,
+}
+
+impl core::fmt::Display for HrTimerError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            HrTimerError::NotFound => write!(f, "hrtimer not found"),
+            HrTimerError::InvalidPeriod => write!(f, "hrtimer period must be non-zero"),
+        }
+    }
+}
+
+/// タイマーのコールバック
+enum Callback {
+    /// ワンショット
+    Once(HrTimerCallback),
+    /// 周期（周期ナノ秒, コールバック）
+    Periodic(u64, HrTimerPeriodicCallback),
+}
+
+/// 1つの高精度タイマー
+struct HrTimer {
+    id: HrTimerId,
+    /// 期限（monotonic_ns()基準）
+    deadline: u64,
+    context: HrTimerContext,
+    callback: Callback,
+}
+
+/// コールバック実行中のタイマーの状態
+///
+/// 実行中のタイマーはキューから取り出されているため、実行中に`cancel`や`modify`が
+/// 呼ばれた場合はここに記録し、実行後の再設定で反映する。
+#[derive(Clone, Copy)]
+struct Running {
+    id: HrTimerId,
+    /// 取り消しが要求された
+    cancelled: bool,
+    /// 新しい期限が要求された
+    new_deadline: Option<u64>,
+}
+
+/// 実行中のタイマーの種類（割り込みハンドラとsoftirqで同時に1つずつ実行され得る）
+#[derive(Clone, Copy)]
+enum Slot {
+    Hard,
+    Soft,
+}
+
+/// タイマーキュー
+struct HrTimerQueue {
+    /// 期限順のタイマー（キー: (期限, ID)）
+    timers: BTreeMap<(u64, HrTimerId), HrTimer>,
+    /// IDから期限への索引
+    deadlines: BTreeMap<HrTimerId, u64>,
+    /// softirqでの実行待ち
+    pending: VecDeque<HrTimer>,
+    /// 割り込みハンドラで実行中のタイマー
+    running_hard: Option<Running>,
+    /// softirqで実行中のタイマー
+    running_soft: Option<Running>,
+}
+
+impl HrTimerQueue {
+    const fn new() -> Self {
+        Self {
+            timers: BTreeMap::new(),
+            deadlines: BTreeMap::new(),
+            pending: VecDeque::new(),
+            running_hard: None,
+            running_soft: None,
+        }
+    }
+
+    fn insert(&mut self, timer: HrTimer) {
+        self.deadlines.insert(timer.id, timer.deadline);
+        self.timers.insert((timer.deadline, timer.id), timer);
+    }
+
+    fn remove(&mut self, id: HrTimerId) -> Option<HrTimer> {
+        if let Some(deadline) = self.deadlines.remove(&id) {
+            return self.timers.remove(&(deadline, id));
+        }
+        let index = self.pending.iter().position(|t| t.id == id)?;
+        self.pending.remove(index)
+    }
+
+    /// 期限切れのタイマーを1つ取り出す
+    fn pop_expired(&mut self, now: u64) -> Option<HrTimer> {
+        let entry = self.timers.first_entry()?;
+        if entry.key().0 > now {
+            return None;
+        }
+        let timer = entry.remove();
+        self.deadlines.remove(&timer.id);
+        Some(timer)
+    }
+
+    fn next_deadline(&self) -> Option<u64> {
+        self.timers
+            .first_key_value()
+            .map(|(&(deadline, _), _)| deadline)
+    }
+
+    fn running_mut(&mut self, slot: Slot) -> &mut Option<Running> {
+        match slot {
+            Slot::Hard => &mut self.running_hard,
+            Slot::Soft => &mut self.running_soft,
+        }
+    }
+
+    /// 実行中のタイマーを探す
+    fn find_running(&mut self, id: HrTimerId) -> Option<&mut Running> {
+        [&mut self.running_hard, &mut self.running_soft]
+            .into_iter()
+            .flatten()
+            .find(|r| r.id == id)
+    }
+}
+
+/// グローバルなタイマーキュー
+static HRTIMER_QUEUE: Mutex<HrTimerQueue> = Mutex::new(HrTimerQueue::new());
+
+/// 周期タイマーの次の期限を計算
+///
+/// 前回の期限に周期を加算する（コールバックの実行遅延が累積しない）。
+/// 処理が遅れて複数周期を過ぎていた場合は、過ぎた周期を飛ばして未来の期限にする。
+fn next_periodic_deadline(deadline: u64, period: u64, now: u64) -> u64 {
+    let next = deadline.saturating_add(period);
+    if next > now {
+        return next;
+    }
+    let overruns = (now - next) / period + 1;
+    next.saturating_add(overruns.saturating_mul(period))
+}
+
+/// タイマーをキューに追加し、BSPに次のイベントの再計算を要求
+fn enqueue(deadline: u64, context: HrTimerContext, callback: Callback) -> HrTimerId {
+    let id = HrTimerId(HRTIMER_ID_COUNTER.fetch_add(1, Ordering::Relaxed));
+    without_interrupts(|| {
+        HRTIMER_QUEUE.lock().insert(HrTimer {
+            id,
+            deadline,
+            context,
+            callback,
+        });
+    });
+    nohz::hrtimer_changed(deadline);
+    id
+}
+
+/// 絶対時刻を期限とするワンショットタイマーを開始
+///
+/// # Arguments
+/// * `deadline_ns` - 期限（`time::monotonic_ns()`基準）。過去の時刻なら次の割り込みで実行される
+/// * `context` - コールバックを実行するコンテキスト
+/// * `callback` - 期限切れ時に実行するコールバック
+pub fn start(deadline_ns: u64, context: HrTimerContext, callback: HrTimerCallback) -> HrTimerId {
+    enqueue(deadline_ns, context, Callback::Once(callback))
+}
+
+/// 現在から指定時間後を期限とするワンショットタイマーを開始
+///
+/// # Arguments
+/// * `delay_ns` - 現在からの遅延（ナノ秒）
+/// * `context` - コールバックを実行するコンテキスト
+/// * `callback` - 期限切れ時に実行するコールバック
+pub fn start_after(delay_ns: u64, context: HrTimerContext, callback: HrTimerCallback) -> HrTimerId {
+    start(
+        super::monotonic_ns().saturating_add(delay_ns),
+        context,
+        callback,
+    )
+}
+
+/// 周期タイマーを開始
+///
+/// 各期限は前回の期限に周期を加算して決まるため、コールバックの実行遅延が累積しない。
+///
+/// # Arguments
+/// * `first_deadline_ns` - 最初の期限（`time::monotonic_ns()`基準）
+/// * `period_ns` - 周期（ナノ秒）
+/// * `context` - コールバックを実行するコンテキスト
+/// * `callback` - 期限毎に実行するコールバック
+///
+/// # Errors
+/// * `HrTimerError::InvalidPeriod` - 周期が0の場合
+pub fn start_periodic(
+    first_deadline_ns: u64,
+    period_ns: u64,
+    context: HrTimerContext,
+    callback: HrTimerPeriodicCallback,
+) -> Result<HrTimerId, HrTimerError> {
+    if period_ns == 0 {
+        return Err(HrTimerError::InvalidPeriod);
+    }
+    Ok(enqueue(
+        first_deadline_ns,
+        context,
+        Callback::Periodic(period_ns, callback),
+    ))
+}
+
+/// タイマーを取り消す
+///
+/// 実行中の周期タイマーを取り消した場合、実行中のコールバックは完了するが再設定されない。
+///
+/// # Returns
+/// タイマーが有効だった場合true
+pub fn cancel(id: HrTimerId) -> bool {
+    without_interrupts(|| {
+        let mut queue = HRTIMER_QUEUE.lock();
+        if queue.remove(id).is_some() {
+            return true;
+        }
+        match queue.find_running(id) {
+            Some(running) => {
+                running.cancelled = true;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// タイマーの期限を変更する
+///
+/// 実行中の周期タイマーに対しては、実行完了後の次の期限として反映される。
+///
+/// # Arguments
+/// * `id` - タイマーID
+/// * `new_deadline_ns` - 新しい期限（`time::monotonic_ns()`基準）
+///
+/// # Errors
+/// * `HrTimerError::NotFound` - タイマーが存在しない場合（期限切れ済み・取り消し済み）
+pub fn modify(id: HrTimerId, new_deadline_ns: u64) -> Result<(), HrTimerError> {
+    without_interrupts(|| {
+        let mut queue = HRTIMER_QUEUE.lock();
+        if let Some(mut timer) = queue.remove(id) {
+            timer.deadline = new_deadline_ns;
+            queue.insert(timer);
+            return Ok(());
+        }
+        match queue.find_running(id) {
+            Some(running) if !running.cancelled => {
+                running.new_deadline = Some(new_deadline_ns);
+                Ok(())
+            }
+            _ => Err(HrTimerError::NotFound),
+        }
+    })?;
+    nohz::hrtimer_changed(new_deadline_ns);
+    Ok(())
+}
+
+/// 最も早いタイマーの期限
+pub fn next_deadline() -> Option<u64> {
+    without_interrupts(|| HRTIMER_QUEUE.lock().next_deadline())
+}
+
+/// タイマーのコールバックを実行し、周期タイマーなら再設定する
+///
+/// キューのロックを保持せずに呼び出すこと。コールバックは呼び出し元の割り込み状態で実行される。
+fn run_timer(timer: HrTimer, slot: Slot) {
+    let HrTimer {
+        id,
+        deadline,
+        context,
+        callback,
+    } = timer;
+    without_interrupts(|| {
+        *HRTIMER_QUEUE.lock().running_mut(slot) = Some(Running {
+            id,
+            cancelled: false,
+            new_deadline: None,
+        });
+    });
+
+    let (period, mut callback) = match callback {
+        Callback::Once(callback) => {
+            callback();
+            without_interrupts(|| *HRTIMER_QUEUE.lock().running_mut(slot) = None);
+            return;
+        }
+        Callback::Periodic(period, callback) => (period, callback),
+    };
+    callback();
+
+    let rearmed = without_interrupts(|| {
+        let mut queue = HRTIMER_QUEUE.lock();
+        let running = queue.running_mut(slot).take().filter(|r| !r.cancelled)?;
+        let deadline = running
+            .new_deadline
+            .unwrap_or_else(|| next_periodic_deadline(deadline, period, super::monotonic_ns()));
+        queue.insert(HrTimer {
+            id,
+            deadline,
+            context,
+            callback: Callback::Periodic(period, callback),
+        });
+        Some(deadline)
+    });
+    // 割り込みハンドラ内ではハンドラの最後に次のイベントが設定される
+    if let Some(deadline) = rearmed
+        && matches!(slot, Slot::Soft)
+    {
+        nohz::hrtimer_changed(deadline);
+    }
+}
+
+/// 期限切れのタイマーを処理する
+///
+/// BSPのタイマー割り込みから割り込み無効状態で呼び出す。
+/// `HardIrq`のコールバックはこの場で実行し、`SoftIrq`のものはsoftirqに回す。
+pub fn run_expired(now_ns: u64) {
+    let mut raise = false;
+    loop {
+        let Some(timer) = HRTIMER_QUEUE.lock().pop_expired(now_ns) else {
+            break;
+        };
+        match timer.context {
+            HrTimerContext::HardIrq => run_timer(timer, Slot::Hard),
+            HrTimerContext::SoftIrq => {
+                HRTIMER_QUEUE.lock().pending.push_back(timer);
+                raise = true;
+            }
+        }
+    }
+    if raise {
+        timer::raise_softirq();
+    }
+}
+
+/// softirqでの実行待ちのタイマーを処理する
+///
+/// `timer::do_softirq()`から割り込み有効状態で呼び出される。
+pub fn run_softirq() {
+    while let Some(timer) = without_interrupts(|| HRTIMER_QUEUE.lock().pending.pop_front()) {
+        run_timer(timer, Slot::Soft);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_next_periodic_deadline_does_not_drift() {
+        // 期限1000で遅れて1500に処理しても、次の期限は2000（1500+1000ではない）
+        assert_eq!(next_periodic_deadline(1_000, 1_000, 1_500), 2_000);
+        // 複数周期を過ぎていた場合は過ぎた分を飛ばす
+        assert_eq!(next_periodic_deadline(1_000, 1_000, 4_200), 5_000);
+        // ちょうど次の期限の時刻なら、その次に進める
+        assert_eq!(next_periodic_deadline(1_000, 1_000, 2_000), 3_000);
+    }
+
+    #[test_case]
+    fn test_queue_orders_by_deadline_and_removes_by_id() {
+        let mut queue = HrTimerQueue::new();
+        for (id, deadline) in [(1, 300), (2, 100), (3, 200)] {
+            queue.insert(HrTimer {
+                id: HrTimerId(id),
+                deadline,
+                context: HrTimerContext::HardIrq,
+                callback: Callback::Once(Box::new(|| {})),
+            });
+        }
+        assert_eq!(queue.next_deadline(), Some(100));
+        assert!(queue.remove(HrTimerId(2)).is_some());
+        assert!(queue.remove(HrTimerId(2)).is_none());
+        assert_eq!(queue.next_deadline(), Some(200));
+        assert!(queue.pop_expired(150).is_none());
+        assert_eq!(queue.pop_expired(250).map(|t| t.id), Some(HrTimerId(3)));
+    }
+}
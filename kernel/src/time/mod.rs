@@ -0,0 +1,98 @@
+//! 時刻管理
+//!
+//! クロックソース（時刻の読み取り元: TSC, HPET, ACPI PM Timer, PIT）と
+//! クロックイベント（タイマー割り込みの発生源: Local APIC Timer, HPET, PIT）を
+//! 評価値付きで登録し、起動時に最適なものを選択します。
+//! 実行中に異常が検出された場合は次に評価の高いものに切り替えます。
+//!
+//! 時刻が必要なコードはデバイスを直接読まず、`monotonic_ns()`を使用してください。
+//! アイドル中のCPUのtickは`nohz`で止められます。
+//! tickより細かい精度が必要なタイマーには`hrtimer`を使用してください。
+
+pub mod clockevent;
+pub mod clocksource;
+pub mod hrtimer;
+pub mod nohz;
+
+pub use clockevent::{ClockEventDevice, ClockEventError};
+pub use clocksource::{ClockSource, ClockSourceError};
+pub use hrtimer::{HrTimerContext, HrTimerError, HrTimerId};
+
+use crate::{acpi_pm, apic, hpet, pit, tsc};
+
+/// 起動からの単調増加時刻（ナノ秒）
+///
+/// 全CPUで共通の時刻を返す。`init()`より前は0を返す。
+#[inline]
+pub fn monotonic_ns() -> u64 {
+    clocksource::monotonic_ns()
+}
+
+/// 起動からの単調増加時刻（ミリ秒）
+pub fn monotonic_ms() -> u64 {
+    monotonic_ns() / 1_000_000
+}
+
+/// クロックソースが選択されているかどうか
+pub fn has_clocksource() -> bool {
+    clocksource::is_available()
+}
+
+/// 利用可能なクロックソースとクロックイベントデバイスを登録
+///
+/// TSC・HPET・ACPI PM Timerの検出とキャリブレーションが完了した後に、
+/// 割り込み無効状態で呼び出すこと。
+pub fn init() {
+    if tsc::is_stable() {
+        register_clocksource(&tsc::TSC_CLOCKSOURCE);
+    }
+    if hpet::is_available() {
+        register_clocksource(&hpet::HPET_CLOCKSOURCE);
+    }
+    if acpi_pm::is_available() {
+        register_clocksource(&acpi_pm::ACPI_PM_CLOCKSOURCE);
+    }
+    pit::start_counter();
+    register_clocksource(&pit::PIT_CLOCKSOURCE);
+
+    register_clockevent(&apic::APIC_CLOCKEVENT);
+    if hpet::is_available() {
+        register_clockevent(&hpet::HPET_CLOCKEVENT);
+    }
+    register_clockevent(&pit::PIT_CLOCKEVENT);
+
+    nohz::init();
+
+    if let Some(name) = clocksource::current_name() {
+        crate::info!("Time initialized: clocksource={}", name);
+    }
+}
+
+/// クロックソースを登録（失敗はログ出力のみ）
+fn register_clocksource(cs: &'static ClockSource) {
+    if let Err(e) = clocksource::register(cs) {
+        crate::warn!("Failed to register clocksource {}: {}", cs.name, e);
+    }
+}
+
+/// クロックイベントデバイスを登録（失敗はログ出力のみ）
+fn register_clockevent(dev: &'static ClockEventDevice) {
+    if let Err(e) = clockevent::register(dev) {
+        crate::warn!("Failed to register clockevent {}: {}", dev.name, e);
+    }
+}
+
+/// タイマー割り込み毎の時刻管理処理
+///
+/// BSPのタイマー割り込みから呼び出す。クロックソースの経過分の繰り込みと
+/// ウォッチドッグ、tick間隔の検査を行う。
+///
+/// # Arguments
+/// * `periodic` - 周期tickによる割り込みかどうか（ワンショットではtick間隔を検査しない）
+pub fn tick(periodic: bool) {
+    clocksource::accumulate();
+    clocksource::watchdog_check();
+    if periodic {
+        clockevent::check_tick_rate(monotonic_ns());
+    }
+}
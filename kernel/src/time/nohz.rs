@@ -0,0 +1,287 @@
+//! tickless idle（dynamic ticks）
+//!
+//! アイドルタスクしか実行するものがないCPUでは周期的なtick割り込みを止め、
+//! 次のイベントまでワンショットのタイマー割り込みで待機します。
+//!
+//! - BSP: タイマーキューの先頭（sleep_ms中のタスクの起床を含む）の期限まで
+//! - AP: グローバルなタイマーを処理しないため、上限時間（負荷分散の継続用）まで
+//!
+//! どちらもクロックソースのラップアラウンド周期の半分を超えては止めない。
+//!
+//! いずれかの割り込みで起床すると、割り込み復帰時（スケジューリングの前）に
+//! `restart_tick()`で周期割り込みを再開し、BSPは止めていた間のtick数を
+//! `timer::catch_up_ticks()`で補う。これにより`timer::current_tick()`は
+//! tickを止めていても実時間に沿って進む。
+//!
+//! # 高精度タイマー
+//! BSPは次のtickより前に`hrtimer`の期限がある場合、アイドルでなくても
+//! ワンショットでその期限に割り込みを発生させる。処理後はtick境界に
+//! ワンショットを設定し、tick境界で周期割り込みに戻す。
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::{clockevent, clocksource, hrtimer};
+use crate::io::without_interrupts;
+use crate::irq::{self, InterruptFrame, IrqVector};
+use crate::{cpu, ipi, percpu, sched, timer};
+
+/// tickを止める時間の上限（ナノ秒）
+const MAX_IDLE_NS: u64 = 1_000_000_000;
+
+/// ワンショットに設定する時間の下限（ナノ秒）
+///
+/// 短すぎる値はHPETのコンパレータ書き込み前にカウンタが通過して割り込みを取りこぼすため。
+const MIN_ONESHOT_NS: u64 = 10_000;
+
+/// タイマー追加をBSPに通知するIPIのベクタ（0は未登録）
+static KICK_VECTOR: AtomicU8 = AtomicU8::new(0);
+
+/// 周期tickで動作中
+const MODE_PERIODIC: u8 = 0;
+/// アイドルでtickを止めている
+const MODE_IDLE: u8 = 1;
+/// hrtimerの期限にワンショットを設定している（BSPのみ）
+const MODE_HRTIMER: u8 = 2;
+/// 次のtick境界にワンショットを設定している（BSPのみ）
+const MODE_TICK_BOUNDARY: u8 = 3;
+
+percpu! {
+    /// このCPUのクロックイベントデバイスの動作状態（MODE_*）
+    static TICK_MODE: AtomicU8 = AtomicU8::new(MODE_PERIODIC);
+}
+
+/// タイマー割り込みの発生理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerEntry {
+    /// 周期tick
+    Periodic,
+    /// tick境界に設定したワンショット
+    TickBoundary,
+    /// hrtimerの期限やtickless idleからの起床のワンショット
+    OneShot,
+}
+
+/// 現在のCPUのtickを止めているかどうか
+pub fn is_tick_stopped() -> bool {
+    TICK_MODE.get().load(Ordering::Acquire) == MODE_IDLE
+}
+
+/// 可能であれば現在のCPUの周期tickを止め、次のイベントにワンショットを設定する
+///
+/// 割り込み無効状態で呼び出すこと。
+fn stop_tick() {
+    if is_tick_stopped()
+        || !super::has_clocksource()
+        || !clockevent::local_supports_oneshot()
+        || !sched::can_stop_tick()
+    {
+        return;
+    }
+    let period = timer::tick_period_ns();
+    if period == 0 {
+        return;
+    }
+
+    let now = super::monotonic_ns();
+    let mut sleep_ns = MAX_IDLE_NS.min(clocksource::max_idle_ns());
+    if cpu::is_bsp() {
+        if let Some(expiry_ns) = timer::next_expiry_ns(now) {
+            sleep_ns = sleep_ns.min(expiry_ns);
+        }
+        if let Some(deadline) = hrtimer::next_deadline() {
+            sleep_ns = sleep_ns.min(deadline.saturating_sub(now));
+        }
+    }
+    // 次のイベントが1tick以内なら周期tickのままにする
+    if sleep_ns <= period {
+        return;
+    }
+    if clockevent::program_oneshot_ns(sleep_ns).is_ok() {
+        TICK_MODE.get().store(MODE_IDLE, Ordering::Release);
+    }
+}
+
+/// 周期tick以外の状態から復帰する
+///
+/// BSPは止めていた間のtick数を補う。ワンショットは発火済みのため、
+/// BSPは`program_next_event()`で次のイベントを設定し、APは周期tickを再開する。
+fn leave_oneshot() {
+    if cpu::is_bsp() {
+        timer::catch_up_ticks(super::monotonic_ns());
+        clockevent::reset_tick_rate_check();
+        return;
+    }
+    if let Err(e) = clockevent::resume_periodic() {
+        crate::warn!("Failed to restart timer tick: {}", e);
+    }
+}
+
+/// tickを止めていれば周期tickを再開する
+///
+/// 割り込み無効状態で、割り込み復帰処理から呼び出す。
+/// タイマー割り込みでは`timer_interrupt_enter()`が先に状態を戻しているため何もしない。
+///
+/// # Returns
+/// tickを再開した場合true（BSPでは止めていた間のtick数を補い済み）
+pub fn restart_tick() -> bool {
+    if TICK_MODE
+        .get()
+        .compare_exchange(
+            MODE_IDLE,
+            MODE_PERIODIC,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_err()
+    {
+        return false;
+    }
+    leave_oneshot();
+    if cpu::is_bsp() {
+        program_next_event(TimerEntry::OneShot);
+    }
+    true
+}
+
+/// タイマー割り込みの入口で呼び出し、割り込みの発生理由を返す
+///
+/// ワンショットによる割り込みであれば、BSPでは止めていた間のtick数を補い、
+/// APでは周期tickを再開する。BSPはハンドラの最後に`program_next_event()`を呼ぶこと。
+pub fn timer_interrupt_enter() -> TimerEntry {
+    let entry = match TICK_MODE.get().swap(MODE_PERIODIC, Ordering::AcqRel) {
+        MODE_PERIODIC => return TimerEntry::Periodic,
+        MODE_TICK_BOUNDARY => TimerEntry::TickBoundary,
+        _ => TimerEntry::OneShot,
+    };
+    leave_oneshot();
+    entry
+}
+
+/// BSPのクロックイベントデバイスに次のイベントを設定する
+///
+/// 次のtickより前にhrtimerの期限があればそこにワンショットを設定する。
+/// なければ周期tickに戻すが、tick境界以外では周期がずれるため、
+/// まずtick境界にワンショットを設定する。
+/// 割り込み無効状態で、BSPから呼び出す。
+///
+/// # Arguments
+/// * `entry` - デバイスの現在の状態（`Periodic`なら周期tickが動作中）
+pub fn program_next_event(entry: TimerEntry) {
+    if !cpu::is_bsp() {
+        return;
+    }
+    let mode = TICK_MODE.get();
+    let now = super::monotonic_ns();
+    let next_tick = timer::next_tick_ns();
+    if super::has_clocksource()
+        && clockevent::local_supports_oneshot()
+        && let Some(deadline) = hrtimer::next_deadline()
+        && deadline < next_tick
+    {
+        let delay = deadline.saturating_sub(now).max(MIN_ONESHOT_NS);
+        if clockevent::program_oneshot_ns(delay).is_ok() {
+            mode.store(MODE_HRTIMER, Ordering::Release);
+            return;
+        }
+    }
+
+    match entry {
+        TimerEntry::Periodic => {}
+        // 境界の直前に発火してtickを数え損ねないよう、下限時間分だけ後ろにずらす
+        TimerEntry::OneShot
+            if clockevent::program_oneshot_ns(
+                next_tick.saturating_sub(now).saturating_add(MIN_ONESHOT_NS),
+            )
+            .is_ok() =>
+        {
+            mode.store(MODE_TICK_BOUNDARY, Ordering::Release);
+        }
+        TimerEntry::OneShot | TimerEntry::TickBoundary => {
+            mode.store(MODE_PERIODIC, Ordering::Release);
+            if let Err(e) = clockevent::resume_periodic() {
+                crate::warn!("Failed to restart timer tick: {}", e);
+            }
+        }
+    }
+}
+
+/// BSPのデバイスに設定済みのイベントを、hrtimerの変更に合わせて設定し直す
+///
+/// 割り込み無効状態で、BSPから呼び出す。
+fn reprogram() {
+    match TICK_MODE.get().load(Ordering::Acquire) {
+        // アイドル中は割り込み復帰時のrestart_tick()で設定される
+        MODE_IDLE => {}
+        MODE_PERIODIC => program_next_event(TimerEntry::Periodic),
+        _ => program_next_event(TimerEntry::OneShot),
+    }
+}
+
+/// アイドルタスクのループ本体
+///
+/// tickを止められる状態ならワンショットを設定してから、割り込みが来るまでCPUを休止させる。
+pub fn idle() {
+    // SAFETY: CLI命令は割り込みフラグを無効化するのみで安全。
+    // tickの判定から休止までの間に割り込みが入らないようにする。
+    unsafe {
+        core::arch::asm!("cli", options(nomem, nostack));
+    }
+    stop_tick();
+    // SAFETY: STIの直後の命令が完了するまで割り込みは保留されるため、
+    // STIとHLTの間に届いた割り込みで休止を取りこぼすことはない。
+    // HLTは次の割り込みで復帰するため、メモリ安全性に影響しない。
+    unsafe {
+        core::arch::asm!("sti", "hlt", options(nomem, nostack));
+    }
+}
+
+/// タイマーが追加されたことを、tickを止めているBSPに通知する
+///
+/// BSPは次の割り込みまで起床しないため、追加されたタイマーの期限で
+/// ワンショットを設定し直すようIPIで起こす。
+pub fn notify_timer_added() {
+    let vector = KICK_VECTOR.load(Ordering::Acquire);
+    if vector == 0 || cpu::is_bsp() || TICK_MODE.get_for(0).load(Ordering::Acquire) != MODE_IDLE {
+        return;
+    }
+    let _ = ipi::send_to_cpu(0, vector);
+}
+
+/// hrtimerが追加・変更されたことをBSPに通知する
+///
+/// 期限が次のtickより後で、BSPがtickを止めていなければ、次のtickで処理されるため何もしない。
+///
+/// # Arguments
+/// * `deadline_ns` - 追加・変更されたタイマーの期限
+pub(super) fn hrtimer_changed(deadline_ns: u64) {
+    let bsp_mode = TICK_MODE.get_for(0).load(Ordering::Acquire);
+    if bsp_mode != MODE_IDLE && deadline_ns >= timer::next_tick_ns() {
+        return;
+    }
+    if cpu::is_bsp() {
+        without_interrupts(reprogram);
+        return;
+    }
+    let vector = KICK_VECTOR.load(Ordering::Acquire);
+    if vector != 0 {
+        let _ = ipi::send_to_cpu(0, vector);
+    }
+}
+
+/// 通知IPIハンドラ
+///
+/// tickを止めていた場合の再開は割り込み復帰処理で行われる。
+/// それ以外ではhrtimerの期限に合わせてイベントを設定し直す。
+fn kick_ipi_handler(_frame: &InterruptFrame) {
+    if cpu::is_bsp() {
+        reprogram();
+    }
+}
+
+/// 通知IPIのベクタを登録
+pub(super) fn init() {
+    match irq::request_irq(IrqVector::Any, kick_ipi_handler, "nohz-kick") {
+        Ok(vector) => KICK_VECTOR.store(vector, Ordering::Release),
+        Err(e) => crate::warn!("Failed to register nohz kick IPI: {}", e),
+    }
+}
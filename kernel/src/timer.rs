@@ -1,41 +1,44 @@
 //! タイマー管理モジュール
 //!
-//! Linuxの hrtimer/timerfd に似た、タイマーキューとコールバック機構を提供します。
+//! Linuxの timer_list に似た、タイマーキューとコールバック機構を提供します。
+//! タイマーキューは階層型タイマーホイール（`timer_wheel`）で、登録と取り消しはO(1)です。
 //! 割り込みハンドラでは期限切れタイマーの検出とsoftirqフラグのセットのみを行い、
 //! 実際のコールバック実行は割り込み復帰時のsoftirq処理で行うことで
 //! 割り込み無効時間を最小化します（Linux風 Bottom Half）。
 
 use alloc::boxed::Box;
-use alloc::collections::{BinaryHeap, VecDeque};
-use core::cmp::Ordering;
+use alloc::collections::VecDeque;
 use core::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 
+use crate::percpu;
+use crate::timer_wheel::{TimerHandle, TimerWheel};
+
 /// グローバルタイマーカウンタ（tick数）
 static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
 
-/// タイマーIDカウンタ
-static TIMER_ID_COUNTER: AtomicU64 = AtomicU64::new(1);
-
 /// タイマー周波数（Hz）
 static TIMER_FREQUENCY_HZ: AtomicU64 = AtomicU64::new(0);
 
-/// softirq（遅延処理）が保留中かどうかを示すフラグ
-static SOFTIRQ_PENDING: AtomicBool = AtomicBool::new(false);
+/// 最後にtick数を進めた時刻（time::monotonic_ns()基準、0は未記録）
+///
+/// tickless idleでtickを止めていた間の分を`catch_up_ticks()`で補う基準点。
+static LAST_TICK_NS: AtomicU64 = AtomicU64::new(0);
 
-/// softirq処理中かどうかを示すフラグ（再入防止）
-static IN_SOFTIRQ: AtomicBool = AtomicBool::new(false);
+percpu! {
+    /// softirq（遅延処理）が保留中かどうかを示すフラグ
+    static SOFTIRQ_PENDING: AtomicBool = AtomicBool::new(false);
+
+    /// softirq処理中かどうかを示すフラグ（再入防止）
+    static IN_SOFTIRQ: AtomicBool = AtomicBool::new(false);
+}
 
 /// タイマーコールバック型
 pub type TimerCallback = Box<dyn FnOnce() + Send + 'static>;
 
 /// タイマー構造体
 pub struct Timer {
-    /// タイマーID
-    id: u64,
-    /// 期限切れ時刻（tick数）
-    expires_at: u64,
     /// コールバック関数
     callback: Option<TimerCallback>,
 }
@@ -44,44 +47,17 @@ impl Timer {
     /// 新しいタイマーを作成
     ///
     /// # Arguments
-    /// * `delay_ticks` - 現在時刻からの遅延（tick数）
     /// * `callback` - 期限切れ時に実行するコールバック
-    pub fn new(delay_ticks: u64, callback: TimerCallback) -> Self {
-        let id = TIMER_ID_COUNTER.fetch_add(1, AtomicOrdering::SeqCst);
-        let expires_at = current_tick() + delay_ticks;
+    pub fn new(callback: TimerCallback) -> Self {
         Self {
-            id,
-            expires_at,
             callback: Some(callback),
         }
     }
 }
 
-// BinaryHeap用の順序付け（期限が早い方が優先度高い）
-impl Ord for Timer {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // 逆順にして、期限が早い方が先に来るようにする
-        other.expires_at.cmp(&self.expires_at)
-    }
-}
-
-impl PartialOrd for Timer {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Eq for Timer {}
-
-impl PartialEq for Timer {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
-    }
-}
-
-// タイマーキュー（期限でソートされた優先度付きキュー）
+// タイマーキュー（期限をtick単位で管理する階層型タイマーホイール）
 lazy_static! {
-    static ref TIMER_QUEUE: Mutex<BinaryHeap<Timer>> = Mutex::new(BinaryHeap::new());
+    static ref TIMER_QUEUE: Mutex<TimerWheel<Timer>> = Mutex::new(TimerWheel::new());
 }
 
 // ペンディングキュー（割り込みハンドラから期限切れタイマーを受け取る）
@@ -107,9 +83,69 @@ pub fn current_tick() -> u64 {
 /// # Returns
 /// インクリメント後のtick数
 pub fn increment_tick() -> u64 {
+    LAST_TICK_NS.store(crate::time::monotonic_ns(), AtomicOrdering::Relaxed);
     TICK_COUNT.fetch_add(1, AtomicOrdering::SeqCst) + 1
 }
 
+/// 1tickの長さ（ナノ秒、未初期化なら0）
+pub fn tick_period_ns() -> u64 {
+    let frequency = TIMER_FREQUENCY_HZ.load(AtomicOrdering::SeqCst);
+    if frequency == 0 {
+        return 0;
+    }
+    1_000_000_000 / frequency
+}
+
+/// 次のtickの予定時刻（time::monotonic_ns()基準）
+pub fn next_tick_ns() -> u64 {
+    LAST_TICK_NS
+        .load(AtomicOrdering::Relaxed)
+        .saturating_add(tick_period_ns())
+}
+
+/// 前回のtickから経過した時間の分だけtick数を進める
+///
+/// tickless idleでtickを止めていた間に進むはずだったtick数を補う。
+/// BSPから割り込み無効状態で呼び出す。
+///
+/// # Arguments
+/// * `now_ns` - 現在時刻（time::monotonic_ns()）
+///
+/// # Returns
+/// 進めたtick数
+pub fn catch_up_ticks(now_ns: u64) -> u64 {
+    let period = tick_period_ns();
+    let last = LAST_TICK_NS.load(AtomicOrdering::Relaxed);
+    if period == 0 || last == 0 {
+        return 0;
+    }
+    let missed = now_ns.saturating_sub(last) / period;
+    if missed > 0 {
+        LAST_TICK_NS.store(last + missed * period, AtomicOrdering::Relaxed);
+        TICK_COUNT.fetch_add(missed, AtomicOrdering::SeqCst);
+    }
+    missed
+}
+
+/// 次のタイマーが期限切れになるまでの時間（ナノ秒）
+///
+/// 割り込み無効状態で呼び出す。
+///
+/// # Arguments
+/// * `now_ns` - 現在時刻（time::monotonic_ns()）
+///
+/// # Returns
+/// 登録済みのタイマーがない場合はNone、既に期限切れなら0
+pub fn next_expiry_ns(now_ns: u64) -> Option<u64> {
+    // 遠い期限は下限値になるため、その時点で起床して再計算される
+    let expires_at = TIMER_QUEUE.lock().next_expiry()?;
+    let ticks = expires_at.saturating_sub(current_tick());
+    let deadline = LAST_TICK_NS
+        .load(AtomicOrdering::Relaxed)
+        .saturating_add(ticks.saturating_mul(tick_period_ns()));
+    Some(deadline.saturating_sub(now_ns))
+}
+
 /// タイマーをキューに登録
 ///
 /// # Arguments
@@ -117,10 +153,13 @@ pub fn increment_tick() -> u64 {
 /// * `callback` - 期限切れ時に実行するコールバック
 ///
 /// # Returns
-/// タイマーID
+/// タイマーID（`cancel_timer()`で取り消しに使用）
+///
+/// # Note
+/// コールバックはsoftirqで実行されるため、ブロックする処理や重い処理は
+/// `workqueue::queue_work()`でワーカータスクに任せること。
 pub fn register_timer(delay_ticks: u64, callback: TimerCallback) -> u64 {
-    let timer = Timer::new(delay_ticks, callback);
-    let id = timer.id;
+    let timer = Timer::new(callback);
 
     // 割り込みを無効化してからロックを取得（デッドロック回避）
     let flags = unsafe {
@@ -136,9 +175,12 @@ pub fn register_timer(delay_ticks: u64, callback: TimerCallback) -> u64 {
     };
 
     let mut queue = TIMER_QUEUE.lock();
-    queue.push(timer);
+    let id = queue.insert(current_tick() + delay_ticks, timer).as_u64();
     drop(queue);
 
+    // BSPがtickを止めていれば、新しい期限でワンショットを設定し直させる
+    crate::time::nohz::notify_timer_added();
+
     // 割り込みを復元
     unsafe {
         if flags & 0x200 != 0 {
@@ -149,9 +191,28 @@ pub fn register_timer(delay_ticks: u64, callback: TimerCallback) -> u64 {
     id
 }
 
+/// 登録済みのタイマーを取り消す
+///
+/// 期限切れでペンディングキューに移動済みのタイマーは取り消せない。
+///
+/// # Arguments
+/// * `id` - `register_timer()`が返したタイマーID
+///
+/// # Returns
+/// 取り消した場合true、期限切れ済み・取り消し済みならfalse
+pub fn cancel_timer(id: u64) -> bool {
+    crate::io::without_interrupts(|| {
+        TIMER_QUEUE
+            .lock()
+            .cancel(TimerHandle::from_u64(id))
+            .is_some()
+    })
+}
+
 /// 期限切れタイマーを検出してペンディングキューに移動（割り込みハンドラから呼ばれる）
 ///
 /// この関数は割り込みコンテキストで実行されるため、最小限の処理のみを行います。
+/// tickless idleで複数tickを補った後は、その間のtickをまとめて処理します。
 /// 実際のコールバック実行は do_softirq() -> process_pending_timers() で行われます。
 pub fn check_timers() {
     let current = current_tick();
@@ -160,17 +221,10 @@ pub fn check_timers() {
     let mut has_pending = false;
 
     // 期限切れのタイマーをペンディングキューに移動
-    while let Some(timer) = queue.peek() {
-        if timer.expires_at <= current {
-            if let Some(timer) = queue.pop() {
-                pending.push_back(timer);
-                has_pending = true;
-            }
-        } else {
-            // まだ期限切れではない
-            break;
-        }
-    }
+    queue.advance(current, |timer| {
+        pending.push_back(timer);
+        has_pending = true;
+    });
 
     // 期限切れタイマーがあればsoftirqをスケジュール
     if has_pending {
@@ -184,13 +238,13 @@ pub fn check_timers() {
 /// 実際の処理は割り込み復帰時の do_softirq() で行われます。
 #[inline]
 pub fn raise_softirq() {
-    SOFTIRQ_PENDING.store(true, AtomicOrdering::Release);
+    SOFTIRQ_PENDING.get().store(true, AtomicOrdering::Release);
 }
 
 /// softirqが保留中かどうかを確認
 #[inline]
 pub fn softirq_pending() -> bool {
-    SOFTIRQ_PENDING.load(AtomicOrdering::Acquire)
+    SOFTIRQ_PENDING.get().load(AtomicOrdering::Acquire)
 }
 
 /// softirq処理中かどうかを確認
@@ -200,7 +254,7 @@ pub fn softirq_pending() -> bool {
 /// IN_SOFTIRQフラグがクリアされずに残り、全softirq処理が永続的にスキップされる問題を防ぐ。
 #[inline]
 pub fn in_softirq() -> bool {
-    IN_SOFTIRQ.load(AtomicOrdering::Acquire)
+    IN_SOFTIRQ.get().load(AtomicOrdering::Acquire)
 }
 
 /// softirq処理を実行（割り込み復帰時に呼ばれる）
@@ -216,19 +270,20 @@ pub fn do_softirq() {
     // 再入チェック: 既にsoftirq処理中なら何もしない
     // これにより、do_softirq()実行中にタイマー割り込みが発生しても
     // 再度do_softirq()が呼ばれることを防ぐ
-    if IN_SOFTIRQ.swap(true, AtomicOrdering::AcqRel) {
+    if IN_SOFTIRQ.get().swap(true, AtomicOrdering::AcqRel) {
         return;
     }
 
     // softirqフラグをクリアして処理開始
     // 処理中に新しいタイマーが期限切れになった場合、
     // check_timers()がフラグを再セットするのでループで対応
-    while SOFTIRQ_PENDING.swap(false, AtomicOrdering::AcqRel) {
+    while SOFTIRQ_PENDING.get().swap(false, AtomicOrdering::AcqRel) {
         process_pending_timers();
+        crate::time::hrtimer::run_softirq();
     }
 
     // 再入フラグをクリア
-    IN_SOFTIRQ.store(false, AtomicOrdering::Release);
+    IN_SOFTIRQ.get().store(false, AtomicOrdering::Release);
 }
 
 /// ペンディングキューのタイマーを処理（メインループから呼ばれる）
@@ -0,0 +1,535 @@
+//! 階層型タイマーホイール
+//!
+//! tick単位の期限を持つタイマーを、64スロット×4段のホイールで管理します
+//! （旧Linuxの`timer_list`と同じカスケード方式）。
+//!
+//! - 段0: 64tick以内に期限が来るタイマー（1スロット=1tick）
+//! - 段n: 64^(n+1)tick以内に期限が来るタイマー（1スロット=64^n tick）
+//!
+//! 挿入と取り消しはO(1)で、期限切れ処理は1tick毎に段0の1スロットを取り出すだけです。
+//! 段0が一周する毎に上の段の1スロットを下の段に振り分け直します（カスケード）。
+//! 64^4 tickより先の期限は最上段の最も遠いスロットに置き、カスケード時に再配置します。
+//!
+//! タイマーは内部のスラブに格納し、スロット毎の双方向リストをインデックスで連結します。
+//! ハンドルはスラブのインデックスと世代番号からなるため、期限切れ後や再利用後の
+//! 古いハンドルでの取り消しは失敗します。
+
+use alloc::vec::Vec;
+
+/// 1段あたりのスロット数のビット数
+const SLOT_BITS: u32 = 6;
+
+/// 1段あたりのスロット数
+const SLOTS_PER_LEVEL: usize = 1 << SLOT_BITS;
+
+/// スロット番号のマスク
+const SLOT_MASK: u64 = SLOTS_PER_LEVEL as u64 - 1;
+
+/// 段数
+const LEVELS: usize = 4;
+
+/// ホイールで直接表現できる最大の期限までの距離（tick）
+const MAX_DELTA: u64 = (1 << (SLOT_BITS as usize * LEVELS)) - 1;
+
+/// リストの終端
+const NIL: u32 = u32::MAX;
+
+/// タイマーのハンドル（取り消しに使用）
+///
+/// 下位32ビットがスラブのインデックス、上位32ビットが世代番号。世代は1から始まるため0にはならない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerHandle(u64);
+
+impl TimerHandle {
+    /// ハンドルの数値表現
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// 数値表現からハンドルを復元
+    pub fn from_u64(value: u64) -> Self {
+        Self(value)
+    }
+
+    fn new(index: u32, generation: u32) -> Self {
+        Self(((generation as u64) << 32) | index as u64)
+    }
+
+    fn index(self) -> u32 {
+        self.0 as u32
+    }
+
+    fn generation(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+}
+
+/// スラブの1要素
+struct Entry<T> {
+    /// 世代番号（解放する度に進める）
+    generation: u32,
+    /// 期限（tick）
+    expires: u64,
+    /// タイマーの値（Noneなら空き）
+    value: Option<T>,
+    /// 所属するスロット（段×64+スロット番号）
+    bucket: u16,
+    prev: u32,
+    next: u32,
+}
+
+/// 階層型タイマーホイール
+pub struct TimerWheel<T> {
+    /// タイマーの格納領域
+    entries: Vec<Entry<T>>,
+    /// 空きインデックス
+    free: Vec<u32>,
+    /// スロット毎のリストの先頭
+    heads: [u32; SLOTS_PER_LEVEL * LEVELS],
+    /// 段毎の空でないスロットのビットマップ
+    occupied: [u64; LEVELS],
+    /// 次に処理するtick
+    current: u64,
+    /// 登録中のタイマー数
+    len: usize,
+}
+
+impl<T> Default for TimerWheel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TimerWheel<T> {
+    /// 空のホイールを作成
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            free: Vec::new(),
+            heads: [NIL; SLOTS_PER_LEVEL * LEVELS],
+            occupied: [0; LEVELS],
+            current: 0,
+            len: 0,
+        }
+    }
+
+    /// 登録中のタイマー数
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// タイマーが登録されていないかどうか
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 次に処理するtick
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    /// タイマーを登録
+    ///
+    /// # Arguments
+    /// * `expires` - 期限（tick）。処理済みのtickなら次の`advance()`で期限切れになる
+    /// * `value` - 期限切れ時に返す値
+    pub fn insert(&mut self, expires: u64, value: T) -> TimerHandle {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.entries.push(Entry {
+                    generation: 1,
+                    expires: 0,
+                    value: None,
+                    bucket: 0,
+                    prev: NIL,
+                    next: NIL,
+                });
+                (self.entries.len() - 1) as u32
+            }
+        };
+        let entry = &mut self.entries[index as usize];
+        entry.expires = expires;
+        entry.value = Some(value);
+        let handle = TimerHandle::new(index, entry.generation);
+        self.link(index);
+        self.len += 1;
+        handle
+    }
+
+    /// タイマーを取り消す
+    ///
+    /// # Returns
+    /// 登録中だった場合はその値、期限切れ済み・取り消し済みならNone
+    pub fn cancel(&mut self, handle: TimerHandle) -> Option<T> {
+        let entry = self.entries.get(handle.index() as usize)?;
+        if entry.generation != handle.generation() || entry.value.is_none() {
+            return None;
+        }
+        self.unlink(handle.index());
+        Some(self.release(handle.index()))
+    }
+
+    /// 指定tickまでを処理し、期限切れのタイマーの値を期限順に渡す
+    ///
+    /// 同じtickに期限が来るタイマーの順序は不定。
+    ///
+    /// # Arguments
+    /// * `now` - 処理するtick（これ以前に期限が来るタイマーがすべて期限切れになる）
+    /// * `expire` - 期限切れのタイマーの値を受け取る関数
+    pub fn advance(&mut self, now: u64, mut expire: impl FnMut(T)) {
+        while self.current <= now {
+            if self.len == 0 {
+                self.current = now + 1;
+                break;
+            }
+            let slot = (self.current & SLOT_MASK) as usize;
+            if slot == 0 {
+                self.cascade();
+            }
+            while self.heads[slot] != NIL {
+                let index = self.heads[slot];
+                self.unlink(index);
+                expire(self.release(index));
+            }
+            self.current += 1;
+        }
+    }
+
+    /// 次に期限切れになるタイマーのtickの下限
+    ///
+    /// 段0のタイマーは正確な期限、上の段のタイマーはスロットの先頭のtickを返す。
+    /// 戻り値のtickで`advance()`しても期限切れにならない場合がある。
+    pub fn next_expiry(&self) -> Option<u64> {
+        if self.len == 0 {
+            return None;
+        }
+        let mut earliest = None;
+        for level in 0..LEVELS {
+            let shift = SLOT_BITS * level as u32;
+            let base = self.current >> shift;
+            // 現在のスロットは、カスケード前（下の段が一周した直後）ならまだ処理対象で、
+            // カスケード後なら一周後の期限のタイマーだけが入っている
+            let pending = self.current & ((1 << shift) - 1) == 0;
+            let start = if pending { base } else { base + 1 };
+            let Some(offset) = first_set_from(self.occupied[level], (start & SLOT_MASK) as u32)
+            else {
+                continue;
+            };
+            let tick = (start + offset as u64) << shift;
+            let tick = tick.max(self.current);
+            earliest = Some(earliest.map_or(tick, |e: u64| e.min(tick)));
+        }
+        earliest
+    }
+
+    /// 段0が一周したときに上の段の該当スロットを下の段に振り分け直す
+    fn cascade(&mut self) {
+        for level in 1..LEVELS {
+            let slot = (self.current >> (SLOT_BITS * level as u32)) & SLOT_MASK;
+            let bucket = level * SLOTS_PER_LEVEL + slot as usize;
+            // リストを切り離してから再配置する（同じスロットに戻っても無限ループしない）
+            let mut index = core::mem::replace(&mut self.heads[bucket], NIL);
+            self.occupied[level] &= !(1 << slot);
+            while index != NIL {
+                let next = self.entries[index as usize].next;
+                self.link(index);
+                index = next;
+            }
+            // この段も一周していなければ上の段は処理不要
+            if slot != 0 {
+                break;
+            }
+        }
+    }
+
+    /// 期限に応じたスロットにタイマーを連結
+    fn link(&mut self, index: u32) {
+        let expires = self.entries[index as usize].expires;
+        let bucket = bucket_for(self.current, expires);
+        let head = self.heads[bucket];
+        {
+            let entry = &mut self.entries[index as usize];
+            entry.bucket = bucket as u16;
+            entry.prev = NIL;
+            entry.next = head;
+        }
+        if head != NIL {
+            self.entries[head as usize].prev = index;
+        }
+        self.heads[bucket] = index;
+        self.occupied[bucket / SLOTS_PER_LEVEL] |= 1 << (bucket % SLOTS_PER_LEVEL);
+    }
+
+    /// タイマーをスロットのリストから外す
+    fn unlink(&mut self, index: u32) {
+        let (bucket, prev, next) = {
+            let entry = &self.entries[index as usize];
+            (entry.bucket as usize, entry.prev, entry.next)
+        };
+        if prev == NIL {
+            self.heads[bucket] = next;
+        } else {
+            self.entries[prev as usize].next = next;
+        }
+        if next != NIL {
+            self.entries[next as usize].prev = prev;
+        }
+        if self.heads[bucket] == NIL {
+            self.occupied[bucket / SLOTS_PER_LEVEL] &= !(1 << (bucket % SLOTS_PER_LEVEL));
+        }
+    }
+
+    /// リストから外したタイマーを解放して値を取り出す
+    fn release(&mut self, index: u32) -> T {
+        let entry = &mut self.entries[index as usize];
+        entry.generation = entry.generation.wrapping_add(1).max(1);
+        let value = entry.value.take();
+        self.free.push(index);
+        self.len -= 1;
+        // link()されているエントリは必ず値を持つ
+        value.expect("timer wheel entry without value")
+    }
+}
+
+/// 期限に対応するスロット（段×64+スロット番号）
+///
+/// # Arguments
+/// * `current` - 次に処理するtick
+/// * `expires` - 期限（tick）
+fn bucket_for(current: u64, expires: u64) -> usize {
+    if expires < current {
+        // 処理済みのtickなら次に処理するスロットに置く
+        return (current & SLOT_MASK) as usize;
+    }
+    let delta = expires - current;
+    let expires = if delta > MAX_DELTA {
+        current + MAX_DELTA
+    } else {
+        expires
+    };
+    let delta = expires - current;
+    let mut level = 0;
+    while level < LEVELS - 1 && delta >> (SLOT_BITS * (level as u32 + 1)) != 0 {
+        level += 1;
+    }
+    let slot = (expires >> (SLOT_BITS * level as u32)) & SLOT_MASK;
+    level * SLOTS_PER_LEVEL + slot as usize
+}
+
+/// ビットマップの`start`番目から循環して最初に立っているビットまでの距離
+fn first_set_from(bitmap: u64, start: u32) -> Option<u32> {
+    if bitmap == 0 {
+        return None;
+    }
+    Some(bitmap.rotate_right(start).trailing_zeros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::collections::{BTreeSet, BinaryHeap, VecDeque};
+
+    /// 1tickずつ進めて、各タイマーが期限ちょうどで期限切れになることを確認
+    #[test_case]
+    fn test_timer_wheel_expires_on_exact_tick() {
+        let mut wheel = TimerWheel::new();
+        let deadlines = [0u64, 1, 63, 64, 65, 200, 4095, 4096, 5000, 300_000];
+        for &expires in &deadlines {
+            wheel.insert(expires, expires);
+        }
+        let mut fired = Vec::new();
+        for tick in 0..=300_000 {
+            wheel.advance(tick, |expires| fired.push((tick, expires)));
+        }
+        assert_eq!(fired.len(), deadlines.len());
+        for (tick, expires) in fired {
+            assert_eq!(tick, expires);
+        }
+        assert!(wheel.is_empty());
+    }
+
+    #[test_case]
+    fn test_timer_wheel_cancel_and_stale_handle() {
+        let mut wheel = TimerWheel::new();
+        let a = wheel.insert(10, 'a');
+        let b = wheel.insert(5000, 'b');
+        assert_eq!(wheel.cancel(b), Some('b'));
+        assert_eq!(wheel.cancel(b), None);
+
+        let mut fired = Vec::new();
+        wheel.advance(10, |v| fired.push(v));
+        assert_eq!(fired, ['a']);
+        assert_eq!(wheel.cancel(a), None);
+
+        // 再利用されたスロットに古いハンドルは効かない
+        let c = wheel.insert(20, 'c');
+        assert_eq!(c.index(), a.index());
+        assert_eq!(wheel.cancel(a), None);
+        assert_eq!(wheel.cancel(c), Some('c'));
+    }
+
+    #[test_case]
+    fn test_timer_wheel_next_expiry_is_lower_bound() {
+        let mut wheel = TimerWheel::new();
+        assert_eq!(wheel.next_expiry(), None);
+        wheel.advance(100, |_: u64| {});
+        wheel.insert(5000, 5000);
+        let bound = wheel.next_expiry().unwrap();
+        assert!(bound > 100 && bound <= 5000);
+        wheel.insert(130, 130);
+        assert_eq!(wheel.next_expiry(), Some(130));
+        // 処理済みのtickに登録したタイマーは即座に期限切れ扱い
+        wheel.insert(50, 50);
+        assert_eq!(wheel.next_expiry(), Some(101));
+    }
+
+    /// 従来のタイマーキュー（期限順の二分ヒープ）の要素
+    ///
+    /// 置き換え前の`timer::Timer`と同じく、期限が早いほど優先度が高い。
+    struct HeapTimer {
+        id: u64,
+        expires_at: u64,
+        timer: crate::timer::Timer,
+    }
+
+    impl Ord for HeapTimer {
+        fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+            other.expires_at.cmp(&self.expires_at)
+        }
+    }
+
+    impl PartialOrd for HeapTimer {
+        fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Eq for HeapTimer {}
+
+    impl PartialEq for HeapTimer {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    const BENCH_TIMERS: u64 = 4096;
+    const BENCH_TICKS: u64 = 10_000;
+
+    fn bench_deadline(i: u64) -> u64 {
+        (i * 7919) % BENCH_TICKS + 1
+    }
+
+    fn bench_timer() -> crate::timer::Timer {
+        crate::timer::Timer::new(Box::new(|| {}))
+    }
+
+    /// 処理を実行し、結果と経過時間（TSCサイクル数）を返す
+    ///
+    /// テストカーネルはTSCをキャリブレーションしないため、ナノ秒には変換しない。
+    fn measure<R>(f: impl FnOnce() -> R) -> (R, u64) {
+        let start = crate::tsc::rdtsc();
+        let result = f();
+        let cycles = crate::tsc::rdtsc() - start;
+        assert!(cycles > 0, "TSC did not advance during the benchmark");
+        (result, cycles)
+    }
+
+    /// 従来の`check_timers`と同じく、期限切れのタイマーを
+    /// tick毎にペンディングキューへ移す
+    fn drain_heap(
+        heap: &mut BinaryHeap<HeapTimer>,
+        cancelled: &BTreeSet<u64>,
+        pending: &mut VecDeque<crate::timer::Timer>,
+    ) {
+        for tick in 0..=BENCH_TICKS {
+            while heap.peek().is_some_and(|t| t.expires_at <= tick) {
+                if let Some(t) = heap.pop().filter(|t| !cancelled.contains(&t.id)) {
+                    pending.push_back(t.timer);
+                }
+            }
+        }
+    }
+
+    fn drain_wheel(
+        wheel: &mut TimerWheel<crate::timer::Timer>,
+        pending: &mut VecDeque<crate::timer::Timer>,
+    ) {
+        for tick in 0..=BENCH_TICKS {
+            wheel.advance(tick, |timer| pending.push_back(timer));
+        }
+    }
+
+    /// 従来の二分ヒープとの比較（所要TSCサイクル数をシリアルに出力）
+    ///
+    /// 置き換え前と同じ登録・期限切れ処理（取り消しなし）と、半数を取り消す場合を測る。
+    /// ヒープの取り消しは、IDを墓標集合に入れて取り出し時に捨てる遅延削除で行う。
+    #[test_case]
+    fn bench_timer_wheel_vs_binary_heap() {
+        let (heap_fired, heap_cycles) = measure(|| {
+            let mut heap = BinaryHeap::new();
+            let mut pending = VecDeque::new();
+            for i in 0..BENCH_TIMERS {
+                heap.push(HeapTimer {
+                    id: i,
+                    expires_at: bench_deadline(i),
+                    timer: bench_timer(),
+                });
+            }
+            drain_heap(&mut heap, &BTreeSet::new(), &mut pending);
+            pending.len()
+        });
+        let (wheel_fired, wheel_cycles) = measure(|| {
+            let mut wheel = TimerWheel::new();
+            let mut pending = VecDeque::new();
+            for i in 0..BENCH_TIMERS {
+                wheel.insert(bench_deadline(i), bench_timer());
+            }
+            drain_wheel(&mut wheel, &mut pending);
+            pending.len()
+        });
+        assert_eq!(heap_fired, BENCH_TIMERS as usize);
+        assert_eq!(wheel_fired, BENCH_TIMERS as usize);
+
+        let (heap_cancel_fired, heap_cancel_cycles) = measure(|| {
+            let mut heap = BinaryHeap::new();
+            let mut cancelled = BTreeSet::new();
+            let mut pending = VecDeque::new();
+            for i in 0..BENCH_TIMERS {
+                heap.push(HeapTimer {
+                    id: i,
+                    expires_at: bench_deadline(i),
+                    timer: bench_timer(),
+                });
+            }
+            for i in (0..BENCH_TIMERS).step_by(2) {
+                cancelled.insert(i);
+            }
+            drain_heap(&mut heap, &cancelled, &mut pending);
+            pending.len()
+        });
+        let (wheel_cancel_fired, wheel_cancel_cycles) = measure(|| {
+            let mut wheel = TimerWheel::new();
+            let mut pending = VecDeque::new();
+            let handles: Vec<_> = (0..BENCH_TIMERS)
+                .map(|i| wheel.insert(bench_deadline(i), bench_timer()))
+                .collect();
+            for handle in handles.iter().step_by(2) {
+                wheel.cancel(*handle);
+            }
+            drain_wheel(&mut wheel, &mut pending);
+            pending.len()
+        });
+        assert_eq!(heap_cancel_fired, BENCH_TIMERS as usize / 2);
+        assert_eq!(wheel_cancel_fired, BENCH_TIMERS as usize / 2);
+
+        crate::serial_print!(
+            "(heap: {} cycles, wheel: {} cycles; with cancel: heap {} cycles, wheel {} cycles) ",
+            heap_cycles,
+            wheel_cycles,
+            heap_cancel_cycles,
+            wheel_cancel_cycles
+        );
+    }
+}
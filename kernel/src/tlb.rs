@@ -0,0 +1,315 @@
+//! TLB shootdown
+//!
+//! ページテーブルの変更（マッピング解除・属性変更）後に、全CPUのTLBから
+//! 古いエントリを無効化します。
+//!
+//! # プロトコル
+//! 1. 変更した仮想アドレス範囲を`TlbFlushBatch`に集める
+//! 2. `flush()`で自CPUのTLBを無効化し、他のオンラインCPUのpendingフラグをセットしてIPIを送信
+//! 3. 各CPUはIPIハンドラで範囲を`invlpg`（閾値を超える場合はCR3リロード）で無効化し、応答する
+//! 4. 要求元は全CPUの応答を待ってから戻る
+//!
+//! カーネルのマッピングは全CPUで同じページテーブルを共有しているため、
+//! 自分以外の全オンラインCPUが対象となる。
+//!
+//! # デッドロック回避
+//! ページテーブル操作は割り込み無効状態で行われるため、応答待ちも割り込み無効のまま行う。
+//! 2つのCPUが同時にshootdownを開始すると互いのIPIを受け取れずデッドロックするため、
+//! shootdownは`SHOOTDOWN_LOCK`で直列化し、ロック待ちの間も自CPU宛ての要求を処理する。
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::cpu::{self, MAX_CPUS};
+use crate::io::without_interrupts;
+use crate::irq::{self, InterruptFrame, IrqVector};
+use crate::{ipi, paging, percpu};
+
+/// invlpgで個別に無効化するページ数の上限（超えた場合はTLB全体をフラッシュ）
+pub const FULL_FLUSH_THRESHOLD: usize = 32;
+
+/// 1バッチに保持できる範囲数（超えた場合はTLB全体をフラッシュ）
+const MAX_BATCH_RANGES: usize = 8;
+
+/// 他CPUの応答を待つ最大スピン回数
+const ACK_WAIT_SPINS: usize = 100_000_000;
+
+/// shootdown IPIのベクタ（0は未登録）
+static SHOOTDOWN_VECTOR: AtomicU8 = AtomicU8::new(0);
+
+/// shootdownを直列化するロック
+static SHOOTDOWN_LOCK: Mutex<()> = Mutex::new(());
+
+/// 処理中のshootdown要求（SHOOTDOWN_LOCKの保持者のみが書き込む）
+static CURRENT_REQUEST: Mutex<TlbFlushBatch> = Mutex::new(TlbFlushBatch::new());
+
+/// 応答待ちのCPU数
+static PENDING_ACKS: AtomicUsize = AtomicUsize::new(0);
+
+percpu! {
+    /// このCPU宛てのshootdown要求があるかどうか
+    static SHOOTDOWN_PENDING: AtomicBool = AtomicBool::new(false);
+}
+
+/// 無効化する仮想アドレス範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlbRange {
+    /// 開始仮想アドレス（ページ境界）
+    pub start: u64,
+    /// ページ数
+    pub pages: usize,
+    /// ページサイズ（4KB / 2MB / 1GB）
+    pub page_size: u64,
+}
+
+/// TLB無効化対象の範囲を集めるバッチ
+///
+/// ページテーブルを変更するたびに範囲を追加し、最後に`flush()`で
+/// 全CPUのTLBをまとめて無効化する。
+#[derive(Debug, Clone, Copy)]
+pub struct TlbFlushBatch {
+    ranges: [TlbRange; MAX_BATCH_RANGES],
+    len: usize,
+    /// TLB全体のフラッシュが必要かどうか
+    full: bool,
+}
+
+impl Default for TlbFlushBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TlbFlushBatch {
+    /// 空のバッチを作成
+    pub const fn new() -> Self {
+        Self {
+            ranges: [TlbRange {
+                start: 0,
+                pages: 0,
+                page_size: 0,
+            }; MAX_BATCH_RANGES],
+            len: 0,
+            full: false,
+        }
+    }
+
+    /// 無効化する範囲を追加
+    ///
+    /// # Arguments
+    /// * `start` - 開始仮想アドレス（ページ境界に切り下げられる）
+    /// * `size` - サイズ（バイト単位、ページ境界に切り上げられる）
+    /// * `page_size` - 範囲をマッピングしているページのサイズ（2の累乗）
+    pub fn add(&mut self, start: u64, size: u64, page_size: u64) {
+        debug_assert!(page_size.is_power_of_two());
+        let mask = page_size - 1;
+        let aligned_start = start & !mask;
+        let end = start.saturating_add(size).saturating_add(mask) & !mask;
+        let pages = ((end - aligned_start) / page_size) as usize;
+        if pages == 0 {
+            return;
+        }
+        if self.len == MAX_BATCH_RANGES {
+            self.full = true;
+            return;
+        }
+        self.ranges[self.len] = TlbRange {
+            start: aligned_start,
+            pages,
+            page_size,
+        };
+        self.len += 1;
+    }
+
+    /// TLB全体のフラッシュを要求
+    pub fn add_all(&mut self) {
+        self.full = true;
+    }
+
+    /// 無効化対象がないかどうか
+    pub fn is_empty(&self) -> bool {
+        self.len == 0 && !self.full
+    }
+
+    /// 無効化対象のページ数（invlpgの実行回数）
+    pub fn page_count(&self) -> usize {
+        self.ranges[..self.len].iter().map(|r| r.pages).sum()
+    }
+
+    /// 個別のinvlpgではなくTLB全体をフラッシュすべきかどうか
+    pub fn needs_full_flush(&self) -> bool {
+        self.full || self.page_count() > FULL_FLUSH_THRESHOLD
+    }
+
+    /// 現在のCPUのTLBからバッチの範囲を無効化
+    fn flush_local(&self) {
+        if self.needs_full_flush() {
+            paging::reload_cr3();
+            return;
+        }
+        for range in &self.ranges[..self.len] {
+            for i in 0..range.pages as u64 {
+                invlpg(range.start + i * range.page_size);
+            }
+        }
+    }
+
+    /// 全CPUのTLBからバッチの範囲を無効化
+    ///
+    /// 自CPUのTLBを無効化した後、他のオンラインCPUへshootdown IPIを送信し、
+    /// 全CPUが無効化を完了するまで待機する。
+    pub fn flush(self) {
+        if self.is_empty() {
+            return;
+        }
+        without_interrupts(|| {
+            self.flush_local();
+            shootdown(&self);
+        });
+    }
+}
+
+/// 単一範囲を全CPUのTLBから無効化
+///
+/// # Arguments
+/// * `start` - 開始仮想アドレス
+/// * `size` - サイズ（バイト単位）
+/// * `page_size` - 範囲をマッピングしているページのサイズ
+pub fn flush_range(start: u64, size: u64, page_size: u64) {
+    let mut batch = TlbFlushBatch::new();
+    batch.add(start, size, page_size);
+    batch.flush();
+}
+
+/// 指定した仮想アドレスを含むページのTLBエントリを現在のCPUで無効化
+#[inline]
+pub fn invlpg(addr: u64) {
+    // SAFETY: INVLPGはTLBとページング構造キャッシュのエントリを無効化するだけで、
+    // メモリ内容には影響しない。Ring 0で実行される。
+    unsafe {
+        asm!("invlpg [{}]", in(reg) addr, options(nostack, preserves_flags));
+    }
+}
+
+/// このCPU宛てのshootdown要求があれば処理して応答する
+fn process_pending() {
+    if !SHOOTDOWN_PENDING.get().swap(false, Ordering::AcqRel) {
+        return;
+    }
+    let batch = *CURRENT_REQUEST.lock();
+    batch.flush_local();
+    PENDING_ACKS.fetch_sub(1, Ordering::AcqRel);
+}
+
+/// 他のオンラインCPUへshootdown要求を送り、応答を待つ
+///
+/// 割り込み無効状態で呼び出すこと。
+fn shootdown(batch: &TlbFlushBatch) {
+    let vector = SHOOTDOWN_VECTOR.load(Ordering::Acquire);
+    if vector == 0 || cpu::online_cpus() <= 1 {
+        return;
+    }
+
+    // 他CPUのshootdownを待つ間も、自CPU宛ての要求には応答する
+    let _guard = loop {
+        if let Some(guard) = SHOOTDOWN_LOCK.try_lock() {
+            break guard;
+        }
+        process_pending();
+        core::hint::spin_loop();
+    };
+
+    let this_cpu = cpu::current_cpu();
+    let mut targets = [false; MAX_CPUS];
+    let mut count = 0;
+    for (cpu, target) in targets.iter_mut().enumerate().take(cpu::cpu_count()) {
+        if cpu != this_cpu && cpu::is_online(cpu) {
+            *target = true;
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return;
+    }
+
+    *CURRENT_REQUEST.lock() = *batch;
+    PENDING_ACKS.store(count, Ordering::Release);
+
+    for (cpu, _) in targets.iter().enumerate().filter(|(_, t)| **t) {
+        SHOOTDOWN_PENDING
+            .get_for(cpu)
+            .store(true, Ordering::Release);
+        // 送信できなかった場合（直前にオフラインになった等）は応答待ちから外す
+        if ipi::send_to_cpu(cpu, vector).is_err()
+            && SHOOTDOWN_PENDING.get_for(cpu).swap(false, Ordering::AcqRel)
+        {
+            PENDING_ACKS.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+
+    for _ in 0..ACK_WAIT_SPINS {
+        if PENDING_ACKS.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        core::hint::spin_loop();
+    }
+    crate::warn!(
+        "TLB shootdown timed out ({} CPUs did not respond)",
+        PENDING_ACKS.load(Ordering::Acquire)
+    );
+}
+
+/// shootdown IPIハンドラ
+fn shootdown_ipi_handler(_frame: &InterruptFrame) {
+    process_pending();
+}
+
+/// shootdown IPIのベクタを登録
+///
+/// APを起動する前に呼び出すこと。
+pub fn init() {
+    match irq::request_irq(IrqVector::Any, shootdown_ipi_handler, "tlb-shootdown") {
+        Ok(vector) => SHOOTDOWN_VECTOR.store(vector, Ordering::Release),
+        Err(e) => crate::warn!("Failed to register TLB shootdown IPI: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_batch_rounds_to_page_boundaries() {
+        let mut batch = TlbFlushBatch::new();
+        assert!(batch.is_empty());
+        // 0x1800から0x2000バイト → 0x1000..0x4000の3ページ
+        batch.add(0x1800, 0x2000, 0x1000);
+        assert_eq!(batch.page_count(), 3);
+        assert_eq!(batch.ranges[0].start, 0x1000);
+        // サイズ0の範囲は無視する
+        batch.add(0x5000, 0, 0x1000);
+        assert_eq!(batch.page_count(), 3);
+        // 2MBページの範囲は1ページとして数える
+        batch.add(0x20_0000, 0x20_0000, 0x20_0000);
+        assert_eq!(batch.page_count(), 4);
+        assert!(!batch.needs_full_flush());
+    }
+
+    #[test_case]
+    fn test_batch_switches_to_full_flush() {
+        let mut batch = TlbFlushBatch::new();
+        batch.add(0, (FULL_FLUSH_THRESHOLD * 0x1000) as u64, 0x1000);
+        assert!(!batch.needs_full_flush());
+        batch.add(0x10_0000, 0x1000, 0x1000);
+        assert!(batch.needs_full_flush());
+
+        // 範囲数の上限を超えた場合も全体フラッシュに切り替える
+        let mut batch = TlbFlushBatch::new();
+        for i in 0..=MAX_BATCH_RANGES as u64 {
+            batch.add(i * 0x10_0000, 0x1000, 0x1000);
+        }
+        assert_eq!(batch.page_count(), MAX_BATCH_RANGES);
+        assert!(batch.needs_full_flush());
+    }
+}
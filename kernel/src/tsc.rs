@@ -0,0 +1,292 @@
+//! TSC (Time Stamp Counter) クロックソース
+//!
+//! RDTSC命令で読み取れるTSCをHPETまたはPITでキャリブレーションし、
+//! MMIOアクセスを伴わない最も高速なクロックソースとして`time`モジュールに提供します。
+//!
+//! # Invariant TSC
+//! CPUID.80000007H:EDX\[bit 8\]が立っている場合、TSCはCPUの周波数変更やC-stateに
+//! 関わらず一定の速度で進む。この場合のみTSCをクロックソースとして登録する。
+//! 全CPUのTSCはリセット時に同期されている前提で、CPU間での比較も可能とする。
+//!
+//! # 変換
+//! サイクル数からナノ秒への変換は除算を避けるため、キャリブレーション時に計算した
+//! 倍率（2^32を1とする固定小数点）との乗算とシフトで行う。
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::hpet;
+use crate::pit;
+use crate::time::ClockSource;
+use crate::timer_device::{ElapsedTimer, TimerDevice};
+
+/// 1秒あたりのナノ秒数
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// ナノ秒変換倍率の固定小数点シフト量
+const NS_SHIFT: u32 = 32;
+
+/// CPUID.01H:EDX のTSCサポートビット
+const CPUID_EDX_TSC: u32 = 1 << 4;
+
+/// CPUID.01H:ECX のTSC-deadlineサポートビット
+const CPUID_ECX_TSC_DEADLINE: u32 = 1 << 24;
+
+/// CPUID.80000007H:EDX のInvariant TSCビット
+const CPUID_EDX_INVARIANT_TSC: u32 = 1 << 8;
+
+/// キャリブレーションされたTSC周波数（Hz、0は未キャリブレーション）
+static TSC_FREQUENCY_HZ: AtomicU64 = AtomicU64::new(0);
+
+/// サイクル数→ナノ秒の変換倍率（2^NS_SHIFT固定小数点）
+static TSC_NS_MULT: AtomicU64 = AtomicU64::new(0);
+
+/// キャリブレーション完了時のTSC値（elapsed_ns()の基準）
+static TSC_BASE: AtomicU64 = AtomicU64::new(0);
+
+/// TSCをクロックソースとして使用できるかどうか（Invariantかつキャリブレーション済み）
+static TSC_STABLE: AtomicBool = AtomicBool::new(false);
+
+/// TSC操作のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TscError {
+    /// CPUがTSCをサポートしていない
+    NotSupported,
+    /// キャリブレーションに失敗
+    CalibrationFailed,
+}
+
+impl core::fmt::Display for TscError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            TscError::NotSupported => write!(f, "TSC is not supported"),
+            TscError::CalibrationFailed => write!(f, "TSC calibration failed"),
+        }
+    }
+}
+
+/// CPUIDを実行し、(EAX, ECX, EDX)を返す
+fn cpuid(leaf: u32) -> (u32, u32, u32) {
+    let (eax, ecx, edx): (u32, u32, u32);
+    // SAFETY: CPUID命令はx86_64で常に利用可能で、副作用はない。
+    // RBXはLLVMが予約しているため退避・復元する。
+    unsafe {
+        asm!(
+            "push rbx",
+            "cpuid",
+            "pop rbx",
+            inout("eax") leaf => eax,
+            inout("ecx") 0u32 => ecx,
+            lateout("edx") edx,
+            options(nomem),
+        );
+    }
+    (eax, ecx, edx)
+}
+
+/// TSCの現在値を読み取る
+#[inline]
+pub fn rdtsc() -> u64 {
+    let (lo, hi): (u32, u32);
+    // SAFETY: RDTSCはTSCを読み取るだけで、メモリ安全性に影響しない。
+    // CR4.TSDに関わらずRing 0では常に実行できる。
+    unsafe {
+        asm!("rdtsc", out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags));
+    }
+    ((hi as u64) << 32) | lo as u64
+}
+
+/// CPUがTSCをサポートしているかどうか
+pub fn is_supported() -> bool {
+    cpuid(1).2 & CPUID_EDX_TSC != 0
+}
+
+/// TSCがInvariant（周波数変更やC-stateの影響を受けない）かどうか
+pub fn is_invariant() -> bool {
+    let (max_extended, _, _) = cpuid(0x8000_0000);
+    max_extended >= 0x8000_0007 && cpuid(0x8000_0007).2 & CPUID_EDX_INVARIANT_TSC != 0
+}
+
+/// Local APICタイマーのTSC-deadlineモードをサポートしているかどうか
+pub fn supports_deadline() -> bool {
+    cpuid(1).1 & CPUID_ECX_TSC_DEADLINE != 0
+}
+
+/// 周波数からサイクル数→ナノ秒の変換倍率を計算
+fn ns_mult(freq_hz: u64) -> u64 {
+    (((NS_PER_SEC as u128) << NS_SHIFT) / freq_hz as u128) as u64
+}
+
+/// 変換倍率を使ってサイクル数をナノ秒に変換
+fn cycles_to_ns_with(cycles: u64, mult: u64) -> u64 {
+    ((cycles as u128 * mult as u128) >> NS_SHIFT) as u64
+}
+
+/// ナノ秒を指定周波数のサイクル数に変換
+fn ns_to_cycles_with(ns: u64, freq_hz: u64) -> u64 {
+    (ns as u128 * freq_hz as u128 / NS_PER_SEC as u128) as u64
+}
+
+/// 指定したタイマーデバイスで待機する間に進んだTSCサイクル数を測定
+fn measure_tsc_cycles<T: TimerDevice>(timer: &T, ms: u64) -> u64 {
+    let start = rdtsc();
+    timer.delay_ms(ms);
+    rdtsc().wrapping_sub(start)
+}
+
+/// TSCをキャリブレーション
+///
+/// HPET（利用可能な場合）またはPITを使ってTSCの周波数を測定します。
+/// HPETは高精度なので1回測定、PITは5回測定して中央値を採用します。
+/// この関数は割り込みが無効な状態で呼び出す必要があります。
+///
+/// # Returns
+/// TSC周波数（Hz）
+///
+/// # Errors
+/// * `TscError::NotSupported` - CPUがTSCをサポートしていない場合
+/// * `TscError::CalibrationFailed` - 測定結果が0の場合
+pub fn calibrate() -> Result<u64, TscError> {
+    const CALIBRATION_MS: u64 = 50;
+
+    if !is_supported() {
+        return Err(TscError::NotSupported);
+    }
+
+    let freq_hz = if hpet::HPET.is_available() {
+        let cycles = measure_tsc_cycles(&hpet::HPET, CALIBRATION_MS);
+        cycles * (1000 / CALIBRATION_MS)
+    } else {
+        const MEASUREMENTS: usize = 5;
+        let mut measurements = [0u64; MEASUREMENTS];
+        for measurement in measurements.iter_mut() {
+            *measurement = measure_tsc_cycles(&pit::PIT, CALIBRATION_MS);
+        }
+        // ソートして中央値を取る（外れ値の影響を排除）
+        measurements.sort_unstable();
+        measurements[MEASUREMENTS / 2] * (1000 / CALIBRATION_MS)
+    };
+
+    if freq_hz == 0 {
+        return Err(TscError::CalibrationFailed);
+    }
+
+    TSC_NS_MULT.store(ns_mult(freq_hz), Ordering::Relaxed);
+    TSC_BASE.store(rdtsc(), Ordering::Relaxed);
+    TSC_FREQUENCY_HZ.store(freq_hz, Ordering::Release);
+
+    let invariant = is_invariant();
+    TSC_STABLE.store(invariant, Ordering::Release);
+
+    crate::info!(
+        "TSC calibrated ({}): {} kHz, invariant={}, deadline={}",
+        if hpet::HPET.is_available() {
+            "HPET"
+        } else {
+            "PIT"
+        },
+        freq_hz / 1000,
+        invariant,
+        supports_deadline()
+    );
+    if !invariant {
+        crate::warn!("TSC is not invariant, not using it as clocksource");
+    }
+
+    Ok(freq_hz)
+}
+
+/// キャリブレーションされたTSC周波数（Hz、未キャリブレーションなら0）
+pub fn frequency() -> u64 {
+    TSC_FREQUENCY_HZ.load(Ordering::Acquire)
+}
+
+/// TSCをクロックソースとして使用しているかどうか
+pub fn is_stable() -> bool {
+    TSC_STABLE.load(Ordering::Acquire)
+}
+
+/// TSCサイクル数をナノ秒に変換（未キャリブレーションなら0）
+#[inline]
+pub fn cycles_to_ns(cycles: u64) -> u64 {
+    cycles_to_ns_with(cycles, TSC_NS_MULT.load(Ordering::Relaxed))
+}
+
+/// ナノ秒をTSCサイクル数に変換（未キャリブレーションなら0）
+pub fn ns_to_cycles(ns: u64) -> u64 {
+    ns_to_cycles_with(ns, frequency())
+}
+
+/// 現在から指定ナノ秒後のTSC値（TSC-deadlineの設定に使用）
+pub fn deadline_after_ns(ns: u64) -> u64 {
+    rdtsc().saturating_add(ns_to_cycles(ns))
+}
+
+/// TSC タイマーデバイス
+pub struct Tsc;
+
+impl TimerDevice for Tsc {
+    fn is_available(&self) -> bool {
+        frequency() != 0
+    }
+
+    fn frequency(&self) -> u64 {
+        frequency()
+    }
+
+    fn delay_ns(&self, ns: u64) {
+        let start = rdtsc();
+        let cycles = ns_to_cycles(ns);
+        while rdtsc().wrapping_sub(start) < cycles {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl ElapsedTimer for Tsc {
+    fn elapsed_ns(&self) -> u64 {
+        cycles_to_ns(rdtsc().wrapping_sub(TSC_BASE.load(Ordering::Relaxed)))
+    }
+}
+
+/// グローバルTSCインスタンス
+pub static TSC: Tsc = Tsc;
+
+/// TSCのカウンタマスク（64ビット幅）
+fn counter_mask() -> u64 {
+    u64::MAX
+}
+
+/// TSCクロックソース（Invariant TSCの場合のみ登録される）
+pub static TSC_CLOCKSOURCE: ClockSource =
+    ClockSource::new("tsc", 300, rdtsc, counter_mask, frequency);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_cycles_to_ns_conversion() {
+        // 1GHz: 1サイクル = 1ns
+        let mult = ns_mult(1_000_000_000);
+        assert_eq!(cycles_to_ns_with(12_345, mult), 12_345);
+        // 2GHz: 1000サイクル = 500ns
+        let mult = ns_mult(2_000_000_000);
+        assert_eq!(cycles_to_ns_with(1000, mult), 500);
+        // 3GHz: 1秒分のサイクル数で誤差1ns以内
+        let mult = ns_mult(3_000_000_000);
+        let ns = cycles_to_ns_with(3_000_000_000, mult);
+        assert!(ns.abs_diff(NS_PER_SEC) <= 1);
+        // 長時間（約1日分）でもオーバーフローしない
+        let day_cycles = 3_000_000_000u64 * 86_400;
+        let ns = cycles_to_ns_with(day_cycles, mult);
+        assert!(ns.abs_diff(86_400 * NS_PER_SEC) < 1_000_000);
+    }
+
+    #[test_case]
+    fn test_ns_to_cycles_conversion() {
+        assert_eq!(ns_to_cycles_with(500, 2_000_000_000), 1000);
+        assert_eq!(ns_to_cycles_with(NS_PER_SEC, 3_000_000_000), 3_000_000_000);
+        assert_eq!(ns_to_cycles_with(1_000, 0), 0);
+    }
+}
@@ -0,0 +1,143 @@
+//! ワークキュー（プロセスコンテキストでの遅延処理）
+//!
+//! タイマーのsoftirq（`raise_softirq()`/`do_softirq()`）のコールバックや割り込みハンドラは
+//! ブロックできず、重いログ出力やメモリ割り当ても避けるべきです。
+//! そうした処理はワークキューに積むことで、ワーカータスクのプールが
+//! 通常のタスクとして（プロセスコンテキストで）実行します。
+//!
+//! # ワークアイテム
+//! - ワーカータスク上で実行されるため、ブロック・メモリ割り当て・`BlockingMutex`の取得ができる
+//! - 積まれた順に取り出されるが、ワーカーが複数あるため完了順は保証しない
+//! - `init()`の前に積まれたワークは、ワーカーの起動後に実行される
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex as SpinMutex;
+
+use crate::io::without_interrupts;
+use crate::sched::{self, SpawnOptions, nice};
+use crate::sync::WaitQueue;
+
+/// ワーカータスクの数（デフォルト）
+pub const DEFAULT_WORKERS: usize = 2;
+
+/// ワークアイテム
+type Work = Box<dyn FnOnce() + Send + 'static>;
+
+/// 実行待ちのワーク
+static PENDING: SpinMutex<VecDeque<Work>> = SpinMutex::new(VecDeque::new());
+
+/// ワークを待つワーカータスクの待機キュー
+static WORKERS: WaitQueue = WaitQueue::new();
+
+/// ワーカータスクを起動済みかどうか
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// ワーカータスクのプールを起動する
+///
+/// ヒープとスケジューラの初期化後に1度だけ呼び出す（2回目以降は何もしない）。
+///
+/// # Arguments
+/// * `workers` - ワーカータスクの数（最小1）
+pub fn init(workers: usize) {
+    if STARTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    for i in 0..workers.max(1) {
+        if let Err(e) = sched::spawn(
+            format!("kworker/{}", i),
+            SpawnOptions::normal(nice::DEFAULT),
+            worker_task,
+        ) {
+            crate::warn!("Failed to start kworker/{}: {}", i, e);
+        }
+    }
+}
+
+/// ワークを積み、ワーカータスクを1つ起床させる
+///
+/// ブロックしないため、割り込みハンドラやタイマーのコールバックからも呼び出せる。
+pub fn queue_work(work: impl FnOnce() + Send + 'static) {
+    let work: Work = Box::new(work);
+    without_interrupts(|| PENDING.lock().push_back(work));
+    WORKERS.wake_one();
+}
+
+/// 指定時間の経過後にワークを積む
+///
+/// 期限はタイマーで管理し、期限が過ぎるとワーカータスクで実行される。
+///
+/// # Arguments
+/// * `work` - 実行する処理
+/// * `delay_ms` - 遅延時間（ミリ秒、最小1tick）
+///
+/// # Returns
+/// 期限前に取り消すためのハンドル（破棄しても取り消されない）
+pub fn queue_delayed_work(work: impl FnOnce() + Send + 'static, delay_ms: u64) -> DelayedWork {
+    let ticks = crate::timer::ms_to_ticks(delay_ms).max(1);
+    let timer = crate::timer::register_timer(ticks, Box::new(move || queue_work(work)));
+    DelayedWork { timer }
+}
+
+/// `queue_delayed_work()`で積んだワークのハンドル
+#[derive(Debug)]
+pub struct DelayedWork {
+    /// 期限を管理するタイマーのID
+    timer: u64,
+}
+
+impl DelayedWork {
+    /// 期限前のワークを取り消す
+    ///
+    /// # Returns
+    /// 取り消せた場合はtrue、既に期限が過ぎてワークが積まれていた場合はfalse
+    pub fn cancel(self) -> bool {
+        crate::timer::cancel_timer(self.timer)
+    }
+}
+
+/// 実行待ちのワークの数
+pub fn pending_work() -> usize {
+    without_interrupts(|| PENDING.lock().len())
+}
+
+/// 実行待ちのワークを1つ取り出す
+fn pop_work() -> Option<Work> {
+    without_interrupts(|| PENDING.lock().pop_front())
+}
+
+/// ワーカータスクの本体
+fn worker_task() {
+    loop {
+        let mut work = None;
+        // 確認から待機までの間に積まれたワークはwait_event()が検出する
+        WORKERS.wait_event(|| {
+            work = pop_work();
+            work.is_some()
+        });
+        if let Some(work) = work {
+            work();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_queued_work_runs_in_order() {
+        // テストではワーカータスクを起動しないため、積んだワークはキューに残る
+        static ORDER: SpinMutex<alloc::vec::Vec<u32>> = SpinMutex::new(alloc::vec::Vec::new());
+        let before = pending_work();
+        queue_work(|| ORDER.lock().push(1));
+        queue_work(|| ORDER.lock().push(2));
+        assert_eq!(pending_work(), before + 2);
+        while let Some(work) = pop_work() {
+            work();
+        }
+        assert_eq!(*ORDER.lock(), [1, 2]);
+    }
+}